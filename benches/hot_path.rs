@@ -0,0 +1,128 @@
+//! Micro-benchmarks for the per-request hot path.
+//!
+//! Run with `cargo bench`; pass a filter (e.g. `cargo bench -- detect`) to
+//! run a single group.
+
+use std::hint::black_box;
+use std::time::Duration;
+
+use cloudscraper_rs::challenges::core::ChallengeResponse;
+use cloudscraper_rs::{
+    AdaptiveTimingStrategy, ChallengeDetector, DefaultAdaptiveTiming, MetricsCollector,
+    RequestKind, StateManager, TimingRequest,
+};
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+
+const TURNSTILE_PAGE: &str = r#"<html><head><title>Just a moment...</title></head><body>
+    <div class="cf-turnstile" data-sitekey="0123456789ABCDEFGHIJ0123456789ABCDEFGHIJ"></div>
+    <script src="https://challenges.cloudflare.com/turnstile/v0/api.js"></script>
+    </body></html>"#;
+
+/// A typical article page: markup-heavy, no challenge markers.
+fn clean_page() -> String {
+    let paragraph = "<p class=\"body\">Lorem ipsum dolor sit amet, consectetur adipiscing \
+        elit, sed do eiusmod tempor incididunt ut labore et dolore magna aliqua.</p>\n";
+    format!(
+        "<html><head><title>Article</title></head><body>{}</body></html>",
+        paragraph.repeat(600)
+    )
+}
+
+fn detect(c: &mut Criterion) {
+    let url = url::Url::parse("https://example.com/article").unwrap();
+    let mut headers = http::HeaderMap::new();
+    headers.insert(http::header::SERVER, "cloudflare".parse().unwrap());
+    let clean = clean_page();
+    let mut detector = ChallengeDetector::new();
+
+    let mut group = c.benchmark_group("detect");
+    for (name, status, body) in [
+        ("clean", 200, clean.as_str()),
+        ("turnstile", 403, TURNSTILE_PAGE),
+    ] {
+        let response = ChallengeResponse {
+            url: &url,
+            status,
+            headers: &headers,
+            body,
+            request_method: &http::Method::GET,
+        };
+        group.bench_function(name, |b| b.iter(|| detector.detect(black_box(&response))));
+    }
+    group.finish();
+}
+
+fn record_response(c: &mut Criterion) {
+    let mut group = c.benchmark_group("metrics_record_response");
+    for recorded in [100usize, 10_000, 100_000] {
+        let metrics = MetricsCollector::new();
+        for i in 0..recorded {
+            metrics.record_response(
+                &format!("site{}.example.com", i % 50),
+                200,
+                Duration::from_millis(100 + (i % 400) as u64),
+                Duration::from_millis(500),
+            );
+        }
+        group.bench_with_input(
+            BenchmarkId::from_parameter(recorded),
+            &metrics,
+            |b, metrics| {
+                b.iter(|| {
+                    metrics.record_response(
+                        black_box("site7.example.com"),
+                        200,
+                        Duration::from_millis(250),
+                        Duration::from_millis(500),
+                    )
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+/// `prepare_request` clones the domain state to read its sticky headers.
+fn state_clone(c: &mut Criterion) {
+    let state = StateManager::new();
+    for i in 0..200 {
+        state.record_outcome(
+            "example.com",
+            i % 10 != 0,
+            Some(Duration::from_millis(300)),
+            Some(Duration::from_millis(800)),
+            (i % 10 == 0).then(|| "challenge".to_string()),
+        );
+    }
+    state.update("example.com", |domain| {
+        for i in 0..20 {
+            domain.set_cookie(format!("cookie{i}"), "x".repeat(64));
+            domain.set_header(format!("x-sticky-{i}"), "value");
+        }
+    });
+
+    c.bench_function("state_get_domain", |b| {
+        b.iter(|| state.get(black_box("example.com")))
+    });
+}
+
+fn calculate_delay(c: &mut Criterion) {
+    let mut timing = DefaultAdaptiveTiming::new();
+    let request = TimingRequest::new(RequestKind::Get, 2_000);
+    for _ in 0..100 {
+        timing.calculate_delay("example.com", &request);
+    }
+
+    c.bench_function("adaptive_timing_calculate_delay", |b| {
+        b.iter(|| timing.calculate_delay(black_box("example.com"), &request))
+    });
+}
+
+criterion_group!(
+    benches,
+    detect,
+    record_response,
+    state_clone,
+    calculate_delay
+);
+criterion_main!(benches);
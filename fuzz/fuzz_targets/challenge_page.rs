@@ -0,0 +1,22 @@
+//! `cargo +nightly fuzz run challenge_page`
+//!
+//! The first input byte picks the status code, the rest is the page body.
+
+#![no_main]
+
+use http::HeaderMap;
+use http::header::SERVER;
+use libfuzzer_sys::fuzz_target;
+
+const STATUSES: [u16; 4] = [200, 403, 429, 503];
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&selector, body)) = data.split_first() else {
+        return;
+    };
+    let status = STATUSES[usize::from(selector) % STATUSES.len()];
+    let mut headers = HeaderMap::new();
+    headers.insert(SERVER, "cloudflare".parse().unwrap());
+    let body = String::from_utf8_lossy(body);
+    let _ = cloudscraper_rs::parse_challenge(&body, "https://example.com/", status, &headers);
+});
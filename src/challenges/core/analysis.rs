@@ -3,7 +3,11 @@
 //! Provides the building blocks needed to inspect Cloudflare challenge HTML and
 //! extract the parameters required by the solvers.
 
-use http::header::SERVER;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use http::HeaderMap;
+use http::header::{RETRY_AFTER, SERVER, SET_COOKIE};
 use once_cell::sync::Lazy;
 use regex::{Regex, RegexBuilder};
 use thiserror::Error;
@@ -15,6 +19,8 @@ use super::types::{ChallengeResponse, ChallengeSubmission};
 #[derive(Debug, Clone)]
 pub struct IuamChallengeBlueprint {
     pub action: String,
+    /// Declared form method; legacy pages submit with GET.
+    pub method: http::Method,
     pub hidden_fields: Vec<(String, String)>,
 }
 
@@ -24,10 +30,11 @@ impl IuamChallengeBlueprint {
         base_url: &Url,
         mut payload: Vec<(String, String)>,
     ) -> Result<ChallengeSubmission, ChallengeParseError> {
-        payload.extend(self.hidden_fields);
+        payload.extend(self.hidden_fields.iter().cloned());
         let form_fields = payload
             .into_iter()
             .collect::<std::collections::HashMap<_, _>>();
+        let ordered_fields = self.hidden_fields;
 
         let submit_url = base_url
             .join(&self.action)
@@ -39,7 +46,9 @@ impl IuamChallengeBlueprint {
             form_fields,
             Default::default(),
             std::time::Duration::from_secs(0),
-        ))
+        )
+        .with_ordered_fields(ordered_fields)
+        .with_form_method(self.method))
     }
 }
 
@@ -73,11 +82,16 @@ pub fn parse_iuam_challenge(
         .map(|m| html_escape::decode_html_entities(m.as_str()).to_string())
         .ok_or(ChallengeParseError::FormNotFound)?;
 
+    let method = captures
+        .name("tag")
+        .map(|m| form_method(m.as_str()))
+        .unwrap_or(http::Method::POST);
     let inputs = captures.name("inputs").map(|m| m.as_str()).unwrap_or("");
     let hidden_fields = extract_hidden_fields(inputs)?;
 
     Ok(IuamChallengeBlueprint {
         action,
+        method,
         hidden_fields,
     })
 }
@@ -130,16 +144,158 @@ fn extract_hidden_fields(fragment: &str) -> Result<Vec<(String, String)>, Challe
     Ok(payload)
 }
 
+/// Method declared by a `<form ...>` opening tag; POST unless it says GET.
+pub fn form_method(form_tag: &str) -> http::Method {
+    let declared = FORM_METHOD_RE
+        .captures(form_tag)
+        .and_then(|captures| captures.get(1))
+        .map(|m| m.as_str());
+    match declared {
+        Some(method) if method.eq_ignore_ascii_case("get") => http::Method::GET,
+        _ => http::Method::POST,
+    }
+}
+
+/// Every `<input>` with a `name` and `value` in `body`, in page order and
+/// keeping repeated names, the way a browser serializes the form.
+pub fn extract_input_fields(body: &str) -> Vec<(String, String)> {
+    INPUT_FIELD_RE
+        .captures_iter(body)
+        .filter_map(|caps| Some((caps.get(1)?.as_str(), caps.get(2)?.as_str())))
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
 /// Detect whether the response is served by Cloudflare.
 pub fn is_cloudflare_response(response: &ChallengeResponse<'_>) -> bool {
-    response
-        .headers
+    is_cloudflare_headers(response.headers)
+}
+
+/// Detect a Cloudflare `Server` header in a raw header map.
+pub fn is_cloudflare_headers(headers: &HeaderMap) -> bool {
+    headers
         .get(SERVER)
         .and_then(|value| value.to_str().ok())
         .map(|value| value.to_ascii_lowercase().starts_with("cloudflare"))
         .unwrap_or(false)
 }
 
+/// Detect a Cloudflare challenge interstitial.
+///
+/// Classic interstitials use 403/429/503. Newer managed challenges may answer
+/// 200 and gate the page in JavaScript, so those only count when the body
+/// carries the inline challenge bootstrap (see [`is_inline_challenge_page`]).
+pub fn is_challenge_response(response: &ChallengeResponse<'_>) -> bool {
+    is_cloudflare_response(response)
+        && match response.status {
+            403 | 429 | 503 => true,
+            200 => is_inline_challenge_page(response.body),
+            _ => false,
+        }
+}
+
+/// Whether `body` bootstraps a challenge rather than merely mentioning one.
+///
+/// Requires both a `<script>` assigning `window._cf_chl_opt`/`_cf_chl_ctx` an
+/// object and a challenge-platform orchestrate script being loaded.
+pub fn is_inline_challenge_page(body: &str) -> bool {
+    INLINE_CHALLENGE_BOOTSTRAP_RE.is_match(body) && INLINE_CHALLENGE_PLATFORM_RE.is_match(body)
+}
+
+/// Name of the cookie Cloudflare issues once a challenge has been cleared.
+pub const CLEARANCE_COOKIE: &str = "cf_clearance";
+
+/// Check whether any `Set-Cookie` header grants a non-empty clearance cookie.
+pub fn has_clearance_cookie(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .filter_map(|cookie| cookie.split(';').next())
+        .filter_map(|pair| pair.split_once('='))
+        .any(|(name, value)| name.trim() == CLEARANCE_COOKIE && !value.trim().is_empty())
+}
+
+/// Bot-management cookie Cloudflare reissues roughly every half hour.
+pub const BOT_MANAGEMENT_COOKIE: &str = "__cf_bm";
+
+/// `__cf_bm` lifetime assumed when the cookie does not carry one.
+pub const BOT_MANAGEMENT_COOKIE_TTL: chrono::Duration = chrono::Duration::minutes(30);
+
+/// When the clearance cookie granted in `headers` stops being valid.
+///
+/// `Max-Age` wins over `Expires`, as in browsers. Returns `None` when no
+/// clearance cookie is set or it carries no lifetime.
+pub fn clearance_expiry(headers: &HeaderMap, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    cookie_expiry(set_cookie_named(headers, CLEARANCE_COOKIE)?, now)
+}
+
+/// When a `__cf_bm` cookie issued in `headers` expires, falling back to
+/// [`BOT_MANAGEMENT_COOKIE_TTL`]. `None` when no such cookie is set.
+pub fn bot_management_expiry(headers: &HeaderMap, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let cookie = set_cookie_named(headers, BOT_MANAGEMENT_COOKIE)?;
+    Some(cookie_expiry(cookie, now).unwrap_or(now + BOT_MANAGEMENT_COOKIE_TTL))
+}
+
+/// First `Set-Cookie` value granting a non-empty cookie called `name`.
+fn set_cookie_named<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .find(|cookie| {
+            cookie
+                .split(';')
+                .next()
+                .and_then(|pair| pair.split_once('='))
+                .is_some_and(|(cookie_name, value)| {
+                    cookie_name.trim() == name && !value.trim().is_empty()
+                })
+        })
+}
+
+/// Wait a `Retry-After` header in `headers` asks for, given as seconds or
+/// as an HTTP-date relative to `now`; a date already past means no wait.
+pub fn retry_after(headers: &HeaderMap, now: DateTime<Utc>) -> Option<Duration> {
+    let raw = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = raw.parse::<f64>() {
+        return Duration::try_from_secs_f64(seconds).ok();
+    }
+    let date = DateTime::parse_from_rfc2822(raw)
+        .or_else(|_| DateTime::parse_from_rfc3339(raw))
+        .ok()?;
+    Some(
+        (date.with_timezone(&Utc) - now)
+            .to_std()
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+fn cookie_expiry(cookie: &str, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let mut expires = None;
+    for attribute in cookie.split(';').skip(1) {
+        let Some((name, value)) = attribute.split_once('=') else {
+            continue;
+        };
+        let value = value.trim();
+        if name.trim().eq_ignore_ascii_case("max-age") {
+            let seconds = value.parse::<i64>().ok()?;
+            return Some(now + chrono::Duration::seconds(seconds));
+        }
+        if name.trim().eq_ignore_ascii_case("expires") {
+            expires = DateTime::parse_from_rfc2822(value)
+                .ok()
+                .map(|at| at.with_timezone(&Utc));
+        }
+    }
+    expires
+}
+
+/// Heuristic check for challenge markers left in a body served after a submission.
+pub fn looks_like_challenge_page(body: &str) -> bool {
+    CHALLENGE_MARKER_RE.is_match(body)
+}
+
 /// Build origin header value from URL (`scheme://host[:port]`).
 pub fn origin_from_url(url: &Url) -> String {
     let mut origin = format!("{}://{}", url.scheme(), url.host_str().unwrap_or(""));
@@ -152,10 +308,126 @@ pub fn origin_from_url(url: &Url) -> String {
 
 static IUAM_FORM_RE: Lazy<Regex> = Lazy::new(|| {
     RegexBuilder::new(
-        r#"(?si)<form[^>]*id=['"]challenge-form['"][^>]*action=['"](?P<action>[^"']*__cf_chl_f_tk=[^"']+)['"][^>]*>(?P<inputs>.*?)</form>"#,
+        r#"(?si)(?P<tag><form[^>]*id=['"]challenge-form['"][^>]*action=['"](?P<action>[^"']*__cf_chl_f_tk=[^"']+)['"][^>]*>)(?P<inputs>.*?)</form>"#,
     )
     .case_insensitive(true)
     .dot_matches_new_line(true)
     .build()
     .unwrap()
 });
+
+static FORM_METHOD_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?i)\smethod\s*=\s*['"]?([a-z]+)"#).expect("invalid form method regex")
+});
+
+static INPUT_FIELD_RE: Lazy<Regex> = Lazy::new(|| {
+    RegexBuilder::new(r#"<input[^>]*name=['"]([^'"]+)['"][^>]*value=['"]([^'"]*)['"]"#)
+        .case_insensitive(true)
+        .dot_matches_new_line(true)
+        .build()
+        .expect("invalid input field regex")
+});
+
+static CHALLENGE_MARKER_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r#"(?i)/cdn-cgi/challenge-platform/|window\._cf_chl_(?:opt|ctx)\s*=|id=['"]challenge-form['"]|class=['"]cf-turnstile['"]"#,
+    )
+    .expect("invalid challenge marker regex")
+});
+
+static INLINE_CHALLENGE_BOOTSTRAP_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?is)<script[^>]*>[^<]*window\._cf_chl_(?:opt|ctx)\s*=\s*\{"#)
+        .expect("invalid inline challenge bootstrap regex")
+});
+
+static INLINE_CHALLENGE_PLATFORM_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?i)['"]/cdn-cgi/challenge-platform/[^'"\s]*orchestrate/"#)
+        .expect("invalid inline challenge platform regex")
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::HeaderValue;
+
+    #[test]
+    fn detects_clearance_cookie_among_multiple_set_cookie_headers() {
+        let mut headers = HeaderMap::new();
+        headers.append(SET_COOKIE, HeaderValue::from_static("__cf_bm=abc; path=/"));
+        assert!(!has_clearance_cookie(&headers));
+
+        headers.append(
+            SET_COOKIE,
+            HeaderValue::from_static("cf_clearance=token123; path=/; HttpOnly"),
+        );
+        assert!(has_clearance_cookie(&headers));
+
+        let mut cleared = HeaderMap::new();
+        cleared.append(
+            SET_COOKIE,
+            HeaderValue::from_static("cf_clearance=; Max-Age=0"),
+        );
+        assert!(!has_clearance_cookie(&cleared));
+    }
+
+    #[test]
+    fn flags_bodies_that_still_carry_challenge_markers() {
+        assert!(looks_like_challenge_page(
+            "<script>window._cf_chl_opt = {cType: 'managed'};</script>"
+        ));
+        assert!(!looks_like_challenge_page(
+            "<html><body>Welcome back</body></html>"
+        ));
+    }
+
+    #[test]
+    fn parses_retry_after_seconds_and_dates() {
+        let now = DateTime::parse_from_rfc2822("Wed, 21 Oct 2015 07:28:00 GMT")
+            .unwrap()
+            .with_timezone(&Utc);
+        let header = |value: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(RETRY_AFTER, HeaderValue::from_static(value));
+            headers
+        };
+        assert_eq!(
+            retry_after(&header("120"), now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            retry_after(&header("Wed, 21 Oct 2015 07:30:00 GMT"), now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            retry_after(&header("Wed, 21 Oct 2015 07:00:00 GMT"), now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(retry_after(&header("-5"), now), None);
+        assert_eq!(retry_after(&header("soon"), now), None);
+        assert_eq!(retry_after(&HeaderMap::new(), now), None);
+    }
+
+    #[test]
+    fn estimates_clearance_expiry_from_cookie_attributes() {
+        let now = Utc::now();
+        let mut headers = HeaderMap::new();
+        headers.append(
+            SET_COOKIE,
+            HeaderValue::from_static(
+                "cf_clearance=abc; Expires=Wed, 21 Oct 2015 07:28:00 GMT; Max-Age=1800; path=/",
+            ),
+        );
+        assert_eq!(
+            clearance_expiry(&headers, now),
+            Some(now + chrono::Duration::seconds(1800))
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.append(
+            SET_COOKIE,
+            HeaderValue::from_static("cf_clearance=abc; Expires=Wed, 21 Oct 2015 07:28:00 GMT"),
+        );
+        let expiry = clearance_expiry(&headers, now).unwrap();
+        assert_eq!(expiry.to_rfc3339(), "2015-10-21T07:28:00+00:00");
+    }
+}
@@ -0,0 +1,152 @@
+//! Time-decayed success counters and learning-rate schedules.
+//!
+//! Outcomes lose half their weight every `half_life`, so recent behaviour
+//! dominates long-lived statistics such as proxy and pattern scores.
+//! [`LearningRate`] decides how far one sample moves an exponentially
+//! weighted average.
+
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+/// Default half-life applied when none is configured.
+pub const DEFAULT_SUCCESS_HALF_LIFE: Duration = Duration::from_secs(30 * 60);
+
+/// Success ratio where each outcome's weight halves every `half_life`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct DecayingRate {
+    #[serde(with = "crate::serde_millis::duration")]
+    half_life: Duration,
+    successes: f64,
+    total: f64,
+    #[serde(with = "crate::serde_millis::option_instant_age")]
+    updated_at: Option<Instant>,
+}
+
+impl DecayingRate {
+    pub fn new(half_life: Duration) -> Self {
+        Self {
+            half_life,
+            successes: 0.0,
+            total: 0.0,
+            updated_at: None,
+        }
+    }
+
+    pub fn half_life(&self) -> Duration {
+        self.half_life
+    }
+
+    pub fn record(&mut self, success: bool) {
+        self.record_at(success, Instant::now());
+    }
+
+    /// Record an outcome observed at `now`.
+    pub fn record_at(&mut self, success: bool, now: Instant) {
+        let factor = self.decay_factor(now);
+        self.successes = self.successes * factor + if success { 1.0 } else { 0.0 };
+        self.total = self.total * factor + 1.0;
+        self.updated_at = Some(now);
+    }
+
+    /// Weighted success ratio, or `None` when nothing has been recorded.
+    pub fn rate(&self) -> Option<f64> {
+        (self.total > f64::EPSILON).then(|| self.successes / self.total)
+    }
+
+    /// Effective number of observations remaining at `now`.
+    pub fn weight_at(&self, now: Instant) -> f64 {
+        self.total * self.decay_factor(now)
+    }
+
+    fn decay_factor(&self, now: Instant) -> f64 {
+        let Some(updated_at) = self.updated_at else {
+            return 1.0;
+        };
+        if self.half_life.is_zero() {
+            return 0.0;
+        }
+        let elapsed = now.saturating_duration_since(updated_at).as_secs_f64();
+        0.5_f64.powf(elapsed / self.half_life.as_secs_f64())
+    }
+}
+
+impl Default for DecayingRate {
+    fn default() -> Self {
+        Self::new(DEFAULT_SUCCESS_HALF_LIFE)
+    }
+}
+
+/// Weight of a new sample in an exponentially weighted average.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum LearningRate {
+    /// Every sample moves the estimate by the same share.
+    Fixed(f64),
+    /// `1 / (1 + decay * (n + 1))` for a sample following `n` others, so
+    /// early samples move the estimate quickly, but never below `floor`.
+    /// The initial estimate counts as one sample.
+    Decaying { decay: f64, floor: f64 },
+}
+
+impl LearningRate {
+    /// Running mean of the samples until the rate reaches `floor`.
+    pub const fn decaying(floor: f64) -> Self {
+        Self::Decaying { decay: 1.0, floor }
+    }
+
+    /// Rate for a sample arriving after `updates` earlier ones.
+    pub fn rate(&self, updates: u64) -> f64 {
+        match *self {
+            Self::Fixed(alpha) => alpha.clamp(0.0, 1.0),
+            Self::Decaying { decay, floor } => {
+                let rate = 1.0 / (1.0 + decay.max(0.0) * (updates as f64 + 1.0));
+                rate.max(floor).clamp(0.0, 1.0)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recent_outcomes_outweigh_old_ones() {
+        let start = Instant::now();
+        let mut rate = DecayingRate::new(Duration::from_secs(60));
+        for _ in 0..50 {
+            rate.record_at(true, start);
+        }
+        let later = start + Duration::from_secs(600);
+        for _ in 0..3 {
+            rate.record_at(false, later);
+        }
+
+        assert!(rate.rate().unwrap() < 0.5);
+        assert!(rate.weight_at(later) < 4.0);
+    }
+
+    #[test]
+    fn decaying_learning_rate_converges_fast_then_settles() {
+        let blend = |rate: LearningRate, samples: &[f64]| {
+            let mut estimate = 1.0;
+            for (n, sample) in samples.iter().enumerate() {
+                let alpha = rate.rate(n as u64);
+                estimate = (1.0 - alpha) * estimate + alpha * sample;
+            }
+            estimate
+        };
+        let fixed = LearningRate::Fixed(0.05);
+        let decaying = LearningRate::decaying(0.05);
+
+        let early = [5.0; 10];
+        assert!(blend(decaying, &early) > 4.5);
+        assert!(blend(fixed, &early) < 2.7);
+
+        let mut settled = vec![5.0; 200];
+        settled.push(50.0);
+        let before = blend(decaying, &settled[..200]);
+        assert!((blend(decaying, &settled) - before - 2.25).abs() < 1e-3);
+        assert_eq!(decaying.rate(1000), 0.05);
+    }
+}
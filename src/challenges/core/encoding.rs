@@ -0,0 +1,102 @@
+//! Challenge body decoding.
+//!
+//! reqwest decompresses bodies itself and drops `Content-Encoding`, but a
+//! custom [`ChallengeHttpClient`](super::ChallengeHttpClient) may hand back the
+//! raw bytes. Detection on a still-compressed body finds nothing, so bodies
+//! are decoded by their `Content-Encoding` before any regex sees them.
+
+use std::borrow::Cow;
+use std::io::Read;
+
+use flate2::read::{GzDecoder, ZlibDecoder};
+use http::HeaderMap;
+use http::header::CONTENT_ENCODING;
+
+/// Decompressed bodies larger than this are cut off, guarding against
+/// decompression bombs.
+const MAX_DECODED_BYTES: u64 = 32 * 1024 * 1024;
+
+/// Body text after undoing every `Content-Encoding` listed in `headers`.
+///
+/// Falls back to the raw bytes when an encoding is unknown or the data does
+/// not decode, e.g. because the transport already decompressed it.
+pub fn decode_body<'a>(bytes: &'a [u8], headers: &HeaderMap) -> Cow<'a, str> {
+    let codings: Vec<String> = headers
+        .get_all(CONTENT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|coding| coding.trim().to_ascii_lowercase())
+        .filter(|coding| !coding.is_empty() && coding != "identity")
+        .collect();
+    if codings.is_empty() {
+        return String::from_utf8_lossy(bytes);
+    }
+
+    // Codings are listed in the order they were applied.
+    let mut decoded = bytes.to_vec();
+    for coding in codings.iter().rev() {
+        match decompress(&decoded, coding) {
+            Some(next) => decoded = next,
+            None => return String::from_utf8_lossy(bytes),
+        }
+    }
+    Cow::Owned(String::from_utf8_lossy(&decoded).into_owned())
+}
+
+fn decompress(bytes: &[u8], coding: &str) -> Option<Vec<u8>> {
+    match coding {
+        "gzip" | "x-gzip" => read_all(GzDecoder::new(bytes)),
+        // Servers disagree on whether `deflate` is zlib-wrapped; try both.
+        "deflate" => read_all(ZlibDecoder::new(bytes))
+            .or_else(|| read_all(flate2::read::DeflateDecoder::new(bytes))),
+        "br" => read_all(brotli_decompressor::Decompressor::new(bytes, 4096)),
+        _ => None,
+    }
+}
+
+fn read_all(reader: impl Read) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    reader.take(MAX_DECODED_BYTES).read_to_end(&mut out).ok()?;
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use http::HeaderValue;
+
+    use super::*;
+    use crate::challenges::core::ChallengeResponse;
+    use crate::challenges::detectors::{ChallengeDetector, ChallengeType};
+
+    #[test]
+    fn gzip_turnstile_page_is_detected() {
+        let page = r#"<div class="cf-turnstile" data-sitekey="0123456789ABCDEFGHIJ0123456789ABCDEFGHIJ"></div>
+            <script src="https://challenges.cloudflare.com/turnstile/v0/api.js"></script>"#;
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(page.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut fixture = ChallengeResponse::builder()
+            .url("https://example.com/login")
+            .status(403)
+            .server_cloudflare()
+            .header("content-encoding", "gzip")
+            .build();
+        fixture.body = decode_body(&compressed, &fixture.headers).into_owned();
+        assert_eq!(fixture.body, page);
+        let detection = ChallengeDetector::new()
+            .detect(&fixture.as_response())
+            .expect("turnstile page");
+        assert_eq!(detection.challenge_type, ChallengeType::Turnstile);
+
+        // Already-decoded bodies still carrying the header pass through.
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_ENCODING, HeaderValue::from_static("br"));
+        assert_eq!(decode_body(page.as_bytes(), &headers), page);
+    }
+}
@@ -4,17 +4,21 @@
 //! Cloudflare's required delay, following redirects, and surfacing meaningful
 //! errors back to the caller.
 
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::Duration;
 
 use async_trait::async_trait;
 use http::Method;
-use http::header::{HeaderMap, HeaderName, HeaderValue, LOCATION, REFERER};
+use http::header::{
+    CONTENT_LENGTH, CONTENT_TYPE, HeaderMap, HeaderName, HeaderValue, LOCATION, REFERER, SET_COOKIE,
+};
 use thiserror::Error;
 use tokio::time::sleep;
 use url::Url;
 
+use super::encoding::decode_body;
 use super::types::ChallengeSubmission;
 
 /// Contract that abstracts the underlying HTTP transport used during challenge replay.
@@ -53,6 +57,11 @@ pub struct ChallengeHttpResponse {
 }
 
 impl ChallengeHttpResponse {
+    /// Body text, decompressed per `Content-Encoding` if still encoded.
+    pub fn text(&self) -> Cow<'_, str> {
+        decode_body(&self.body, &self.headers)
+    }
+
     pub fn location(&self) -> Option<&str> {
         self.headers
             .get(LOCATION)
@@ -77,6 +86,31 @@ pub enum ChallengeExecutionError {
     Client(#[from] ChallengeHttpClientError),
 }
 
+/// Solves the follow-up challenge a submission can be answered with.
+///
+/// Managed challenges often take two rounds: the first answer is met by a
+/// second challenge page rather than the origin. Futures need not be `Send`
+/// so implementations can drive the scraper's pipeline directly.
+#[async_trait(?Send)]
+pub trait ChallengeStageSolver {
+    /// Submission for `response` when it is itself a solvable challenge.
+    async fn next_stage(
+        &self,
+        response: &ChallengeHttpResponse,
+        request_method: &Method,
+    ) -> Option<ChallengeSubmission>;
+}
+
+/// Final response of a multi-stage challenge flow.
+#[derive(Debug, Clone)]
+pub struct StagedChallengeResponse {
+    pub response: ChallengeHttpResponse,
+    /// Submissions sent, including the first.
+    pub stages: usize,
+    /// Total solver-mandated wait across all stages.
+    pub waited: Duration,
+}
+
 /// Context about the original request that triggered the challenge.
 #[derive(Debug, Clone)]
 pub struct OriginalRequest {
@@ -107,42 +141,109 @@ impl OriginalRequest {
     }
 }
 
+/// What to send once the challenge answer has been accepted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PostClearanceStrategy {
+    /// Follow Cloudflare's redirect by replaying the original method and body.
+    #[default]
+    ReplayOriginal,
+    /// Issue a fresh GET to the originally requested URL, whatever its method was.
+    RefetchGet,
+    /// Return the submission response without requesting the origin again.
+    ReturnChallengeResult,
+}
+
 /// Executes the Cloudflare response submission for IUAM-style challenges.
 ///
 /// Submission steps:
 /// 1. Wait the enforced delay duration.
 /// 2. POST the computed payload back to Cloudflare.
 /// 3. If the response is a redirect, follow it manually (respecting relative URLs).
-/// 4. Return the final response so callers can resume normal processing.
+/// 4. Return the final response so callers can resume normal processing. Any
+///    `Set-Cookie` headers issued by the intermediate redirect are carried over
+///    so callers can verify that clearance was granted.
 pub async fn execute_challenge_submission(
     client: Arc<dyn ChallengeHttpClient>,
     submission: ChallengeSubmission,
     original_request: OriginalRequest,
+) -> Result<ChallengeHttpResponse, ChallengeExecutionError> {
+    execute_challenge_submission_with(
+        client,
+        submission,
+        original_request,
+        PostClearanceStrategy::ReplayOriginal,
+    )
+    .await
+}
+
+/// Like [`execute_challenge_submission`], choosing the follow-up request with
+/// `strategy` instead of always replaying the original request.
+pub async fn execute_challenge_submission_with(
+    client: Arc<dyn ChallengeHttpClient>,
+    submission: ChallengeSubmission,
+    original_request: OriginalRequest,
+    strategy: PostClearanceStrategy,
 ) -> Result<ChallengeHttpResponse, ChallengeExecutionError> {
     if submission.wait > Duration::from_millis(0) {
         sleep(submission.wait).await;
     }
 
-    let submission_headers = convert_headers(&submission.headers)?;
-    let first_response = client
-        .send_form(
-            &submission.method,
-            &submission.url,
-            &submission_headers,
-            &submission.form_fields,
-            submission.allow_redirects,
-        )
-        .await?;
+    let mut submission_headers = convert_headers(&submission.headers)?;
+    // GET submissions already carry their fields in the URL query.
+    let first_response = if submission.method == Method::GET {
+        client
+            .send_with_body(
+                &Method::GET,
+                &submission.url,
+                &submission_headers,
+                None,
+                submission.allow_redirects,
+            )
+            .await?
+    } else if submission.ordered_fields.is_some() {
+        // `send_form` takes a map, so encode the ordered fields ourselves.
+        let body = url::form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(submission.ordered_form())
+            .finish();
+        submission_headers
+            .entry(CONTENT_TYPE)
+            .or_insert(HeaderValue::from_static(
+                "application/x-www-form-urlencoded",
+            ));
+        client
+            .send_with_body(
+                &submission.method,
+                &submission.url,
+                &submission_headers,
+                Some(body.as_bytes()),
+                submission.allow_redirects,
+            )
+            .await?
+    } else {
+        client
+            .send_form(
+                &submission.method,
+                &submission.url,
+                &submission_headers,
+                &submission.form_fields,
+                submission.allow_redirects,
+            )
+            .await?
+    };
 
     if first_response.status == 400 {
         return Err(ChallengeExecutionError::InvalidAnswer);
     }
 
-    if !first_response.is_redirect {
-        return Ok(first_response);
-    }
+    let refetch = match strategy {
+        PostClearanceStrategy::ReturnChallengeResult => return Ok(first_response),
+        PostClearanceStrategy::ReplayOriginal if !first_response.is_redirect => {
+            return Ok(first_response);
+        }
+        PostClearanceStrategy::ReplayOriginal => false,
+        PostClearanceStrategy::RefetchGet => true,
+    };
 
-    let redirect_target = resolve_redirect(&first_response, &original_request.url);
     let mut follow_headers = original_request.headers.clone();
     follow_headers.insert(
         REFERER,
@@ -150,17 +251,79 @@ pub async fn execute_challenge_submission(
             .map_err(|_| ChallengeExecutionError::InvalidHeader("referer".into()))?,
     );
 
-    let follow_response = client
-        .send_with_body(
-            &original_request.method,
-            &redirect_target,
-            &follow_headers,
-            original_request.body.as_deref(),
-            true,
+    let mut follow_response = if refetch {
+        follow_headers.remove(CONTENT_TYPE);
+        follow_headers.remove(CONTENT_LENGTH);
+        client
+            .send_with_body(
+                &Method::GET,
+                &original_request.url,
+                &follow_headers,
+                None,
+                true,
+            )
+            .await?
+    } else {
+        let redirect_target = resolve_redirect(&first_response, &original_request.url);
+        client
+            .send_with_body(
+                &original_request.method,
+                &redirect_target,
+                &follow_headers,
+                original_request.body.as_deref(),
+                true,
+            )
+            .await?
+    };
+
+    for cookie in first_response.headers.get_all(SET_COOKIE) {
+        follow_response.headers.append(SET_COOKIE, cookie.clone());
+    }
+
+    Ok(follow_response)
+}
+
+/// Submit `submission`, then keep solving while the answer is another
+/// challenge, sending at most `max_stages` submissions in total.
+pub async fn execute_challenge_stages(
+    client: Arc<dyn ChallengeHttpClient>,
+    mut submission: ChallengeSubmission,
+    original_request: OriginalRequest,
+    strategy: PostClearanceStrategy,
+    solver: &dyn ChallengeStageSolver,
+    max_stages: usize,
+) -> Result<StagedChallengeResponse, ChallengeExecutionError> {
+    let mut stages = 1;
+    let mut waited = Duration::ZERO;
+    loop {
+        waited += submission.wait;
+        let response = execute_challenge_submission_with(
+            client.clone(),
+            submission,
+            original_request.clone(),
+            strategy,
         )
         .await?;
 
-    Ok(follow_response)
+        let next = if stages < max_stages {
+            solver.next_stage(&response, &original_request.method).await
+        } else {
+            None
+        };
+        match next {
+            Some(next) => {
+                submission = next;
+                stages += 1;
+            }
+            None => {
+                return Ok(StagedChallengeResponse {
+                    response,
+                    stages,
+                    waited,
+                });
+            }
+        }
+    }
 }
 
 fn convert_headers(
@@ -200,12 +363,16 @@ mod tests {
 
     struct StubClient {
         responses: Mutex<Vec<ChallengeHttpResponse>>,
+        sent_with_body: Mutex<Vec<(Method, Url, bool)>>,
+        bodies: Mutex<Vec<Vec<u8>>>,
     }
 
     impl StubClient {
         fn new(responses: Vec<ChallengeHttpResponse>) -> Self {
             Self {
                 responses: Mutex::new(responses.into_iter().rev().collect()),
+                sent_with_body: Mutex::new(Vec::new()),
+                bodies: Mutex::new(Vec::new()),
             }
         }
 
@@ -233,12 +400,17 @@ mod tests {
 
         async fn send_with_body(
             &self,
-            _method: &Method,
-            _url: &Url,
+            method: &Method,
+            url: &Url,
             _headers: &HeaderMap,
-            _body: Option<&[u8]>,
+            body: Option<&[u8]>,
             _allow_redirects: bool,
         ) -> Result<ChallengeHttpResponse, ChallengeHttpClientError> {
+            self.sent_with_body
+                .lock()
+                .unwrap()
+                .push((method.clone(), url.clone(), body.is_some()));
+            self.bodies.lock().unwrap().extend(body.map(<[u8]>::to_vec));
             Ok(self.pop_response())
         }
     }
@@ -313,4 +485,281 @@ mod tests {
 
         assert_eq!(response.url.as_str(), "https://example.com/redirected");
     }
+
+    #[tokio::test]
+    async fn carries_redirect_cookies_into_final_response() {
+        let submission = ChallengeSubmission::new(
+            Method::POST,
+            Url::parse("https://example.com/submit").unwrap(),
+            HashMap::new(),
+            HashMap::new(),
+            Duration::from_millis(0),
+        );
+        let original = OriginalRequest::new(
+            Method::GET,
+            Url::parse("https://example.com/protected").unwrap(),
+        );
+
+        let mut redirect_headers = HeaderMap::new();
+        redirect_headers.insert(LOCATION, HeaderValue::from_static("/protected"));
+        redirect_headers.append(
+            SET_COOKIE,
+            HeaderValue::from_static("cf_clearance=granted; path=/"),
+        );
+
+        let client = Arc::new(StubClient::new(vec![
+            make_response(302, "https://example.com/submit", redirect_headers),
+            make_response(200, "https://example.com/protected", HeaderMap::new()),
+        ]));
+
+        let response = execute_challenge_submission(client, submission, original)
+            .await
+            .unwrap();
+
+        assert!(crate::challenges::core::has_clearance_cookie(
+            &response.headers
+        ));
+    }
+
+    #[tokio::test]
+    async fn refetch_get_requests_original_url_instead_of_replaying() {
+        let submission = ChallengeSubmission::new(
+            Method::POST,
+            Url::parse("https://example.com/submit").unwrap(),
+            HashMap::new(),
+            HashMap::new(),
+            Duration::from_millis(0),
+        );
+        let original = OriginalRequest::new(
+            Method::POST,
+            Url::parse("https://example.com/api/orders").unwrap(),
+        )
+        .with_body(Some(b"qty=1".to_vec()));
+
+        let client = Arc::new(StubClient::new(vec![
+            make_response(200, "https://example.com/submit", HeaderMap::new()),
+            make_response(200, "https://example.com/api/orders", HeaderMap::new()),
+        ]));
+
+        let response = execute_challenge_submission_with(
+            client.clone(),
+            submission,
+            original,
+            PostClearanceStrategy::RefetchGet,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.url.as_str(), "https://example.com/api/orders");
+        let sent_with_body = client.sent_with_body.lock().unwrap();
+        assert_eq!(
+            *sent_with_body,
+            vec![(
+                Method::GET,
+                Url::parse("https://example.com/api/orders").unwrap(),
+                false
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn return_challenge_result_skips_the_redirect() {
+        let submission = ChallengeSubmission::new(
+            Method::POST,
+            Url::parse("https://example.com/submit").unwrap(),
+            HashMap::new(),
+            HashMap::new(),
+            Duration::from_millis(0),
+        );
+        let original = OriginalRequest::new(
+            Method::GET,
+            Url::parse("https://example.com/protected").unwrap(),
+        );
+        let mut redirect_headers = HeaderMap::new();
+        redirect_headers.insert(LOCATION, HeaderValue::from_static("/protected"));
+
+        let client = Arc::new(StubClient::new(vec![make_response(
+            302,
+            "https://example.com/submit",
+            redirect_headers,
+        )]));
+
+        let response = execute_challenge_submission_with(
+            client.clone(),
+            submission,
+            original,
+            PostClearanceStrategy::ReturnChallengeResult,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status, 302);
+        assert!(client.sent_with_body.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn get_submission_is_sent_without_a_body() {
+        let submission = ChallengeSubmission::new(
+            Method::POST,
+            Url::parse("https://example.com/submit").unwrap(),
+            HashMap::from([(String::from("jschl_answer"), String::from("42"))]),
+            HashMap::new(),
+            Duration::from_millis(0),
+        )
+        .with_form_method(Method::GET);
+        let original = OriginalRequest::new(
+            Method::GET,
+            Url::parse("https://example.com/protected").unwrap(),
+        );
+        let client = Arc::new(StubClient::new(vec![make_response(
+            200,
+            "https://example.com/protected",
+            HeaderMap::new(),
+        )]));
+
+        execute_challenge_submission(client.clone(), submission, original)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            *client.sent_with_body.lock().unwrap(),
+            vec![(
+                Method::GET,
+                Url::parse("https://example.com/submit?jschl_answer=42").unwrap(),
+                false
+            )]
+        );
+    }
+
+    /// Answers every 403 with another zero-wait submission.
+    struct SecondStage;
+
+    #[async_trait(?Send)]
+    impl ChallengeStageSolver for SecondStage {
+        async fn next_stage(
+            &self,
+            response: &ChallengeHttpResponse,
+            _request_method: &Method,
+        ) -> Option<ChallengeSubmission> {
+            (response.status == 403).then(|| {
+                ChallengeSubmission::new(
+                    Method::POST,
+                    Url::parse("https://example.com/submit?stage=2").unwrap(),
+                    HashMap::new(),
+                    HashMap::new(),
+                    Duration::from_millis(0),
+                )
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn solves_two_stage_managed_flow() {
+        let submission = ChallengeSubmission::new(
+            Method::POST,
+            Url::parse("https://example.com/submit").unwrap(),
+            HashMap::new(),
+            HashMap::new(),
+            Duration::from_millis(0),
+        );
+        let original = OriginalRequest::new(
+            Method::GET,
+            Url::parse("https://example.com/protected").unwrap(),
+        );
+        let client = Arc::new(StubClient::new(vec![
+            make_response(403, "https://example.com/submit", HeaderMap::new()),
+            make_response(200, "https://example.com/protected", HeaderMap::new()),
+        ]));
+
+        let staged = execute_challenge_stages(
+            client,
+            submission,
+            original,
+            PostClearanceStrategy::ReplayOriginal,
+            &SecondStage,
+            2,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(staged.response.status, 200);
+        assert_eq!(staged.stages, 2);
+    }
+
+    #[tokio::test]
+    async fn stops_after_max_stages() {
+        let submission = ChallengeSubmission::new(
+            Method::POST,
+            Url::parse("https://example.com/submit").unwrap(),
+            HashMap::new(),
+            HashMap::new(),
+            Duration::from_millis(0),
+        );
+        let original = OriginalRequest::new(
+            Method::GET,
+            Url::parse("https://example.com/protected").unwrap(),
+        );
+        let client = Arc::new(StubClient::new(vec![
+            make_response(403, "https://example.com/submit", HeaderMap::new()),
+            make_response(403, "https://example.com/submit", HeaderMap::new()),
+        ]));
+
+        let staged = execute_challenge_stages(
+            client,
+            submission,
+            original,
+            PostClearanceStrategy::ReplayOriginal,
+            &SecondStage,
+            2,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(staged.response.status, 403);
+        assert_eq!(staged.stages, 2);
+    }
+
+    #[tokio::test]
+    async fn ordered_fields_keep_duplicates_and_page_order() {
+        let page = r#"<form id="challenge-form" method="POST">
+            <input type="hidden" name="r" value="token">
+            <input type="hidden" name="tag" value="first">
+            <input type="hidden" name="jschl_answer" value="">
+            <input type="hidden" name="tag" value="second">
+            <input type="hidden" name="unused" value="x">
+        </form>"#;
+        let inputs = super::super::extract_input_fields(page);
+        assert_eq!(inputs.len(), 5);
+        let form_fields = HashMap::from([
+            (String::from("r"), String::from("token")),
+            (String::from("tag"), String::from("second")),
+            (String::from("jschl_answer"), String::from("42")),
+            (String::from("cf_captcha_token"), String::new()),
+        ]);
+        let submission = ChallengeSubmission::new(
+            Method::POST,
+            Url::parse("https://example.com/submit").unwrap(),
+            form_fields,
+            HashMap::new(),
+            Duration::from_millis(0),
+        )
+        .with_ordered_fields(inputs);
+
+        let client = Arc::new(StubClient::new(vec![make_response(
+            200,
+            "https://example.com/submit",
+            HeaderMap::new(),
+        )]));
+        let original =
+            OriginalRequest::new(Method::GET, Url::parse("https://example.com").unwrap());
+        execute_challenge_submission(client.clone(), submission, original)
+            .await
+            .unwrap();
+
+        let bodies = client.bodies.lock().unwrap();
+        assert_eq!(
+            std::str::from_utf8(&bodies[0]).unwrap(),
+            "r=token&tag=first&jschl_answer=42&tag=second&cf_captcha_token="
+        );
+    }
 }
@@ -0,0 +1,122 @@
+//! Owned challenge responses for detector and solver tests.
+//!
+//! [`ChallengeResponse`] only borrows its parts, so tests need somewhere to
+//! keep them. [`ChallengeResponseBuilder`] collects the parts into an
+//! [`OwnedChallengeResponse`] that lends out a `ChallengeResponse` on demand.
+//!
+//! ```
+//! use cloudscraper_rs::challenges::core::ChallengeResponse;
+//! use cloudscraper_rs::{ChallengeDetector, ChallengeType};
+//!
+//! let page = ChallengeResponse::builder()
+//!     .url("https://example.com/login")
+//!     .status(403)
+//!     .server_cloudflare()
+//!     .body(r#"<div class="cf-turnstile" data-sitekey="0123456789ABCDEFGHIJ0123456789ABCDEFGHIJ"></div>
+//!         <script src="https://challenges.cloudflare.com/turnstile/v0/api.js"></script>"#)
+//!     .build();
+//!
+//! let detection = ChallengeDetector::new()
+//!     .detect(&page.as_response())
+//!     .expect("turnstile page");
+//! assert_eq!(detection.challenge_type, ChallengeType::Turnstile);
+//! ```
+
+use http::header::SERVER;
+use http::{HeaderMap, HeaderName, HeaderValue, Method};
+use url::Url;
+
+use super::types::ChallengeResponse;
+
+/// Response parts owned on behalf of a borrowed [`ChallengeResponse`].
+#[derive(Debug, Clone)]
+pub struct OwnedChallengeResponse {
+    pub url: Url,
+    pub status: u16,
+    pub headers: HeaderMap,
+    pub body: String,
+    pub request_method: Method,
+}
+
+impl OwnedChallengeResponse {
+    pub fn as_response(&self) -> ChallengeResponse<'_> {
+        ChallengeResponse {
+            url: &self.url,
+            status: self.status,
+            headers: &self.headers,
+            body: &self.body,
+            request_method: &self.request_method,
+        }
+    }
+}
+
+/// Builder for [`OwnedChallengeResponse`]; defaults to an empty
+/// `200` answer to `GET https://example.com/`.
+#[derive(Debug, Clone)]
+pub struct ChallengeResponseBuilder {
+    response: OwnedChallengeResponse,
+}
+
+impl Default for ChallengeResponseBuilder {
+    fn default() -> Self {
+        Self {
+            response: OwnedChallengeResponse {
+                url: Url::parse("https://example.com/").expect("static url"),
+                status: 200,
+                headers: HeaderMap::new(),
+                body: String::new(),
+                request_method: Method::GET,
+            },
+        }
+    }
+}
+
+impl ChallengeResponseBuilder {
+    /// Panics if `url` does not parse; fixtures are expected to be literals.
+    pub fn url(mut self, url: &str) -> Self {
+        self.response.url = Url::parse(url).expect("invalid fixture url");
+        self
+    }
+
+    pub fn status(mut self, status: u16) -> Self {
+        self.response.status = status;
+        self
+    }
+
+    /// Panics on an invalid header name or value.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        let name = HeaderName::from_bytes(name.as_bytes()).expect("invalid fixture header name");
+        let value = HeaderValue::from_str(value).expect("invalid fixture header value");
+        self.response.headers.append(name, value);
+        self
+    }
+
+    /// Add `Server: cloudflare`, which most detectors require.
+    pub fn server_cloudflare(mut self) -> Self {
+        self.response
+            .headers
+            .insert(SERVER, HeaderValue::from_static("cloudflare"));
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<String>) -> Self {
+        self.response.body = body.into();
+        self
+    }
+
+    pub fn method(mut self, method: Method) -> Self {
+        self.response.request_method = method;
+        self
+    }
+
+    pub fn build(self) -> OwnedChallengeResponse {
+        self.response
+    }
+}
+
+impl ChallengeResponse<'_> {
+    /// Start building an owned response for tests.
+    pub fn builder() -> ChallengeResponseBuilder {
+        ChallengeResponseBuilder::default()
+    }
+}
@@ -1,19 +1,31 @@
 //! Core utilities shared by challenge detectors, analyzers, and solvers.
 
 pub mod analysis;
+pub mod decay;
+pub mod encoding;
 pub mod executor;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod fixture;
 pub mod reqwest_client;
 pub mod timing;
 pub mod types;
 
 pub use analysis::{
-    ChallengeParseError, IuamChallengeBlueprint, is_cloudflare_response, origin_from_url,
-    parse_iuam_challenge,
+    BOT_MANAGEMENT_COOKIE, BOT_MANAGEMENT_COOKIE_TTL, CLEARANCE_COOKIE, ChallengeParseError,
+    IuamChallengeBlueprint, bot_management_expiry, clearance_expiry, extract_input_fields,
+    form_method, has_clearance_cookie, is_challenge_response, is_cloudflare_headers,
+    is_cloudflare_response, is_inline_challenge_page, looks_like_challenge_page, origin_from_url,
+    parse_iuam_challenge, retry_after,
 };
+pub use decay::{DEFAULT_SUCCESS_HALF_LIFE, DecayingRate, LearningRate};
+pub use encoding::decode_body;
 pub use executor::{
     ChallengeExecutionError, ChallengeHttpClient, ChallengeHttpClientError, ChallengeHttpResponse,
-    OriginalRequest, execute_challenge_submission,
+    ChallengeStageSolver, OriginalRequest, PostClearanceStrategy, StagedChallengeResponse,
+    execute_challenge_stages, execute_challenge_submission, execute_challenge_submission_with,
 };
+#[cfg(any(test, feature = "test-utils"))]
+pub use fixture::{ChallengeResponseBuilder, OwnedChallengeResponse};
 pub use reqwest_client::ReqwestChallengeHttpClient;
 pub use timing::{DelayStrategy, TimingFeedback};
 pub use types::{ChallengeResponse, ChallengeSubmission};
@@ -154,7 +154,7 @@ fn convert_back_headers(map: &HeaderMap) -> Result<HttpHeaderMap, ChallengeHttpC
             .map_err(|err| ChallengeHttpClientError::Transport(err.to_string()))?;
         let http_value = HttpHeaderValue::from_bytes(value.as_bytes())
             .map_err(|err| ChallengeHttpClientError::Transport(err.to_string()))?;
-        headers.insert(http_name, http_value);
+        headers.append(http_name, http_value);
     }
     Ok(headers)
 }
@@ -2,7 +2,7 @@
 
 use http::{HeaderMap, Method};
 use std::collections::HashMap;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use url::Url;
 
 /// Minimal representation of an HTTP response emitted by the scraper.
@@ -21,9 +21,15 @@ pub struct ChallengeSubmission {
     pub method: Method,
     pub url: Url,
     pub form_fields: HashMap<String, String>,
+    /// The form's inputs in page order, repeated names included. When set,
+    /// the body follows this order; `form_fields` still decides which names
+    /// are sent and with what value.
+    pub ordered_fields: Option<Vec<(String, String)>>,
     pub headers: HashMap<String, String>,
     pub wait: Duration,
     pub allow_redirects: bool,
+    /// When the captcha token in the payload stops being accepted.
+    pub token_expires_at: Option<Instant>,
 }
 
 impl ChallengeSubmission {
@@ -38,14 +44,82 @@ impl ChallengeSubmission {
             method,
             url,
             form_fields,
+            ordered_fields: None,
             headers,
             wait,
             allow_redirects: false,
+            token_expires_at: None,
         }
     }
 
+    /// Record that the payload's token lives `ttl` from now, if known.
+    pub fn with_token_ttl(mut self, ttl: Option<Duration>) -> Self {
+        self.token_expires_at = ttl.map(|ttl| Instant::now() + ttl);
+        self
+    }
+
+    /// Whether the token will have less than `margin` left once `wait` has
+    /// elapsed and the submission goes out.
+    pub fn token_expires_within(&self, margin: Duration) -> bool {
+        self.token_expires_at.is_some_and(|expires_at| {
+            expires_at.saturating_duration_since(Instant::now() + self.wait) < margin
+        })
+    }
+
+    /// Keep the page's own field order and duplicates for the body.
+    pub fn with_ordered_fields(mut self, inputs: Vec<(String, String)>) -> Self {
+        self.ordered_fields = Some(inputs);
+        self
+    }
+
+    /// Fields as they will be encoded.
+    ///
+    /// Page inputs absent from `form_fields` are dropped, a value the solver
+    /// changed replaces the first input of that name, and fields the page
+    /// lacks follow in name order. Without `ordered_fields` this is just
+    /// `form_fields` sorted by name.
+    pub fn ordered_form(&self) -> Vec<(String, String)> {
+        let mut ordered: Vec<(String, String)> = self
+            .ordered_fields
+            .iter()
+            .flatten()
+            .filter(|(name, _)| self.form_fields.contains_key(name))
+            .cloned()
+            .collect();
+        let mut extra = Vec::new();
+        for (name, value) in &self.form_fields {
+            let mut inputs = ordered.iter_mut().filter(|(input, _)| input == name);
+            match inputs.next() {
+                None => extra.push((name.clone(), value.clone())),
+                Some(first) => {
+                    if first.1 != *value && !inputs.any(|(_, other)| other == value) {
+                        first.1 = value.clone();
+                    }
+                }
+            }
+        }
+        extra.sort();
+        ordered.extend(extra);
+        ordered
+    }
+
     pub fn with_allow_redirects(mut self, allow: bool) -> Self {
         self.allow_redirects = allow;
         self
     }
+
+    /// Submit with the form's declared `method`.
+    ///
+    /// For GET the fields are appended to the URL query, since there is no
+    /// body to carry them; `form_fields` is kept for inspection.
+    pub fn with_form_method(mut self, method: Method) -> Self {
+        if method == Method::GET {
+            let fields = self.ordered_form();
+            self.url.query_pairs_mut().extend_pairs(fields);
+            self.headers
+                .retain(|name, _| !name.eq_ignore_ascii_case("content-type"));
+        }
+        self.method = method;
+        self
+    }
 }
@@ -0,0 +1,131 @@
+//! Reload detector patterns when their file changes, behind the
+//! `hot-reload` feature.
+
+use std::path::{Path, PathBuf};
+
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+
+use super::{ChallengeDetector, PatternError};
+
+/// Keeps a pattern file watched; dropping it stops the reloads.
+#[derive(Debug)]
+pub struct PatternWatcher {
+    path: PathBuf,
+    _watcher: RecommendedWatcher,
+}
+
+impl PatternWatcher {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl ChallengeDetector {
+    /// Load the patterns in `path` now and again whenever the file changes.
+    ///
+    /// The first load must succeed. Later files that fail to parse or
+    /// compile are logged and ignored, keeping the previous patterns. The
+    /// parent directory is watched, so files replaced by a rename (as most
+    /// editors and config management tools do) are picked up too.
+    pub fn watch_patterns(&self, path: impl AsRef<Path>) -> Result<PatternWatcher, PatternError> {
+        let path = std::path::absolute(path.as_ref())?;
+        self.reload_patterns(&path)?;
+
+        let detector = self.clone();
+        let watched = path.clone();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            let event = match event {
+                Ok(event) => event,
+                Err(err) => {
+                    log::warn!("pattern file watch error: {err}");
+                    return;
+                }
+            };
+            if event.kind.is_access() || !event.paths.iter().any(|path| path == &watched) {
+                return;
+            }
+            match detector.reload_patterns(&watched) {
+                Ok(count) => log::info!(
+                    "reloaded {count} detection patterns from {}",
+                    watched.display()
+                ),
+                Err(err) => log::warn!(
+                    "keeping previous detection patterns; {} is invalid: {err}",
+                    watched.display()
+                ),
+            }
+        })?;
+        let dir = path.parent().unwrap_or(Path::new("/"));
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+
+        Ok(PatternWatcher {
+            path,
+            _watcher: watcher,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use http::header::SERVER;
+    use http::{HeaderMap, Method};
+    use url::Url;
+
+    use super::*;
+    use crate::challenges::core::ChallengeResponse;
+    use crate::challenges::detectors::ChallengeType;
+
+    const SHIELD_PAGE: &str = "<h1>Checking your browser</h1><div id=\"edge-shield\"></div>";
+
+    fn detects_shield(detector: &mut ChallengeDetector) -> bool {
+        let url = Url::parse("https://example.com/").unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(SERVER, "cloudflare".parse().unwrap());
+        detector
+            .detect(&ChallengeResponse {
+                url: &url,
+                status: 403,
+                headers: &headers,
+                body: SHIELD_PAGE,
+                request_method: &Method::GET,
+            })
+            .is_some_and(|detection| detection.pattern_id == "edge_shield")
+    }
+
+    #[test]
+    fn picks_up_new_signatures_and_ignores_broken_files() {
+        let dir =
+            std::env::temp_dir().join(format!("cloudscraper-patterns-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("patterns.json");
+        std::fs::write(&path, "[]").unwrap();
+
+        let mut detector = ChallengeDetector::new();
+        let watcher = detector.clone().watch_patterns(&path).unwrap();
+        assert!(!detects_shield(&mut detector));
+
+        let updated = serde_json::json!([{
+            "id": "edge_shield",
+            "name": "Edge Shield",
+            "challenge_type": ChallengeType::BotManagement,
+            "response_strategy": "EnhancedEvasion",
+            "base_confidence": 0.9,
+            "patterns": ["id=\"edge-shield\""],
+        }]);
+        std::fs::write(&path, updated.to_string()).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !detects_shield(&mut detector) {
+            assert!(Instant::now() < deadline, "pattern file was not reloaded");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+
+        std::fs::write(&path, "[{\"id\": ").unwrap();
+        std::thread::sleep(Duration::from_millis(200));
+        assert!(detects_shield(&mut detector));
+
+        drop(watcher);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
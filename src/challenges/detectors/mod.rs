@@ -5,13 +5,62 @@
 
 use once_cell::sync::Lazy;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, VecDeque};
-use std::time::SystemTime;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+use url::Url;
 
-use crate::challenges::core::{ChallengeResponse, is_cloudflare_response};
+use crate::challenges::core::{
+    ChallengeResponse, DEFAULT_SUCCESS_HALF_LIFE, DecayingRate, is_challenge_response,
+};
+use crate::challenges::solvers::origin_error::{OriginErrorHandler, origin_error_name};
+use crate::modules::storage::{StorageBackend, StorageError, load_json, store_json};
+
+#[cfg(feature = "hot-reload")]
+mod hot_reload;
+#[cfg(feature = "hot-reload")]
+pub use hot_reload::PatternWatcher;
+
+/// [`StorageBackend`] key of the [`ChallengeDetector`] snapshot.
+pub const DETECTOR_STORAGE_KEY: &str = "detector";
+/// Base confidence of patterns learned at runtime.
+const ADAPTIVE_BASE_CONFIDENCE: f32 = 0.8;
+
+/// Compiled program size allowed per detection regex.
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+/// Lazy DFA cache allowed per detection regex.
+const REGEX_DFA_SIZE_LIMIT: usize = 2 << 20;
+/// Only this many leading body bytes are matched; challenge markers sit near
+/// the top of the page.
+pub const MAX_DETECTION_BODY_BYTES: usize = 256 * 1024;
+/// Runner-up challenge types scoring within this much of the winner are
+/// reported as competing.
+pub const COMPETING_TYPE_MARGIN: f32 = 0.15;
+
+/// Raised when a detection pattern cannot be registered.
+#[derive(Debug, Error)]
+pub enum PatternError {
+    #[error("invalid challenge detection regex `{pattern}`: {source}")]
+    InvalidRegex {
+        pattern: String,
+        #[source]
+        source: regex::Error,
+    },
+    #[error("cannot read pattern file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("malformed pattern file: {0}")]
+    Format(#[from] serde_json::Error),
+    #[cfg(feature = "hot-reload")]
+    #[error("cannot watch pattern file: {0}")]
+    Watch(#[from] notify::Error),
+}
 
 /// High level challenge categories supported by the detector.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ChallengeType {
     JavaScriptV1,
     JavaScriptV2,
@@ -20,11 +69,27 @@ pub enum ChallengeType {
     RateLimit,
     AccessDenied,
     BotManagement,
+    /// Cloudflare 520-526: the origin failed, no challenge was served.
+    OriginError,
     Unknown,
 }
 
+impl ChallengeType {
+    /// A challenge page a visitor solves, as opposed to a block, a rate
+    /// limit or an origin failure.
+    pub fn is_solvable_page(self) -> bool {
+        matches!(
+            self,
+            ChallengeType::JavaScriptV1
+                | ChallengeType::JavaScriptV2
+                | ChallengeType::ManagedV3
+                | ChallengeType::Turnstile
+        )
+    }
+}
+
 /// Recommended response strategy for a detected challenge.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ResponseStrategy {
     JsExecution,
     AdvancedJsExecution,
@@ -55,6 +120,7 @@ struct ChallengePattern {
 }
 
 impl ChallengePattern {
+    /// Built-in patterns only; panics on an invalid regex.
     fn new(
         id: impl Into<String>,
         name: impl Into<String>,
@@ -63,12 +129,31 @@ impl ChallengePattern {
         base_confidence: f32,
         raw_patterns: &[&str],
     ) -> Self {
+        Self::try_new(
+            id,
+            name,
+            challenge_type,
+            response_strategy,
+            base_confidence,
+            raw_patterns,
+        )
+        .unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    fn try_new(
+        id: impl Into<String>,
+        name: impl Into<String>,
+        challenge_type: ChallengeType,
+        response_strategy: ResponseStrategy,
+        base_confidence: f32,
+        raw_patterns: &[&str],
+    ) -> Result<Self, PatternError> {
         let patterns = raw_patterns
             .iter()
             .map(|pattern| build_regex(pattern))
-            .collect();
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             id: id.into(),
             name: name.into(),
             challenge_type,
@@ -76,7 +161,7 @@ impl ChallengePattern {
             base_confidence,
             patterns,
             adaptive: false,
-        }
+        })
     }
 
     fn into_adaptive(mut self) -> Self {
@@ -178,6 +263,21 @@ static KNOWN_PATTERNS: Lazy<Vec<ChallengePattern>> = Lazy::new(|| {
     ]
 });
 
+/// One entry of a pattern file read by [`ChallengeDetector::reload_patterns`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PatternDefinition {
+    /// Reusing a built-in id replaces that built-in pattern.
+    pub id: String,
+    pub name: String,
+    pub challenge_type: ChallengeType,
+    pub response_strategy: ResponseStrategy,
+    pub base_confidence: f32,
+    pub patterns: Vec<String>,
+}
+
+/// Global patterns shared by a detector and its clones.
+type SharedPatterns = Arc<RwLock<Arc<Vec<ChallengePattern>>>>;
+
 /// Detection output returned to the pipeline.
 #[derive(Debug, Clone)]
 pub struct ChallengeDetection {
@@ -190,44 +290,93 @@ pub struct ChallengeDetection {
     pub status_code: u16,
     pub url: String,
     pub matched_indicators: Vec<String>,
+    /// Other challenge types that scored within [`COMPETING_TYPE_MARGIN`] of
+    /// the winner, best first. Non-empty means the page is ambiguous.
+    pub competing_types: Vec<(ChallengeType, f32)>,
+}
+
+/// Snapshot of a learned domain pattern, for auditing.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AdaptivePatternInfo {
+    pub id: String,
+    pub name: String,
+    pub challenge_type: ChallengeType,
+    pub response_strategy: ResponseStrategy,
+    /// Source of each regex, as registered.
+    pub patterns: Vec<String>,
+}
+
+/// Matches of one challenge type, combined across patterns.
+struct TypeMatches<'a> {
+    best: &'a ChallengePattern,
+    best_confidence: f32,
+    confidence: f32,
+    indicators: Vec<String>,
+}
+
+impl<'a> TypeMatches<'a> {
+    /// Treat each pattern as independent evidence (noisy-OR), so agreeing
+    /// patterns raise the confidence without exceeding 1.0.
+    fn add(&mut self, pattern: &'a ChallengePattern, confidence: f32, matched: Vec<String>) {
+        self.confidence += (1.0 - self.confidence) * confidence;
+        if confidence > self.best_confidence {
+            self.best = pattern;
+            self.best_confidence = confidence;
+        }
+        for indicator in matched {
+            if !self.indicators.contains(&indicator) {
+                self.indicators.push(indicator);
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 struct PatternStats {
-    attempts: u32,
-    successes: u32,
+    outcomes: DecayingRate,
 }
 
 impl PatternStats {
     fn record(&mut self, success: bool) {
-        self.attempts = self.attempts.saturating_add(1);
-        if success {
-            self.successes = self.successes.saturating_add(1);
-        }
+        self.outcomes.record(success);
     }
 
     fn success_rate(&self) -> f32 {
-        if self.attempts == 0 {
-            0.0
-        } else {
-            self.successes as f32 / self.attempts as f32
-        }
+        self.outcomes.rate().unwrap_or(0.0) as f32
     }
 }
 
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct DetectorSnapshot {
+    adaptive_patterns: HashMap<String, Vec<AdaptivePatternInfo>>,
+    stats: HashMap<String, DecayingRate>,
+}
+
 #[derive(Debug, Clone)]
 struct DetectionRecord {
     timestamp: SystemTime,
     pattern_id: String,
+    challenge_type: ChallengeType,
     confidence: f32,
     url: String,
 }
 
+impl DetectionRecord {
+    fn matches_domain(&self, domain: &str) -> bool {
+        Url::parse(&self.url)
+            .ok()
+            .and_then(|url| url.host_str().map(|host| host.eq_ignore_ascii_case(domain)))
+            .unwrap_or(false)
+    }
+}
+
 /// Public view of a recorded challenge detection.
 #[derive(Debug, Clone)]
 pub struct DetectionLogEntry {
     pub timestamp: SystemTime,
     pub pattern_id: String,
+    pub challenge_type: ChallengeType,
     pub confidence: f32,
     pub url: String,
 }
@@ -237,6 +386,7 @@ impl From<&DetectionRecord> for DetectionLogEntry {
         Self {
             timestamp: record.timestamp,
             pattern_id: record.pattern_id.clone(),
+            challenge_type: record.challenge_type,
             confidence: record.confidence,
             url: record.url.clone(),
         }
@@ -244,13 +394,16 @@ impl From<&DetectionRecord> for DetectionLogEntry {
 }
 
 /// Pattern-based challenge detector with adaptive learning support.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ChallengeDetector {
-    known_patterns: Vec<ChallengePattern>,
+    /// Swapped whole by [`reload_patterns`](Self::reload_patterns); clones
+    /// of the detector see the same list.
+    known_patterns: SharedPatterns,
     adaptive_patterns: HashMap<String, Vec<ChallengePattern>>, // domain -> patterns
     stats: HashMap<String, PatternStats>,
     history: VecDeque<DetectionRecord>,
     max_history: usize,
+    success_half_life: Duration,
 }
 
 impl Default for ChallengeDetector {
@@ -262,79 +415,82 @@ impl Default for ChallengeDetector {
 impl ChallengeDetector {
     pub fn new() -> Self {
         Self {
-            known_patterns: KNOWN_PATTERNS.clone(),
+            known_patterns: Arc::new(RwLock::new(Arc::new(KNOWN_PATTERNS.clone()))),
             adaptive_patterns: HashMap::new(),
             stats: HashMap::new(),
             history: VecDeque::with_capacity(128),
             max_history: 1000,
+            success_half_life: DEFAULT_SUCCESS_HALF_LIFE,
         }
     }
 
+    /// Age at which a recorded outcome counts half as much towards pattern confidence.
+    pub fn with_success_half_life(mut self, half_life: Duration) -> Self {
+        self.success_half_life = half_life;
+        self
+    }
+
     /// Detect a challenge in the provided HTTP response context.
     pub fn detect(&mut self, response: &ChallengeResponse<'_>) -> Option<ChallengeDetection> {
+        let detection = self.classify(response)?;
+        self.record_detection(detection.clone());
+        Some(detection)
+    }
+
+    /// [`detect`](Self::detect) without adding to the detection history.
+    pub fn classify(&self, response: &ChallengeResponse<'_>) -> Option<ChallengeDetection> {
+        if OriginErrorHandler::is_origin_error(response) {
+            return Some(origin_error_detection(response));
+        }
         if !self.is_cloudflare_challenge(response) {
             return None;
         }
 
-        let mut best: Option<(ChallengeDetection, f32)> = None;
-
-        for pattern in &self.known_patterns {
-            if let Some((confidence, matched)) = self.evaluate_pattern(pattern, response)
-                && best
-                    .as_ref()
-                    .is_none_or(|(_, current)| confidence > *current)
+        let known = Arc::clone(&self.known_patterns.read().expect("pattern lock poisoned"));
+        let adaptive = response_domain(response)
+            .and_then(|domain| self.adaptive_patterns.get(&domain))
+            .into_iter()
+            .flatten();
+        let mut by_type: Vec<TypeMatches<'_>> = Vec::new();
+        for pattern in known.iter().chain(adaptive) {
+            let Some((confidence, matched)) = self.evaluate_pattern(pattern, response) else {
+                continue;
+            };
+            match by_type
+                .iter_mut()
+                .find(|group| group.best.challenge_type == pattern.challenge_type)
             {
-                best = Some((
-                    ChallengeDetection {
-                        pattern_id: pattern.id.clone(),
-                        pattern_name: pattern.name.clone(),
-                        challenge_type: pattern.challenge_type,
-                        response_strategy: pattern.response_strategy,
-                        confidence,
-                        is_adaptive: pattern.adaptive,
-                        status_code: response.status,
-                        url: response.url.as_str().to_string(),
-                        matched_indicators: matched,
-                    },
+                Some(group) => group.add(pattern, confidence, matched),
+                None => by_type.push(TypeMatches {
+                    best: pattern,
+                    best_confidence: confidence,
                     confidence,
-                ));
+                    indicators: matched,
+                }),
             }
         }
 
-        if let Some(domain) = response_domain(response)
-            && let Some(patterns) = self.adaptive_patterns.get(&domain)
-        {
-            for pattern in patterns {
-                if let Some((confidence, matched)) = self.evaluate_pattern(pattern, response)
-                    && best
-                        .as_ref()
-                        .is_none_or(|(_, current)| confidence > *current)
-                {
-                    best = Some((
-                        ChallengeDetection {
-                            pattern_id: pattern.id.clone(),
-                            pattern_name: pattern.name.clone(),
-                            challenge_type: pattern.challenge_type,
-                            response_strategy: pattern.response_strategy,
-                            confidence,
-                            is_adaptive: true,
-                            status_code: response.status,
-                            url: response.url.as_str().to_string(),
-                            matched_indicators: matched,
-                        },
-                        confidence,
-                    ));
-                }
+        // Stable sort: on a tie the earlier (built-in) type wins.
+        by_type.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+        by_type.first().map(|winner| {
+            let competing_types = by_type[1..]
+                .iter()
+                .filter(|other| winner.confidence - other.confidence <= COMPETING_TYPE_MARGIN)
+                .map(|other| (other.best.challenge_type, other.confidence))
+                .collect();
+            ChallengeDetection {
+                pattern_id: winner.best.id.clone(),
+                pattern_name: winner.best.name.clone(),
+                challenge_type: winner.best.challenge_type,
+                response_strategy: winner.best.response_strategy,
+                confidence: winner.confidence,
+                is_adaptive: winner.best.adaptive,
+                status_code: response.status,
+                url: response.url.as_str().to_string(),
+                matched_indicators: winner.indicators.clone(),
+                competing_types,
             }
-        }
-
-        let result = best.map(|(detection, _)| detection);
-
-        if let Some(ref detection) = result {
-            self.record_detection(detection.clone());
-        }
-
-        result
+        })
     }
 
     fn evaluate_pattern(
@@ -342,10 +498,11 @@ impl ChallengeDetector {
         pattern: &ChallengePattern,
         response: &ChallengeResponse<'_>,
     ) -> Option<(f32, Vec<String>)> {
+        let body = detection_prefix(response.body);
         let matches: Vec<_> = pattern
             .patterns
             .iter()
-            .filter(|regex| regex.is_match(response.body))
+            .filter(|regex| regex.is_match(body))
             .map(|regex| regex.as_str().to_string())
             .collect();
 
@@ -370,7 +527,7 @@ impl ChallengeDetector {
     }
 
     fn is_cloudflare_challenge(&self, response: &ChallengeResponse<'_>) -> bool {
-        is_cloudflare_response(response) && matches!(response.status, 403 | 429 | 503)
+        is_challenge_response(response)
     }
 
     fn record_detection(&mut self, detection: ChallengeDetection) {
@@ -380,6 +537,7 @@ impl ChallengeDetector {
         self.history.push_back(DetectionRecord {
             timestamp: SystemTime::now(),
             pattern_id: detection.pattern_id,
+            challenge_type: detection.challenge_type,
             confidence: detection.confidence,
             url: detection.url,
         });
@@ -390,19 +548,52 @@ impl ChallengeDetector {
         self.history.iter().map(DetectionLogEntry::from)
     }
 
+    /// Iterate over historical detections for a single host (oldest -> newest).
+    pub fn detection_history_for<'a>(
+        &'a self,
+        domain: &'a str,
+    ) -> impl Iterator<Item = DetectionLogEntry> + 'a {
+        self.history
+            .iter()
+            .filter(move |record| record.matches_domain(domain))
+            .map(DetectionLogEntry::from)
+    }
+
+    /// Iterate over detections recorded at or after `since` (oldest -> newest).
+    pub fn detection_history_since(
+        &self,
+        since: SystemTime,
+    ) -> impl Iterator<Item = DetectionLogEntry> + '_ {
+        self.history
+            .iter()
+            .filter(move |record| record.timestamp >= since)
+            .map(DetectionLogEntry::from)
+    }
+
+    /// Number of retained detections per challenge type.
+    pub fn detection_count_by_type(&self) -> HashMap<ChallengeType, usize> {
+        let mut counts = HashMap::new();
+        for record in &self.history {
+            *counts.entry(record.challenge_type).or_insert(0) += 1;
+        }
+        counts
+    }
+
     /// Update success metrics for a pattern to influence future confidence scores.
     pub fn learn_from_outcome(&mut self, pattern_id: &str, success: bool) {
         let entry = self
             .stats
             .entry(pattern_id.to_string())
             .or_insert(PatternStats {
-                attempts: 0,
-                successes: 0,
+                outcomes: DecayingRate::new(self.success_half_life),
             });
         entry.record(success);
     }
 
     /// Register an adaptive, domain-specific pattern discovered at runtime.
+    ///
+    /// Fails without registering anything if a regex is invalid or compiles
+    /// past the detector's size limits.
     pub fn add_adaptive_pattern(
         &mut self,
         domain: &str,
@@ -410,31 +601,183 @@ impl ChallengeDetector {
         raw_patterns: Vec<&str>,
         challenge_type: ChallengeType,
         response_strategy: ResponseStrategy,
-    ) {
-        let pattern = ChallengePattern::new(
-            format!("adaptive_{}_{}", domain, raw_patterns.len()),
+    ) -> Result<(), PatternError> {
+        let domain = domain.to_lowercase();
+        let index = self.adaptive_patterns.get(&domain).map_or(0, Vec::len) + 1;
+        let pattern = ChallengePattern::try_new(
+            format!("adaptive_{domain}_{index}"),
             pattern_name,
             challenge_type,
             response_strategy,
-            0.8,
+            ADAPTIVE_BASE_CONFIDENCE,
             &raw_patterns,
-        )
+        )?
         .into_adaptive();
 
         self.adaptive_patterns
-            .entry(domain.to_lowercase())
+            .entry(domain)
             .or_default()
             .push(pattern);
+        Ok(())
+    }
+
+    /// Adaptive patterns learned for `domain`, in registration order.
+    pub fn adaptive_patterns_for(&self, domain: &str) -> Vec<AdaptivePatternInfo> {
+        self.adaptive_patterns
+            .get(&domain.to_lowercase())
+            .into_iter()
+            .flatten()
+            .map(|pattern| AdaptivePatternInfo {
+                id: pattern.id.clone(),
+                name: pattern.name.clone(),
+                challenge_type: pattern.challenge_type,
+                response_strategy: pattern.response_strategy,
+                patterns: pattern
+                    .patterns
+                    .iter()
+                    .map(|regex| regex.as_str().to_string())
+                    .collect(),
+            })
+            .collect()
+    }
+
+    /// Save adaptive patterns and pattern outcome stats under
+    /// [`DETECTOR_STORAGE_KEY`].
+    pub fn save_to(&self, storage: &dyn StorageBackend) -> Result<(), StorageError> {
+        let snapshot = DetectorSnapshot {
+            adaptive_patterns: self
+                .adaptive_patterns
+                .keys()
+                .map(|domain| (domain.clone(), self.adaptive_patterns_for(domain)))
+                .collect(),
+            stats: self
+                .stats
+                .iter()
+                .map(|(id, stats)| (id.clone(), stats.outcomes))
+                .collect(),
+        };
+        store_json(storage, DETECTOR_STORAGE_KEY, &snapshot)
+    }
+
+    /// Restore a snapshot written by [`save_to`](Self::save_to), replacing
+    /// the adaptive patterns of every domain it covers. Returns whether one
+    /// was found.
+    pub fn load_from(&mut self, storage: &dyn StorageBackend) -> Result<bool, StorageError> {
+        let Some(snapshot) = load_json::<DetectorSnapshot>(storage, DETECTOR_STORAGE_KEY)? else {
+            return Ok(false);
+        };
+        for (domain, infos) in snapshot.adaptive_patterns {
+            let patterns = infos
+                .into_iter()
+                .map(|info| {
+                    let raw: Vec<&str> = info.patterns.iter().map(String::as_str).collect();
+                    ChallengePattern::try_new(
+                        info.id,
+                        info.name,
+                        info.challenge_type,
+                        info.response_strategy,
+                        ADAPTIVE_BASE_CONFIDENCE,
+                        &raw,
+                    )
+                    .map(ChallengePattern::into_adaptive)
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|err| StorageError::Invalid(err.to_string()))?;
+            self.adaptive_patterns
+                .insert(domain.to_lowercase(), patterns);
+        }
+        for (id, outcomes) in snapshot.stats {
+            self.stats.insert(id, PatternStats { outcomes });
+        }
+        Ok(true)
+    }
+
+    /// Replace the global patterns with the built-ins plus the
+    /// [`PatternDefinition`] list in the JSON file at `path`, returning how
+    /// many it defined.
+    ///
+    /// Every regex is compiled before the swap, so a bad file leaves the
+    /// current patterns in place. Clones of this detector, such as the one
+    /// inside a built scraper, pick the new patterns up on their next
+    /// detection.
+    pub fn reload_patterns(&self, path: &Path) -> Result<usize, PatternError> {
+        let definitions: Vec<PatternDefinition> = serde_json::from_slice(&std::fs::read(path)?)?;
+        let mut patterns = KNOWN_PATTERNS.clone();
+        for definition in &definitions {
+            let raw: Vec<&str> = definition.patterns.iter().map(String::as_str).collect();
+            let pattern = ChallengePattern::try_new(
+                definition.id.clone(),
+                definition.name.clone(),
+                definition.challenge_type,
+                definition.response_strategy,
+                definition.base_confidence.clamp(0.0, 1.0),
+                &raw,
+            )?;
+            match patterns.iter_mut().find(|known| known.id == pattern.id) {
+                Some(known) => *known = pattern,
+                None => patterns.push(pattern),
+            }
+        }
+        *self.known_patterns.write().expect("pattern lock poisoned") = Arc::new(patterns);
+        Ok(definitions.len())
+    }
+
+    /// Forget every adaptive pattern learned for `domain`, along with their
+    /// outcome stats. Returns how many were removed.
+    pub fn clear_adaptive_patterns(&mut self, domain: &str) -> usize {
+        let Some(patterns) = self.adaptive_patterns.remove(&domain.to_lowercase()) else {
+            return 0;
+        };
+        for pattern in &patterns {
+            self.stats.remove(&pattern.id);
+        }
+        patterns.len()
     }
 }
 
-fn build_regex(pattern: &str) -> Regex {
+/// Origin errors are recognised by status alone; their pages vary.
+fn origin_error_detection(response: &ChallengeResponse<'_>) -> ChallengeDetection {
+    ChallengeDetection {
+        pattern_id: "cf_origin_error".into(),
+        pattern_name: "Cloudflare Origin Error".into(),
+        challenge_type: ChallengeType::OriginError,
+        response_strategy: ResponseStrategy::DelayRetry,
+        confidence: 1.0,
+        is_adaptive: false,
+        status_code: response.status,
+        url: response.url.as_str().to_string(),
+        matched_indicators: origin_error_name(response.status)
+            .map(|name| format!("{} {name}", response.status))
+            .into_iter()
+            .collect(),
+        competing_types: Vec::new(),
+    }
+}
+
+fn build_regex(pattern: &str) -> Result<Regex, PatternError> {
     regex::RegexBuilder::new(pattern)
         .case_insensitive(true)
         .multi_line(true)
         .dot_matches_new_line(true)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .dfa_size_limit(REGEX_DFA_SIZE_LIMIT)
         .build()
-        .unwrap_or_else(|err| panic!("invalid challenge detection regex `{}`: {}", pattern, err))
+        .map_err(|source| PatternError::InvalidRegex {
+            pattern: pattern.to_string(),
+            source,
+        })
+}
+
+/// Leading `MAX_DETECTION_BODY_BYTES` of `body`, cut on a char boundary.
+fn detection_prefix(body: &str) -> &str {
+    if body.len() <= MAX_DETECTION_BODY_BYTES {
+        return body;
+    }
+    let mut end = MAX_DETECTION_BODY_BYTES;
+    while !body.is_char_boundary(end) {
+        end -= 1;
+    }
+    &body[..end]
 }
 
 #[cfg(test)]
@@ -442,7 +785,7 @@ mod tests {
     use super::*;
     use http::header::SERVER;
     use http::{HeaderMap, Method};
-    use url::Url;
+    use std::time::Duration;
 
     struct ResponseFixture {
         url: Url,
@@ -454,10 +797,14 @@ mod tests {
 
     impl ResponseFixture {
         fn new(body: &str, status: u16) -> Self {
+            Self::for_url("https://example.com/", body, status)
+        }
+
+        fn for_url(url: &str, body: &str, status: u16) -> Self {
             let mut headers = HeaderMap::new();
             headers.insert(SERVER, "cloudflare".parse().unwrap());
             Self {
-                url: Url::parse("https://example.com/").unwrap(),
+                url: Url::parse(url).unwrap(),
                 headers,
                 method: Method::GET,
                 body: body.to_string(),
@@ -498,4 +845,192 @@ mod tests {
             ResponseStrategy::CaptchaSolving
         );
     }
+
+    #[test]
+    fn detects_managed_challenge_served_with_200() {
+        let html = r#"
+            <html><head><title>Just a moment...</title></head>
+            <body>
+              <div class="main-wrapper" data-ray="8a1b2c3d4e5f6a7b"></div>
+              <script>window._cf_chl_ctx = {"cvId":"3"};</script>
+              <script>(function(){var cpo=document.createElement('script');
+                cpo.src='/cdn-cgi/challenge-platform/h/g/orchestrate/managed/v1?ray=8a1b';
+                document.head.appendChild(cpo);}());</script>
+            </body></html>
+        "#;
+        let mut detector = ChallengeDetector::new();
+        let fixture = ResponseFixture::new(html, 200);
+        let detection = detector.detect(&fixture.response()).expect("should detect");
+        assert_eq!(detection.challenge_type, ChallengeType::ManagedV3);
+
+        let article = r#"
+            <html><body>
+              <p>Cloudflare pages set <code>window._cf_chl_opt = {...}</code> before loading
+              /cdn-cgi/challenge-platform/h/g/orchestrate/managed/v1 and data-ray="8a1b".</p>
+            </body></html>
+        "#;
+        let fixture = ResponseFixture::new(article, 200);
+        assert!(detector.detect(&fixture.response()).is_none());
+    }
+
+    #[test]
+    fn classify_leaves_history_untouched() {
+        let html = r#"<div class="cf-turnstile" data-sitekey="0123456789ABCDEFGHIJ0123456789ABCDEFGHIJ"></div>
+            <script src="https://challenges.cloudflare.com/turnstile/v0/api.js"></script>"#;
+        let mut detector = ChallengeDetector::new();
+        let fixture = ResponseFixture::new(html, 403);
+
+        let classified = detector
+            .classify(&fixture.response())
+            .expect("should classify");
+        assert_eq!(classified.challenge_type, ChallengeType::Turnstile);
+        assert_eq!(detector.detection_history().count(), 0);
+
+        detector.detect(&fixture.response()).expect("should detect");
+        assert_eq!(detector.detection_history().count(), 1);
+    }
+
+    #[test]
+    fn filters_history_by_domain_time_and_type() {
+        let turnstile = r#"<div class="cf-turnstile" data-sitekey="0123456789ABCDEFGHIJ0123456789ABCDEFGHIJ"></div>
+            <script src="https://challenges.cloudflare.com/turnstile/v0/api.js"></script>"#;
+        let rate_limited = "<title>Rate Limited</title> You are being rate limited";
+
+        let mut detector = ChallengeDetector::new();
+        let before = SystemTime::now() - Duration::from_secs(1);
+        for (url, body, status) in [
+            ("https://example.com/a", turnstile, 403),
+            ("https://example.com/b", rate_limited, 429),
+            ("https://other.org/", turnstile, 403),
+        ] {
+            let fixture = ResponseFixture::for_url(url, body, status);
+            detector.detect(&fixture.response()).expect("should detect");
+        }
+
+        let example: Vec<_> = detector.detection_history_for("example.com").collect();
+        assert_eq!(example.len(), 2);
+        assert_eq!(example[0].challenge_type, ChallengeType::Turnstile);
+
+        assert_eq!(detector.detection_history_since(before).count(), 3);
+        let future = SystemTime::now() + Duration::from_secs(3600);
+        assert_eq!(detector.detection_history_since(future).count(), 0);
+
+        let counts = detector.detection_count_by_type();
+        assert_eq!(counts.get(&ChallengeType::Turnstile), Some(&2));
+        assert_eq!(counts.get(&ChallengeType::RateLimit), Some(&1));
+    }
+
+    #[test]
+    fn rejects_oversized_patterns_and_bounds_matching() {
+        let mut detector = ChallengeDetector::new();
+        for raw in [r"(\w{100}){100}", "(unclosed"] {
+            let result = detector.add_adaptive_pattern(
+                "example.com",
+                "Rejected",
+                vec![raw],
+                ChallengeType::Unknown,
+                ResponseStrategy::DelayRetry,
+            );
+            assert!(matches!(result, Err(PatternError::InvalidRegex { .. })));
+        }
+
+        detector
+            .add_adaptive_pattern(
+                "example.com",
+                "Nested repetition",
+                vec![r"(a+)+$"],
+                ChallengeType::Unknown,
+                ResponseStrategy::DelayRetry,
+            )
+            .unwrap();
+        let body = format!("{}b", "a".repeat(4 * MAX_DETECTION_BODY_BYTES));
+        let fixture = ResponseFixture::new(&body, 503);
+        let started = std::time::Instant::now();
+        detector.detect(&fixture.response());
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn combines_agreeing_patterns_and_reports_competing_types() {
+        let html = r#"<div class="cf-turnstile" data-sitekey="0123456789ABCDEFGHIJ0123456789ABCDEFGHIJ"></div>
+            <script src="https://challenges.cloudflare.com/turnstile/v0/api.js"></script>"#;
+        let fixture = ResponseFixture::new(html, 403);
+
+        let single = ChallengeDetector::new()
+            .detect(&fixture.response())
+            .expect("should detect");
+        assert!(single.competing_types.is_empty());
+
+        let mut detector = ChallengeDetector::new();
+        detector
+            .add_adaptive_pattern(
+                "example.com",
+                "Turnstile loader",
+                vec![r"turnstile/v0/api\.js", r"data-sitekey="],
+                ChallengeType::Turnstile,
+                ResponseStrategy::CaptchaSolving,
+            )
+            .unwrap();
+        let combined = detector.detect(&fixture.response()).expect("should detect");
+        assert_eq!(combined.challenge_type, ChallengeType::Turnstile);
+        assert!(combined.confidence > single.confidence);
+        assert!(combined.confidence <= 1.0);
+        assert_eq!(
+            combined.matched_indicators.len(),
+            single.matched_indicators.len() + 2
+        );
+
+        let mut detector = ChallengeDetector::new();
+        detector
+            .add_adaptive_pattern(
+                "example.com",
+                "Managed lookalike",
+                vec![r"data-sitekey="],
+                ChallengeType::ManagedV3,
+                ResponseStrategy::BrowserSimulation,
+            )
+            .unwrap();
+        let ambiguous = detector.detect(&fixture.response()).expect("should detect");
+        assert_eq!(ambiguous.challenge_type, ChallengeType::ManagedV3);
+        assert_eq!(
+            ambiguous.competing_types,
+            vec![(ChallengeType::Turnstile, single.confidence)]
+        );
+    }
+
+    #[test]
+    fn lists_and_clears_adaptive_patterns() {
+        let mut detector = ChallengeDetector::new();
+        for (name, raw, challenge_type) in [
+            ("Login wall", r"please sign in", ChallengeType::Unknown),
+            (
+                "Turnstile loader",
+                r"turnstile/v0",
+                ChallengeType::Turnstile,
+            ),
+        ] {
+            detector
+                .add_adaptive_pattern(
+                    "Example.com",
+                    name,
+                    vec![raw],
+                    challenge_type,
+                    ResponseStrategy::DelayRetry,
+                )
+                .unwrap();
+        }
+        detector.learn_from_outcome("adaptive_example.com_2", true);
+
+        let learned = detector.adaptive_patterns_for("example.com");
+        assert_eq!(learned.len(), 2);
+        assert_ne!(learned[0].id, learned[1].id);
+        assert_eq!(learned[1].name, "Turnstile loader");
+        assert_eq!(learned[1].challenge_type, ChallengeType::Turnstile);
+        assert_eq!(learned[1].patterns, vec![r"turnstile/v0".to_string()]);
+
+        assert_eq!(detector.clear_adaptive_patterns("EXAMPLE.com"), 2);
+        assert!(detector.adaptive_patterns_for("example.com").is_empty());
+        assert!(!detector.stats.contains_key("adaptive_example.com_2"));
+        assert_eq!(detector.clear_adaptive_patterns("example.com"), 0);
+    }
 }
@@ -2,6 +2,7 @@
 
 pub mod core;
 pub mod detectors;
+pub mod parse;
 pub mod pipeline;
 pub mod solvers;
 pub mod user_agents;
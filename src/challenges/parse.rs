@@ -0,0 +1,173 @@
+//! Side-effect free parsing of challenge pages.
+//!
+//! [`parse_challenge`] runs detection and the solvers' extraction steps on a
+//! raw response without solving anything. It never panics on any input, which
+//! makes it the entry point for the `challenge_page` fuzz target.
+
+use std::collections::BTreeMap;
+
+use http::{HeaderMap, Method};
+use thiserror::Error;
+use url::Url;
+
+use crate::challenges::core::{ChallengeResponse, parse_iuam_challenge};
+use crate::challenges::detectors::{ChallengeDetector, ChallengeType};
+use crate::challenges::solvers::javascript_v2::JavascriptV2Solver;
+use crate::challenges::solvers::managed_v3::ManagedV3Solver;
+use crate::challenges::solvers::turnstile::TurnstileSolver;
+
+/// What [`parse_challenge`] found on a page.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedChallenge {
+    pub challenge_type: ChallengeType,
+    pub confidence: f32,
+    /// Values the matching solver would use, e.g. `form_action`, `site_key`
+    /// or hidden form fields. Empty for types that need no page data.
+    pub fields: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Error)]
+pub enum ParseError {
+    #[error("invalid page url: {0}")]
+    InvalidUrl(#[from] url::ParseError),
+    #[error("response is not a recognised Cloudflare challenge")]
+    NotChallenge,
+    #[error("{challenge_type:?} challenge is malformed: {reason}")]
+    Malformed {
+        challenge_type: ChallengeType,
+        reason: String,
+    },
+}
+
+/// Detect the challenge on a page and extract the fields its solver needs.
+pub fn parse_challenge(
+    body: &str,
+    url: &str,
+    status: u16,
+    headers: &HeaderMap,
+) -> Result<ParsedChallenge, ParseError> {
+    let url = Url::parse(url)?;
+    let response = ChallengeResponse {
+        url: &url,
+        status,
+        headers,
+        body,
+        request_method: &Method::GET,
+    };
+    let detection = ChallengeDetector::new()
+        .detect(&response)
+        .ok_or(ParseError::NotChallenge)?;
+    let challenge_type = detection.challenge_type;
+    let malformed = |reason: String| ParseError::Malformed {
+        challenge_type,
+        reason,
+    };
+
+    let fields: Vec<(String, String)> = match challenge_type {
+        ChallengeType::JavaScriptV1 => {
+            let blueprint =
+                parse_iuam_challenge(&response).map_err(|err| malformed(err.to_string()))?;
+            let mut fields = vec![
+                ("form_action".to_string(), blueprint.action),
+                ("form_method".to_string(), blueprint.method.to_string()),
+            ];
+            fields.extend(blueprint.hidden_fields);
+            fields
+        }
+        ChallengeType::JavaScriptV2 => named(
+            JavascriptV2Solver::extract_fields(body).map_err(|err| malformed(err.to_string()))?,
+        ),
+        ChallengeType::ManagedV3 => {
+            named(ManagedV3Solver::extract_fields(body).map_err(|err| malformed(err.to_string()))?)
+        }
+        ChallengeType::Turnstile => named(
+            TurnstileSolver::extract_fields(&response).map_err(|err| malformed(err.to_string()))?,
+        ),
+        ChallengeType::RateLimit
+        | ChallengeType::AccessDenied
+        | ChallengeType::BotManagement
+        | ChallengeType::OriginError
+        | ChallengeType::Unknown => Vec::new(),
+    };
+
+    Ok(ParsedChallenge {
+        challenge_type,
+        confidence: detection.confidence,
+        fields: fields.into_iter().collect(),
+    })
+}
+
+fn named(fields: Vec<(&'static str, String)>) -> Vec<(String, String)> {
+    fields
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::header::SERVER;
+
+    fn cloudflare() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(SERVER, "cloudflare".parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn extracts_turnstile_fields() {
+        let body = r#"<div class="cf-turnstile" data-sitekey="0123456789ABCDEFGHIJ0123456789ABCDEFGHIJ"></div>
+            <script src="https://challenges.cloudflare.com/turnstile/v0/api.js"></script>"#;
+        let parsed =
+            parse_challenge(body, "https://example.com/login", 403, &cloudflare()).unwrap();
+        assert_eq!(parsed.challenge_type, ChallengeType::Turnstile);
+        assert_eq!(
+            parsed.fields["site_key"],
+            "0123456789ABCDEFGHIJ0123456789ABCDEFGHIJ"
+        );
+        assert_eq!(parsed.fields["form_action"], "https://example.com/login");
+
+        assert!(matches!(
+            parse_challenge(body, "not a url", 403, &cloudflare()),
+            Err(ParseError::InvalidUrl(_))
+        ));
+        assert!(matches!(
+            parse_challenge("<p>hello</p>", "https://example.com/", 200, &cloudflare()),
+            Err(ParseError::NotChallenge)
+        ));
+    }
+
+    #[test]
+    fn malformed_pages_do_not_panic() {
+        let form = r#"<div class="cf-browser-verification"></div> data-ray="ab"
+            <form id="challenge-form" action="/?__cf_chl_rt_tk=abc">"#;
+        let regressions = [
+            // `>` of the VM script tag only appeared after the closing tag,
+            // so the script slice started past its end.
+            format!("{form} window._cf_chl_ctx = {{}}; <script window._cf_chl_enter </script>>"),
+            // Unterminated context object used to be scanned to end of input.
+            format!(
+                "{form} window._cf_chl_ctx = {{\"cvId\": \"{}",
+                "x".repeat(512 * 1024)
+            ),
+            // Multi-byte text right after the marker.
+            format!("{form} window._cf_chl_ctx = é{{\"é\": \"é\"}}"),
+        ];
+        for body in &regressions {
+            let _ = parse_challenge(body, "https://example.com/", 403, &cloudflare());
+        }
+
+        let parsed =
+            parse_challenge(&regressions[0], "https://example.com/", 403, &cloudflare()).unwrap();
+        assert_eq!(parsed.challenge_type, ChallengeType::ManagedV3);
+        assert!(!parsed.fields.contains_key("vm_script"));
+        assert!(matches!(
+            parse_challenge(&regressions[1], "https://example.com/", 403, &cloudflare()),
+            Err(ParseError::Malformed {
+                challenge_type: ChallengeType::ManagedV3,
+                ..
+            })
+        ));
+    }
+}
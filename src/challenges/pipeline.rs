@@ -7,11 +7,16 @@
 //! unsupported).
 
 use std::fmt;
+use std::sync::Arc;
 
+use http::{HeaderMap, Method};
 use thiserror::Error;
+use url::Url;
 
 use crate::challenges::core::{ChallengeResponse, ChallengeSubmission};
-use crate::challenges::detectors::{ChallengeDetection, ChallengeDetector, ChallengeType};
+use crate::challenges::detectors::{
+    ChallengeDetection, ChallengeDetector, ChallengeType, ResponseStrategy,
+};
 use crate::challenges::solvers::{
     FailureRecorder, FingerprintManager, MitigationPlan, TlsProfileManager,
     access_denied::{AccessDeniedError, AccessDeniedHandler, ProxyPool},
@@ -19,9 +24,12 @@ use crate::challenges::solvers::{
     javascript_v1::{JavascriptV1Error, JavascriptV1Solver},
     javascript_v2::{JavascriptV2Error, JavascriptV2Solver},
     managed_v3::{ManagedV3Error, ManagedV3Solver},
+    origin_error::{OriginErrorError, OriginErrorHandler},
     rate_limit::{RateLimitError, RateLimitHandler},
     turnstile::{TurnstileError, TurnstileSolver},
 };
+use crate::external_deps::captcha::CaptchaProvider;
+use crate::external_deps::interpreters::JavascriptInterpreter;
 
 /// Operational context passed to the pipeline when mitigation handlers need to
 /// mutate shared services (proxy pool, TLS manager, fingerprint generator…).
@@ -32,6 +40,9 @@ pub struct PipelineContext<'a> {
     pub failure_recorder: Option<&'a dyn FailureRecorder>,
     pub fingerprint_manager: Option<&'a mut dyn FingerprintManager>,
     pub tls_manager: Option<&'a mut dyn TlsProfileManager>,
+    /// User agent the challenged request was sent with; JavaScript solvers
+    /// report it as `navigator.userAgent`.
+    pub user_agent: Option<&'a str>,
 }
 
 /// High level result returned by the pipeline after analysing a response.
@@ -61,12 +72,76 @@ pub enum ChallengePipelineResult {
     },
 }
 
+impl ChallengePipelineResult {
+    /// The detection behind this result, if a challenge was detected.
+    pub fn detection(&self) -> Option<&ChallengeDetection> {
+        match self {
+            Self::NoChallenge => None,
+            Self::Submission { detection, .. }
+            | Self::Mitigation { detection, .. }
+            | Self::Unsupported { detection, .. }
+            | Self::Failed { detection, .. } => Some(detection),
+        }
+    }
+}
+
+/// Outcome of [`ChallengePipeline::prepare`]: everything up to, but not
+/// including, sending the answer.
+#[derive(Debug)]
+pub enum PipelinePrepareResult {
+    /// The response does not look like a Cloudflare challenge.
+    NoChallenge,
+    /// Send `submission` with your own client, then report how it went with
+    /// [`ChallengePipeline::record_outcome`] and `detection.pattern_id`.
+    Prepared {
+        detection: ChallengeDetection,
+        submission: ChallengeSubmission,
+    },
+    /// Nothing to submit; follow the retry/back-off plan instead.
+    Mitigation {
+        detection: ChallengeDetection,
+        plan: MitigationPlan,
+    },
+    /// The challenge is unsupported or its solver failed.
+    Failed {
+        detection: ChallengeDetection,
+        error: PipelineError,
+    },
+}
+
 /// Reason why the pipeline could not act on a detected challenge.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum UnsupportedReason {
     MissingSolver(&'static str),
     MissingDependency(&'static str),
     UnknownChallenge,
+    /// Detection confidence is below the pipeline's `min_solve_confidence`
+    /// and the solver would spend a captcha solve.
+    LowConfidence,
+}
+
+/// Challenge types a pipeline can act on, and why it cannot act on the rest.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    pub supported: Vec<ChallengeType>,
+    pub missing: Vec<(ChallengeType, UnsupportedReason)>,
+    /// Names of the custom solvers tried on [`ChallengeType::Unknown`] pages,
+    /// in the order they are tried.
+    pub custom_solvers: Vec<&'static str>,
+}
+
+impl Capabilities {
+    pub fn can_solve(&self, challenge_type: ChallengeType) -> bool {
+        self.supported.contains(&challenge_type)
+    }
+
+    /// What the configuration lacks to handle `challenge_type`, if anything.
+    pub fn missing_reason(&self, challenge_type: ChallengeType) -> Option<UnsupportedReason> {
+        self.missing
+            .iter()
+            .find(|(missing, _)| *missing == challenge_type)
+            .map(|(_, reason)| *reason)
+    }
 }
 
 /// Wrapper around individual solver error types.
@@ -86,6 +161,28 @@ pub enum PipelineError {
     AccessDenied(#[from] AccessDeniedError),
     #[error("bot management handler error: {0}")]
     BotManagement(#[from] BotManagementError),
+    #[error("origin error handler error: {0}")]
+    OriginError(#[from] OriginErrorError),
+    #[error("custom solver error: {0}")]
+    Custom(#[source] Box<dyn std::error::Error + Send + Sync>),
+    #[error("no challenge detected")]
+    NoChallenge,
+    #[error("{challenge_type:?} challenge cannot be solved: {reason}")]
+    Unsupported {
+        challenge_type: ChallengeType,
+        reason: UnsupportedReason,
+    },
+}
+
+impl PipelineError {
+    /// A captcha provider was asked for a token and could not deliver one.
+    pub fn is_captcha_failure(&self) -> bool {
+        matches!(
+            self,
+            PipelineError::JavascriptV2(JavascriptV2Error::Captcha(_))
+                | PipelineError::Turnstile(TurnstileError::Captcha(_))
+        )
+    }
 }
 
 impl fmt::Display for UnsupportedReason {
@@ -98,12 +195,70 @@ impl fmt::Display for UnsupportedReason {
                 write!(f, "missing required dependency: {name}")
             }
             UnsupportedReason::UnknownChallenge => write!(f, "unrecognised challenge"),
+            UnsupportedReason::LowConfidence => {
+                write!(f, "detection confidence too low to spend a captcha solve")
+            }
         }
     }
 }
 
 // Display is provided by the thiserror derive.
 
+/// What a [`DynChallengeSolver`] asks the caller to do next.
+#[derive(Debug)]
+pub enum SolverOutput {
+    Submission(ChallengeSubmission),
+    Mitigation(MitigationPlan),
+}
+
+/// Solver for challenges the built-in detector does not recognise, e.g. a
+/// proprietary gate deployed behind Cloudflare.
+#[async_trait::async_trait(?Send)]
+pub trait DynChallengeSolver: Send + Sync {
+    /// Name reported as the detection's pattern.
+    fn name(&self) -> &'static str {
+        "custom"
+    }
+
+    fn matches(&self, response: &ChallengeResponse<'_>) -> bool;
+
+    /// Also offer pages the detector does not flag at all. Every ordinary
+    /// response then goes through [`matches`](Self::matches), so keep it
+    /// strict.
+    fn matches_undetected(&self) -> bool {
+        false
+    }
+
+    async fn solve(
+        &self,
+        response: &ChallengeResponse<'_>,
+        context: &PipelineContext<'_>,
+    ) -> Result<SolverOutput, PipelineError>;
+}
+
+#[async_trait::async_trait(?Send)]
+impl<S: DynChallengeSolver + ?Sized> DynChallengeSolver for Arc<S> {
+    fn name(&self) -> &'static str {
+        (**self).name()
+    }
+
+    fn matches(&self, response: &ChallengeResponse<'_>) -> bool {
+        (**self).matches(response)
+    }
+
+    fn matches_undetected(&self) -> bool {
+        (**self).matches_undetected()
+    }
+
+    async fn solve(
+        &self,
+        response: &ChallengeResponse<'_>,
+        context: &PipelineContext<'_>,
+    ) -> Result<SolverOutput, PipelineError> {
+        (**self).solve(response, context).await
+    }
+}
+
 /// Coordinates challenge detection and solver selection.
 pub struct ChallengePipeline {
     detector: ChallengeDetector,
@@ -114,6 +269,9 @@ pub struct ChallengePipeline {
     rate_limit: Option<RateLimitHandler>,
     access_denied: Option<AccessDeniedHandler>,
     bot_management: Option<BotManagementHandler>,
+    origin_error: Option<OriginErrorHandler>,
+    custom_solvers: Vec<Box<dyn DynChallengeSolver>>,
+    min_solve_confidence: f32,
 }
 
 impl ChallengePipeline {
@@ -128,6 +286,9 @@ impl ChallengePipeline {
             rate_limit: None,
             access_denied: None,
             bot_management: None,
+            origin_error: None,
+            custom_solvers: Vec::new(),
+            min_solve_confidence: 0.0,
         }
     }
 
@@ -188,13 +349,152 @@ impl ChallengePipeline {
         self
     }
 
+    /// Back off from Cloudflare 520-526 origin errors.
+    pub fn with_origin_error(mut self, handler: OriginErrorHandler) -> Self {
+        self.origin_error = Some(handler);
+        self
+    }
+
+    /// Register a solver tried, in registration order, on pages detected as
+    /// [`ChallengeType::Unknown`], and on undetected pages when it opts in
+    /// through [`DynChallengeSolver::matches_undetected`].
+    pub fn with_custom_solver(mut self, solver: Box<dyn DynChallengeSolver>) -> Self {
+        self.custom_solvers.push(solver);
+        self
+    }
+
+    /// Skip captcha-backed solvers (Turnstile, v2 captcha) for detections
+    /// below `confidence`, reporting [`UnsupportedReason::LowConfidence`].
+    pub fn with_min_solve_confidence(mut self, confidence: f32) -> Self {
+        self.min_solve_confidence = confidence.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Report which challenge types the attached solvers and handlers can
+    /// handle, without needing a live challenge.
+    ///
+    /// JavaScript v2 counts as supported with its solver attached; its
+    /// captcha variant additionally needs a captcha provider.
+    pub fn capabilities(&self) -> Capabilities {
+        let turnstile = match &self.turnstile {
+            None => Some(UnsupportedReason::MissingSolver("turnstile")),
+            Some(solver) if !solver.has_captcha_provider() => {
+                Some(UnsupportedReason::MissingDependency("captcha_provider"))
+            }
+            Some(_) => None,
+        };
+        let checks = [
+            (
+                ChallengeType::JavaScriptV1,
+                self.javascript_v1
+                    .is_none()
+                    .then_some(UnsupportedReason::MissingSolver("javascript_v1")),
+            ),
+            (
+                ChallengeType::JavaScriptV2,
+                self.javascript_v2
+                    .is_none()
+                    .then_some(UnsupportedReason::MissingSolver("javascript_v2")),
+            ),
+            (
+                ChallengeType::ManagedV3,
+                self.managed_v3
+                    .is_none()
+                    .then_some(UnsupportedReason::MissingSolver("managed_v3")),
+            ),
+            (ChallengeType::Turnstile, turnstile),
+            (
+                ChallengeType::RateLimit,
+                self.rate_limit
+                    .is_none()
+                    .then_some(UnsupportedReason::MissingSolver("rate_limit")),
+            ),
+            (
+                ChallengeType::AccessDenied,
+                self.access_denied
+                    .is_none()
+                    .then_some(UnsupportedReason::MissingSolver("access_denied")),
+            ),
+            (
+                ChallengeType::BotManagement,
+                self.bot_management
+                    .is_none()
+                    .then_some(UnsupportedReason::MissingSolver("bot_management")),
+            ),
+            (
+                ChallengeType::OriginError,
+                self.origin_error
+                    .is_none()
+                    .then_some(UnsupportedReason::MissingSolver("origin_error")),
+            ),
+        ];
+
+        let mut capabilities = Capabilities::default();
+        for (challenge_type, missing) in checks {
+            match missing {
+                Some(reason) => capabilities.missing.push((challenge_type, reason)),
+                None => capabilities.supported.push(challenge_type),
+            }
+        }
+        if !self.custom_solvers.is_empty() {
+            capabilities.supported.push(ChallengeType::Unknown);
+        }
+        capabilities.custom_solvers = self.custom_solvers.iter().map(|s| s.name()).collect();
+        capabilities
+    }
+
+    fn below_solve_confidence(&self, detection: &ChallengeDetection) -> bool {
+        detection.confidence < self.min_solve_confidence
+    }
+
     /// Evaluate a response and decide which solver should handle it.
     pub async fn evaluate<'a>(
         &'a mut self,
         response: &ChallengeResponse<'_>,
         context: PipelineContext<'a>,
     ) -> ChallengePipelineResult {
-        let Some(detection) = self.detector.detect(response) else {
+        let detection = self.detector.detect(response);
+        self.dispatch(detection, response, context).await
+    }
+
+    /// Solve a challenge [`evaluate`](Self::evaluate) already detected, without
+    /// detecting it again or adding to the detection history.
+    pub async fn solve_detected<'a>(
+        &'a self,
+        detection: ChallengeDetection,
+        response: &ChallengeResponse<'_>,
+        context: PipelineContext<'a>,
+    ) -> ChallengePipelineResult {
+        self.dispatch(Some(detection), response, context).await
+    }
+
+    async fn dispatch<'a>(
+        &'a self,
+        detection: Option<ChallengeDetection>,
+        response: &ChallengeResponse<'_>,
+        context: PipelineContext<'a>,
+    ) -> ChallengePipelineResult {
+        let unknown = detection
+            .as_ref()
+            .map(|detection| detection.challenge_type == ChallengeType::Unknown);
+        if unknown != Some(false)
+            && let Some(solver) = self.custom_solvers.iter().find(|solver| {
+                (unknown.is_some() || solver.matches_undetected()) && solver.matches(response)
+            })
+        {
+            let detection = detection.unwrap_or_else(|| custom_detection(solver.name(), response));
+            return match solver.solve(response, &context).await {
+                Ok(SolverOutput::Submission(submission)) => ChallengePipelineResult::Submission {
+                    detection,
+                    submission,
+                },
+                Ok(SolverOutput::Mitigation(plan)) => {
+                    ChallengePipelineResult::Mitigation { detection, plan }
+                }
+                Err(error) => ChallengePipelineResult::Failed { detection, error },
+            };
+        }
+        let Some(detection) = detection else {
             return ChallengePipelineResult::NoChallenge;
         };
 
@@ -204,6 +504,7 @@ impl ChallengePipeline {
             failure_recorder,
             fingerprint_manager,
             tls_manager,
+            user_agent,
         } = context;
 
         let detection_for_branch = detection.clone();
@@ -216,7 +517,7 @@ impl ChallengePipeline {
                         UnsupportedReason::MissingSolver("javascript_v1"),
                     );
                 };
-                match solver.solve(response) {
+                match solver.solve_as(response, user_agent).await {
                     Ok(submission) => ChallengePipelineResult::Submission {
                         detection: detection_for_branch,
                         submission,
@@ -236,6 +537,9 @@ impl ChallengePipeline {
                 };
 
                 let result = if JavascriptV2Solver::is_captcha_challenge(response) {
+                    if self.below_solve_confidence(&detection_for_branch) {
+                        return unsupported(detection_for_branch, UnsupportedReason::LowConfidence);
+                    }
                     solver.solve_with_captcha(response).await
                 } else {
                     solver.solve(response)
@@ -263,7 +567,7 @@ impl ChallengePipeline {
                         UnsupportedReason::MissingSolver("managed_v3"),
                     );
                 };
-                match solver.solve(response) {
+                match solver.solve_as(response, user_agent).await {
                     Ok(submission) => ChallengePipelineResult::Submission {
                         detection: detection_for_branch,
                         submission,
@@ -281,6 +585,9 @@ impl ChallengePipeline {
                         UnsupportedReason::MissingSolver("turnstile"),
                     );
                 };
+                if self.below_solve_confidence(&detection_for_branch) {
+                    return unsupported(detection_for_branch, UnsupportedReason::LowConfidence);
+                }
                 match solver.solve(response).await {
                     Ok(submission) => ChallengePipelineResult::Submission {
                         detection: detection_for_branch,
@@ -350,12 +657,63 @@ impl ChallengePipeline {
                     },
                 }
             }
+            ChallengeType::OriginError => {
+                let Some(handler) = self.origin_error.as_ref() else {
+                    return unsupported(
+                        detection_for_branch,
+                        UnsupportedReason::MissingSolver("origin_error"),
+                    );
+                };
+                match handler.plan(response, failure_recorder) {
+                    Ok(plan) => ChallengePipelineResult::Mitigation {
+                        detection: detection_for_branch,
+                        plan,
+                    },
+                    Err(err) => ChallengePipelineResult::Failed {
+                        detection: detection_for_branch,
+                        error: PipelineError::OriginError(err),
+                    },
+                }
+            }
             ChallengeType::Unknown => {
                 unsupported(detection_for_branch, UnsupportedReason::UnknownChallenge)
             }
         }
     }
 
+    /// Detect and solve a challenge but leave the submission to the caller,
+    /// for users driving their own transport. Solving itself never sends a
+    /// request; captcha-backed solvers only call their captcha provider.
+    pub async fn prepare<'a>(
+        &'a mut self,
+        response: &ChallengeResponse<'_>,
+        context: PipelineContext<'a>,
+    ) -> PipelinePrepareResult {
+        match self.evaluate(response, context).await {
+            ChallengePipelineResult::NoChallenge => PipelinePrepareResult::NoChallenge,
+            ChallengePipelineResult::Submission {
+                detection,
+                submission,
+            } => PipelinePrepareResult::Prepared {
+                detection,
+                submission,
+            },
+            ChallengePipelineResult::Mitigation { detection, plan } => {
+                PipelinePrepareResult::Mitigation { detection, plan }
+            }
+            ChallengePipelineResult::Unsupported { detection, reason } => {
+                let error = PipelineError::Unsupported {
+                    challenge_type: detection.challenge_type,
+                    reason,
+                };
+                PipelinePrepareResult::Failed { detection, error }
+            }
+            ChallengePipelineResult::Failed { detection, error } => {
+                PipelinePrepareResult::Failed { detection, error }
+            }
+        }
+    }
+
     /// Feed the detector with challenge outcome data for adaptive scoring.
     pub fn record_outcome(&mut self, pattern_id: &str, success: bool) {
         self.detector.learn_from_outcome(pattern_id, success);
@@ -368,9 +726,408 @@ impl Default for ChallengePipeline {
     }
 }
 
+/// Detect and solve a challenge page fetched by another HTTP client.
+///
+/// Runs the default detector and the matching solver, returning the
+/// submission the caller should send back. Mitigation-only challenges (rate
+/// limits, blocks) have nothing to submit and report `Unsupported`.
+pub async fn solve_challenge_page(
+    html: &str,
+    url: &Url,
+    status: u16,
+    headers: &HeaderMap,
+    interpreter: Arc<dyn JavascriptInterpreter>,
+    captcha_provider: Option<Arc<dyn CaptchaProvider>>,
+) -> Result<ChallengeSubmission, PipelineError> {
+    let mut javascript_v2 = JavascriptV2Solver::new();
+    let mut turnstile = TurnstileSolver::new();
+    if let Some(provider) = captcha_provider {
+        javascript_v2.set_captcha_provider(provider.clone());
+        turnstile = turnstile.with_captcha_provider(provider);
+    }
+    let mut pipeline = ChallengePipeline::default()
+        .with_javascript_v1(JavascriptV1Solver::new(interpreter.clone()))
+        .with_javascript_v2(javascript_v2)
+        .with_managed_v3(ManagedV3Solver::new(interpreter))
+        .with_turnstile(turnstile);
+
+    let response = ChallengeResponse {
+        url,
+        status,
+        headers,
+        body: html,
+        request_method: &Method::GET,
+    };
+    match pipeline
+        .prepare(&response, PipelineContext::default())
+        .await
+    {
+        PipelinePrepareResult::Prepared { submission, .. } => Ok(submission),
+        PipelinePrepareResult::Failed { error, .. } => Err(error),
+        PipelinePrepareResult::NoChallenge => Err(PipelineError::NoChallenge),
+        PipelinePrepareResult::Mitigation { detection, .. } => Err(PipelineError::Unsupported {
+            challenge_type: detection.challenge_type,
+            reason: UnsupportedReason::MissingSolver("mitigation"),
+        }),
+    }
+}
+
+/// Detection reported for a page only a custom solver recognised.
+fn custom_detection(name: &str, response: &ChallengeResponse<'_>) -> ChallengeDetection {
+    ChallengeDetection {
+        pattern_id: format!("custom_{name}"),
+        pattern_name: name.to_string(),
+        challenge_type: ChallengeType::Unknown,
+        response_strategy: ResponseStrategy::None,
+        confidence: 1.0,
+        is_adaptive: false,
+        status_code: response.status,
+        url: response.url.as_str().to_string(),
+        matched_indicators: Vec::new(),
+        competing_types: Vec::new(),
+    }
+}
+
 fn unsupported(
     detection: ChallengeDetection,
     reason: UnsupportedReason,
 ) -> ChallengePipelineResult {
     ChallengePipelineResult::Unsupported { detection, reason }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::external_deps::interpreters::InterpreterError;
+    use http::header::SERVER;
+    use std::time::Duration;
+
+    struct StubInterpreter;
+
+    impl JavascriptInterpreter for StubInterpreter {
+        fn solve_challenge(
+            &self,
+            _page_html: &str,
+            _host: &str,
+        ) -> Result<String, InterpreterError> {
+            Ok("42".into())
+        }
+    }
+
+    fn cloudflare_headers() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(SERVER, "cloudflare".parse().unwrap());
+        headers
+    }
+
+    #[tokio::test]
+    async fn solves_iuam_page_without_scraper() {
+        let html = r#"
+            <html>
+              <head><title>Just a moment...</title></head>
+              <body>
+                <form id="challenge-form" action="/cdn-cgi/l/chk_jschl?__cf_chl_f_tk=foo" method="POST">
+                  <input type='hidden' name='r' value='abc'/>
+                  <input type='hidden' name='jschl_vc' value='def'/>
+                  <input type='hidden' name='pass' value='ghi'/>
+                </form>
+                <script>setTimeout(function(){ var a = document.getElementById('challenge-form');
+                  a.submit();
+                }, 4000);</script>
+                <script src='/cdn-cgi/images/trace/jsch/'></script>
+              </body>
+            </html>
+        "#;
+        let url = Url::parse("https://example.com/").unwrap();
+
+        let submission = solve_challenge_page(
+            html,
+            &url,
+            503,
+            &cloudflare_headers(),
+            Arc::new(StubInterpreter),
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(submission.method, Method::POST);
+        assert_eq!(
+            submission
+                .form_fields
+                .get("jschl_answer")
+                .map(String::as_str),
+            Some("42")
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_pages_without_challenge() {
+        let url = Url::parse("https://example.com/").unwrap();
+        let result = solve_challenge_page(
+            "<html>ok</html>",
+            &url,
+            200,
+            &HeaderMap::new(),
+            Arc::new(StubInterpreter),
+            None,
+        )
+        .await;
+        assert!(matches!(result, Err(PipelineError::NoChallenge)));
+    }
+
+    #[tokio::test]
+    async fn routes_200_managed_challenge_to_managed_solver() {
+        let html = r#"
+            <html>
+              <head>
+                <title>Just a moment...</title>
+                <script>window._cf_chl_ctx={"cvId":"cv123"};</script>
+              </head>
+              <body>
+                <div id="challenge-body" data-ray="8a1b2c3d4e5f6a7b"></div>
+                <script>var cpo={};cpo.src="/cdn-cgi/challenge-platform/h/b/orchestrate/managed/v1";</script>
+                <form id="challenge-form" action="/cdn-cgi/challenge-platform/h/b/orchestrate/form?__cf_chl_rt_tk=foo" method="POST">
+                  <input type="hidden" name="r" value="token-r"/>
+                </form>
+              </body>
+            </html>
+        "#;
+        let url = Url::parse("https://example.com/").unwrap();
+        let headers = cloudflare_headers();
+        let response = ChallengeResponse {
+            url: &url,
+            status: 200,
+            headers: &headers,
+            body: html,
+            request_method: &Method::GET,
+        };
+        let mut pipeline = ChallengePipeline::default()
+            .with_managed_v3(ManagedV3Solver::new(Arc::new(StubInterpreter)));
+
+        match pipeline
+            .evaluate(&response, PipelineContext::default())
+            .await
+        {
+            ChallengePipelineResult::Submission {
+                detection,
+                submission,
+            } => {
+                assert_eq!(detection.challenge_type, ChallengeType::ManagedV3);
+                assert_eq!(submission.method, Method::POST);
+            }
+            other => panic!("expected managed submission, got {other:?}"),
+        }
+    }
+
+    struct CountingCaptchaProvider(std::sync::atomic::AtomicUsize);
+
+    #[async_trait::async_trait]
+    impl CaptchaProvider for CountingCaptchaProvider {
+        fn name(&self) -> &'static str {
+            "counting"
+        }
+
+        async fn solve(
+            &self,
+            _task: &crate::external_deps::captcha::CaptchaTask,
+        ) -> crate::external_deps::captcha::CaptchaResult {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(crate::external_deps::captcha::CaptchaSolution::new("token"))
+        }
+    }
+
+    #[tokio::test]
+    async fn low_confidence_turnstile_skips_captcha_provider() {
+        let html = r#"<html><body>
+            <div class="cf-turnstile" data-sitekey="0123456789ABCDEFGHIJ0123456789ABCDEFGHIJ"></div>
+            <script src="https://challenges.cloudflare.com/turnstile/v0/api.js"></script>
+            </body></html>"#;
+        let url = Url::parse("https://example.com/").unwrap();
+        let headers = cloudflare_headers();
+        let response = ChallengeResponse {
+            url: &url,
+            status: 403,
+            headers: &headers,
+            body: html,
+            request_method: &Method::GET,
+        };
+        let provider = Arc::new(CountingCaptchaProvider(Default::default()));
+        let mut pipeline = ChallengePipeline::new(ChallengeDetector::new())
+            .with_turnstile(TurnstileSolver::new().with_captcha_provider(provider.clone()))
+            .with_min_solve_confidence(1.0);
+
+        match pipeline
+            .evaluate(&response, PipelineContext::default())
+            .await
+        {
+            ChallengePipelineResult::Unsupported { detection, reason } => {
+                assert_eq!(detection.challenge_type, ChallengeType::Turnstile);
+                assert!(detection.confidence < 1.0);
+                assert_eq!(reason, UnsupportedReason::LowConfidence);
+            }
+            other => panic!("expected low-confidence rejection, got {other:?}"),
+        }
+        assert_eq!(provider.0.load(std::sync::atomic::Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn prepare_returns_turnstile_submission_without_sending_it() {
+        let html = r#"<html><body>
+            <div class="cf-turnstile" data-sitekey="0123456789ABCDEFGHIJ0123456789ABCDEFGHIJ"></div>
+            <script src="https://challenges.cloudflare.com/turnstile/v0/api.js"></script>
+            </body></html>"#;
+        // Nothing listens here, so any request the pipeline sent would fail.
+        let url = Url::parse("http://127.0.0.1:9/login").unwrap();
+        let headers = cloudflare_headers();
+        let response = ChallengeResponse {
+            url: &url,
+            status: 403,
+            headers: &headers,
+            body: html,
+            request_method: &Method::GET,
+        };
+        let provider = Arc::new(CountingCaptchaProvider(Default::default()));
+        let mut pipeline = ChallengePipeline::new(ChallengeDetector::new())
+            .with_turnstile(TurnstileSolver::new().with_captcha_provider(provider.clone()));
+
+        match pipeline
+            .prepare(&response, PipelineContext::default())
+            .await
+        {
+            PipelinePrepareResult::Prepared {
+                detection,
+                submission,
+            } => {
+                assert_eq!(detection.challenge_type, ChallengeType::Turnstile);
+                assert_eq!(submission.form_fields["cf-turnstile-response"], "token");
+                assert_eq!(submission.url.as_str(), "http://127.0.0.1:9/login");
+                pipeline.record_outcome(&detection.pattern_id, true);
+            }
+            other => panic!("expected a prepared submission, got {other:?}"),
+        }
+        assert_eq!(provider.0.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    struct MarkerSolver {
+        name: &'static str,
+        marker: &'static str,
+        undetected: bool,
+    }
+
+    #[async_trait::async_trait(?Send)]
+    impl DynChallengeSolver for MarkerSolver {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn matches(&self, response: &ChallengeResponse<'_>) -> bool {
+            response.body.contains(self.marker)
+        }
+
+        fn matches_undetected(&self) -> bool {
+            self.undetected
+        }
+
+        async fn solve(
+            &self,
+            response: &ChallengeResponse<'_>,
+            _context: &PipelineContext<'_>,
+        ) -> Result<SolverOutput, PipelineError> {
+            let payload = [("gate".to_string(), self.name.to_string())].into();
+            Ok(SolverOutput::Submission(ChallengeSubmission::new(
+                Method::POST,
+                response.url.join("/gate/verify").unwrap(),
+                payload,
+                Default::default(),
+                Duration::ZERO,
+            )))
+        }
+    }
+
+    #[tokio::test]
+    async fn custom_solvers_handle_unrecognised_pages_in_order() {
+        let mut pipeline = ChallengePipeline::default()
+            .with_custom_solver(Box::new(MarkerSolver {
+                name: "partner_gate",
+                marker: "data-partner-gate",
+                undetected: true,
+            }))
+            .with_custom_solver(Box::new(MarkerSolver {
+                name: "fallback",
+                marker: "<div",
+                undetected: false,
+            }));
+        let capabilities = pipeline.capabilities();
+        assert!(capabilities.supported.contains(&ChallengeType::Unknown));
+        assert_eq!(capabilities.custom_solvers, ["partner_gate", "fallback"]);
+        let url = Url::parse("https://example.com/account").unwrap();
+        let headers = cloudflare_headers();
+        let response = |body| ChallengeResponse {
+            url: &url,
+            status: 403,
+            headers: &headers,
+            body,
+            request_method: &Method::GET,
+        };
+
+        match pipeline
+            .evaluate(
+                &response(r#"<div data-partner-gate="v2"></div>"#),
+                PipelineContext::default(),
+            )
+            .await
+        {
+            ChallengePipelineResult::Submission {
+                detection,
+                submission,
+            } => {
+                assert_eq!(detection.challenge_type, ChallengeType::Unknown);
+                assert_eq!(detection.pattern_id, "custom_partner_gate");
+                assert_eq!(submission.url.path(), "/gate/verify");
+                assert_eq!(submission.form_fields["gate"], "partner_gate");
+            }
+            other => panic!("expected custom submission, got {other:?}"),
+        }
+
+        for body in ["<p>welcome</p>", "<div>welcome</div>"] {
+            assert!(matches!(
+                pipeline
+                    .evaluate(&response(body), PipelineContext::default())
+                    .await,
+                ChallengePipelineResult::NoChallenge
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn origin_errors_back_off_on_the_same_route() {
+        let mut pipeline = ChallengePipeline::default().with_origin_error(
+            OriginErrorHandler::new()
+                .with_delay_range(Duration::from_secs(5), Duration::from_secs(5)),
+        );
+        let url = Url::parse("https://example.com/").unwrap();
+        let headers = cloudflare_headers();
+        for status in [521, 524] {
+            let response = ChallengeResponse {
+                url: &url,
+                status,
+                headers: &headers,
+                body: "<h1>Web server is down</h1>",
+                request_method: &Method::GET,
+            };
+            match pipeline
+                .evaluate(&response, PipelineContext::default())
+                .await
+            {
+                ChallengePipelineResult::Mitigation { detection, plan } => {
+                    assert_eq!(detection.challenge_type, ChallengeType::OriginError);
+                    assert!(plan.should_retry);
+                    assert_eq!(plan.wait, Some(Duration::from_secs(5)));
+                    assert!(plan.new_proxy.is_none());
+                }
+                other => panic!("expected a delayed retry for {status}, got {other:?}"),
+            }
+        }
+    }
+}
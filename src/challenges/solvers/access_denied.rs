@@ -6,13 +6,13 @@
 use std::time::Duration;
 
 use once_cell::sync::Lazy;
-use rand::Rng;
+use rand::rngs::StdRng;
 use regex::{Regex, RegexBuilder};
 use thiserror::Error;
 
 use crate::challenges::core::{ChallengeResponse, is_cloudflare_response};
 
-use super::{ChallengeSolver, MitigationPlan};
+use super::{ChallengeSolver, MitigationPlan, SolverRng};
 
 const DEFAULT_DELAY_MIN_SECS: f32 = 5.0;
 const DEFAULT_DELAY_MAX_SECS: f32 = 15.0;
@@ -21,6 +21,7 @@ const DEFAULT_DELAY_MAX_SECS: f32 = 15.0;
 pub struct AccessDeniedHandler {
     delay_min: Duration,
     delay_max: Duration,
+    rng: SolverRng,
 }
 
 impl AccessDeniedHandler {
@@ -28,6 +29,7 @@ impl AccessDeniedHandler {
         Self {
             delay_min: Duration::from_secs_f32(DEFAULT_DELAY_MIN_SECS),
             delay_max: Duration::from_secs_f32(DEFAULT_DELAY_MAX_SECS),
+            rng: SolverRng::from_entropy(),
         }
     }
 
@@ -38,6 +40,12 @@ impl AccessDeniedHandler {
         self
     }
 
+    /// Draw delays from `rng` instead of an entropy-seeded generator.
+    pub fn with_rng(mut self, rng: StdRng) -> Self {
+        self.rng = SolverRng::new(rng);
+        self
+    }
+
     /// Returns true if the response matches the Access Denied signature.
     pub fn is_access_denied(response: &ChallengeResponse<'_>) -> bool {
         is_cloudflare_response(response)
@@ -91,13 +99,7 @@ impl AccessDeniedHandler {
     }
 
     fn random_delay(&self) -> Duration {
-        if self.delay_max <= self.delay_min {
-            return self.delay_min;
-        }
-        let mut rng = rand::thread_rng();
-        let min = self.delay_min.as_secs_f32();
-        let max = self.delay_max.as_secs_f32();
-        Duration::from_secs_f32(rng.gen_range(min..max))
+        self.rng.duration_between(self.delay_min, self.delay_max)
     }
 }
 
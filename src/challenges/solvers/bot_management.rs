@@ -6,14 +6,16 @@
 use std::time::Duration;
 
 use once_cell::sync::Lazy;
-use rand::Rng;
+use rand::rngs::StdRng;
 use regex::{Regex, RegexBuilder};
 use thiserror::Error;
 
 use crate::challenges::core::{ChallengeResponse, is_cloudflare_response};
+use crate::challenges::detectors::ChallengeType;
 
 use super::{
-    ChallengeSolver, FailureRecorder, FingerprintManager, MitigationPlan, TlsProfileManager,
+    ChallengeSolver, FailureContext, FailureRecorder, FingerprintManager, MitigationPlan,
+    SolverRng, TlsProfileManager,
 };
 
 const DEFAULT_DELAY_MIN_SECS: f32 = 30.0;
@@ -23,6 +25,7 @@ const DEFAULT_DELAY_MAX_SECS: f32 = 60.0;
 pub struct BotManagementHandler {
     delay_min: Duration,
     delay_max: Duration,
+    rng: SolverRng,
 }
 
 impl BotManagementHandler {
@@ -30,6 +33,7 @@ impl BotManagementHandler {
         Self {
             delay_min: Duration::from_secs_f32(DEFAULT_DELAY_MIN_SECS),
             delay_max: Duration::from_secs_f32(DEFAULT_DELAY_MAX_SECS),
+            rng: SolverRng::from_entropy(),
         }
     }
 
@@ -39,6 +43,12 @@ impl BotManagementHandler {
         self
     }
 
+    /// Draw delays from `rng` instead of an entropy-seeded generator.
+    pub fn with_rng(mut self, rng: StdRng) -> Self {
+        self.rng = SolverRng::new(rng);
+        self
+    }
+
     pub fn is_bot_management(response: &ChallengeResponse<'_>) -> bool {
         is_cloudflare_response(response)
             && response.status == 403
@@ -63,7 +73,12 @@ impl BotManagementHandler {
             .to_string();
 
         if let Some(recorder) = state_recorder {
-            recorder.record_failure(&domain, "cf_bot_management");
+            recorder.record_failure_detailed(
+                &domain,
+                FailureContext::new("cf_bot_management")
+                    .with_status(response.status)
+                    .with_challenge_type(ChallengeType::BotManagement),
+            );
         }
 
         let delay = self.random_delay();
@@ -93,13 +108,7 @@ impl BotManagementHandler {
     }
 
     fn random_delay(&self) -> Duration {
-        if self.delay_max <= self.delay_min {
-            return self.delay_min;
-        }
-        let mut rng = rand::thread_rng();
-        let min = self.delay_min.as_secs_f32();
-        let max = self.delay_max.as_secs_f32();
-        Duration::from_secs_f32(rng.gen_range(min..max))
+        self.rng.duration_between(self.delay_min, self.delay_max)
     }
 }
 
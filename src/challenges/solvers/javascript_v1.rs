@@ -16,7 +16,9 @@ use crate::challenges::core::{
     ChallengeResponse, ChallengeSubmission, OriginalRequest, execute_challenge_submission,
     is_cloudflare_response, origin_from_url, parse_iuam_challenge,
 };
-use crate::external_deps::interpreters::{InterpreterError, JavascriptInterpreter};
+use crate::external_deps::interpreters::{
+    InterpreterError, JavascriptInterpreter, evaluate_blocking,
+};
 
 use super::ChallengeSolver;
 
@@ -57,9 +59,19 @@ impl JavascriptV1Solver {
     }
 
     /// Parse the IUAM page and return the ready-to-submit payload.
-    pub fn solve(
+    pub async fn solve(
+        &self,
+        response: &ChallengeResponse<'_>,
+    ) -> Result<ChallengeSubmission, JavascriptV1Error> {
+        self.solve_as(response, None).await
+    }
+
+    /// [`solve`](Self::solve) with the page's `navigator.userAgent` set to the
+    /// user agent the challenge was requested with.
+    pub async fn solve_as(
         &self,
         response: &ChallengeResponse<'_>,
+        user_agent: Option<&str>,
     ) -> Result<ChallengeSubmission, JavascriptV1Error> {
         if !self.is_iuam_challenge(response) {
             return Err(JavascriptV1Error::NotAnIuamChallenge);
@@ -70,10 +82,14 @@ impl JavascriptV1Solver {
 
         let blueprint = parse_iuam_challenge(response).map_err(JavascriptV1Error::Parse)?;
 
-        let answer = self
-            .interpreter
-            .solve_challenge(response.body, host)
-            .map_err(JavascriptV1Error::Interpreter)?;
+        let (page, host) = (response.body.to_string(), host.to_string());
+        let user_agent = user_agent.map(str::to_string);
+        let answer = evaluate_blocking(&self.interpreter, move |interpreter| match user_agent {
+            Some(user_agent) => interpreter.solve_challenge_as(&page, &host, &user_agent),
+            None => interpreter.solve_challenge(&page, &host),
+        })
+        .await
+        .map_err(JavascriptV1Error::Interpreter)?;
 
         let mut submission = blueprint
             .to_submission(&base_url, vec![("jschl_answer".to_string(), answer)])
@@ -97,7 +113,7 @@ impl JavascriptV1Solver {
         response: &ChallengeResponse<'_>,
         original_request: OriginalRequest,
     ) -> Result<ChallengeHttpResponse, JavascriptV1Error> {
-        let submission = self.solve(response)?;
+        let submission = self.solve(response).await?;
         execute_challenge_submission(client, submission, original_request)
             .await
             .map_err(JavascriptV1Error::Submission)
@@ -151,6 +167,7 @@ pub enum JavascriptV1Error {
 mod tests {
     use super::*;
     use crate::challenges::core::ChallengeHttpClientError;
+    use crate::external_deps::interpreters::{BOA_PANIC_SCRIPT, BoaJavascriptInterpreter};
     use async_trait::async_trait;
     use http::{HeaderMap, Method, header::SERVER};
     use std::sync::Mutex;
@@ -204,8 +221,35 @@ mod tests {
         }
     }
 
-    #[test]
-    fn solve_extracts_payload() {
+    #[tokio::test]
+    async fn solve_honors_get_form_method() {
+        let html = r#"
+            <html>
+              <body>
+                <form id='challenge-form' action='/cdn-cgi/l/chk_jschl?__cf_chl_f_tk=foo' method="get">
+                  <input type='hidden' name='r' value='abc'/>
+                  <input type='hidden' name='jschl_vc' value='def'/>
+                  <input type='hidden' name='pass' value='ghi'/>
+                </form>
+                <script>setTimeout(function(){ submit();
+                }, 4000);</script>
+                <script src='/cdn-cgi/images/trace/jsch/'></script>
+              </body>
+            </html>
+        "#;
+
+        let solver = JavascriptV1Solver::new(Arc::new(StubInterpreter));
+        let fixture = ResponseFixture::new(html, 503);
+        let submission = solver.solve(&fixture.response()).await.unwrap();
+        assert_eq!(submission.method, Method::GET);
+        assert_eq!(
+            submission.url.as_str(),
+            "https://example.com/cdn-cgi/l/chk_jschl?__cf_chl_f_tk=foo&r=abc&jschl_vc=def&pass=ghi&jschl_answer=42"
+        );
+    }
+
+    #[tokio::test]
+    async fn solve_extracts_payload() {
         let html = r#"
             <html>
               <body>
@@ -225,7 +269,7 @@ mod tests {
         let fixture = ResponseFixture::new(html, 503);
         let resp = fixture.response();
         assert!(solver.is_iuam_challenge(&resp));
-        let submission = solver.solve(&resp).unwrap();
+        let submission = solver.solve(&resp).await.unwrap();
         assert_eq!(submission.method, Method::POST);
         assert_eq!(
             submission.form_fields.get("jschl_answer"),
@@ -234,6 +278,36 @@ mod tests {
         assert_eq!(submission.wait, Duration::from_millis(4000));
     }
 
+    #[tokio::test]
+    async fn interpreter_panic_fails_the_solve() {
+        let html = format!(
+            r#"
+            <html>
+              <body>
+                <form id='challenge-form' action='/cdn-cgi/l/chk_jschl?__cf_chl_f_tk=foo' method='POST'>
+                  <input type='hidden' name='r' value='abc'/>
+                  <input type='hidden' name='jschl_vc' value='def'/>
+                  <input type='hidden' name='pass' value='ghi'/>
+                </form>
+                <script>{BOA_PANIC_SCRIPT}</script>
+                <script>setTimeout(function(){{ submit();
+                }}, 4000);</script>
+                <script src='/cdn-cgi/images/trace/jsch/'></script>
+              </body>
+            </html>
+        "#
+        );
+
+        let solver = JavascriptV1Solver::new(Arc::new(BoaJavascriptInterpreter::new()));
+        let fixture = ResponseFixture::new(&html, 503);
+        let err = solver.solve(&fixture.response()).await.unwrap_err();
+        assert!(matches!(
+            err,
+            JavascriptV1Error::Interpreter(InterpreterError::Execution(message))
+                if message == "interpreter panicked"
+        ));
+    }
+
     struct StubClient {
         responses: Mutex<Vec<ChallengeHttpResponse>>,
     }
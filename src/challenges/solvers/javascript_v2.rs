@@ -6,22 +6,24 @@
 
 use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use html_escape::decode_html_entities;
 use once_cell::sync::Lazy;
-use rand::Rng;
+use rand::rngs::StdRng;
 use regex::{Regex, RegexBuilder};
 use serde::Deserialize;
 use thiserror::Error;
 
 use crate::challenges::core::{
     ChallengeExecutionError, ChallengeHttpClient, ChallengeHttpResponse, ChallengeResponse,
-    ChallengeSubmission, OriginalRequest, execute_challenge_submission, is_cloudflare_response,
-    origin_from_url,
+    ChallengeSubmission, OriginalRequest, execute_challenge_submission, is_challenge_response,
+    is_cloudflare_response, origin_from_url,
 };
 use crate::external_deps::captcha::{CaptchaError, CaptchaProvider, CaptchaTask};
 
+use super::{ChallengeOptFields, SolverRng, interaction_wait};
+
 /// Default minimum random wait (seconds) before submitting the response.
 const DEFAULT_DELAY_MIN_SECS: f32 = 1.0;
 /// Default maximum random wait (seconds) before submitting the response.
@@ -31,7 +33,9 @@ const DEFAULT_DELAY_MAX_SECS: f32 = 5.0;
 pub struct JavascriptV2Solver {
     delay_min: Duration,
     delay_max: Duration,
+    interaction_window: Option<(Duration, Duration)>,
     captcha_provider: Option<Arc<dyn CaptchaProvider>>, // optional hCaptcha provider
+    rng: SolverRng,
 }
 
 impl JavascriptV2Solver {
@@ -40,7 +44,9 @@ impl JavascriptV2Solver {
         Self {
             delay_min: Duration::from_secs_f32(DEFAULT_DELAY_MIN_SECS),
             delay_max: Duration::from_secs_f32(DEFAULT_DELAY_MAX_SECS),
+            interaction_window: None,
             captcha_provider: None,
+            rng: SolverRng::from_entropy(),
         }
     }
 
@@ -51,6 +57,19 @@ impl JavascriptV2Solver {
         self
     }
 
+    /// Draw delays from `rng` instead of an entropy-seeded generator.
+    pub fn with_rng(mut self, rng: StdRng) -> Self {
+        self.rng = SolverRng::new(rng);
+        self
+    }
+
+    /// Target time from receiving a captcha page to submitting it, captcha
+    /// solve included. Defaults to the delay range.
+    pub fn with_interaction_window(mut self, min: Duration, max: Duration) -> Self {
+        self.interaction_window = Some((min, max.max(min)));
+        self
+    }
+
     /// Attach an hCaptcha provider that will be used when captcha challenges are detected.
     pub fn with_captcha_provider(mut self, provider: Arc<dyn CaptchaProvider>) -> Self {
         self.captcha_provider = Some(provider);
@@ -69,9 +88,7 @@ impl JavascriptV2Solver {
 
     /// Returns `true` when the response matches the Cloudflare v2 JavaScript challenge signature.
     pub fn is_js_challenge(response: &ChallengeResponse<'_>) -> bool {
-        is_cloudflare_response(response)
-            && matches!(response.status, 403 | 429 | 503)
-            && JS_CHALLENGE_RE.is_match(response.body)
+        is_challenge_response(response) && JS_CHALLENGE_RE.is_match(response.body)
     }
 
     /// Returns `true` when the response corresponds to the Cloudflare v2 hCaptcha flow.
@@ -92,7 +109,10 @@ impl JavascriptV2Solver {
 
         let info = Self::extract_challenge_info(response.body)?;
         let payload = Self::generate_payload(response.body, &info.options)?;
-        self.build_submission(response, &info.form_action, payload)
+        let wait = self
+            .random_delay()
+            .max(info.options.fields.min_wait.unwrap_or_default());
+        self.build_submission(response, &info.form_action, payload, wait)
     }
 
     /// Build the challenge submission payload for captcha-protected VM challenges.
@@ -103,6 +123,7 @@ impl JavascriptV2Solver {
         if !Self::is_captcha_challenge(response) {
             return Err(JavascriptV2Error::NotCaptchaChallenge);
         }
+        let received = Instant::now();
 
         let provider = self
             .captcha_provider
@@ -115,7 +136,8 @@ impl JavascriptV2Solver {
         let site_key = Self::extract_site_key(response.body)
             .ok_or(JavascriptV2Error::MissingToken("data-sitekey"))?;
 
-        let mut task = CaptchaTask::new(site_key, response.url.clone());
+        let mut task =
+            CaptchaTask::new(site_key, response.url.clone()).with_poll_seed(self.rng.seed());
         // Preserve challenge-specific context for providers that can use it.
         if let Some(cv_id) = info.options.cv_id.as_ref() {
             task = task.insert_metadata("cv_id", cv_id.clone());
@@ -125,12 +147,25 @@ impl JavascriptV2Solver {
             .solve(&task)
             .await
             .map_err(JavascriptV2Error::Captcha)?;
+        let token_ttl = solution.expires_in;
         payload.insert("h-captcha-response".into(), solution.token);
         for (key, value) in solution.metadata {
             payload.insert(key, value);
         }
 
-        self.build_submission(response, &info.form_action, payload)
+        let (min, max) = self
+            .interaction_window
+            .unwrap_or((self.delay_min, self.delay_max));
+        let elapsed = received.elapsed();
+        let required = info
+            .options
+            .fields
+            .min_wait
+            .unwrap_or_default()
+            .saturating_sub(elapsed);
+        let wait = interaction_wait(&self.rng, min, max, elapsed).max(required);
+        self.build_submission(response, &info.form_action, payload, wait)
+            .map(|submission| submission.with_token_ttl(token_ttl))
     }
 
     /// Execute the full challenge flow, including waiting and submission.
@@ -156,6 +191,7 @@ impl JavascriptV2Solver {
         response: &ChallengeResponse<'_>,
         form_action: &str,
         mut payload: HashMap<String, String>,
+        wait: Duration,
     ) -> Result<ChallengeSubmission, JavascriptV2Error> {
         let action = decode_html_entities(form_action).into_owned();
         let target_url = response
@@ -182,21 +218,31 @@ impl JavascriptV2Solver {
         headers.insert("Referer".into(), response.url.as_str().to_string());
         headers.insert("Origin".into(), origin_from_url(response.url));
 
-        let wait = self.random_delay();
         let submission =
             ChallengeSubmission::new(http::Method::POST, target_url, payload, headers, wait);
         Ok(submission)
     }
 
     fn random_delay(&self) -> Duration {
-        if self.delay_max <= self.delay_min {
-            return self.delay_min;
+        self.rng.duration_between(self.delay_min, self.delay_max)
+    }
+
+    /// Fields `parse_challenge` reports for a v2 page.
+    pub(crate) fn extract_fields(
+        body: &str,
+    ) -> Result<Vec<(&'static str, String)>, JavascriptV2Error> {
+        let info = Self::extract_challenge_info(body)?;
+        let mut fields = vec![("form_action", info.form_action)];
+        if let Some(cv_id) = info.options.cv_id {
+            fields.push(("cv_id", cv_id));
+        }
+        if let Some(page_data) = info.options.chl_page_data {
+            fields.push(("chl_page_data", page_data));
+        }
+        if let Some(site_key) = Self::extract_site_key(body) {
+            fields.push(("site_key", site_key));
         }
-        let mut rng = rand::thread_rng();
-        let min = self.delay_min.as_secs_f32();
-        let max = self.delay_max.as_secs_f32();
-        let secs = rng.gen_range(min..=max);
-        Duration::from_secs_f32(secs)
+        Ok(fields)
     }
 
     fn extract_challenge_info(body: &str) -> Result<ChallengeInfo, JavascriptV2Error> {
@@ -245,6 +291,7 @@ impl JavascriptV2Solver {
         if let Some(page_data) = options.chl_page_data.as_ref() {
             payload.insert("cf_chl_page_data".into(), page_data.clone());
         }
+        options.fields.fill_payload(&mut payload, false);
         Ok(payload)
     }
 
@@ -275,6 +322,8 @@ struct ChallengeOptions {
     #[serde(rename = "chlPageData")]
     chl_page_data: Option<String>,
     #[serde(flatten)]
+    fields: ChallengeOptFields,
+    #[serde(flatten)]
     _extra: serde_json::Value,
 }
 
@@ -474,6 +523,19 @@ mod tests {
         );
     }
 
+    #[test]
+    fn submission_honours_the_page_minimum_wait() {
+        let html = include_str!("../../../tests/fixtures/v2_min_wait.html");
+        let fixture = ResponseFixture::new(html, 403);
+        let solver = JavascriptV2Solver::new()
+            .with_delay_range(Duration::from_secs(1), Duration::from_secs(2));
+
+        let submission = solver.solve(&fixture.response()).expect("should solve");
+        assert_eq!(submission.wait, Duration::from_secs(6));
+        assert_eq!(submission.form_fields.get("cNounce").unwrap(), "82417");
+        assert!(!submission.form_fields.contains_key("cMinWait"));
+    }
+
     #[tokio::test]
     async fn solve_with_captcha_uses_provider() {
         let html = sample_html(true);
@@ -500,4 +562,27 @@ mod tests {
             .expect_err("missing provider should fail");
         matches!(err, JavascriptV2Error::CaptchaProviderMissing);
     }
+
+    const FULL_CHL_OPT: &str = r#"{"cvId":"3","cType":"managed","cNounce":82261,"cRay":"8a1b2c3d4e5f6a7b","cHash":"d2f0c1e5a9b8","md":"Zm9vYmFy.MTIz","cFPWv":"g","chlPageData":"page-data","cUPMDTk":"/?__cf_chl_tk=abc"}"#;
+
+    #[test]
+    fn payload_echoes_challenge_options() {
+        let html = sample_html(false).replace(
+            r#"{"cvId":"cv123","chlPageData":"page-data"}"#,
+            FULL_CHL_OPT,
+        );
+        let fixture = ResponseFixture::new(&html, 403);
+        let submission = JavascriptV2Solver::new()
+            .with_delay_range(Duration::ZERO, Duration::ZERO)
+            .solve(&fixture.response())
+            .expect("should solve");
+
+        let fields = &submission.form_fields;
+        assert_eq!(fields["cRay"], "8a1b2c3d4e5f6a7b");
+        assert_eq!(fields["cHash"], "d2f0c1e5a9b8");
+        assert_eq!(fields["md"], "Zm9vYmFy.MTIz");
+        assert_eq!(fields["cNounce"], "82261");
+        assert_eq!(fields["cv_chal_id"], "3");
+        assert!(!fields.contains_key("cType"));
+    }
 }
@@ -10,27 +10,36 @@ use std::time::Duration;
 use html_escape::decode_html_entities;
 use once_cell::sync::Lazy;
 use rand::Rng;
+use rand::rngs::StdRng;
 use regex::{Regex, RegexBuilder};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 use crate::challenges::core::{
     ChallengeExecutionError, ChallengeHttpClient, ChallengeHttpResponse, ChallengeResponse,
-    ChallengeSubmission, OriginalRequest, execute_challenge_submission, is_cloudflare_response,
-    origin_from_url,
+    ChallengeSubmission, OriginalRequest, execute_challenge_submission, extract_input_fields,
+    is_challenge_response, origin_from_url,
 };
-use crate::external_deps::interpreters::{InterpreterError, JavascriptInterpreter};
+use crate::external_deps::interpreters::{
+    InterpreterError, JavascriptInterpreter, evaluate_blocking,
+};
+use crate::modules::spoofing::OperatingSystem;
 
-use super::ChallengeSolver;
+use super::{ChallengeOptFields, ChallengeSolver, SolverRng};
 
 const DEFAULT_DELAY_MIN_SECS: f32 = 1.0;
 const DEFAULT_DELAY_MAX_SECS: f32 = 5.0;
+/// Longest `_cf_chl_*` object scanned for its closing brace.
+const MAX_JSON_BLOCK_BYTES: usize = 256 * 1024;
+/// `navigator.userAgent` inside the VM when the caller does not supply one.
+const VM_USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36";
 
 /// Cloudflare Managed v3/V3 JavaScript challenge solver.
 pub struct ManagedV3Solver {
     interpreter: Arc<dyn JavascriptInterpreter>,
     delay_min: Duration,
     delay_max: Duration,
+    rng: SolverRng,
 }
 
 impl ManagedV3Solver {
@@ -39,6 +48,7 @@ impl ManagedV3Solver {
             interpreter,
             delay_min: Duration::from_secs_f32(DEFAULT_DELAY_MIN_SECS),
             delay_max: Duration::from_secs_f32(DEFAULT_DELAY_MAX_SECS),
+            rng: SolverRng::from_entropy(),
         }
     }
 
@@ -48,17 +58,32 @@ impl ManagedV3Solver {
         self
     }
 
+    /// Draw delays from `rng` instead of an entropy-seeded generator.
+    pub fn with_rng(mut self, rng: StdRng) -> Self {
+        self.rng = SolverRng::new(rng);
+        self
+    }
+
     pub fn is_challenge(response: &ChallengeResponse<'_>) -> bool {
-        is_cloudflare_response(response)
-            && matches!(response.status, 403 | 429 | 503)
+        is_challenge_response(response)
             && (V3_PLATFORM_RE.is_match(response.body)
                 || V3_CONTEXT_RE.is_match(response.body)
                 || V3_FORM_RE.is_match(response.body))
     }
 
-    pub fn solve(
+    pub async fn solve(
+        &self,
+        response: &ChallengeResponse<'_>,
+    ) -> Result<ChallengeSubmission, ManagedV3Error> {
+        self.solve_as(response, None).await
+    }
+
+    /// [`solve`](Self::solve) with the VM's `navigator.userAgent` set to the
+    /// user agent the challenge was requested with.
+    pub async fn solve_as(
         &self,
         response: &ChallengeResponse<'_>,
+        user_agent: Option<&str>,
     ) -> Result<ChallengeSubmission, ManagedV3Error> {
         if !Self::is_challenge(response) {
             return Err(ManagedV3Error::NotV3Challenge);
@@ -72,15 +97,20 @@ impl ManagedV3Solver {
             .to_string();
 
         let challenge_answer = match info.vm_script {
-            Some(ref script) => self.execute_vm(&info, script, &host).unwrap_or_else(|err| {
-                log::warn!("Managed v3 VM execution failed: {err}; using fallback");
-                Self::fallback_answer(&info)
-            }),
-            None => Self::fallback_answer(&info),
+            Some(ref script) => self
+                .execute_vm(&info, script, &host, user_agent.unwrap_or(VM_USER_AGENT))
+                .await
+                .unwrap_or_else(|err| {
+                    log::warn!("Managed v3 VM execution failed: {err}; using fallback");
+                    self.fallback_answer(&info)
+                }),
+            None => self.fallback_answer(&info),
         };
 
-        let payload = Self::generate_payload(response.body, &challenge_answer)?;
-        self.build_submission(response, &info.form_action, payload)
+        let mut payload = Self::generate_payload(response.body, &challenge_answer)?;
+        info.opt_data.fields.fill_payload(&mut payload, true);
+        let min_wait = info.opt_data.fields.min_wait.unwrap_or_default();
+        self.build_submission(response, &info.form_action, payload, min_wait)
     }
 
     pub async fn solve_and_submit(
@@ -89,19 +119,23 @@ impl ManagedV3Solver {
         response: &ChallengeResponse<'_>,
         original_request: OriginalRequest,
     ) -> Result<ChallengeHttpResponse, ManagedV3Error> {
-        let submission = self.solve(response)?;
+        let submission = self.solve(response).await?;
         execute_challenge_submission(client, submission, original_request)
             .await
             .map_err(ManagedV3Error::Submission)
     }
 
-    fn execute_vm(
+    async fn execute_vm(
         &self,
         info: &ChallengeInfo,
         vm_script: &str,
         host: &str,
+        user_agent: &str,
     ) -> Result<String, ManagedV3Error> {
         let ctx_json = serde_json::to_string(&info.ctx_data).unwrap_or_else(|_| "{}".into());
+        let platform = OperatingSystem::from_user_agent(user_agent)
+            .map_or("Win32", OperatingSystem::navigator_platform);
+        let user_agent = serde_json::to_string(user_agent).unwrap_or_else(|_| "''".into());
         let opt_json = serde_json::to_string(&info.opt_data).unwrap_or_else(|_| "{}".into());
 
         let script = format!(
@@ -114,8 +148,8 @@ impl ManagedV3Solver {
                     pathname: '/'
                 }},
                 navigator: {{
-                    userAgent: 'Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36',
-                    platform: 'Win32',
+                    userAgent: {user_agent},
+                    platform: '{platform}',
                     language: 'en-US'
                 }},
                 document: {{
@@ -149,23 +183,30 @@ impl ManagedV3Solver {
             host = host,
             ctx = ctx_json,
             opt = opt_json,
-            vm_script = vm_script
+            vm_script = vm_script,
+            user_agent = user_agent,
+            platform = platform
         );
 
-        self.interpreter
-            .execute(&script, host)
-            .map_err(ManagedV3Error::Interpreter)
-            .map(|answer| answer.trim().to_string())
+        let host = host.to_string();
+        evaluate_blocking(&self.interpreter, move |interpreter| {
+            interpreter.execute(&script, &host)
+        })
+        .await
+        .map_err(ManagedV3Error::Interpreter)
+        .map(|answer| answer.trim().to_string())
     }
 
-    fn fallback_answer(info: &ChallengeInfo) -> String {
+    fn fallback_answer(&self, info: &ChallengeInfo) -> String {
         if let Some(page_data) = info.opt_data.chl_page_data.as_ref() {
             return (hash_str(page_data) % 1_000_000).to_string();
         }
         if let Some(cv_id) = info.ctx_data.cv_id.as_ref() {
             return (hash_str(cv_id) % 1_000_000).to_string();
         }
-        rand::thread_rng().gen_range(100_000..=999_999).to_string()
+        self.rng
+            .with(|rng| rng.gen_range(100_000..=999_999))
+            .to_string()
     }
 
     fn build_submission(
@@ -173,6 +214,7 @@ impl ManagedV3Solver {
         response: &ChallengeResponse<'_>,
         form_action: &str,
         mut payload: HashMap<String, String>,
+        min_wait: Duration,
     ) -> Result<ChallengeSubmission, ManagedV3Error> {
         let form_action = decode_html_entities(form_action).into_owned();
         let target_url = response
@@ -188,27 +230,18 @@ impl ManagedV3Solver {
         headers.insert("Referer".into(), response.url.as_str().to_string());
         headers.insert("Origin".into(), origin_from_url(response.url));
 
-        let wait = self.random_delay();
+        let wait = self.random_delay().max(min_wait);
         payload.entry("jschl_answer".into()).or_default();
         payload.entry("cf_captcha_token".into()).or_default();
 
-        Ok(ChallengeSubmission::new(
-            http::Method::POST,
-            target_url,
-            payload,
-            headers,
-            wait,
-        ))
+        Ok(
+            ChallengeSubmission::new(http::Method::POST, target_url, payload, headers, wait)
+                .with_ordered_fields(extract_input_fields(response.body)),
+        )
     }
 
     fn random_delay(&self) -> Duration {
-        if self.delay_max <= self.delay_min {
-            return self.delay_min;
-        }
-        let mut rng = rand::thread_rng();
-        let min = self.delay_min.as_secs_f32();
-        let max = self.delay_max.as_secs_f32();
-        Duration::from_secs_f32(rng.gen_range(min..max))
+        self.rng.duration_between(self.delay_min, self.delay_max)
     }
 
     fn extract_challenge_info(body: &str) -> Result<ChallengeInfo, ManagedV3Error> {
@@ -237,6 +270,24 @@ impl ManagedV3Solver {
         })
     }
 
+    /// Fields `parse_challenge` reports for a v3 page.
+    pub(crate) fn extract_fields(
+        body: &str,
+    ) -> Result<Vec<(&'static str, String)>, ManagedV3Error> {
+        let info = Self::extract_challenge_info(body)?;
+        let mut fields = vec![("form_action", info.form_action)];
+        if let Some(cv_id) = info.ctx_data.cv_id.or(info.opt_data.cv_id) {
+            fields.push(("cv_id", cv_id));
+        }
+        if let Some(page_data) = info.ctx_data.chl_page_data.or(info.opt_data.chl_page_data) {
+            fields.push(("chl_page_data", page_data));
+        }
+        if let Some(script) = info.vm_script {
+            fields.push(("vm_script", script));
+        }
+        Ok(fields)
+    }
+
     fn extract_json_block(body: &str, marker: &str) -> Result<Option<String>, ManagedV3Error> {
         let start = match body.find(marker) {
             Some(idx) => idx,
@@ -252,7 +303,10 @@ impl ManagedV3Solver {
         let mut in_string = false;
         let mut escape = false;
 
-        for (offset, ch) in body[brace_start..].char_indices() {
+        for (offset, ch) in body[brace_start..]
+            .char_indices()
+            .take_while(|(offset, _)| *offset < MAX_JSON_BLOCK_BYTES)
+        {
             if in_string {
                 if escape {
                     escape = false;
@@ -295,7 +349,7 @@ impl ManagedV3Solver {
     fn extract_vm_script(body: &str) -> Option<String> {
         let enter_idx = body.find("window._cf_chl_enter")?;
         let script_open = body[..enter_idx].rfind("<script")?;
-        let content_start = body[script_open..].find('>')? + script_open + 1;
+        let content_start = body[script_open..enter_idx].find('>')? + script_open + 1;
         let script_close = body[enter_idx..].find("</script>")? + enter_idx;
         Some(body[content_start..script_close].trim().to_string())
     }
@@ -314,12 +368,9 @@ impl ManagedV3Solver {
         payload.insert("r".into(), r_token);
         payload.insert("jschl_answer".into(), answer.to_string());
 
-        for caps in INPUT_FIELD_RE.captures_iter(body) {
-            if let (Some(name), Some(value)) = (caps.get(1), caps.get(2)) {
-                let key = name.as_str();
-                if key != "jschl_answer" && !payload.contains_key(key) {
-                    payload.insert(key.to_string(), value.as_str().to_string());
-                }
+        for (name, value) in extract_input_fields(body) {
+            if name != "jschl_answer" {
+                payload.entry(name).or_insert(value);
             }
         }
 
@@ -340,6 +391,8 @@ struct ChallengeJson {
     #[serde(rename = "chlPageData")]
     chl_page_data: Option<String>,
     #[serde(flatten)]
+    fields: ChallengeOptFields,
+    #[serde(flatten)]
     extra: serde_json::Value,
 }
 
@@ -406,14 +459,6 @@ static R_TOKEN_RE: Lazy<Regex> = Lazy::new(|| {
         .expect("invalid v3 r token regex")
 });
 
-static INPUT_FIELD_RE: Lazy<Regex> = Lazy::new(|| {
-    RegexBuilder::new(r#"<input[^>]*name=['"]([^'"]+)['"][^>]*value=['"]([^'"]*)['"]"#)
-        .case_insensitive(true)
-        .dot_matches_new_line(true)
-        .build()
-        .expect("invalid v3 input regex")
-});
-
 fn hash_str(input: &str) -> u64 {
     use std::collections::hash_map::DefaultHasher;
     use std::hash::{Hash, Hasher};
@@ -509,25 +554,55 @@ mod tests {
         )
     }
 
-    #[test]
-    fn solve_uses_vm_answer() {
+    #[tokio::test]
+    async fn solve_uses_vm_answer() {
         let html = sample_html(true);
         let fixture = ResponseFixture::new(&html, 403);
         let solver = ManagedV3Solver::new(Arc::new(StubInterpreter));
         assert!(ManagedV3Solver::is_challenge(&fixture.response()));
-        let submission = solver.solve(&fixture.response()).expect("should solve");
+        let submission = solver
+            .solve(&fixture.response())
+            .await
+            .expect("should solve");
         assert_eq!(
             submission.form_fields.get("jschl_answer"),
             Some(&"987654".to_string())
         );
     }
 
-    #[test]
-    fn fallback_when_no_vm() {
+    #[tokio::test]
+    async fn fallback_when_no_vm() {
         let html = sample_html(false);
         let fixture = ResponseFixture::new(&html, 403);
         let solver = ManagedV3Solver::new(Arc::new(StubInterpreter));
-        let submission = solver.solve(&fixture.response()).expect("fallback works");
+        let submission = solver
+            .solve(&fixture.response())
+            .await
+            .expect("fallback works");
         assert!(submission.form_fields.contains_key("jschl_answer"));
     }
+
+    #[tokio::test]
+    async fn payload_echoes_challenge_options() {
+        let html = sample_html(false).replace(
+            r#"{"chlPageData":"page-data"}"#,
+            r#"{"cType":"managed","cNounce":"82261","cRay":"8a1b2c3d4e5f6a7b","cHash":"d2f0c1e5a9b8","md":"Zm9vYmFy.MTIz","cFPWv":"g","chlPageData":"page-data"}"#,
+        );
+        let fixture = ResponseFixture::new(&html, 403);
+        let solver = ManagedV3Solver::new(Arc::new(StubInterpreter));
+        let submission = solver
+            .solve(&fixture.response())
+            .await
+            .expect("should solve");
+
+        let fields = &submission.form_fields;
+        assert_eq!(fields["cRay"], "8a1b2c3d4e5f6a7b");
+        assert_eq!(fields["cHash"], "d2f0c1e5a9b8");
+        assert_eq!(fields["cType"], "managed");
+        assert_eq!(fields["cFPWv"], "g");
+
+        let info = ManagedV3Solver::extract_challenge_info(&html).unwrap();
+        let opt = serde_json::to_string(&info.opt_data).unwrap();
+        assert_eq!(opt.matches("cRay").count(), 1);
+    }
 }
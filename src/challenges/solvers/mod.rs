@@ -7,12 +7,21 @@ pub mod bot_management;
 pub mod javascript_v1;
 pub mod javascript_v2;
 pub mod managed_v3;
+pub mod origin_error;
 pub mod rate_limit;
 pub mod turnstile;
 
 use std::collections::HashMap;
+use std::sync::Mutex;
 use std::time::Duration;
 
+use chrono::{DateTime, Utc};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::challenges::detectors::ChallengeType;
+
 /// Common solver interface to be implemented once logic is ported.
 pub trait ChallengeSolver {
     fn name(&self) -> &'static str;
@@ -21,6 +30,57 @@ pub trait ChallengeSolver {
 /// Records domain-level mitigation failures without depending on the full state manager.
 pub trait FailureRecorder {
     fn record_failure(&self, domain: &str, reason: &str);
+
+    /// Record a failure with its context; recorders that only keep reasons
+    /// get `context.reason`.
+    fn record_failure_detailed(&self, domain: &str, context: FailureContext) {
+        self.record_failure(domain, &context.reason);
+    }
+}
+
+/// What is known about a failure when it is recorded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FailureContext {
+    pub reason: String,
+    pub status: Option<u16>,
+    pub challenge_type: Option<ChallengeType>,
+    pub proxy: Option<String>,
+    /// The failure was a captcha that could not be solved.
+    pub captcha_failure: bool,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl FailureContext {
+    pub fn new(reason: impl Into<String>) -> Self {
+        Self {
+            reason: reason.into(),
+            status: None,
+            challenge_type: None,
+            proxy: None,
+            captcha_failure: false,
+            timestamp: Utc::now(),
+        }
+    }
+
+    pub fn with_status(mut self, status: u16) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    pub fn with_challenge_type(mut self, challenge_type: ChallengeType) -> Self {
+        self.challenge_type = Some(challenge_type);
+        self
+    }
+
+    pub fn with_proxy(mut self, proxy: impl Into<String>) -> Self {
+        self.proxy = Some(proxy.into());
+        self
+    }
+
+    pub fn captcha_failure(mut self) -> Self {
+        self.captcha_failure = true;
+        self
+    }
 }
 
 /// Provides fingerprint invalidation semantics for mitigation strategies.
@@ -89,6 +149,142 @@ impl MitigationPlan {
     }
 }
 
+/// Random source behind a solver's delays; entropy-seeded unless the solver
+/// is given a generator through `with_rng`.
+pub(crate) struct SolverRng(Mutex<StdRng>);
+
+impl SolverRng {
+    pub(crate) fn new(rng: StdRng) -> Self {
+        Self(Mutex::new(rng))
+    }
+
+    pub(crate) fn from_entropy() -> Self {
+        Self::new(StdRng::from_entropy())
+    }
+
+    pub(crate) fn with<R>(&self, f: impl FnOnce(&mut StdRng) -> R) -> R {
+        f(&mut self.0.lock().expect("solver rng poisoned"))
+    }
+
+    /// Uniform duration in `[min, max]`, or `min` for an empty range.
+    pub(crate) fn duration_between(&self, min: Duration, max: Duration) -> Duration {
+        if max <= min {
+            return min;
+        }
+        self.with(|rng| rng.gen_range(min..=max))
+    }
+
+    /// Seed for a generator handed to code outside the solver, such as a
+    /// captcha provider's poll jitter.
+    pub(crate) fn seed(&self) -> u64 {
+        self.with(|rng| rng.r#gen())
+    }
+}
+
+/// Wait before submitting a captcha answer so the time from page receipt to
+/// submission lands on a target drawn from `[min, max]`. Time already spent
+/// solving counts toward the target.
+pub(crate) fn interaction_wait(
+    rng: &SolverRng,
+    min: Duration,
+    max: Duration,
+    elapsed: Duration,
+) -> Duration {
+    rng.duration_between(min, max).saturating_sub(elapsed)
+}
+
+/// Submission fields carried by the `_cf_chl_opt` object of v2 and managed
+/// v3 pages, echoed back under their original names.
+///
+/// | field     | v2 | v3 | meaning                          |
+/// |-----------|----|----|----------------------------------|
+/// | `cRay`    | ✓  | ✓  | ray id the challenge was issued for |
+/// | `cHash`   | ✓  | ✓  | challenge integrity hash         |
+/// | `md`      | ✓  | ✓  | opaque orchestration metadata    |
+/// | `cNounce` | ✓  | ✓  | per-challenge nonce              |
+/// | `cType`   |    | ✓  | `managed`, `interactive`, …      |
+/// | `cFPWv`   |    | ✓  | fingerprint widget version       |
+///
+/// `cvId` and `chlPageData` stay with each solver; they are sent under
+/// solver-specific names. Hidden form inputs with the same name win.
+///
+/// Some pages also say how long the browser must wait before submitting;
+/// answering sooner is rejected as an invalid answer. We assume v2 pages
+/// carry it in `cMinWait` and managed v3 pages in `chlApiMinWait`, both in
+/// seconds and both in `_cf_chl_opt`; only a hand-written fixture backs
+/// either name so far. It is read into `min_wait` and never echoed back.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub(crate) struct ChallengeOptFields {
+    #[serde(rename = "cType", default, deserialize_with = "lenient_string")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub c_type: Option<String>,
+    #[serde(rename = "cNounce", default, deserialize_with = "lenient_string")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub c_nounce: Option<String>,
+    #[serde(rename = "cRay", default, deserialize_with = "lenient_string")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub c_ray: Option<String>,
+    #[serde(rename = "cHash", default, deserialize_with = "lenient_string")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub c_hash: Option<String>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub md: Option<String>,
+    #[serde(rename = "cFPWv", default, deserialize_with = "lenient_string")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub c_fpwv: Option<String>,
+    #[serde(rename = "cMinWait", alias = "chlApiMinWait", default)]
+    #[serde(deserialize_with = "lenient_seconds", skip_serializing)]
+    pub min_wait: Option<Duration>,
+}
+
+impl ChallengeOptFields {
+    /// Add the fields a v2 (`managed == false`) or v3 submission expects.
+    pub(crate) fn fill_payload(&self, payload: &mut HashMap<String, String>, managed: bool) {
+        let mut fields = vec![
+            ("cRay", &self.c_ray),
+            ("cHash", &self.c_hash),
+            ("md", &self.md),
+            ("cNounce", &self.c_nounce),
+        ];
+        if managed {
+            fields.extend([("cType", &self.c_type), ("cFPWv", &self.c_fpwv)]);
+        }
+        for (name, value) in fields {
+            if let Some(value) = value {
+                payload
+                    .entry(name.to_string())
+                    .or_insert_with(|| value.clone());
+            }
+        }
+    }
+}
+
+/// Strings as-is and numbers in decimal; anything else is treated as absent.
+fn lenient_string<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    Ok(
+        match Option::<serde_json::Value>::deserialize(deserializer)? {
+            Some(serde_json::Value::String(value)) => Some(value),
+            Some(serde_json::Value::Number(value)) => Some(value.to_string()),
+            _ => None,
+        },
+    )
+}
+
+/// Non-negative seconds, as a number or numeric string.
+fn lenient_seconds<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error> {
+    let seconds = match Option::<serde_json::Value>::deserialize(deserializer)? {
+        Some(serde_json::Value::Number(value)) => value.as_f64(),
+        Some(serde_json::Value::String(value)) => value.trim().parse().ok(),
+        _ => None,
+    };
+    Ok(seconds
+        .filter(|seconds: &f64| seconds.is_finite() && *seconds >= 0.0)
+        .map(Duration::from_secs_f64))
+}
+
 /// Helper enum referencing all solver variants.
 #[allow(dead_code)]
 pub enum SolverVariant {
@@ -0,0 +1,158 @@
+//! Handler for Cloudflare origin errors (HTTP 520-526).
+//!
+//! These are not challenges: Cloudflare could not get a usable answer from
+//! the origin. Retrying at once is pointless and another proxy would not
+//! help, so the plan is a delayed retry through the same route.
+
+use std::time::Duration;
+
+use chrono::Utc;
+use rand::rngs::StdRng;
+use thiserror::Error;
+
+use crate::challenges::core::{ChallengeResponse, is_cloudflare_response, retry_after};
+use crate::challenges::detectors::ChallengeType;
+
+use super::{ChallengeSolver, FailureContext, FailureRecorder, MitigationPlan, SolverRng};
+
+const DEFAULT_DELAY_MIN_SECS: u64 = 15;
+const DEFAULT_DELAY_MAX_SECS: u64 = 45;
+
+/// Advises backoff windows for 52x origin errors.
+pub struct OriginErrorHandler {
+    delay_min: Duration,
+    delay_max: Duration,
+    rng: SolverRng,
+}
+
+impl OriginErrorHandler {
+    pub fn new() -> Self {
+        Self {
+            delay_min: Duration::from_secs(DEFAULT_DELAY_MIN_SECS),
+            delay_max: Duration::from_secs(DEFAULT_DELAY_MAX_SECS),
+            rng: SolverRng::from_entropy(),
+        }
+    }
+
+    pub fn with_delay_range(mut self, min: Duration, max: Duration) -> Self {
+        self.delay_min = min;
+        self.delay_max = if max < min { min } else { max };
+        self
+    }
+
+    /// Draw delays from `rng` instead of an entropy-seeded generator.
+    pub fn with_rng(mut self, rng: StdRng) -> Self {
+        self.rng = SolverRng::new(rng);
+        self
+    }
+
+    pub fn is_origin_error(response: &ChallengeResponse<'_>) -> bool {
+        is_cloudflare_response(response) && origin_error_name(response.status).is_some()
+    }
+
+    pub fn plan(
+        &self,
+        response: &ChallengeResponse<'_>,
+        state_recorder: Option<&dyn FailureRecorder>,
+    ) -> Result<MitigationPlan, OriginErrorError> {
+        let name = origin_error_name(response.status)
+            .filter(|_| is_cloudflare_response(response))
+            .ok_or(OriginErrorError::NotOriginError)?;
+
+        if let Some(recorder) = state_recorder
+            && let Some(domain) = response.url.host_str()
+        {
+            recorder.record_failure_detailed(
+                domain,
+                FailureContext::new(format!("cf_{}", response.status))
+                    .with_status(response.status)
+                    .with_challenge_type(ChallengeType::OriginError),
+            );
+        }
+
+        let (delay, source) = match retry_after(response.headers, Utc::now()) {
+            Some(delay) => (delay, "header"),
+            None => (
+                self.rng.duration_between(self.delay_min, self.delay_max),
+                "default",
+            ),
+        };
+        Ok(MitigationPlan::retry_after(delay, "origin_error")
+            .insert_metadata("origin_status", response.status.to_string())
+            .insert_metadata("origin_error", name)
+            .insert_metadata("delay_source", source))
+    }
+}
+
+impl Default for OriginErrorHandler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ChallengeSolver for OriginErrorHandler {
+    fn name(&self) -> &'static str {
+        "origin_error"
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum OriginErrorError {
+    #[error("response is not a Cloudflare origin error")]
+    NotOriginError,
+}
+
+/// Cloudflare's name for a 52x status.
+pub fn origin_error_name(status: u16) -> Option<&'static str> {
+    Some(match status {
+        520 => "unknown_error",
+        521 => "web_server_down",
+        522 => "connection_timed_out",
+        523 => "origin_unreachable",
+        524 => "timeout_occurred",
+        525 => "ssl_handshake_failed",
+        526 => "invalid_ssl_certificate",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::header::{RETRY_AFTER, SERVER};
+    use http::{HeaderMap, HeaderValue, Method};
+    use url::Url;
+
+    fn plan_for(status: u16, headers: &mut HeaderMap) -> Result<MitigationPlan, OriginErrorError> {
+        headers.insert(SERVER, HeaderValue::from_static("cloudflare"));
+        let url = Url::parse("https://example.com/").unwrap();
+        let response = ChallengeResponse {
+            url: &url,
+            status,
+            headers,
+            body: "<title>example.com | 52x: origin error</title>",
+            request_method: &Method::GET,
+        };
+        OriginErrorHandler::new()
+            .with_delay_range(Duration::from_secs(15), Duration::from_secs(45))
+            .plan(&response, None)
+    }
+
+    #[test]
+    fn origin_errors_wait_without_rotating() {
+        let plan = plan_for(521, &mut HeaderMap::new()).unwrap();
+        assert!(plan.should_retry);
+        assert!(plan.new_proxy.is_none());
+        let wait = plan.wait.unwrap();
+        assert!((Duration::from_secs(15)..=Duration::from_secs(45)).contains(&wait));
+        assert_eq!(plan.metadata["origin_error"], "web_server_down");
+
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("7"));
+        let plan = plan_for(524, &mut headers).unwrap();
+        assert_eq!(plan.wait, Some(Duration::from_secs(7)));
+        assert_eq!(plan.metadata["origin_status"], "524");
+
+        assert!(plan_for(502, &mut HeaderMap::new()).is_err());
+    }
+}
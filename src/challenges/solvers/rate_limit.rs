@@ -5,15 +5,16 @@
 
 use std::time::Duration;
 
-use chrono::{DateTime, Utc};
+use chrono::Utc;
 use once_cell::sync::Lazy;
-use rand::Rng;
+use rand::rngs::StdRng;
 use regex::{Regex, RegexBuilder};
 use thiserror::Error;
 
-use crate::challenges::core::{ChallengeResponse, is_cloudflare_response};
+use crate::challenges::core::{ChallengeResponse, is_cloudflare_response, retry_after};
+use crate::challenges::detectors::ChallengeType;
 
-use super::{ChallengeSolver, FailureRecorder, MitigationPlan};
+use super::{ChallengeSolver, FailureContext, FailureRecorder, MitigationPlan, SolverRng};
 
 const DEFAULT_DELAY_MIN_SECS: f32 = 60.0;
 const DEFAULT_DELAY_MAX_SECS: f32 = 180.0;
@@ -22,6 +23,7 @@ const DEFAULT_DELAY_MAX_SECS: f32 = 180.0;
 pub struct RateLimitHandler {
     delay_min: Duration,
     delay_max: Duration,
+    rng: SolverRng,
 }
 
 impl RateLimitHandler {
@@ -29,6 +31,7 @@ impl RateLimitHandler {
         Self {
             delay_min: Duration::from_secs_f32(DEFAULT_DELAY_MIN_SECS),
             delay_max: Duration::from_secs_f32(DEFAULT_DELAY_MAX_SECS),
+            rng: SolverRng::from_entropy(),
         }
     }
 
@@ -38,6 +41,12 @@ impl RateLimitHandler {
         self
     }
 
+    /// Draw delays from `rng` instead of an entropy-seeded generator.
+    pub fn with_rng(mut self, rng: StdRng) -> Self {
+        self.rng = SolverRng::new(rng);
+        self
+    }
+
     pub fn is_rate_limited(response: &ChallengeResponse<'_>) -> bool {
         is_cloudflare_response(response)
             && response.status == 429
@@ -56,7 +65,12 @@ impl RateLimitHandler {
         if let Some(recorder) = state_recorder
             && let Some(domain) = response.url.host_str()
         {
-            recorder.record_failure(domain, "cf_rate_limit");
+            recorder.record_failure_detailed(
+                domain,
+                FailureContext::new("cf_rate_limit")
+                    .with_status(response.status)
+                    .with_challenge_type(ChallengeType::RateLimit),
+            );
         }
 
         let (delay, source) = self.determine_delay(response);
@@ -68,7 +82,7 @@ impl RateLimitHandler {
     }
 
     fn determine_delay(&self, response: &ChallengeResponse<'_>) -> (Duration, String) {
-        if let Some(delay) = self.retry_after_header(response) {
+        if let Some(delay) = retry_after(response.headers, Utc::now()) {
             return (delay, "header".into());
         }
 
@@ -79,27 +93,6 @@ impl RateLimitHandler {
         (self.random_delay(), "default".into())
     }
 
-    fn retry_after_header(&self, response: &ChallengeResponse<'_>) -> Option<Duration> {
-        use http::header::RETRY_AFTER;
-
-        let raw = response.headers.get(RETRY_AFTER)?.to_str().ok()?;
-        if let Ok(seconds) = raw.trim().parse::<f64>()
-            && seconds.is_finite()
-            && seconds >= 0.0
-        {
-            return Some(Duration::from_secs_f64(seconds));
-        }
-
-        if let Ok(date) = DateTime::parse_from_rfc2822(raw.trim())
-            .or_else(|_| DateTime::parse_from_rfc3339(raw.trim()))
-            && let Ok(duration) = (date.with_timezone(&Utc) - Utc::now()).to_std()
-        {
-            return Some(duration);
-        }
-
-        None
-    }
-
     fn delay_from_body(&self, body: &str) -> Option<Duration> {
         let caps = RATE_LIMIT_DELAY_RE.captures(body)?;
         let amount: u64 = caps.get(1)?.as_str().parse().ok()?;
@@ -114,13 +107,7 @@ impl RateLimitHandler {
     }
 
     fn random_delay(&self) -> Duration {
-        if self.delay_max <= self.delay_min {
-            return self.delay_min;
-        }
-        let mut rng = rand::thread_rng();
-        let min = self.delay_min.as_secs_f32();
-        let max = self.delay_max.as_secs_f32();
-        Duration::from_secs_f32(rng.gen_range(min..max))
+        self.rng.duration_between(self.delay_min, self.delay_max)
     }
 }
 
@@ -6,22 +6,22 @@
 
 use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use html_escape::decode_html_entities;
 use once_cell::sync::Lazy;
-use rand::Rng;
+use rand::rngs::StdRng;
 use regex::{Regex, RegexBuilder};
 use thiserror::Error;
 
 use crate::challenges::core::{
     ChallengeExecutionError, ChallengeHttpClient, ChallengeHttpResponse, ChallengeResponse,
-    ChallengeSubmission, OriginalRequest, execute_challenge_submission, is_cloudflare_response,
-    origin_from_url,
+    ChallengeSubmission, OriginalRequest, execute_challenge_submission, extract_input_fields,
+    is_cloudflare_response, origin_from_url,
 };
 use crate::external_deps::captcha::{CaptchaError, CaptchaProvider, CaptchaTask};
 
-use super::ChallengeSolver;
+use super::{ChallengeSolver, SolverRng, interaction_wait};
 
 const DEFAULT_DELAY_MIN_SECS: f32 = 1.0;
 const DEFAULT_DELAY_MAX_SECS: f32 = 5.0;
@@ -30,7 +30,9 @@ const DEFAULT_DELAY_MAX_SECS: f32 = 5.0;
 pub struct TurnstileSolver {
     delay_min: Duration,
     delay_max: Duration,
+    interaction_window: Option<(Duration, Duration)>,
     captcha_provider: Option<Arc<dyn CaptchaProvider>>,
+    rng: SolverRng,
 }
 
 impl TurnstileSolver {
@@ -39,7 +41,9 @@ impl TurnstileSolver {
         Self {
             delay_min: Duration::from_secs_f32(DEFAULT_DELAY_MIN_SECS),
             delay_max: Duration::from_secs_f32(DEFAULT_DELAY_MAX_SECS),
+            interaction_window: None,
             captcha_provider: None,
+            rng: SolverRng::from_entropy(),
         }
     }
 
@@ -50,6 +54,19 @@ impl TurnstileSolver {
         self
     }
 
+    /// Draw delays from `rng` instead of an entropy-seeded generator.
+    pub fn with_rng(mut self, rng: StdRng) -> Self {
+        self.rng = SolverRng::new(rng);
+        self
+    }
+
+    /// Target time from receiving the page to submitting the token, captcha
+    /// solve included. Defaults to the delay range.
+    pub fn with_interaction_window(mut self, min: Duration, max: Duration) -> Self {
+        self.interaction_window = Some((min, max.max(min)));
+        self
+    }
+
     /// Attach a captcha provider used to solve Turnstile tokens.
     pub fn with_captcha_provider(mut self, provider: Arc<dyn CaptchaProvider>) -> Self {
         self.captcha_provider = Some(provider);
@@ -66,6 +83,10 @@ impl TurnstileSolver {
         self.captcha_provider = None;
     }
 
+    pub fn has_captcha_provider(&self) -> bool {
+        self.captcha_provider.is_some()
+    }
+
     /// Returns `true` when the response resembles a Turnstile challenge page.
     pub fn is_turnstile_challenge(response: &ChallengeResponse<'_>) -> bool {
         is_cloudflare_response(response)
@@ -83,6 +104,7 @@ impl TurnstileSolver {
         if !Self::is_turnstile_challenge(response) {
             return Err(TurnstileError::NotTurnstileChallenge);
         }
+        let received = Instant::now();
 
         let provider = self
             .captcha_provider
@@ -90,15 +112,18 @@ impl TurnstileSolver {
             .ok_or(TurnstileError::CaptchaProviderMissing)?;
 
         let info = Self::extract_turnstile_info(response)?;
-        let task =
-            CaptchaTask::new(info.site_key.clone(), response.url.clone()).with_action("turnstile");
         let solution = provider
-            .solve(&task)
+            .solve(&self.captcha_task(response, &info))
             .await
             .map_err(TurnstileError::Captcha)?;
 
         let payload = Self::build_payload(response.body, solution.token);
-        self.build_submission(response, &info.form_action, payload)
+        let (min, max) = self
+            .interaction_window
+            .unwrap_or((self.delay_min, self.delay_max));
+        let wait = interaction_wait(&self.rng, min, max, received.elapsed());
+        self.build_submission(response, &info.form_action, payload, wait)
+            .map(|submission| submission.with_token_ttl(solution.expires_in))
     }
 
     /// Solve and submit the challenge using the supplied HTTP client.
@@ -114,11 +139,24 @@ impl TurnstileSolver {
             .map_err(TurnstileError::Submission)
     }
 
+    /// Captcha task for the widget, carrying its `data-action` (falling back
+    /// to `"turnstile"`) and `data-cdata` when present.
+    fn captcha_task(&self, response: &ChallengeResponse<'_>, info: &TurnstileInfo) -> CaptchaTask {
+        let task = CaptchaTask::new(info.site_key.clone(), response.url.clone())
+            .with_action(info.action.as_deref().unwrap_or("turnstile"))
+            .with_poll_seed(self.rng.seed());
+        match &info.c_data {
+            Some(c_data) => task.with_c_data(c_data.clone()),
+            None => task,
+        }
+    }
+
     fn build_submission(
         &self,
         response: &ChallengeResponse<'_>,
         form_action: &str,
         mut payload: HashMap<String, String>,
+        wait: Duration,
     ) -> Result<ChallengeSubmission, TurnstileError> {
         let form_action = decode_html_entities(form_action).into_owned();
         let target_url = response
@@ -134,26 +172,30 @@ impl TurnstileSolver {
         headers.insert("Referer".into(), response.url.as_str().to_string());
         headers.insert("Origin".into(), origin_from_url(response.url));
 
-        let wait = self.random_delay();
         payload.entry("cf-turnstile-response".into()).or_default();
 
-        Ok(ChallengeSubmission::new(
-            http::Method::POST,
-            target_url,
-            payload,
-            headers,
-            wait,
-        ))
+        Ok(
+            ChallengeSubmission::new(http::Method::POST, target_url, payload, headers, wait)
+                .with_ordered_fields(extract_input_fields(response.body)),
+        )
     }
 
-    fn random_delay(&self) -> Duration {
-        if self.delay_max <= self.delay_min {
-            return self.delay_min;
+    /// Fields `parse_challenge` reports for a Turnstile page.
+    pub(crate) fn extract_fields(
+        response: &ChallengeResponse<'_>,
+    ) -> Result<Vec<(&'static str, String)>, TurnstileError> {
+        let info = Self::extract_turnstile_info(response)?;
+        let mut fields = vec![
+            ("site_key", info.site_key),
+            ("form_action", info.form_action),
+        ];
+        if let Some(action) = info.action {
+            fields.push(("action", action));
+        }
+        if let Some(c_data) = info.c_data {
+            fields.push(("c_data", c_data));
         }
-        let mut rng = rand::thread_rng();
-        let min = self.delay_min.as_secs_f32();
-        let max = self.delay_max.as_secs_f32();
-        Duration::from_secs_f32(rng.gen_range(min..max))
+        Ok(fields)
     }
 
     fn extract_turnstile_info(
@@ -172,9 +214,19 @@ impl TurnstileSolver {
             .map(|m| m.as_str().to_string())
             .unwrap_or_else(|| response.url.as_str().to_string());
 
+        let widget = TURNSTILE_WIDGET_TAG_RE.find(body).map(|m| m.as_str());
+        let widget_attr = |re: &Regex| {
+            widget
+                .and_then(|tag| re.captures(tag))
+                .and_then(|caps| caps.get(1))
+                .map(|m| decode_html_entities(m.as_str()).into_owned())
+        };
+
         Ok(TurnstileInfo {
             site_key,
             form_action,
+            action: widget_attr(&WIDGET_ACTION_RE),
+            c_data: widget_attr(&WIDGET_CDATA_RE),
         })
     }
 
@@ -182,12 +234,9 @@ impl TurnstileSolver {
         let mut payload = HashMap::new();
         payload.insert("cf-turnstile-response".into(), token);
 
-        for caps in INPUT_FIELD_RE.captures_iter(body) {
-            if let (Some(name), Some(value)) = (caps.get(1), caps.get(2)) {
-                let key = name.as_str();
-                if key != "cf-turnstile-response" && !payload.contains_key(key) {
-                    payload.insert(key.to_string(), value.as_str().to_string());
-                }
+        for (name, value) in extract_input_fields(body) {
+            if name != "cf-turnstile-response" {
+                payload.entry(name).or_insert(value);
             }
         }
 
@@ -210,6 +259,8 @@ impl ChallengeSolver for TurnstileSolver {
 struct TurnstileInfo {
     site_key: String,
     form_action: String,
+    action: Option<String>,
+    c_data: Option<String>,
 }
 
 #[derive(Debug, Error)]
@@ -236,6 +287,27 @@ static TURNSTILE_WIDGET_RE: Lazy<Regex> = Lazy::new(|| {
         .expect("invalid turnstile widget regex")
 });
 
+static TURNSTILE_WIDGET_TAG_RE: Lazy<Regex> = Lazy::new(|| {
+    RegexBuilder::new(r#"<[a-z]+\b[^>]*class=['"][^'"]*cf-turnstile[^'"]*['"][^>]*>"#)
+        .case_insensitive(true)
+        .build()
+        .expect("invalid turnstile widget tag regex")
+});
+
+static WIDGET_ACTION_RE: Lazy<Regex> = Lazy::new(|| {
+    RegexBuilder::new(r#"data-action=['"]([^'"]*)['"]"#)
+        .case_insensitive(true)
+        .build()
+        .expect("invalid turnstile action regex")
+});
+
+static WIDGET_CDATA_RE: Lazy<Regex> = Lazy::new(|| {
+    RegexBuilder::new(r#"data-cdata=['"]([^'"]*)['"]"#)
+        .case_insensitive(true)
+        .build()
+        .expect("invalid turnstile cdata regex")
+});
+
 static TURNSTILE_SCRIPT_RE: Lazy<Regex> = Lazy::new(|| {
     RegexBuilder::new(r#"src=['"]https://challenges\.cloudflare\.com/turnstile/v0/api\.js"#)
         .case_insensitive(true)
@@ -260,14 +332,6 @@ static FORM_ACTION_RE: Lazy<Regex> = Lazy::new(|| {
         .expect("invalid turnstile form action regex")
 });
 
-static INPUT_FIELD_RE: Lazy<Regex> = Lazy::new(|| {
-    RegexBuilder::new(r#"<input[^>]*name=['"]([^'"]+)['"][^>]*value=['"]([^'"]*)['"]"#)
-        .case_insensitive(true)
-        .dot_matches_new_line(true)
-        .build()
-        .expect("invalid input field regex")
-});
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -393,4 +457,80 @@ mod tests {
             .expect_err("should fail");
         assert!(matches!(err, TurnstileError::CaptchaProviderMissing));
     }
+
+    struct RecordingCaptchaProvider(std::sync::Mutex<Option<CaptchaTask>>);
+
+    #[async_trait]
+    impl CaptchaProvider for RecordingCaptchaProvider {
+        fn name(&self) -> &'static str {
+            "recording"
+        }
+
+        async fn solve(&self, task: &CaptchaTask) -> CaptchaResult {
+            *self.0.lock().unwrap() = Some(task.clone());
+            Ok(CaptchaSolution::new("turnstile-token"))
+        }
+    }
+
+    #[tokio::test]
+    async fn forwards_widget_action_and_cdata() {
+        let html = r#"
+            <html><body>
+              <div data-action="login" class="cf-turnstile" data-cdata="sess&#x3D;42"
+                   data-sitekey="ABCDEFGHIJKLMNOPQRSTUVWXYZ1234567890abcd"></div>
+              <script src="https://challenges.cloudflare.com/turnstile/v0/api.js"></script>
+            </body></html>
+        "#;
+        let fixture = ResponseFixture::new(html, 403);
+        let provider = Arc::new(RecordingCaptchaProvider(Default::default()));
+        let solver = TurnstileSolver::new()
+            .with_delay_range(Duration::ZERO, Duration::ZERO)
+            .with_captcha_provider(provider.clone());
+
+        solver.solve(&fixture.response()).await.unwrap();
+
+        let task = provider.0.lock().unwrap().clone().unwrap();
+        assert_eq!(task.action.as_deref(), Some("login"));
+        assert_eq!(task.c_data.as_deref(), Some("sess=42"));
+        assert!(task.poll_seed.is_some());
+    }
+
+    struct SlowCaptchaProvider(Duration);
+
+    #[async_trait]
+    impl CaptchaProvider for SlowCaptchaProvider {
+        fn name(&self) -> &'static str {
+            "slow"
+        }
+
+        async fn solve(&self, _task: &CaptchaTask) -> CaptchaResult {
+            tokio::time::sleep(self.0).await;
+            Ok(CaptchaSolution::new("turnstile-token"))
+        }
+    }
+
+    #[tokio::test]
+    async fn submission_wait_absorbs_captcha_solve_time() {
+        let html = sample_html(true);
+        let fixture = ResponseFixture::new(&html, 403);
+        let window = Duration::from_millis(400);
+        let solve = |solve_time| {
+            TurnstileSolver::new()
+                .with_interaction_window(window, window)
+                .with_captcha_provider(Arc::new(SlowCaptchaProvider(solve_time)))
+        };
+
+        let quick = solve(Duration::from_millis(100))
+            .solve(&fixture.response())
+            .await
+            .unwrap();
+        assert!(quick.wait <= Duration::from_millis(300));
+        assert!(quick.wait >= Duration::from_millis(200));
+
+        let slow = solve(Duration::from_millis(500))
+            .solve(&fixture.response())
+            .await
+            .unwrap();
+        assert_eq!(slow.wait, Duration::ZERO);
+    }
 }
@@ -6,8 +6,8 @@
 //! - Allow custom overrides while falling back to sensible defaults.
 
 use once_cell::sync::Lazy;
+use rand::Rng;
 use rand::seq::SliceRandom;
-use rand::thread_rng;
 use serde::Deserialize;
 use std::borrow::Cow;
 use std::collections::HashMap;
@@ -53,6 +53,10 @@ pub struct UserAgentOptions {
     pub desktop: bool,
     pub mobile: bool,
     pub allow_brotli: bool,
+    /// Fail with [`UserAgentError::ProfileNotFound`] when the platform and
+    /// browser filters match nothing, instead of falling back to any
+    /// permitted profile.
+    pub strict: bool,
 }
 
 impl Default for UserAgentOptions {
@@ -64,6 +68,7 @@ impl Default for UserAgentOptions {
             desktop: true,
             mobile: true,
             allow_brotli: false,
+            strict: false,
         }
     }
 }
@@ -72,6 +77,8 @@ impl Default for UserAgentOptions {
 #[derive(Debug, Clone)]
 pub struct UserAgentProfile {
     pub headers: HashMap<String, String>,
+    /// `headers` in the order the selected browser sends them.
+    pub ordered_headers: Vec<(String, String)>,
     pub cipher_suites: Vec<String>,
 }
 
@@ -111,10 +118,18 @@ static USER_AGENT_MANAGER: Lazy<Result<UserAgentManager, UserAgentError>> = Lazy
 
 /// Retrieve a profile using given options.
 pub fn get_user_agent_profile(opts: UserAgentOptions) -> Result<UserAgentProfile, UserAgentError> {
+    get_user_agent_profile_with_rng(opts, &mut rand::thread_rng())
+}
+
+/// Retrieve a profile, drawing platform, browser and agent from `rng`.
+pub fn get_user_agent_profile_with_rng<R: Rng + ?Sized>(
+    opts: UserAgentOptions,
+    rng: &mut R,
+) -> Result<UserAgentProfile, UserAgentError> {
     let manager = USER_AGENT_MANAGER
         .as_ref()
         .map_err(|err| UserAgentError::InitializationFailure(err.to_string()))?;
-    manager.select_profile(opts)
+    manager.select_profile(opts, rng)
 }
 
 fn permitted_device_kinds(opts: &UserAgentOptions) -> Vec<DeviceKind> {
@@ -129,7 +144,11 @@ fn permitted_device_kinds(opts: &UserAgentOptions) -> Vec<DeviceKind> {
 }
 
 impl UserAgentManager {
-    fn select_profile(&self, opts: UserAgentOptions) -> Result<UserAgentProfile, UserAgentError> {
+    fn select_profile<R: Rng + ?Sized>(
+        &self,
+        opts: UserAgentOptions,
+        rng: &mut R,
+    ) -> Result<UserAgentProfile, UserAgentError> {
         if !opts.desktop && !opts.mobile {
             return Err(UserAgentError::InvalidOptions(
                 "Desktop and mobile cannot both be disabled".into(),
@@ -140,9 +159,36 @@ impl UserAgentManager {
             return self.custom_profile(custom);
         }
 
-        let permitted_kinds = permitted_device_kinds(&opts);
+        match self.filtered_profile(&opts, rng) {
+            Err(UserAgentError::ProfileNotFound)
+                if !opts.strict && (opts.platform.is_some() || opts.browser.is_some()) =>
+            {
+                log::warn!(
+                    "no user-agent profile for platform {:?} and browser {:?}; using any permitted profile",
+                    opts.platform,
+                    opts.browser
+                );
+                self.filtered_profile(
+                    &UserAgentOptions {
+                        platform: None,
+                        browser: None,
+                        ..opts
+                    },
+                    rng,
+                )
+            }
+            result => result,
+        }
+    }
 
-        let platform = self.resolve_platform(&opts, &permitted_kinds)?;
+    fn filtered_profile<R: Rng + ?Sized>(
+        &self,
+        opts: &UserAgentOptions,
+        rng: &mut R,
+    ) -> Result<UserAgentProfile, UserAgentError> {
+        let permitted_kinds = permitted_device_kinds(opts);
+
+        let platform = self.resolve_platform(opts, &permitted_kinds, rng)?;
 
         let filtered = self.collect_profiles(&permitted_kinds, &platform);
 
@@ -150,19 +196,19 @@ impl UserAgentManager {
             return Err(UserAgentError::ProfileNotFound);
         }
 
-        let browser = match opts.browser {
+        let browser = match &opts.browser {
             Some(browser) => {
-                if !filtered.contains_key(&browser) {
-                    return Err(UserAgentError::InvalidOptions(
-                        format!("Browser '{browser}' not available for platform '{platform}'")
-                            .into(),
-                    ));
+                if !filtered.contains_key(browser) {
+                    log::debug!("browser '{browser}' not available for platform '{platform}'");
+                    return Err(UserAgentError::ProfileNotFound);
                 }
-                browser
+                browser.clone()
             }
             None => {
-                let browsers: Vec<String> = filtered.keys().cloned().collect();
-                random_choice(&browsers)
+                // Sorted so a seeded `rng` picks the same browser every run.
+                let mut browsers: Vec<String> = filtered.keys().cloned().collect();
+                browsers.sort();
+                random_choice(rng, &browsers)
             }
         };
 
@@ -174,7 +220,7 @@ impl UserAgentManager {
             return Err(UserAgentError::ProfileNotFound);
         }
 
-        let user_agent = random_choice(agents);
+        let user_agent = random_choice(rng, agents);
         let mut headers = self
             .data
             .headers
@@ -196,6 +242,7 @@ impl UserAgentManager {
             .unwrap_or_default();
 
         Ok(UserAgentProfile {
+            ordered_headers: ordered_headers(&map, &browser),
             headers: map,
             cipher_suites,
         })
@@ -214,12 +261,15 @@ impl UserAgentManager {
                 .unwrap_or_else(default_cipher_suites);
 
             Ok(UserAgentProfile {
+                ordered_headers: ordered_headers(&map, browser),
                 headers: map,
                 cipher_suites,
             })
         } else {
+            let headers = default_headers(&custom);
             Ok(UserAgentProfile {
-                headers: default_headers(&custom),
+                ordered_headers: ordered_headers(&headers, "chrome"),
+                headers,
                 cipher_suites: default_cipher_suites(),
             })
         }
@@ -240,10 +290,11 @@ impl UserAgentManager {
         None
     }
 
-    fn resolve_platform(
+    fn resolve_platform<R: Rng + ?Sized>(
         &self,
         opts: &UserAgentOptions,
         permitted_kinds: &[DeviceKind],
+        rng: &mut R,
     ) -> Result<String, UserAgentError> {
         const VALID: &[&str] = &["linux", "windows", "darwin", "android", "ios"];
 
@@ -271,7 +322,7 @@ impl UserAgentManager {
                     return Err(UserAgentError::ProfileNotFound);
                 }
 
-                Ok(random_choice(&candidates).to_string())
+                Ok(random_choice(rng, &candidates).to_string())
             }
         }
     }
@@ -355,6 +406,37 @@ fn header_profile_to_map(profile: &HeaderProfile) -> HashMap<String, String> {
     map
 }
 
+/// Navigation request header order per browser family.
+const CHROME_HEADER_ORDER: &[&str] =
+    &["User-Agent", "Accept", "Accept-Encoding", "Accept-Language"];
+const FIREFOX_HEADER_ORDER: &[&str] =
+    &["User-Agent", "Accept", "Accept-Language", "Accept-Encoding"];
+
+/// `headers` in `browser`'s canonical order; unknown names follow, sorted.
+fn ordered_headers(headers: &HashMap<String, String>, browser: &str) -> Vec<(String, String)> {
+    let order = if browser.eq_ignore_ascii_case("firefox") {
+        FIREFOX_HEADER_ORDER
+    } else {
+        CHROME_HEADER_ORDER
+    };
+    let mut ordered: Vec<(String, String)> = order
+        .iter()
+        .filter_map(|name| {
+            headers
+                .get(*name)
+                .map(|value| (name.to_string(), value.clone()))
+        })
+        .collect();
+    let mut rest: Vec<(String, String)> = headers
+        .iter()
+        .filter(|(name, _)| !order.contains(&name.as_str()))
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    rest.sort();
+    ordered.extend(rest);
+    ordered
+}
+
 fn strip_brotli(headers: &mut HashMap<String, String>) {
     if let Some(encoding) = headers.get_mut("Accept-Encoding") {
         let filtered = encoding
@@ -367,10 +449,9 @@ fn strip_brotli(headers: &mut HashMap<String, String>) {
     }
 }
 
-fn random_choice<T: Clone>(items: &[T]) -> T {
-    let mut rng = thread_rng();
+fn random_choice<R: Rng + ?Sized, T: Clone>(rng: &mut R, items: &[T]) -> T {
     items
-        .choose(&mut rng)
+        .choose(rng)
         .cloned()
         .expect("random choice on empty slice")
 }
@@ -427,8 +508,66 @@ mod tests {
     #[test]
     fn default_selection_returns_profile() {
         if let Ok(manager) = USER_AGENT_MANAGER.as_ref() {
-            let profile = manager.select_profile(UserAgentOptions::default()).unwrap();
+            let profile = manager
+                .select_profile(UserAgentOptions::default(), &mut rand::thread_rng())
+                .unwrap();
             assert!(profile.headers.contains_key("User-Agent"));
         }
     }
+
+    #[test]
+    fn ordered_headers_follow_browser_canonical_order() {
+        if let Ok(manager) = USER_AGENT_MANAGER.as_ref() {
+            for (browser, expected) in [
+                ("chrome", CHROME_HEADER_ORDER),
+                ("firefox", FIREFOX_HEADER_ORDER),
+            ] {
+                let profile = manager
+                    .select_profile(
+                        UserAgentOptions {
+                            platform: Some("windows".into()),
+                            browser: Some(browser.into()),
+                            ..Default::default()
+                        },
+                        &mut rand::thread_rng(),
+                    )
+                    .unwrap();
+                let names: Vec<&str> = profile
+                    .ordered_headers
+                    .iter()
+                    .map(|(name, _)| name.as_str())
+                    .collect();
+                assert_eq!(names, expected);
+                assert_eq!(profile.ordered_headers[0].1, profile.headers["User-Agent"]);
+            }
+        }
+    }
+
+    #[test]
+    fn impossible_filters_fall_back_unless_strict() {
+        if let Ok(manager) = USER_AGENT_MANAGER.as_ref() {
+            let impossible = UserAgentOptions {
+                platform: Some("ios".into()),
+                browser: Some("no-such-browser".into()),
+                desktop: true,
+                mobile: false,
+                ..Default::default()
+            };
+            let profile = manager
+                .select_profile(impossible.clone(), &mut rand::thread_rng())
+                .unwrap();
+            assert!(profile.headers.contains_key("User-Agent"));
+
+            assert!(matches!(
+                manager.select_profile(
+                    UserAgentOptions {
+                        strict: true,
+                        ..impossible
+                    },
+                    &mut rand::thread_rng(),
+                ),
+                Err(UserAgentError::ProfileNotFound)
+            ));
+        }
+    }
 }
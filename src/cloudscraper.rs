@@ -5,59 +5,154 @@
 //! ergonomic HTTP client capable of transparently handling Cloudflare
 //! defences.
 
-use std::collections::HashMap;
-use std::sync::Arc;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex as StdMutex, Weak};
 use std::time::{Duration, Instant};
 
 use bytes::Bytes;
 use http::{HeaderMap, HeaderName, HeaderValue, Method};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::de::DeserializeOwned;
 use thiserror::Error;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, Semaphore};
 use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
 use url::Url;
+use uuid::Uuid;
 
 use crate::challenges::core::{
-    ChallengeExecutionError, ChallengeHttpClient, ChallengeResponse, ChallengeSubmission,
-    OriginalRequest, ReqwestChallengeHttpClient, execute_challenge_submission,
+    BOT_MANAGEMENT_COOKIE, CLEARANCE_COOKIE, ChallengeExecutionError, ChallengeHttpClient,
+    ChallengeHttpResponse, ChallengeResponse, ChallengeStageSolver, ChallengeSubmission,
+    LearningRate, OriginalRequest, PostClearanceStrategy, ReqwestChallengeHttpClient,
+    bot_management_expiry, clearance_expiry, decode_body, execute_challenge_stages,
+    has_clearance_cookie, is_cloudflare_headers, looks_like_challenge_page, retry_after,
+};
+use crate::challenges::detectors::{
+    ChallengeDetection, ChallengeDetector, ChallengeType, DETECTOR_STORAGE_KEY,
 };
-use crate::challenges::detectors::ChallengeDetection;
 use crate::challenges::pipeline::{
-    ChallengePipeline, ChallengePipelineResult, PipelineContext, PipelineError, UnsupportedReason,
+    Capabilities, ChallengePipeline, ChallengePipelineResult, DynChallengeSolver, PipelineContext,
+    PipelineError, PipelinePrepareResult, UnsupportedReason,
 };
 use crate::challenges::solvers::access_denied::ProxyPool;
 use crate::challenges::solvers::{
-    MitigationPlan, TlsProfileManager, access_denied::AccessDeniedHandler,
+    FailureContext, MitigationPlan, TlsProfileManager, access_denied::AccessDeniedHandler,
     bot_management::BotManagementHandler, javascript_v1::JavascriptV1Solver,
-    javascript_v2::JavascriptV2Solver, managed_v3::ManagedV3Solver, rate_limit::RateLimitHandler,
-    turnstile::TurnstileSolver,
+    javascript_v2::JavascriptV2Solver, managed_v3::ManagedV3Solver,
+    origin_error::OriginErrorHandler, rate_limit::RateLimitHandler, turnstile::TurnstileSolver,
 };
 use crate::challenges::user_agents::{
-    UserAgentError, UserAgentOptions, UserAgentProfile, get_user_agent_profile,
+    UserAgentError, UserAgentOptions, UserAgentProfile, get_user_agent_profile_with_rng,
 };
-use crate::external_deps::captcha::CaptchaProvider;
+use crate::external_deps::captcha::{CaptchaConfig, CaptchaProvider};
 use crate::external_deps::interpreters::{BoaJavascriptInterpreter, JavascriptInterpreter};
 use crate::modules::adaptive_timing::{
-    AdaptiveTimingStrategy, BehaviorProfile, DefaultAdaptiveTiming, RequestKind, TimingOutcome,
-    TimingRequest,
+    AdaptiveTimingStrategy, BehaviorProfile, DefaultAdaptiveTiming, DomainTimingSnapshot,
+    RequestKind, TimingOutcome, TimingRequest,
 };
 use crate::modules::anti_detection::{
-    AntiDetectionContext, AntiDetectionStrategy, DefaultAntiDetection,
+    AntiDetectionConfig, AntiDetectionContext, AntiDetectionStrategy, DefaultAntiDetection,
+};
+use crate::modules::blacklist::{BlacklistKey, IdentityBlacklist};
+use crate::modules::cache::{CacheConfig, ResponseCache};
+use crate::modules::cassette::{
+    self, CassetteError, CassettePlayer, CassetteRecorder, RecordingChallengeClient,
+    ReplayedResponse,
 };
+use crate::modules::concurrency::{AdaptiveConcurrency, AimdConfig};
 use crate::modules::events::{
-    ChallengeEvent, EventDispatcher, LoggingHandler, MetricsHandler, PostResponseEvent,
-    PreRequestEvent, RetryEvent, ScraperEvent,
+    ChallengeEvent, EventDispatcher, EventHandler, LoggingHandler, MetricsHandler,
+    PostResponseEvent, PreRequestEvent, RetryEvent, ScraperEvent, cloudflare_diagnostics,
 };
+use crate::modules::har::HarLog;
 use crate::modules::metrics::MetricsCollector;
-use crate::modules::ml::{FeatureVector, MLOptimizer};
+use crate::modules::ml::{FeatureVector, ML_STORAGE_KEY, MLOptimizer};
 use crate::modules::performance::PerformanceMonitor;
-use crate::modules::proxy::{ProxyConfig, ProxyManager};
-use crate::modules::spoofing::{ConsistencyLevel, FingerprintGenerator};
-use crate::modules::state::StateManager;
-use crate::modules::tls::{DefaultTLSManager, TLSConfig};
+use crate::modules::proxy::{PROXY_STORAGE_KEY, ProxyConfig, ProxyManager};
+use crate::modules::spoofing::{
+    BrowserFingerprint, BrowserType, ConsistencyLevel, FieldDiff, FingerprintGenerator,
+};
+use crate::modules::state::{
+    ChallengeStatus, DomainSeed, STATE_STORAGE_KEY, StateManager, StateScope,
+};
+use crate::modules::storage::{MemoryStorage, StorageBackend, StorageError};
+use crate::modules::tls::{
+    BrowserProfile, DefaultTLSManager, TLSConfig, TlsBackend, TlsVersion,
+    default_profile as default_tls_profile,
+};
+use crate::multipart::MultipartForm;
+use crate::scraper_core::{CoreParts, ScraperCore};
 
 /// Result alias used across the orchestration layer.
 pub type CloudScraperResult<T> = Result<T, CloudScraperError>;
 
+/// Hook that edits outgoing headers after every internal layer has run.
+pub type RequestInterceptor = dyn Fn(&mut HeaderMap, &Url, &Method) + Send + Sync;
+
+/// Hook that inspects each response before challenge evaluation.
+pub type ResponseInterceptor = dyn Fn(&ScraperResponse) -> ResponseAction + Send + Sync;
+
+/// Hook that patches each challenge submission before it is sent.
+pub type SubmissionInterceptor = dyn Fn(&mut ChallengeSubmission) + Send + Sync;
+
+/// Hook that sees the computed pre-request delay and returns the one to wait.
+pub type DelayInterceptor = dyn Fn(&Url, Duration) -> Duration + Send + Sync;
+
+/// What a request fetches, which decides the `Accept` header a browser
+/// would send for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResourceType {
+    /// Top-level navigation; keeps the browser profile's `Accept`.
+    #[default]
+    Document,
+    /// `fetch`/XHR call to a JSON API.
+    Api,
+    Image,
+    Script,
+    Stylesheet,
+}
+
+impl ResourceType {
+    /// `Accept` value for this resource, or `None` to keep the profile's.
+    pub fn accept(self) -> Option<&'static str> {
+        match self {
+            ResourceType::Document => None,
+            ResourceType::Api => Some("application/json, text/plain, */*"),
+            ResourceType::Image => {
+                Some("image/avif,image/webp,image/apng,image/svg+xml,image/*,*/*;q=0.8")
+            }
+            ResourceType::Script => Some("*/*"),
+            ResourceType::Stylesheet => Some("text/css,*/*;q=0.1"),
+        }
+    }
+}
+
+/// Whether requests reuse pooled connections.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConnectionStrategy {
+    /// Keep idle connections per [`CloudScraperConfig::pool_max_idle_per_host`].
+    #[default]
+    Pooled,
+    /// Open a new connection, with a full TLS handshake, for every request,
+    /// like a browser that refuses connection reuse. Expect noticeably lower
+    /// throughput and higher latency, especially through proxies.
+    FreshPerRequest,
+}
+
+/// Decision returned by a [`ResponseInterceptor`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResponseAction {
+    /// Hand the response on to challenge evaluation.
+    Continue,
+    /// Treat the response as blocked and send the request again.
+    Retry,
+}
+
 /// High-level error surfaced by the orchestrator.
 #[derive(Debug, Error)]
 pub enum CloudScraperError {
@@ -79,8 +174,122 @@ pub enum CloudScraperError {
     InvalidHeader(String),
     #[error("mitigation required but retries exhausted: {0:?}")]
     Mitigation(Box<MitigationPlan>),
+    #[error("gave up after {attempts} attempts: {}", challenge_list(.encountered))]
+    ChallengeExhausted {
+        attempts: usize,
+        /// Challenge met on each attempt, in order.
+        encountered: Vec<ChallengeType>,
+        /// Mitigation suggested for the last one.
+        plan: Box<MitigationPlan>,
+    },
     #[error("challenge handling aborted: {0}")]
     Aborted(String),
+    #[error("response deserialization failed: {0}")]
+    Deserialize(#[from] serde_json::Error),
+    #[error("cassette error: {0}")]
+    Cassette(#[from] CassetteError),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("storage error: {0}")]
+    Storage(#[from] StorageError),
+    #[error("request cancelled")]
+    Cancelled,
+    #[error("tls configuration failed: {0}")]
+    Tls(String),
+    #[error("incoherent configuration: {0}")]
+    IncoherentConfig(String),
+    #[error("response interceptor still requested a retry after {attempts} attempts")]
+    InterceptorRetry {
+        attempts: usize,
+        last_response: Box<ScraperResponse>,
+    },
+    #[error("{challenge_type:?} challenge reappeared unchanged on {attempts} consecutive attempts")]
+    ChallengeLoop {
+        challenge_type: ChallengeType,
+        attempts: usize,
+        last_response: Box<ScraperResponse>,
+    },
+    #[error("no untried proxy left after trying {}", .tried.len())]
+    ProxiesExhausted { tried: Vec<String> },
+    #[error("every proxy is blacklisted on {domain}")]
+    ProxiesBlacklisted { domain: String },
+    #[error("no {component} left that is not blacklisted on {domain}")]
+    IdentityBlacklisted {
+        domain: String,
+        component: &'static str,
+    },
+}
+
+fn challenge_list(challenges: &[ChallengeType]) -> String {
+    challenges
+        .iter()
+        .map(|challenge| format!("{challenge:?}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Identity a response was fetched with, so a ban can be traced to the
+/// exact proxy, fingerprint and pacing that triggered it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RequestContext {
+    pub proxy: Option<String>,
+    pub user_agent: Option<String>,
+    /// JA3 hash of the TLS profile assigned to the domain. This is the
+    /// per-domain assignment tracked by the TLS manager, not the handshake
+    /// on the wire, which follows the preferred profile of the pooled
+    /// clients.
+    pub ja3_hash: Option<String>,
+    pub canvas_fingerprint: Option<String>,
+    pub behavior_profile: Option<BehaviorProfile>,
+    /// Pacing delay waited before the request was sent.
+    pub delay: Duration,
+}
+
+/// Identity the scraper currently presents to a domain, from
+/// [`CloudScraper::identity_report`].
+///
+/// Diff the report of a domain that clears against one that is banned to
+/// see what sets them apart.
+#[derive(Debug, Clone, Default)]
+pub struct IdentityReport {
+    pub domain: String,
+    pub proxy: Option<String>,
+    pub fingerprint: Option<BrowserFingerprint>,
+    pub tls_profile: Option<BrowserProfile>,
+}
+
+impl IdentityReport {
+    /// Fields that differ from `other`: the proxy, then fingerprint and TLS
+    /// profile fields, or just their presence when one side has none.
+    pub fn diff(&self, other: &IdentityReport) -> Vec<FieldDiff> {
+        let presence = |present: bool| if present { "present" } else { "none" };
+        let mut diffs = Vec::new();
+        FieldDiff::compare(
+            &mut diffs,
+            "proxy",
+            self.proxy.as_deref().unwrap_or("direct"),
+            other.proxy.as_deref().unwrap_or("direct"),
+        );
+        match (&self.fingerprint, &other.fingerprint) {
+            (Some(left), Some(right)) => diffs.extend(left.diff(right)),
+            (left, right) => FieldDiff::compare(
+                &mut diffs,
+                "fingerprint",
+                presence(left.is_some()),
+                presence(right.is_some()),
+            ),
+        }
+        match (&self.tls_profile, &other.tls_profile) {
+            (Some(left), Some(right)) => diffs.extend(left.diff(right)),
+            (left, right) => FieldDiff::compare(
+                &mut diffs,
+                "tls_profile",
+                presence(left.is_some()),
+                presence(right.is_some()),
+            ),
+        }
+        diffs
+    }
 }
 
 /// Read-only HTTP response returned by the scraper.
@@ -90,6 +299,8 @@ pub struct ScraperResponse {
     headers: HeaderMap,
     body: Bytes,
     url: Url,
+    context: RequestContext,
+    challenge_solved: Option<ChallengeType>,
 }
 
 impl ScraperResponse {
@@ -99,9 +310,32 @@ impl ScraperResponse {
             headers,
             body,
             url,
+            context: RequestContext::default(),
+            challenge_solved: None,
         }
     }
 
+    fn with_challenge_solved(mut self, challenge_type: Option<ChallengeType>) -> Self {
+        self.challenge_solved = challenge_type;
+        self
+    }
+
+    /// Challenge solved on the way to this response, or `None` when the page
+    /// came back clean without one.
+    pub fn challenge_solved(&self) -> Option<ChallengeType> {
+        self.challenge_solved
+    }
+
+    fn with_context(mut self, context: RequestContext) -> Self {
+        self.context = context;
+        self
+    }
+
+    /// Identity the request was sent with; empty for passthrough requests.
+    pub fn context(&self) -> &RequestContext {
+        &self.context
+    }
+
     /// HTTP status code as returned by Cloudflare/target origin.
     pub fn status(&self) -> u16 {
         self.status
@@ -117,15 +351,61 @@ impl ScraperResponse {
         &self.headers
     }
 
+    /// Value of a single header, if present and valid UTF-8.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).and_then(|value| value.to_str().ok())
+    }
+
+    /// `Content-Type` header value.
+    pub fn content_type(&self) -> Option<&str> {
+        self.header("content-type")
+    }
+
+    /// Cloudflare `CF-RAY` identifier, useful when correlating with Cloudflare logs.
+    pub fn cf_ray(&self) -> Option<&str> {
+        self.header("cf-ray")
+    }
+
+    /// `cf-mitigated` header, e.g. `challenge` when Cloudflare answered with
+    /// a challenge instead of the origin's response.
+    pub fn cf_mitigated(&self) -> Option<&str> {
+        self.header("cf-mitigated")
+    }
+
+    /// `cf-cache-status` header, e.g. `HIT` or `DYNAMIC`.
+    pub fn cf_cache_status(&self) -> Option<&str> {
+        self.header("cf-cache-status")
+    }
+
+    /// `Server-Timing` header, which Cloudflare uses for edge timings.
+    pub fn server_timing(&self) -> Option<&str> {
+        self.header("server-timing")
+    }
+
+    /// Whether the response was served by Cloudflare.
+    pub fn is_cloudflare(&self) -> bool {
+        is_cloudflare_headers(&self.headers)
+    }
+
     /// Convenience helper returning the body as UTF-8 text.
     pub async fn text(&self) -> CloudScraperResult<String> {
         Ok(String::from_utf8(self.body.to_vec())?)
     }
 
+    /// Borrow the raw body, e.g. from a synchronous [`ResponseInterceptor`].
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
     /// Raw body bytes.
     pub async fn bytes(&self) -> Bytes {
         self.body.clone()
     }
+
+    /// Deserialize the JSON body into `T`.
+    pub async fn json<T: DeserializeOwned>(&self) -> CloudScraperResult<T> {
+        Ok(serde_json::from_slice(&self.body)?)
+    }
 }
 
 /// Scraper configuration used by the builder.
@@ -136,18 +416,120 @@ pub struct CloudScraperConfig {
     pub proxies: Vec<String>,
     pub proxy_config: ProxyConfig,
     pub enable_metrics: bool,
+    /// Capture every exchange for [`CloudScraper::export_har`].
+    pub enable_har_log: bool,
+    /// Revalidate repeat `GET`s with `ETag` / `Last-Modified`; `None` disables.
+    pub cache: Option<CacheConfig>,
+    /// Requests in flight at once across the whole scraper, retries
+    /// included; `None` leaves them unbounded.
+    pub max_concurrent_requests: Option<usize>,
+    /// Per-domain in-flight limits learned by AIMD; `None` leaves domains
+    /// bounded only by `max_concurrent_requests`.
+    pub adaptive_concurrency: Option<AimdConfig>,
+    /// How long [`CloudScraper::blacklist_identity`] entries last.
+    pub identity_blacklist_ttl: Duration,
     pub enable_performance_monitoring: bool,
     pub enable_tls_fingerprinting: bool,
     pub enable_anti_detection: bool,
+    pub anti_detection_config: AntiDetectionConfig,
     pub enable_spoofing: bool,
     pub enable_adaptive_timing: bool,
+    /// Hard ceiling for adaptive timing delays; `None` keeps the module default.
+    pub max_adaptive_delay: Option<Duration>,
+    /// Opt-in schedule for the state, adaptive timing and ML averages; `None`
+    /// keeps each one's fixed rate.
+    pub learning_rate_schedule: Option<LearningRate>,
     pub enable_ml_optimization: bool,
     pub behavior_profile: BehaviorProfile,
+    /// Step a domain's behaviour profile towards `Research` while it keeps
+    /// challenging, and back after a clean streak.
+    pub auto_profile_escalation: bool,
     pub spoofing_consistency: ConsistencyLevel,
+    /// Browser used for spoofed fingerprints; follows the user agent when
+    /// unset, and a custom one unless alignment is disabled.
+    pub spoofing_browser: Option<BrowserType>,
+    /// Reject user-agent, TLS and spoofing browsers that disagree at build time.
+    pub strict_coherence: bool,
+    /// With a custom user agent, derive the fingerprint platform and TLS
+    /// browser from it, and the spoofed browser unless one is set.
+    pub align_to_custom_user_agent: bool,
+    /// `Accept-Language` values rotated per domain instead of the profile's fixed one.
+    pub accept_languages: Vec<String>,
     pub captcha_provider: Option<Arc<dyn CaptchaProvider>>,
+    pub captcha_config: CaptchaConfig,
+    /// Tried in registration order on pages detected as an unknown challenge.
+    pub custom_solvers: Vec<Arc<dyn DynChallengeSolver>>,
     pub interpreter: Option<Arc<dyn JavascriptInterpreter>>,
     pub tls_config: TLSConfig,
+    pub tls_backend: TlsBackend,
+    /// Idle connections kept per host; each proxy's client has its own pool.
+    pub pool_max_idle_per_host: usize,
+    /// How long an idle pooled connection is kept; `None` keeps it forever.
+    pub pool_idle_timeout: Option<Duration>,
+    /// `FreshPerRequest` overrides the pool size with no idle connections.
+    pub connection_strategy: ConnectionStrategy,
+    /// Origins sent a `HEAD` every `warm_interval` so their pooled
+    /// connections and clearance cookies stay warm between requests.
+    pub warm_domains: Vec<Url>,
+    pub warm_interval: Duration,
     pub max_challenge_attempts: usize,
+    /// Retries, each through a fresh proxy when one is available, after a
+    /// connect failure, or a timeout or dropped connection on an idempotent
+    /// method.
+    pub max_transient_retries: usize,
+    /// Origin statuses retried when no challenge is involved, e.g. a flaky
+    /// `502` behind Cloudflare.
+    pub retry_statuses: HashSet<u16>,
+    pub max_status_retries: usize,
+    /// Wait before the first status retry, doubled for each one after it.
+    /// A `Retry-After` header takes precedence.
+    pub status_retry_backoff: Duration,
+    /// Longest `Retry-After` a status retry waits out; longer ones are cut
+    /// to this.
+    pub max_retry_after: Duration,
+    /// Detection confidence required before spending a captcha solve.
+    pub min_solve_confidence: f32,
+    /// Submissions allowed per challenge when answers are met by a new one.
+    pub max_challenge_stages: usize,
+    /// Request sent after a challenge answer is accepted.
+    pub post_clearance: PostClearanceStrategy,
+    /// A `cf_clearance` cookie this close to expiry is dropped before the
+    /// next request so the challenge is solved again up front.
+    pub clearance_refresh_margin: Duration,
+    /// Whether per-domain state, fingerprints, proxies, pacing and clearance
+    /// are tracked per host or per registrable domain.
+    pub state_scope: StateScope,
+    /// Identical consecutive detections that count as a challenge loop.
+    pub challenge_loop_threshold: usize,
+    pub rotate_user_agent_every: Option<usize>,
+    pub event_handlers: Vec<Arc<dyn EventHandler>>,
+    /// Run in registration order right before each request is sent.
+    pub request_interceptors: Vec<Arc<RequestInterceptor>>,
+    /// Run in registration order on each response; the first `Retry` wins.
+    pub response_interceptors: Vec<Arc<ResponseInterceptor>>,
+    /// Run in registration order on every challenge submission, including
+    /// later stages.
+    pub submission_interceptors: Vec<Arc<SubmissionInterceptor>>,
+    /// Run in registration order on each pre-request delay, each getting the
+    /// previous one's result.
+    pub delay_interceptors: Vec<Arc<DelayInterceptor>>,
+    /// Directory receiving diagnostic dumps of unsupported challenge pages.
+    pub dump_unsupported_to: Option<PathBuf>,
+    /// Detector handed to the challenge pipeline instead of a fresh default.
+    pub detector: Option<ChallengeDetector>,
+    /// Hosts (and their subdomains) sent as plain requests: no pacing,
+    /// stealth headers or challenge handling.
+    pub passthrough_domains: HashSet<String>,
+    /// State pre-loaded per domain, keyed by lowercase host.
+    pub domain_seeds: HashMap<String, DomainSeed>,
+    /// Cassette file receiving every exchange made by the scraper.
+    pub record_to: Option<PathBuf>,
+    /// Cassette file serving responses instead of the network.
+    pub replay_from: Option<PathBuf>,
+    /// Seed for every random choice the scraper makes (user agent,
+    /// fingerprints, header noise, delays, proxy and TLS rotation). `None`
+    /// seeds from the OS.
+    pub deterministic_seed: Option<u64>,
 }
 
 impl Default for CloudScraperConfig {
@@ -158,18 +540,63 @@ impl Default for CloudScraperConfig {
             proxies: Vec::new(),
             proxy_config: ProxyConfig::default(),
             enable_metrics: true,
+            enable_har_log: false,
+            cache: None,
+            max_concurrent_requests: None,
+            adaptive_concurrency: None,
+            identity_blacklist_ttl: DEFAULT_IDENTITY_BLACKLIST_TTL,
             enable_performance_monitoring: true,
             enable_tls_fingerprinting: true,
             enable_anti_detection: true,
+            anti_detection_config: AntiDetectionConfig::default(),
             enable_spoofing: true,
             enable_adaptive_timing: true,
+            max_adaptive_delay: None,
+            learning_rate_schedule: None,
             enable_ml_optimization: true,
             behavior_profile: BehaviorProfile::Casual,
+            auto_profile_escalation: false,
             spoofing_consistency: ConsistencyLevel::Domain,
+            spoofing_browser: None,
+            strict_coherence: false,
+            align_to_custom_user_agent: true,
+            accept_languages: Vec::new(),
             captcha_provider: None,
+            captcha_config: CaptchaConfig::default(),
+            custom_solvers: Vec::new(),
             interpreter: None,
             tls_config: TLSConfig::default(),
+            tls_backend: TlsBackend::default(),
+            pool_max_idle_per_host: DEFAULT_POOL_MAX_IDLE_PER_HOST,
+            pool_idle_timeout: Some(DEFAULT_POOL_IDLE_TIMEOUT),
+            connection_strategy: ConnectionStrategy::default(),
+            warm_domains: Vec::new(),
+            warm_interval: DEFAULT_WARM_INTERVAL,
             max_challenge_attempts: 3,
+            max_transient_retries: 2,
+            retry_statuses: HashSet::new(),
+            max_status_retries: 2,
+            status_retry_backoff: DEFAULT_STATUS_RETRY_BACKOFF,
+            max_retry_after: DEFAULT_MAX_RETRY_AFTER,
+            min_solve_confidence: 0.0,
+            max_challenge_stages: 2,
+            post_clearance: PostClearanceStrategy::default(),
+            clearance_refresh_margin: DEFAULT_CLEARANCE_REFRESH_MARGIN,
+            state_scope: StateScope::default(),
+            challenge_loop_threshold: 3,
+            rotate_user_agent_every: None,
+            event_handlers: Vec::new(),
+            request_interceptors: Vec::new(),
+            response_interceptors: Vec::new(),
+            submission_interceptors: Vec::new(),
+            delay_interceptors: Vec::new(),
+            dump_unsupported_to: None,
+            detector: None,
+            passthrough_domains: HashSet::new(),
+            domain_seeds: HashMap::new(),
+            record_to: None,
+            replay_from: None,
+            deterministic_seed: None,
         }
     }
 }
@@ -210,11 +637,60 @@ impl CloudScraperBuilder {
         self
     }
 
+    /// Retry connect failures up to `retries` times before surfacing them;
+    /// timeouts and dropped connections only for idempotent methods.
+    pub fn with_max_transient_retries(mut self, retries: usize) -> Self {
+        self.config.max_transient_retries = retries;
+        self
+    }
+
+    /// Retry origin responses with one of `statuses` up to `retries` times,
+    /// backing off between attempts. The last response is returned as-is.
+    pub fn with_retry_statuses<I>(mut self, statuses: I, retries: usize) -> Self
+    where
+        I: IntoIterator<Item = u16>,
+    {
+        self.config.retry_statuses = statuses.into_iter().collect();
+        self.config.max_status_retries = retries;
+        self
+    }
+
+    /// Wait before the first status retry; later ones double it.
+    pub fn with_status_retry_backoff(mut self, backoff: Duration) -> Self {
+        self.config.status_retry_backoff = backoff;
+        self
+    }
+
+    /// Cap the `Retry-After` a status retry honours.
+    pub fn with_max_retry_after(mut self, max: Duration) -> Self {
+        self.config.max_retry_after = max;
+        self
+    }
+
+    /// Fail a request with [`CloudScraperError::ProxiesExhausted`] rather than
+    /// rotate it onto more than `proxies` distinct proxies.
+    pub fn with_max_proxies_per_request(mut self, proxies: usize) -> Self {
+        self.config.proxy_config.max_proxies_per_request = Some(proxies.max(1));
+        self
+    }
+
     pub fn with_captcha_provider(mut self, provider: Arc<dyn CaptchaProvider>) -> Self {
         self.config.captcha_provider = Some(provider);
         self
     }
 
+    /// Solve challenges the built-in detector does not recognise; see
+    /// [`ChallengePipeline::with_custom_solver`].
+    pub fn with_custom_solver(mut self, solver: Arc<dyn DynChallengeSolver>) -> Self {
+        self.config.custom_solvers.push(solver);
+        self
+    }
+
+    pub fn with_captcha_config(mut self, config: CaptchaConfig) -> Self {
+        self.config.captcha_config = config;
+        self
+    }
+
     pub fn with_interpreter(mut self, interpreter: Arc<dyn JavascriptInterpreter>) -> Self {
         self.config.interpreter = Some(interpreter);
         self
@@ -240,6 +716,13 @@ impl CloudScraperBuilder {
         self
     }
 
+    /// Tune header randomisation, noise and burst control, e.g. to keep a
+    /// required `Accept-Language` out of the randomizer's reach.
+    pub fn with_anti_detection_config(mut self, config: AntiDetectionConfig) -> Self {
+        self.config.anti_detection_config = config;
+        self
+    }
+
     pub fn disable_spoofing(mut self) -> Self {
         self.config.enable_spoofing = false;
         self
@@ -255,29 +738,330 @@ impl CloudScraperBuilder {
         self
     }
 
+    /// Record a HAR log of every request, retrievable with
+    /// [`CloudScraper::export_har`].
+    pub fn enable_har_log(mut self) -> Self {
+        self.config.enable_har_log = true;
+        self
+    }
+
+    /// Cache revalidatable responses and answer `304 Not Modified` from the
+    /// stored body. Challenge pages are never cached.
+    pub fn enable_cache(mut self, config: CacheConfig) -> Self {
+        self.config.cache = Some(config);
+        self
+    }
+
+    /// Derive all randomness from `seed` so identical runs prepare identical
+    /// requests. Meant for tests against mock servers.
+    pub fn with_deterministic_seed(mut self, seed: u64) -> Self {
+        self.config.deterministic_seed = Some(seed);
+        self
+    }
+
+    /// Allow at most `requests` in flight at once; further calls wait for a
+    /// slot. Applies to every entry point, so concurrently polled futures
+    /// (e.g. `join_all` over many `get`s) share the same budget.
+    pub fn with_max_concurrent_requests(mut self, requests: usize) -> Self {
+        self.config.max_concurrent_requests = Some(requests.max(1));
+        self
+    }
+
+    /// How long blacklisted identity components stay banned on a domain.
+    pub fn with_identity_blacklist_ttl(mut self, ttl: Duration) -> Self {
+        self.config.identity_blacklist_ttl = ttl;
+        self
+    }
+
+    /// Learn each domain's tolerable parallelism: grow its in-flight
+    /// allowance on success and halve it on a rate limit. Allowances are kept
+    /// with the domain state, so [`CloudScraper::save_to`] persists them, and
+    /// reported in [`CloudScraper::metrics`].
+    pub fn enable_adaptive_concurrency(mut self, config: AimdConfig) -> Self {
+        self.config.adaptive_concurrency = Some(config);
+        self
+    }
+
+    /// Never let adaptive timing wait longer than `max_delay` before a request.
+    pub fn with_max_adaptive_delay(mut self, max_delay: Duration) -> Self {
+        self.config.max_adaptive_delay = Some(max_delay);
+        self
+    }
+
+    /// Use `schedule` for every learned average, e.g.
+    /// [`LearningRate::decaying`] to converge quickly on new domains.
+    pub fn with_learning_rate_schedule(mut self, schedule: LearningRate) -> Self {
+        self.config.learning_rate_schedule = Some(schedule);
+        self
+    }
+
     pub fn with_behavior_profile(mut self, profile: BehaviorProfile) -> Self {
         self.config.behavior_profile = profile;
         self
     }
 
+    /// Pace domains that keep challenging with a more cautious profile
+    /// (Focused, then Casual, then Research) until they calm down again.
+    pub fn enable_auto_profile_escalation(mut self) -> Self {
+        self.config.auto_profile_escalation = true;
+        self
+    }
+
     pub fn with_spoofing_consistency(mut self, level: ConsistencyLevel) -> Self {
         self.config.spoofing_consistency = level;
         self
     }
 
+    pub fn with_spoofing_browser(mut self, browser: BrowserType) -> Self {
+        self.config.spoofing_browser = Some(browser);
+        self
+    }
+
+    /// Choose what is requested once a challenge is solved (replay the
+    /// original request by default).
+    pub fn with_post_clearance_strategy(mut self, strategy: PostClearanceStrategy) -> Self {
+        self.config.post_clearance = strategy;
+        self
+    }
+
+    /// Re-solve a domain's challenge once its clearance is within `margin`
+    /// of expiring; `Duration::ZERO` waits for the clearance to lapse.
+    pub fn with_clearance_refresh_margin(mut self, margin: Duration) -> Self {
+        self.config.clearance_refresh_margin = margin;
+        self
+    }
+
+    /// Track per-domain state per host (the default) or share it between
+    /// hosts of one registrable domain.
+    pub fn with_state_scope(mut self, scope: StateScope) -> Self {
+        self.config.state_scope = scope;
+        self
+    }
+
+    /// Rotate `Accept-Language` per domain among `languages`, preferring the
+    /// ones that fit the spoofed fingerprint's timezone.
+    pub fn with_accept_languages<I, S>(mut self, languages: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.accept_languages = languages.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Send requests to these hosts, and their subdomains, as plain requests
+    /// with the base headers, skipping adaptive timing, anti-detection,
+    /// spoofing and challenge detection.
+    pub fn with_passthrough_domains<I, S>(mut self, domains: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.config.passthrough_domains.extend(
+            domains
+                .into_iter()
+                .map(|domain| domain.as_ref().trim_start_matches('.').to_ascii_lowercase()),
+        );
+        self
+    }
+
+    /// Pre-populate `domain`'s state, e.g. to resume an earlier scrape.
+    ///
+    /// Seeded headers are sent as sticky headers from the first request on.
+    /// Repeated calls for the same domain merge.
+    pub fn with_domain_seed(
+        mut self,
+        domain: impl AsRef<str>,
+        cookies: HashMap<String, String>,
+        headers: HashMap<String, String>,
+    ) -> Self {
+        let seed = self
+            .config
+            .domain_seeds
+            .entry(domain.as_ref().to_ascii_lowercase())
+            .or_default();
+        seed.cookies.extend(cookies);
+        seed.headers.extend(headers);
+        self
+    }
+
+    /// Fail [`build`](Self::build) with [`CloudScraperError::IncoherentConfig`]
+    /// when the user agent, TLS profile and spoofing browser disagree.
+    pub fn strict_coherence(mut self) -> Self {
+        self.config.strict_coherence = true;
+        self
+    }
+
+    /// Keep the configured TLS browser, and the templates' platforms, even
+    /// when they contradict a custom user agent. An explicit
+    /// [`with_spoofing_browser`](Self::with_spoofing_browser) is kept either way.
+    pub fn disable_user_agent_alignment(mut self) -> Self {
+        self.config.align_to_custom_user_agent = false;
+        self
+    }
+
     pub fn with_tls_config(mut self, config: TLSConfig) -> Self {
         self.config.tls_config = config;
         self
     }
 
+    /// Choose the TLS stack used for outgoing requests.
+    pub fn with_tls_backend(mut self, backend: TlsBackend) -> Self {
+        self.config.tls_backend = backend;
+        self
+    }
+
+    /// Size the connection pool. Clients are created per proxy, so the limits
+    /// apply to each proxy separately rather than to the scraper as a whole.
+    pub fn with_connection_pool(
+        mut self,
+        max_idle_per_host: usize,
+        idle_timeout: Option<Duration>,
+    ) -> Self {
+        self.config.pool_max_idle_per_host = max_idle_per_host;
+        self.config.pool_idle_timeout = idle_timeout;
+        self
+    }
+
+    /// Reuse pooled connections (the default) or open one per request. Fresh
+    /// connections pay a TCP and TLS handshake on every request.
+    pub fn with_connection_strategy(mut self, strategy: ConnectionStrategy) -> Self {
+        self.config.connection_strategy = strategy;
+        self
+    }
+
+    /// Keep connections to `domains` warm with a periodic `HEAD`, trading a
+    /// little traffic for no handshake on the first request after idle.
+    /// Pings carry the domain's identity and pacing; with proxies configured,
+    /// a domain is only pinged through the proxy it is already bound to.
+    /// Needs a Tokio runtime when the scraper is built.
+    pub fn with_warm_domains(mut self, domains: Vec<Url>) -> Self {
+        self.config.warm_domains = domains;
+        self
+    }
+
+    /// Time between keep-alive pings; keep it below the pool idle timeout.
+    pub fn with_warm_interval(mut self, interval: Duration) -> Self {
+        self.config.warm_interval = interval;
+        self
+    }
+
     pub fn with_max_challenge_attempts(mut self, attempts: usize) -> Self {
         self.config.max_challenge_attempts = attempts.max(1);
         self
     }
 
+    /// Don't invoke the captcha provider for detections below `confidence`;
+    /// such responses fail with [`UnsupportedReason::LowConfidence`].
+    pub fn with_min_solve_confidence(mut self, confidence: f32) -> Self {
+        self.config.min_solve_confidence = confidence;
+        self
+    }
+
+    /// Bound the submissions sent for multi-stage challenges such as the
+    /// two-round managed flow. `1` disables follow-up stages.
+    pub fn with_max_challenge_stages(mut self, stages: usize) -> Self {
+        self.config.max_challenge_stages = stages.max(1);
+        self
+    }
+
+    /// Give up with [`CloudScraperError::ChallengeLoop`] once the same challenge
+    /// has been detected this many times in a row.
+    pub fn with_challenge_loop_threshold(mut self, detections: usize) -> Self {
+        self.config.challenge_loop_threshold = detections.max(2);
+        self
+    }
+
+    /// Re-select the base user-agent profile every `requests` requests.
+    pub fn with_user_agent_rotation(mut self, requests: usize) -> Self {
+        self.config.rotate_user_agent_every = Some(requests.max(1));
+        self
+    }
+
+    /// Write the raw response of every unsupported challenge into `dir`.
+    pub fn with_unsupported_dump_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.config.dump_unsupported_to = Some(dir.into());
+        self
+    }
+
+    /// Start the challenge pipeline from a pre-configured detector.
+    pub fn with_detector(mut self, detector: ChallengeDetector) -> Self {
+        self.config.detector = Some(detector);
+        self
+    }
+
+    /// Record every request/response pair into a cassette at `path`.
+    ///
+    /// The file is written by [`CloudScraper::finish_recording`], or when the
+    /// scraper is dropped.
+    pub fn record_to(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.record_to = Some(path.into());
+        self
+    }
+
+    /// Serve responses from a previously recorded cassette instead of the network.
+    pub fn replay_from(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.replay_from = Some(path.into());
+        self
+    }
+
+    /// Register an additional handler for scraper events.
+    pub fn with_event_handler(mut self, handler: Arc<dyn EventHandler>) -> Self {
+        self.config.event_handlers.push(handler);
+        self
+    }
+
+    /// Adjust headers of every outgoing request, e.g. a per-domain `Referer` or
+    /// a freshly computed bearer token. Interceptors run after the fingerprint
+    /// and anti-detection layers, so nothing overrides what they set.
+    pub fn with_request_interceptor(mut self, interceptor: Box<RequestInterceptor>) -> Self {
+        self.config
+            .request_interceptors
+            .push(Arc::from(interceptor));
+        self
+    }
+
+    /// Inspect every response before challenge evaluation and force a retry
+    /// with [`ResponseAction::Retry`], e.g. for soft blocks served as 200.
+    /// Retries count against `max_challenge_attempts`.
+    pub fn with_response_interceptor(mut self, interceptor: Box<ResponseInterceptor>) -> Self {
+        self.config
+            .response_interceptors
+            .push(Arc::from(interceptor));
+        self
+    }
+
+    /// Edit solved challenge payloads before submission, e.g. to add a form
+    /// field a particular site requires.
+    pub fn with_submission_interceptor(mut self, interceptor: Box<SubmissionInterceptor>) -> Self {
+        self.config
+            .submission_interceptors
+            .push(Arc::from(interceptor));
+        self
+    }
+
+    /// Cap, floor or log the pacing delay right before it is waited out;
+    /// returning [`Duration::ZERO`] skips the wait.
+    pub fn with_delay_interceptor(mut self, interceptor: Box<DelayInterceptor>) -> Self {
+        self.config.delay_interceptors.push(Arc::from(interceptor));
+        self
+    }
+
     pub fn build(self) -> CloudScraperResult<CloudScraper> {
         CloudScraper::with_config(self.config)
     }
+
+    /// Build the detection, solving and pacing stack without its HTTP
+    /// client, for callers that send requests themselves.
+    pub fn build_core(mut self) -> CloudScraperResult<ScraperCore> {
+        let parts = ScraperParts::new(&mut self.config)?;
+        Ok(ScraperCore::new(CoreParts {
+            inner: parts.inner,
+            state: parts.state,
+            state_scope: self.config.state_scope,
+            accept_languages: self.config.accept_languages,
+        }))
+    }
 }
 
 impl Default for CloudScraperBuilder {
@@ -286,25 +1070,113 @@ impl Default for CloudScraperBuilder {
     }
 }
 
+/// Re-runs the scraper's pipeline on submission answers that are
+/// themselves challenges.
+struct PipelineStages<'a> {
+    inner: &'a Mutex<CloudScraperInner>,
+    interceptors: &'a [Arc<SubmissionInterceptor>],
+    user_agent: Option<&'a str>,
+    loop_tracker: StdMutex<&'a mut ChallengeLoopTracker>,
+    loop_threshold: usize,
+}
+
+#[async_trait::async_trait(?Send)]
+impl ChallengeStageSolver for PipelineStages<'_> {
+    async fn next_stage(
+        &self,
+        response: &ChallengeHttpResponse,
+        request_method: &Method,
+    ) -> Option<ChallengeSubmission> {
+        let body = response.text();
+        let challenge_response = ChallengeResponse {
+            url: &response.url,
+            status: response.status,
+            headers: &response.headers,
+            body: &body,
+            request_method,
+        };
+        let mut guard = self.inner.lock().await;
+        match guard
+            .pipeline
+            .evaluate(
+                &challenge_response,
+                PipelineContext {
+                    user_agent: self.user_agent,
+                    ..Default::default()
+                },
+            )
+            .await
+        {
+            ChallengePipelineResult::Submission {
+                detection,
+                mut submission,
+            } => {
+                let mut tracker = self
+                    .loop_tracker
+                    .lock()
+                    .expect("loop tracker lock poisoned");
+                // The looping page ends the flow uncounted; clearance
+                // verification counts it.
+                if tracker.next_count(&detection) >= self.loop_threshold {
+                    return None;
+                }
+                tracker.observe(&detection);
+                intercept_submission(self.interceptors, &mut submission);
+                Some(submission)
+            }
+            _ => None,
+        }
+    }
+}
+
+fn intercept_submission(
+    interceptors: &[Arc<SubmissionInterceptor>],
+    submission: &mut ChallengeSubmission,
+) {
+    for interceptor in interceptors {
+        interceptor(submission);
+    }
+}
+
 /// Stateful helper shared between concurrent requests.
-struct CloudScraperInner {
-    pipeline: ChallengePipeline,
+pub(crate) struct CloudScraperInner {
+    pub(crate) pipeline: ChallengePipeline,
+    base_headers: HeaderMap,
+    requests_since_ua_rotation: usize,
     proxy_manager: Option<ProxyManager>,
     current_proxy: Option<String>,
-    tls_manager: Option<DefaultTLSManager>,
-    fingerprint: Option<FingerprintGenerator>,
+    /// Proxy each domain was last sent through.
+    domain_proxies: HashMap<String, String>,
+    /// Domains whose next request must avoid the proxy listed here.
+    retired_proxies: HashMap<String, String>,
+    blacklist: IdentityBlacklist,
+    pub(crate) tls_manager: Option<DefaultTLSManager>,
+    pub(crate) fingerprint: Option<FingerprintGenerator>,
     anti_detection: Option<DefaultAntiDetection>,
     adaptive_timing: Option<DefaultAdaptiveTiming>,
     performance_monitor: Option<PerformanceMonitor>,
     ml_optimizer: Option<MLOptimizer>,
+    /// Seeds subsystems created after construction and user-agent rotation.
+    rng: StdRng,
 }
 
 impl CloudScraperInner {
-    fn new(pipeline: ChallengePipeline) -> Self {
+    fn new(
+        pipeline: ChallengePipeline,
+        base_headers: HeaderMap,
+        rng: StdRng,
+        blacklist_ttl: Duration,
+    ) -> Self {
         Self {
+            rng,
             pipeline,
+            base_headers,
+            requests_since_ua_rotation: 0,
             proxy_manager: None,
             current_proxy: None,
+            domain_proxies: HashMap::new(),
+            retired_proxies: HashMap::new(),
+            blacklist: IdentityBlacklist::new(blacklist_ttl),
             tls_manager: None,
             fingerprint: None,
             anti_detection: None,
@@ -313,79 +1185,691 @@ impl CloudScraperInner {
             ml_optimizer: None,
         }
     }
-}
-
-/// Reqwest client pool keyed by proxy endpoint.
-struct ClientPool {
-    base_headers: reqwest::header::HeaderMap,
-    clients: Mutex<HashMap<Option<String>, reqwest::Client>>,
-}
 
-impl ClientPool {
-    fn new(base_headers: reqwest::header::HeaderMap) -> Self {
-        Self {
-            base_headers,
-            clients: Mutex::new(HashMap::new()),
+    /// Headers, fingerprint, proxy, TLS profile and pacing delay for one
+    /// request: everything sent on the wire except the body.
+    pub(crate) fn prepare(
+        &mut self,
+        scope: PrepareScope<'_>,
+        method: &Method,
+        url: &Url,
+        body_size: usize,
+        forced_proxy: Option<String>,
+        binding: Option<&SessionBinding>,
+    ) -> CloudScraperResult<(HeaderMap, AntiDetectionContext, RequestContext)> {
+        let mut headers = match binding {
+            Some(binding) => binding.base_headers.clone(),
+            None => self.base_headers.clone(),
+        };
+        let domain = scope.state_scope.key(url.host_str().unwrap_or_default());
+        if let Some(state) = scope.state.get(&domain) {
+            for (name, value) in state.sticky_headers {
+                let header_name = HeaderName::from_bytes(name.as_bytes())
+                    .map_err(|_| CloudScraperError::InvalidHeader(name.clone()))?;
+                let header_value = HeaderValue::from_str(&value)
+                    .map_err(|_| CloudScraperError::InvalidHeader(name.clone()))?;
+                headers.insert(header_name, header_value);
+            }
         }
-    }
 
-    async fn client(&self, proxy: Option<&str>) -> CloudScraperResult<reqwest::Client> {
-        let mut guard = self.clients.lock().await;
-        let key = proxy.map(|p| p.to_string());
-        if let Some(client) = guard.get(&key) {
-            return Ok(client.clone());
-        }
+        let mut anti_ctx =
+            AntiDetectionContext::new(url.clone(), method.clone()).with_headers(headers.clone());
+        anti_ctx.set_body_size(body_size);
 
-        let mut builder = reqwest::Client::builder()
-            .cookie_store(true)
-            .default_headers(self.base_headers.clone());
+        let mut proxy = binding
+            .and_then(|binding| binding.proxy.clone())
+            .or(forced_proxy);
+        let mut context = RequestContext::default();
 
-        if let Some(endpoint) = proxy {
-            builder = builder.proxy(reqwest::Proxy::all(endpoint)?);
+        if let Some(ref mut generator) = self.fingerprint
+            && url.host_str().is_some()
+        {
+            let fp = match binding {
+                Some(binding) => binding.fingerprint_or(|| generator.generate_for(&domain)),
+                None => {
+                    let blacklisted = |fp: &BrowserFingerprint| {
+                        self.blacklist.contains(
+                            &domain,
+                            &BlacklistKey::Canvas(fp.canvas_fingerprint.clone()),
+                        ) || self
+                            .blacklist
+                            .contains(&domain, &BlacklistKey::UserAgent(fp.user_agent.clone()))
+                    };
+                    let mut fp = generator.generate_for(&domain);
+                    let mut rerolls = 0;
+                    while blacklisted(&fp) {
+                        if rerolls == IDENTITY_REROLL_ATTEMPTS {
+                            return Err(CloudScraperError::IdentityBlacklisted {
+                                domain: domain.to_string(),
+                                component: "fingerprint",
+                            });
+                        }
+                        rerolls += 1;
+                        fp = generator.regenerate_for(&domain);
+                    }
+                    fp
+                }
+            };
+            anti_ctx.set_user_agent(fp.user_agent.clone());
+            context.canvas_fingerprint = Some(fp.canvas_fingerprint.clone());
+            headers.insert(
+                HeaderName::from_static("user-agent"),
+                HeaderValue::from_str(&fp.user_agent)
+                    .map_err(|_| CloudScraperError::InvalidHeader("user-agent".into()))?,
+            );
+            headers.insert(
+                HeaderName::from_static("accept-language"),
+                HeaderValue::from_str(&fp.accept_language)
+                    .map_err(|_| CloudScraperError::InvalidHeader("accept-language".into()))?,
+            );
+        } else if let Some(language) =
+            domain_accept_language(scope.accept_languages, url.host_str())
+        {
+            headers.insert(
+                HeaderName::from_static("accept-language"),
+                HeaderValue::from_str(language)
+                    .map_err(|_| CloudScraperError::InvalidHeader("accept-language".into()))?,
+            );
         }
 
-        let client = builder.build()?;
-        guard.insert(key.clone(), client.clone());
-        Ok(client)
-    }
+        if let Some(ref mut anti) = self.anti_detection {
+            anti.prepare_request(&domain, &mut anti_ctx);
+            headers = anti_ctx.headers.clone();
+        }
+
+        if proxy.is_none() {
+            proxy = next_domain_proxy(self, &domain)?;
+        }
+        if url.host_str().is_some()
+            && let Some(proxy) = proxy.as_ref()
+        {
+            self.domain_proxies
+                .insert(domain.to_string(), proxy.clone());
+        }
+
+        if let Some(ref mut timing) = self.adaptive_timing {
+            let request = TimingRequest::new(request_kind(method), body_size);
+            context.delay = timing.calculate_delay(&domain, &request);
+            context.behavior_profile = Some(timing.domain_profile(&domain));
+        }
+        if let Some(ref mut tls) = self.tls_manager {
+            let mut ja3_hash = tls.assign_profile(&domain).ja3_hash();
+            let mut rerolls = 0;
+            while self
+                .blacklist
+                .contains(&domain, &BlacklistKey::Ja3(ja3_hash.clone()))
+            {
+                if rerolls == IDENTITY_REROLL_ATTEMPTS {
+                    return Err(CloudScraperError::IdentityBlacklisted {
+                        domain: domain.to_string(),
+                        component: "TLS profile",
+                    });
+                }
+                rerolls += 1;
+                tls.rotate_profile(&domain);
+                ja3_hash = tls.assign_profile(&domain).ja3_hash();
+            }
+            context.ja3_hash = Some(ja3_hash);
+        }
+
+        context.proxy = proxy;
+        context.user_agent = headers
+            .get(http::header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        Ok((headers, anti_ctx, context))
+    }
+
+    /// Feed one response to the pacing, anti-detection, performance and ML
+    /// layers; returns the performance alerts it raised.
+    pub(crate) fn learn(
+        &mut self,
+        domain: &str,
+        status: u16,
+        latency: Duration,
+        delay: Duration,
+        success: bool,
+    ) -> Vec<String> {
+        if let Some(timing) = self.adaptive_timing.as_mut() {
+            let outcome = TimingOutcome {
+                success,
+                response_time: latency,
+                applied_delay: delay,
+            };
+            timing.record_outcome(domain, &outcome);
+        }
+
+        if let Some(anti) = self.anti_detection.as_mut() {
+            anti.record_response(domain, status, latency);
+        }
+
+        if let Some(ml) = self.ml_optimizer.as_mut() {
+            let mut features = FeatureVector::new();
+            features.insert("latency".into(), latency.as_secs_f64());
+            features.insert("delay".into(), delay.as_secs_f64());
+            ml.record_attempt(domain, features, success, Some(delay.as_secs_f64()));
+        }
+
+        self.performance_monitor
+            .as_mut()
+            .and_then(|perf| perf.record(domain, latency, success))
+            .map(|report| report.alerts)
+            .unwrap_or_default()
+    }
+}
+
+/// What request preparation reads outside [`CloudScraperInner`].
+#[derive(Clone, Copy)]
+pub(crate) struct PrepareScope<'a> {
+    pub state: &'a StateManager,
+    pub state_scope: StateScope,
+    pub accept_languages: &'a [String],
+}
+
+/// Independent generator for one subsystem, drawn from the scraper's own.
+fn child_rng(rng: &mut StdRng) -> StdRng {
+    StdRng::seed_from_u64(rng.r#gen())
+}
+
+/// Next rotated proxy for `domain`, skipping one retired by
+/// [`CloudScraper::rotate_identity`] when another is available.
+fn next_domain_proxy(
+    inner: &mut CloudScraperInner,
+    domain: &str,
+) -> CloudScraperResult<Option<String>> {
+    let Some(manager) = inner.proxy_manager.as_mut() else {
+        return Ok(None);
+    };
+    let retired = inner.retired_proxies.remove(domain);
+    let blacklisted = inner.blacklist.proxies(domain);
+    if blacklisted.is_empty() {
+        return Ok(match retired {
+            Some(retired) => manager.next_proxy_except(&retired),
+            None => manager.next_proxy(),
+        });
+    }
+    let mut excluded = blacklisted.clone();
+    excluded.extend(retired);
+    manager
+        .next_untried_proxy(&excluded)
+        .or_else(|| manager.next_untried_proxy(&blacklisted))
+        .map(Some)
+        .ok_or_else(|| CloudScraperError::ProxiesBlacklisted {
+            domain: domain.to_string(),
+        })
+}
+
+/// A proxy this request has not tried yet and `domain` has not blacklisted.
+fn untried_domain_proxy(
+    inner: &mut CloudScraperInner,
+    domain: &str,
+    tried: &[String],
+) -> Option<String> {
+    let mut excluded = inner.blacklist.proxies(domain);
+    excluded.extend_from_slice(tried);
+    inner.proxy_manager.as_mut()?.next_untried_proxy(&excluded)
+}
+
+/// Reqwest client pool keyed by proxy endpoint and redirect handling.
+struct ClientPool {
+    backend: TlsBackend,
+    /// Only replaced while `clients` is locked, so every cached client was
+    /// built from the current setup.
+    setup: StdMutex<ClientSetup>,
+    max_idle_per_host: usize,
+    idle_timeout: Option<Duration>,
+    scope: StateScope,
+    clients: Mutex<HashMap<(Option<String>, bool), PooledClient>>,
+}
+
+/// Browser-dependent parts of the pooled clients, replaced when the user
+/// agent rotates.
+#[derive(Debug, Clone)]
+struct ClientSetup {
+    base_headers: reqwest::header::HeaderMap,
+    min_tls_version: Option<TlsVersion>,
+    #[cfg_attr(not(feature = "rustls"), allow(dead_code))]
+    cipher_suites: Vec<String>,
+}
+
+impl ClientSetup {
+    /// Supported groups cannot be configured through native-tls; only the
+    /// protocol floor of the preferred profile is enforced on the wire.
+    /// Cipher suites take effect on the rustls backend.
+    fn new(
+        profile: &UserAgentProfile,
+        tls_manager: Option<&DefaultTLSManager>,
+        spoofed_browser: Option<BrowserType>,
+    ) -> CloudScraperResult<Self> {
+        let ua_browser = profile
+            .headers
+            .get("User-Agent")
+            .and_then(|ua| BrowserType::from_user_agent(ua));
+        let (min_tls_version, cipher_suites) = match tls_manager {
+            Some(tls) => {
+                let preferred = tls.preferred_profile();
+                (
+                    Some(preferred.min_tls_version),
+                    preferred.cipher_suites.clone(),
+                )
+            }
+            None => match spoofed_browser.filter(|browser| Some(*browser) != ua_browser) {
+                Some(browser) => (
+                    None,
+                    default_tls_profile(browser)
+                        .map(|profile| profile.cipher_suites)
+                        .unwrap_or_else(|| profile.cipher_suites.clone()),
+                ),
+                None => (None, profile.cipher_suites.clone()),
+            },
+        };
+        Ok(Self {
+            base_headers: to_reqwest_headers(&to_http_headers(profile)?)?,
+            min_tls_version,
+            cipher_suites,
+        })
+    }
+}
+
+/// Background task pinging warm domains; stops when the scraper is dropped.
+struct ConnectionWarmer(tokio::task::JoinHandle<()>);
+
+impl ConnectionWarmer {
+    /// `None` without a Tokio runtime to run on.
+    fn start(
+        inner: Weak<Mutex<CloudScraperInner>>,
+        pool: Arc<ClientPool>,
+        state: StateManager,
+        config: &CloudScraperConfig,
+    ) -> Option<Self> {
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            log::warn!("no Tokio runtime at build time; warm domains will not be pinged");
+            return None;
+        };
+        let urls = config.warm_domains.clone();
+        let accept_languages = config.accept_languages.clone();
+        let state_scope = config.state_scope;
+        let interval = config.warm_interval.max(Duration::from_millis(1));
+        // A ping still running when the next one is due is abandoned.
+        let timeout = interval.min(WARM_PING_TIMEOUT);
+        Some(Self(runtime.spawn(async move {
+            let mut ticks = tokio::time::interval(interval);
+            ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticks.tick().await;
+                for url in &urls {
+                    let Some(inner) = inner.upgrade() else {
+                        return;
+                    };
+                    let scope = PrepareScope {
+                        state: &state,
+                        state_scope,
+                        accept_languages: &accept_languages,
+                    };
+                    if let Err(err) = Self::ping(&inner, &pool, scope, url, timeout).await {
+                        log::debug!("keep-alive ping to {url} failed: {err}");
+                    }
+                }
+            }
+        })))
+    }
+
+    /// Send a `HEAD` to `url`, prepared and paced like any other request,
+    /// through the proxy already bound to its domain. With proxies configured
+    /// and none bound yet, nothing is sent, so pings never open a route of
+    /// their own.
+    async fn ping(
+        inner: &Mutex<CloudScraperInner>,
+        pool: &ClientPool,
+        scope: PrepareScope<'_>,
+        url: &Url,
+        timeout: Duration,
+    ) -> CloudScraperResult<()> {
+        let (headers, _, context) = {
+            let mut guard = inner.lock().await;
+            let domain = scope.state_scope.key(url.host_str().unwrap_or_default());
+            let bound = guard.domain_proxies.get(domain.as_ref()).cloned();
+            if bound.is_none() && guard.proxy_manager.is_some() {
+                return Ok(());
+            }
+            guard.prepare(scope, &Method::HEAD, url, 0, bound, None)?
+        };
+        sleep(context.delay).await;
+        pool.client(Route::new(context.proxy.as_deref(), None))
+            .await?
+            .head(url.clone())
+            .headers(to_reqwest_headers(&headers)?)
+            .timeout(timeout)
+            .send()
+            .await?;
+        Ok(())
+    }
+}
+
+impl Drop for ConnectionWarmer {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Proxy a request goes through, and the session pinning its client setup.
+#[derive(Clone, Copy, Default)]
+struct Route<'a> {
+    proxy: Option<&'a str>,
+    binding: Option<&'a SessionBinding>,
+}
+
+impl<'a> Route<'a> {
+    fn new(proxy: Option<&'a str>, binding: Option<&'a SessionBinding>) -> Self {
+        Self { proxy, binding }
+    }
+}
+
+/// A pooled client and the cookie jar it shares with the other client on the
+/// same route.
+#[derive(Clone)]
+struct PooledClient {
+    client: reqwest::Client,
+    jar: Arc<ScopedJar>,
+}
+
+/// Cookie jar that widens host-only Cloudflare cookies to the host's
+/// [`StateScope`] key, so sibling subdomains share clearance under
+/// [`StateScope::RegistrableDomain`]. Other cookies keep the origin's scope.
+struct ScopedJar {
+    jar: reqwest::cookie::Jar,
+    scope: StateScope,
+}
+
+impl ScopedJar {
+    fn new(scope: StateScope) -> Self {
+        Self {
+            jar: reqwest::cookie::Jar::default(),
+            scope,
+        }
+    }
+
+    /// Remove cookie `name` as seen from `url`, whether it was stored
+    /// host-only or for `url`'s host or any parent domain.
+    fn expire(&self, name: &str, url: &Url) {
+        let mut cookies = vec![format!("{name}=; Max-Age=0; Path=/")];
+        let mut domain = url.domain();
+        while let Some(current) = domain {
+            cookies.push(format!("{name}=; Max-Age=0; Path=/; Domain={current}"));
+            domain = current
+                .split_once('.')
+                .map(|(_, parent)| parent)
+                .filter(|parent| parent.contains('.'));
+        }
+        let values: Vec<HeaderValue> = cookies
+            .iter()
+            .filter_map(|cookie| HeaderValue::from_str(cookie).ok())
+            .collect();
+        reqwest::cookie::CookieStore::set_cookies(&self.jar, &mut values.iter(), url);
+    }
+}
+
+impl reqwest::cookie::CookieStore for ScopedJar {
+    fn set_cookies(&self, cookie_headers: &mut dyn Iterator<Item = &HeaderValue>, url: &Url) {
+        let host = url.host_str().unwrap_or_default();
+        let key = self.scope.key(host);
+        if key == host {
+            return self.jar.set_cookies(cookie_headers, url);
+        }
+
+        let widened: Vec<HeaderValue> = cookie_headers
+            .map(|value| {
+                let Ok(cookie) = value.to_str() else {
+                    return value.clone();
+                };
+                let mut parts = cookie.split(';');
+                let name = parts
+                    .next()
+                    .and_then(|pair| pair.split_once('='))
+                    .map(|(name, _)| name.trim());
+                let cloudflare =
+                    matches!(name, Some(CLEARANCE_COOKIE) | Some(BOT_MANAGEMENT_COOKIE));
+                let has_domain = parts.any(|attribute| {
+                    attribute
+                        .trim_start()
+                        .to_ascii_lowercase()
+                        .starts_with("domain=")
+                });
+                if !cloudflare || has_domain {
+                    return value.clone();
+                }
+                let mut cookie = value.as_bytes().to_vec();
+                cookie.extend_from_slice(format!("; Domain={key}").as_bytes());
+                HeaderValue::from_bytes(&cookie).unwrap_or_else(|_| value.clone())
+            })
+            .collect();
+        self.jar.set_cookies(&mut widened.iter(), url);
+    }
+
+    fn cookies(&self, url: &Url) -> Option<HeaderValue> {
+        self.jar.cookies(url)
+    }
+}
+
+impl ClientPool {
+    fn new(setup: ClientSetup, backend: TlsBackend, config: &CloudScraperConfig) -> Self {
+        Self {
+            backend,
+            setup: StdMutex::new(setup),
+            max_idle_per_host: match config.connection_strategy {
+                ConnectionStrategy::Pooled => config.pool_max_idle_per_host,
+                ConnectionStrategy::FreshPerRequest => 0,
+            },
+            idle_timeout: config.pool_idle_timeout,
+            scope: config.state_scope,
+            clients: Mutex::new(HashMap::new()),
+        }
+    }
+
+    async fn client(&self, route: Route<'_>) -> CloudScraperResult<reqwest::Client> {
+        self.pooled(route, true).await
+    }
+
+    /// Client for challenge submissions: same proxy and TLS setup as
+    /// [`ClientPool::client`], but redirects are left to the executor.
+    async fn challenge_client(&self, route: Route<'_>) -> CloudScraperResult<reqwest::Client> {
+        self.pooled(route, false).await
+    }
+
+    /// Setup new clients are built from.
+    fn current_setup(&self) -> ClientSetup {
+        self.setup
+            .lock()
+            .expect("client setup lock poisoned")
+            .clone()
+    }
+
+    /// Shared client for `route`, or for a session route one built from the
+    /// session's pinned setup. Either way it uses the proxy's cookie jar.
+    async fn pooled(
+        &self,
+        route: Route<'_>,
+        follow_redirects: bool,
+    ) -> CloudScraperResult<reqwest::Client> {
+        let mut guard = self.clients.lock().await;
+        let key = (route.proxy.map(|p| p.to_string()), follow_redirects);
+        let shared = match guard.get(&key) {
+            Some(pooled) => pooled.clone(),
+            None => {
+                // Both redirect modes of a route share one jar, so submissions
+                // carry the challenged request's cookies and their clearance
+                // is reused.
+                let jar = guard
+                    .iter()
+                    .find(|((endpoint, _), _)| *endpoint == key.0)
+                    .map(|(_, pooled)| pooled.jar.clone())
+                    .unwrap_or_else(|| Arc::new(ScopedJar::new(self.scope)));
+                let client = self.build_client(
+                    &self.current_setup(),
+                    route.proxy,
+                    follow_redirects,
+                    jar.clone(),
+                )?;
+                let pooled = PooledClient { client, jar };
+                guard.insert(key.clone(), pooled.clone());
+                pooled
+            }
+        };
+        let Some(binding) = route.binding else {
+            return Ok(shared.client);
+        };
+
+        let mut pinned = binding
+            .clients
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        if let Some(client) = pinned.get(&key) {
+            return Ok(client.clone());
+        }
+        let client =
+            self.build_client(&binding.setup, route.proxy, follow_redirects, shared.jar)?;
+        pinned.insert(key, client.clone());
+        Ok(client)
+    }
+
+    /// Present a rotated browser: rebuild every cached client from `setup`,
+    /// keeping its cookie jar. Sessions keep the clients they pinned.
+    async fn rekey(&self, setup: ClientSetup) -> CloudScraperResult<()> {
+        let mut guard = self.clients.lock().await;
+        for ((proxy, follow_redirects), pooled) in guard.iter_mut() {
+            pooled.client = self.build_client(
+                &setup,
+                proxy.as_deref(),
+                *follow_redirects,
+                pooled.jar.clone(),
+            )?;
+        }
+        *self.setup.lock().expect("client setup lock poisoned") = setup;
+        Ok(())
+    }
+
+    fn build_client(
+        &self,
+        setup: &ClientSetup,
+        proxy: Option<&str>,
+        follow_redirects: bool,
+        jar: Arc<ScopedJar>,
+    ) -> CloudScraperResult<reqwest::Client> {
+        let setup = setup.clone();
+        let mut builder = reqwest::Client::builder()
+            .cookie_provider(jar)
+            .default_headers(setup.base_headers)
+            .pool_max_idle_per_host(self.max_idle_per_host)
+            .pool_idle_timeout(self.idle_timeout);
+        if !follow_redirects {
+            builder = builder.redirect(reqwest::redirect::Policy::none());
+        }
+
+        if let Some(endpoint) = proxy {
+            builder = builder.proxy(reqwest::Proxy::all(endpoint)?);
+        }
+
+        match self.backend {
+            TlsBackend::NativeTls => {
+                builder = builder.use_native_tls();
+                if let Some(version) = setup.min_tls_version {
+                    builder = builder.min_tls_version(to_reqwest_tls_version(version));
+                }
+            }
+            #[cfg(feature = "rustls")]
+            TlsBackend::Rustls => {
+                let config = crate::modules::tls::rustls_client_config(
+                    &setup.cipher_suites,
+                    setup.min_tls_version.unwrap_or(TlsVersion::Tls12),
+                )
+                .map_err(|err| CloudScraperError::Tls(err.to_string()))?;
+                builder = builder.use_preconfigured_tls(config);
+            }
+            #[cfg(not(feature = "rustls"))]
+            TlsBackend::Rustls => {
+                return Err(CloudScraperError::Tls(
+                    "the rustls backend needs the `rustls` feature".into(),
+                ));
+            }
+            TlsBackend::Boring => {
+                let reason = if cfg!(feature = "tls-boring") {
+                    "the BoringSSL backend is not implemented yet"
+                } else {
+                    "the BoringSSL backend needs the `tls-boring` feature"
+                };
+                return Err(CloudScraperError::Tls(reason.into()));
+            }
+        }
+
+        Ok(builder.build()?)
+    }
+
+    /// Remove cookie `name` for `url` from every pooled client's jar, across
+    /// every host sharing `url`'s scope.
+    async fn expire_cookie(&self, url: &Url, name: &str) {
+        let guard = self.clients.lock().await;
+        for pooled in guard.values() {
+            pooled.jar.expire(name, url);
+        }
+    }
+
+    /// Drop cached clients for the given proxy endpoints.
+    async fn evict(&self, proxies: &[String]) {
+        let mut guard = self.clients.lock().await;
+        guard.retain(|(endpoint, _), _| !endpoint.as_ref().is_some_and(|e| proxies.contains(e)));
+    }
 }
 
 /// Main scraper orchestrator.
 pub struct CloudScraper {
     config: CloudScraperConfig,
-    base_headers_http: HeaderMap,
     client_pool: Arc<ClientPool>,
-    challenge_client: Arc<dyn ChallengeHttpClient>,
+    recorder: Option<Arc<CassetteRecorder>>,
+    player: Option<Arc<CassettePlayer>>,
     state: StateManager,
     metrics: Option<MetricsCollector>,
+    har: Option<Arc<HarLog>>,
+    cache: Option<ResponseCache>,
+    request_slots: Option<Semaphore>,
+    concurrency: Option<AdaptiveConcurrency>,
     events: Arc<EventDispatcher>,
-    inner: Mutex<CloudScraperInner>,
+    inner: Arc<Mutex<CloudScraperInner>>,
+    _warmer: Option<ConnectionWarmer>,
 }
 
-impl CloudScraper {
-    /// Construct a scraper with default configuration.
-    pub fn new() -> CloudScraperResult<Self> {
-        CloudScraper::with_config(CloudScraperConfig::default())
-    }
-
-    /// Obtain a builder to customise the scraper instance.
-    pub fn builder() -> CloudScraperBuilder {
-        CloudScraperBuilder::new()
-    }
+/// Everything a scraper decides without a transport, shared by
+/// [`CloudScraper`] and [`ScraperCore`].
+struct ScraperParts {
+    inner: CloudScraperInner,
+    profile: UserAgentProfile,
+    spoofed_browser: Option<BrowserType>,
+    state: StateManager,
+}
 
-    fn with_config(config: CloudScraperConfig) -> CloudScraperResult<Self> {
-        let profile = get_user_agent_profile(config.user_agent.clone())?;
+impl ScraperParts {
+    fn new(config: &mut CloudScraperConfig) -> CloudScraperResult<Self> {
+        let mut rng = match config.deterministic_seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let profile = get_user_agent_profile_with_rng(config.user_agent.clone(), &mut rng)?;
         let base_headers_http = to_http_headers(&profile)?;
-        let base_headers_reqwest = to_reqwest_headers(&base_headers_http)?;
+        let ua_browser = profile
+            .headers
+            .get("User-Agent")
+            .and_then(|ua| BrowserType::from_user_agent(ua));
+        if config.strict_coherence {
+            check_coherence(config, ua_browser)?;
+        }
 
-        let mut pipeline = ChallengePipeline::default();
+        let mut pipeline = ChallengePipeline::new(config.detector.take().unwrap_or_default());
         let interpreter: Arc<dyn JavascriptInterpreter> = config
             .interpreter
             .clone()
             .unwrap_or_else(|| Arc::new(BoaJavascriptInterpreter::new()));
 
-        let mut js_v2 = JavascriptV2Solver::new();
-        let mut turnstile = TurnstileSolver::new();
+        let mut js_v2 = JavascriptV2Solver::new().with_rng(child_rng(&mut rng));
+        let mut turnstile = TurnstileSolver::new().with_rng(child_rng(&mut rng));
         if let Some(provider) = &config.captcha_provider {
             js_v2 = js_v2.with_captcha_provider(provider.clone());
             turnstile = turnstile.with_captcha_provider(provider.clone());
@@ -394,37 +1878,99 @@ impl CloudScraper {
         pipeline = pipeline
             .with_javascript_v1(JavascriptV1Solver::new(interpreter.clone()))
             .with_javascript_v2(js_v2)
-            .with_managed_v3(ManagedV3Solver::new(interpreter))
+            .with_managed_v3(ManagedV3Solver::new(interpreter).with_rng(child_rng(&mut rng)))
             .with_turnstile(turnstile)
-            .with_rate_limit(RateLimitHandler::new())
-            .with_access_denied(AccessDeniedHandler::new())
-            .with_bot_management(BotManagementHandler::new());
+            .with_rate_limit(RateLimitHandler::new().with_rng(child_rng(&mut rng)))
+            .with_access_denied(AccessDeniedHandler::new().with_rng(child_rng(&mut rng)))
+            .with_bot_management(BotManagementHandler::new().with_rng(child_rng(&mut rng)))
+            .with_origin_error(OriginErrorHandler::new().with_rng(child_rng(&mut rng)))
+            .with_min_solve_confidence(config.min_solve_confidence);
+        for solver in &config.custom_solvers {
+            pipeline = pipeline.with_custom_solver(Box::new(solver.clone()));
+        }
 
-        let mut inner = CloudScraperInner::new(pipeline);
+        let mut inner = CloudScraperInner::new(
+            pipeline,
+            base_headers_http,
+            child_rng(&mut rng),
+            config.identity_blacklist_ttl,
+        );
 
         if !config.proxies.is_empty() {
-            let mut manager = ProxyManager::new(config.proxy_config.clone());
+            let mut manager =
+                ProxyManager::new(config.proxy_config.clone()).with_rng(child_rng(&mut rng));
             manager.load(config.proxies.iter().cloned());
             inner.proxy_manager = Some(manager);
         }
 
+        // A custom user agent is what the caller wants to look like, so every
+        // other layer follows it rather than its own defaults, unless a
+        // spoofing browser was chosen explicitly. `strict_coherence` has
+        // already rejected one that contradicts the user agent.
+        let explicit_browser = config.spoofing_browser.filter(|_| config.enable_spoofing);
+        let custom_user_agent = config
+            .user_agent
+            .custom
+            .as_ref()
+            .filter(|_| config.align_to_custom_user_agent)
+            .filter(|_| match explicit_browser {
+                Some(spoofing) if !ua_browser.is_some_and(|ua| ua.is_compatible_with(spoofing)) => {
+                    log::warn!(
+                        "custom user agent is {ua_browser:?} but fingerprints spoof {spoofing:?}; \
+                         keeping the spoofing browser"
+                    );
+                    false
+                }
+                _ => true,
+            });
+        let aligned_browser = custom_user_agent.and(ua_browser);
+
+        // With spoofing on, the fingerprint's browser is authoritative: its
+        // user agent goes in the header and TLS presents the same browser.
+        let spoofed_browser = config.enable_spoofing.then(|| {
+            explicit_browser
+                .or(aligned_browser)
+                .or(ua_browser)
+                .unwrap_or(BrowserType::Chrome)
+        });
+
         if config.enable_tls_fingerprinting {
-            inner.tls_manager = Some(DefaultTLSManager::new(config.tls_config.clone()));
+            let mut tls =
+                DefaultTLSManager::new(config.tls_config.clone()).with_rng(child_rng(&mut rng));
+            if let Some(browser) = spoofed_browser.or(aligned_browser) {
+                tls.reset_for_browser(browser);
+            }
+            inner.tls_manager = Some(tls);
         }
 
-        if config.enable_spoofing {
-            let mut generator = FingerprintGenerator::default();
-            generator = generator.with_consistency(config.spoofing_consistency);
+        if let Some(browser) = spoofed_browser {
+            let mut generator = FingerprintGenerator::new(browser)
+                .with_consistency(config.spoofing_consistency)
+                .with_accept_languages(config.accept_languages.clone())
+                .with_rng(child_rng(&mut rng));
+            if let Some(user_agent) = custom_user_agent {
+                generator = generator.with_user_agent(user_agent.clone());
+            }
             inner.fingerprint = Some(generator);
         }
 
         if config.enable_anti_detection {
-            inner.anti_detection = Some(DefaultAntiDetection::new(Default::default()));
+            inner.anti_detection = Some(
+                DefaultAntiDetection::new(config.anti_detection_config.clone())
+                    .with_rng(child_rng(&mut rng)),
+            );
         }
 
         if config.enable_adaptive_timing {
-            let mut timing = DefaultAdaptiveTiming::new();
+            let mut timing = DefaultAdaptiveTiming::new().with_rng(child_rng(&mut rng));
+            if let Some(max_delay) = config.max_adaptive_delay {
+                timing = timing.with_absolute_max_delay(max_delay);
+            }
+            if let Some(schedule) = config.learning_rate_schedule {
+                timing = timing.with_learning_rate(schedule);
+            }
             timing.set_behavior_profile(config.behavior_profile);
+            timing.set_profile_escalation(config.auto_profile_escalation);
             inner.adaptive_timing = Some(timing);
         }
 
@@ -433,12 +1979,61 @@ impl CloudScraper {
         }
 
         if config.enable_ml_optimization {
-            inner.ml_optimizer = Some(MLOptimizer::default());
+            let mut optimizer = MLOptimizer::default().with_rng(child_rng(&mut rng));
+            if let Some(schedule) = config.learning_rate_schedule {
+                optimizer = optimizer.with_learning_rate_schedule(schedule);
+            }
+            inner.ml_optimizer = Some(optimizer);
+        }
+
+        let mut state = StateManager::new();
+        if let Some(schedule) = config.learning_rate_schedule {
+            state = state.with_learning_rate(schedule);
         }
+        for (domain, seed) in &config.domain_seeds {
+            state.seed(&config.state_scope.key(domain), seed);
+        }
+
+        Ok(Self {
+            inner,
+            profile,
+            spoofed_browser,
+            state,
+        })
+    }
+}
+
+impl CloudScraper {
+    /// Construct a scraper with default configuration.
+    pub fn new() -> CloudScraperResult<Self> {
+        CloudScraper::with_config(CloudScraperConfig::default())
+    }
+
+    /// Obtain a builder to customise the scraper instance.
+    pub fn builder() -> CloudScraperBuilder {
+        CloudScraperBuilder::new()
+    }
 
-        let client_pool = Arc::new(ClientPool::new(base_headers_reqwest));
-        let challenge_client = Arc::new(ReqwestChallengeHttpClient::new()?);
-        let state = StateManager::new();
+    fn with_config(mut config: CloudScraperConfig) -> CloudScraperResult<Self> {
+        let ScraperParts {
+            inner,
+            profile,
+            spoofed_browser,
+            state,
+        } = ScraperParts::new(&mut config)?;
+        let client_pool = Arc::new(ClientPool::new(
+            ClientSetup::new(&profile, inner.tls_manager.as_ref(), spoofed_browser)?,
+            config.tls_backend,
+            &config,
+        ));
+        let player = match &config.replay_from {
+            Some(path) => Some(Arc::new(CassettePlayer::load(path)?)),
+            None => None,
+        };
+        let recorder = config
+            .record_to
+            .as_ref()
+            .map(|path| Arc::new(CassetteRecorder::new(path.clone())));
         let metrics = config.enable_metrics.then(MetricsCollector::new);
 
         let mut events = EventDispatcher::new();
@@ -446,17 +2041,165 @@ impl CloudScraper {
         if let Some(ref collector) = metrics {
             events.register_handler(Arc::new(MetricsHandler::new(collector.clone())));
         }
+        let har = config.enable_har_log.then(|| Arc::new(HarLog::new()));
+        if let Some(ref har) = har {
+            events.register_handler(har.clone());
+        }
+        for handler in &config.event_handlers {
+            events.register_handler(handler.clone());
+        }
+        let cache = config.cache.clone().map(ResponseCache::new);
+        let request_slots = config.max_concurrent_requests.map(Semaphore::new);
+        let concurrency = config
+            .adaptive_concurrency
+            .clone()
+            .map(|aimd| AdaptiveConcurrency::new(aimd).with_state(state.clone()));
+        let inner = Arc::new(Mutex::new(inner));
+        let warmer = if config.warm_domains.is_empty() || player.is_some() {
+            None
+        } else {
+            ConnectionWarmer::start(
+                Arc::downgrade(&inner),
+                client_pool.clone(),
+                state.clone(),
+                &config,
+            )
+        };
 
         Ok(Self {
             config,
-            base_headers_http,
             client_pool,
-            challenge_client,
+            recorder,
+            player,
             state,
             metrics,
+            har,
+            cache,
+            request_slots,
+            concurrency,
             events: Arc::new(events),
-            inner: Mutex::new(inner),
+            inner,
+            _warmer: warmer,
+        })
+    }
+
+    /// The scraper's metrics, unless built with
+    /// [`CloudScraperBuilder::disable_metrics`].
+    pub fn metrics(&self) -> Option<&MetricsCollector> {
+        self.metrics.as_ref()
+    }
+
+    /// HAR 1.2 JSON of every exchange so far; an empty log unless the
+    /// scraper was built with [`CloudScraperBuilder::enable_har_log`].
+    pub fn export_har(&self) -> String {
+        match &self.har {
+            Some(har) => har.export(),
+            None => HarLog::new().export(),
+        }
+    }
+
+    /// Write the exchanges recorded so far to the cassette set with
+    /// [`CloudScraperBuilder::record_to`]; a no-op when not recording.
+    pub async fn finish_recording(&self) -> CloudScraperResult<()> {
+        let Some(recorder) = self.recorder.clone() else {
+            return Ok(());
+        };
+        run_blocking(move || recorder.finish()).await
+    }
+
+    /// Save domain state, learned detector patterns, ML models and proxy
+    /// stats to `storage`.
+    ///
+    /// Snapshots are taken in memory first; `storage` is only called once
+    /// every lock is released, on the blocking thread pool.
+    pub async fn save_to(&self, storage: Arc<dyn StorageBackend>) -> CloudScraperResult<()> {
+        let snapshot = MemoryStorage::new();
+        self.state.save_to(&snapshot)?;
+        {
+            let guard = self.inner.lock().await;
+            guard.pipeline.detector().save_to(&snapshot)?;
+            if let Some(optimizer) = &guard.ml_optimizer {
+                optimizer.save_to(&snapshot)?;
+            }
+            if let Some(manager) = &guard.proxy_manager {
+                manager.save_to(&snapshot)?;
+            }
+        }
+
+        run_blocking(move || {
+            for key in snapshot.keys() {
+                if let Some(bytes) = snapshot.load(&key)? {
+                    storage.store(&key, &bytes)?;
+                }
+            }
+            Ok::<_, StorageError>(())
+        })
+        .await
+    }
+
+    /// Restore whatever [`save_to`](Self::save_to) left in `storage`;
+    /// missing snapshots are skipped.
+    ///
+    /// `storage` is read on the blocking thread pool before any lock is
+    /// taken.
+    pub async fn load_from(&self, storage: Arc<dyn StorageBackend>) -> CloudScraperResult<()> {
+        let snapshot = run_blocking(move || {
+            let snapshot = MemoryStorage::new();
+            for key in SNAPSHOT_KEYS {
+                if let Some(bytes) = storage.load(key)? {
+                    snapshot.store(key, &bytes)?;
+                }
+            }
+            Ok::<_, StorageError>(snapshot)
         })
+        .await?;
+
+        self.state.load_from(&snapshot)?;
+        let mut guard = self.inner.lock().await;
+        guard.pipeline.detector_mut().load_from(&snapshot)?;
+        if let Some(optimizer) = guard.ml_optimizer.as_mut() {
+            optimizer.load_from(&snapshot)?;
+        }
+        if let Some(manager) = guard.proxy_manager.as_mut() {
+            manager.load_from(&snapshot)?;
+        }
+        Ok(())
+    }
+
+    /// Run detection and solving on a challenge page saved at `path`, as if
+    /// `url` had answered it with `status` and `Server: cloudflare`.
+    ///
+    /// Nothing is sent; the configured interpreter and captcha provider
+    /// still run, so pair this with mocks to develop against captured pages.
+    pub async fn solve_local(
+        &self,
+        path: &Path,
+        url: &Url,
+        status: u16,
+    ) -> CloudScraperResult<PipelinePrepareResult> {
+        let bytes = tokio::fs::read(path).await?;
+        let body = String::from_utf8_lossy(&bytes);
+        let mut headers = HeaderMap::new();
+        headers.insert(http::header::SERVER, HeaderValue::from_static("cloudflare"));
+        let response = ChallengeResponse {
+            url,
+            status,
+            headers: &headers,
+            body: &body,
+            request_method: &Method::GET,
+        };
+
+        let mut guard = self.inner.lock().await;
+        let user_agent = guard
+            .base_headers
+            .get(http::header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let context = PipelineContext {
+            user_agent: user_agent.as_deref(),
+            ..Default::default()
+        };
+        Ok(guard.pipeline.prepare(&response, context).await)
     }
 
     /// Perform an HTTP GET request.
@@ -466,74 +2209,533 @@ impl CloudScraper {
     }
 
     /// Perform an arbitrary HTTP request.
+    ///
+    /// Every event dispatched while handling the request carries the same
+    /// correlation id so a single logical request can be followed through
+    /// detection, solving and retries.
     pub async fn request(
         &self,
         method: Method,
         url: Url,
         body: Option<Vec<u8>>,
     ) -> CloudScraperResult<ScraperResponse> {
-        let mut forced_proxy: Option<String> = None;
-        let mut attempt = 0usize;
-
-        loop {
-            attempt += 1;
+        self.execute(
+            method,
+            url,
+            body.map(RequestBody::from),
+            ResourceType::Document,
+            None,
+            None,
+        )
+        .await
+    }
 
-            let (mut headers_http, anti_ctx, proxy, mut delay) = self
-                .prepare_request(
-                    &method,
-                    &url,
-                    body.as_ref().map(|b| b.len()).unwrap_or(0),
-                    forced_proxy.take(),
-                )
-                .await?;
+    /// POST `form` as `multipart/form-data`.
+    ///
+    /// The form is encoded once and kept in memory, so an upload interrupted
+    /// by a challenge is sent again byte for byte after clearance.
+    pub async fn post_multipart(
+        &self,
+        url: &str,
+        form: MultipartForm,
+    ) -> CloudScraperResult<ScraperResponse> {
+        let url = Url::parse(url)?;
+        let body = RequestBody {
+            bytes: form.to_bytes(),
+            content_type: Some(form.content_type()),
+        };
+        self.execute(
+            Method::POST,
+            url,
+            Some(body),
+            ResourceType::Document,
+            None,
+            None,
+        )
+        .await
+    }
 
-            if let Some(ref ct) = self.config.content_type {
-                headers_http.insert(
-                    HeaderName::from_static("content-type"),
-                    HeaderValue::from_str(ct)
-                        .map_err(|_| CloudScraperError::InvalidHeader("content-type".into()))?,
-                );
-            }
+    /// Like [`request`](Self::request), with the `Accept` header a browser
+    /// sends when fetching `resource` rather than navigating to a page.
+    pub async fn request_as(
+        &self,
+        method: Method,
+        url: Url,
+        body: Option<Vec<u8>>,
+        resource: ResourceType,
+    ) -> CloudScraperResult<ScraperResponse> {
+        self.execute(
+            method,
+            url,
+            body.map(RequestBody::from),
+            resource,
+            None,
+            None,
+        )
+        .await
+    }
+
+    /// Perform a request that aborts with [`CloudScraperError::Cancelled`]
+    /// once `cancel` fires.
+    ///
+    /// The token is checked at every retry boundary and raced against each
+    /// send, challenge solve (including captcha polling) and wait.
+    pub async fn request_with_cancel(
+        &self,
+        method: Method,
+        url: Url,
+        body: Option<Vec<u8>>,
+        cancel: &CancellationToken,
+    ) -> CloudScraperResult<ScraperResponse> {
+        self.execute(
+            method,
+            url,
+            body.map(RequestBody::from),
+            ResourceType::Document,
+            None,
+            Some(cancel),
+        )
+        .await
+    }
+
+    /// Swap in a new proxy list without rebuilding the scraper.
+    ///
+    /// Endpoints present in both lists keep their health statistics; clients
+    /// cached for removed endpoints are dropped.
+    pub async fn reload_proxies(&self, proxies: Vec<String>) {
+        let removed = {
+            let mut guard = self.inner.lock().await;
+            let inner = &mut *guard;
+            let removed = match inner.proxy_manager.as_mut() {
+                Some(manager) => manager.reload(proxies),
+                None => {
+                    let mut manager = ProxyManager::new(self.config.proxy_config.clone())
+                        .with_rng(child_rng(&mut inner.rng));
+                    manager.load(proxies);
+                    inner.proxy_manager = Some(manager);
+                    Vec::new()
+                }
+            };
+            if inner
+                .current_proxy
+                .as_ref()
+                .is_some_and(|current| removed.contains(current))
+            {
+                inner.current_proxy = None;
+            }
+            removed
+        };
+        self.client_pool.evict(&removed).await;
+    }
+
+    /// Present a new identity to `domain` after an out-of-band soft ban.
+    ///
+    /// Drops the domain's cached fingerprint, switches it to a different TLS
+    /// profile, and makes its next request use a proxy other than the last
+    /// one. These change together so the new identity stays coherent.
+    pub async fn rotate_identity(&self, domain: &str) {
+        let domain = self.domain_key(&domain.to_ascii_lowercase()).into_owned();
+        let mut guard = self.inner.lock().await;
+        let inner = &mut *guard;
+        if let Some(generator) = inner.fingerprint.as_mut() {
+            generator.invalidate(&domain);
+        }
+        if let Some(tls) = inner.tls_manager.as_mut() {
+            tls.rotate_profile(&domain);
+        }
+        if let Some(last) = inner.domain_proxies.get(&domain) {
+            inner.retired_proxies.insert(domain, last.clone());
+        }
+    }
+
+    /// Never present `key` to `domain` again until the blacklist TTL runs
+    /// out, e.g. after learning out-of-band that a proxy is banned there.
+    ///
+    /// Blacklisted fingerprints and TLS profiles are regenerated or rotated
+    /// away from, and requests fail with
+    /// [`CloudScraperError::IdentityBlacklisted`] when no other one turns up,
+    /// e.g. for a blacklisted pinned user agent. A blacklisted proxy is
+    /// skipped for the domain, and requests fail with
+    /// [`CloudScraperError::ProxiesBlacklisted`] once every proxy is.
+    /// Session-pinned identities are left as they are.
+    pub async fn blacklist_identity(&self, domain: &str, key: BlacklistKey) {
+        let domain = self.domain_key(&domain.to_ascii_lowercase()).into_owned();
+        let mut guard = self.inner.lock().await;
+        guard.blacklist.prune();
+        guard.blacklist.add(&domain, key);
+    }
+
+    /// Proxy, fingerprint and TLS profile `domain` was last presented with.
+    ///
+    /// Read-only: nothing is generated or rotated, so parts the domain has
+    /// not been assigned yet are `None`.
+    pub async fn identity_report(&self, domain: &str) -> IdentityReport {
+        let domain = self.domain_key(&domain.to_ascii_lowercase()).into_owned();
+        let guard = self.inner.lock().await;
+        IdentityReport {
+            proxy: guard.domain_proxies.get(&domain).cloned(),
+            fingerprint: guard
+                .fingerprint
+                .as_ref()
+                .and_then(|generator| generator.peek(&domain).cloned()),
+            tls_profile: guard
+                .tls_manager
+                .as_ref()
+                .and_then(|tls| tls.assigned_profile(&domain).cloned()),
+            domain,
+        }
+    }
+
+    /// What adaptive timing has learned about `domain` so far.
+    ///
+    /// Returns `None` when adaptive timing is disabled or the domain has not
+    /// been requested yet.
+    pub async fn timing_snapshot(&self, domain: &str) -> Option<DomainTimingSnapshot> {
+        let guard = self.inner.lock().await;
+        guard
+            .adaptive_timing
+            .as_ref()
+            .and_then(|timing| timing.snapshot(&self.domain_key(domain)))
+    }
+
+    /// Burst limit anti-detection has learned for `domain`; `None` when
+    /// anti-detection is disabled.
+    pub async fn burst_limit(&self, domain: &str) -> Option<usize> {
+        let guard = self.inner.lock().await;
+        guard
+            .anti_detection
+            .as_ref()
+            .map(|anti| anti.burst_limit(&self.domain_key(domain)))
+    }
+
+    /// Requests `domain` may have in flight under adaptive concurrency;
+    /// `None` when it is disabled.
+    pub fn current_concurrency(&self, domain: &str) -> Option<usize> {
+        self.concurrency
+            .as_ref()
+            .map(|limits| limits.current(&self.domain_key(domain)))
+    }
+
+    /// Learned pre-request delay for `domain` that kept requests succeeding.
+    pub async fn optimal_delay(&self, domain: &str) -> Option<Duration> {
+        self.timing_snapshot(domain)
+            .await
+            .and_then(|snapshot| snapshot.optimal_timing)
+            .or_else(|| self.state.optimal_delay(&self.domain_key(domain)))
+    }
+
+    /// Behaviour profile that suits what has been observed on `domain`.
+    ///
+    /// Frequent failures or challenges suggest [`BehaviorProfile::Research`];
+    /// a clean, fast domain suggests [`BehaviorProfile::Focused`]. Falls back
+    /// to the configured profile until the domain has been requested.
+    pub async fn recommend_profile(&self, domain: &str) -> BehaviorProfile {
+        let domain = domain.to_ascii_lowercase();
+        let Some(state) = self.state.get(&self.domain_key(&domain)) else {
+            return self.config.behavior_profile;
+        };
+        // Metrics are kept per host; fold every host sharing the scope key.
+        let key = self.domain_key(&domain);
+        let (mut requests, mut successes) = (0u64, 0u64);
+        let (mut latency_secs, mut timed) = (0f64, 0u64);
+        if let Some(metrics) = &self.metrics {
+            for stats in metrics.snapshot().domains {
+                if self.domain_key(&stats.domain) != key {
+                    continue;
+                }
+                requests += stats.total_requests;
+                successes += stats.successes;
+                if let Some(latency) = stats.average_latency {
+                    latency_secs += latency.as_secs_f64() * stats.total_requests as f64;
+                    timed += stats.total_requests;
+                }
+            }
+        }
+        let success_rate = if requests > 0 {
+            successes as f32 / requests as f32
+        } else {
+            state.timing.success_rate
+        };
+        let latency = if timed > 0 {
+            Duration::from_secs_f64(latency_secs / timed as f64)
+        } else {
+            Duration::from_secs_f32(state.timing.avg_response_time_secs)
+        };
+        recommended_profile(ProfileSignals {
+            success_rate,
+            challenge_rate: state.challenge_rate(),
+            failure_streak: state.failure_streak,
+            under_attack: state.under_attack(),
+            latency,
+        })
+    }
+
+    /// Pace `domain` with [`recommend_profile`](Self::recommend_profile)'s
+    /// suggestion and return it. Has no effect when adaptive timing is
+    /// disabled.
+    pub async fn adopt_recommended_profile(&self, domain: &str) -> BehaviorProfile {
+        let profile = self.recommend_profile(domain).await;
+        if let Some(timing) = self.inner.lock().await.adaptive_timing.as_mut() {
+            timing.set_domain_profile(&self.domain_key(&domain.to_ascii_lowercase()), profile);
+        }
+        profile
+    }
+
+    /// Challenge types this configuration can solve, and what is missing for
+    /// the others — e.g. Turnstile without a captcha provider.
+    pub async fn capabilities(&self) -> Capabilities {
+        self.inner.lock().await.pipeline.capabilities()
+    }
+
+    /// Open a session bound to a single proxy and browser identity.
+    ///
+    /// Requests issued through the returned [`Session`] reuse the same proxy,
+    /// fingerprint, headers and TLS setup instead of rotating per request,
+    /// which mirrors a real browser session more faithfully.
+    pub async fn session(&self) -> Session<'_> {
+        // Snapshotted under the lock that user-agent rotation holds, so the
+        // headers and client setup belong to the same browser.
+        let mut guard = self.inner.lock().await;
+        let proxy = guard.proxy_manager.as_mut().and_then(|pm| pm.next_proxy());
+        let binding = SessionBinding {
+            proxy,
+            fingerprint: StdMutex::new(None),
+            base_headers: guard.base_headers.clone(),
+            setup: self.client_pool.current_setup(),
+            clients: StdMutex::new(HashMap::new()),
+        };
+        drop(guard);
+        Session {
+            scraper: self,
+            binding,
+        }
+    }
+
+    /// Run the pipeline on `challenge_response`, or only its solver when
+    /// `detection` is already known.
+    async fn evaluate_challenge(
+        &self,
+        challenge_response: &ChallengeResponse<'_>,
+        detection: Option<ChallengeDetection>,
+        user_agent: Option<&str>,
+    ) -> ChallengePipelineResult {
+        let mut guard = self.inner.lock().await;
+        let CloudScraperInner {
+            pipeline,
+            proxy_manager,
+            current_proxy,
+            tls_manager,
+            fingerprint,
+            ..
+        } = &mut *guard;
 
-            if let Some(hint) = anti_ctx.delay_hint()
-                && hint > delay
+        let context = PipelineContext {
+            proxy_pool: proxy_manager.as_mut().map(|pm| pm as &mut dyn ProxyPool),
+            current_proxy: current_proxy.as_deref(),
+            failure_recorder: Some(&self.state),
+            fingerprint_manager: fingerprint
+                .as_mut()
+                .map(|fp| fp as &mut dyn crate::challenges::solvers::FingerprintManager),
+            tls_manager: tls_manager
+                .as_mut()
+                .map(|tls| tls as &mut dyn TlsProfileManager),
+            user_agent,
+        };
+        match detection {
+            Some(detection) => {
+                pipeline
+                    .solve_detected(detection, challenge_response, context)
+                    .await
+            }
+            None => pipeline.evaluate(challenge_response, context).await,
+        }
+    }
+
+    /// Solve again, up to [`MAX_STALE_TOKEN_RESOLVES`] times, while the
+    /// captcha token would reach the server with less than
+    /// `min_token_ttl_before_submit` to live.
+    async fn refresh_stale_token(
+        &self,
+        challenge_response: &ChallengeResponse<'_>,
+        user_agent: Option<&str>,
+        mut detection: ChallengeDetection,
+        mut submission: ChallengeSubmission,
+    ) -> (ChallengeDetection, ChallengeSubmission) {
+        let min_ttl = self.config.captcha_config.min_token_ttl_before_submit;
+        for _ in 0..MAX_STALE_TOKEN_RESOLVES {
+            if !submission.token_expires_within(min_ttl) {
+                break;
+            }
+            log::debug!(
+                "captcha token for {} would expire before submission; solving again",
+                detection.url
+            );
+            match self
+                .evaluate_challenge(challenge_response, Some(detection.clone()), user_agent)
+                .await
             {
-                delay = hint;
+                ChallengePipelineResult::Submission {
+                    detection: fresh_detection,
+                    submission: fresh_submission,
+                } => {
+                    detection = fresh_detection;
+                    submission = fresh_submission;
+                }
+                _ => break,
             }
+        }
+        (detection, submission)
+    }
 
-            self.events
-                .dispatch(ScraperEvent::PreRequest(PreRequestEvent {
-                    url: url.clone(),
-                    method: method.clone(),
-                    headers: headers_http.clone(),
-                    timestamp: chrono::Utc::now(),
-                }));
+    /// `hint` unless this request already tried it or `url`'s domain
+    /// blacklisted it, in which case another proxy fit for both.
+    async fn untried_proxy(
+        &self,
+        url: &Url,
+        hint: &str,
+        tried: &[String],
+    ) -> CloudScraperResult<String> {
+        let exhausted = || CloudScraperError::ProxiesExhausted {
+            tried: tried.to_vec(),
+        };
+        if self
+            .config
+            .proxy_config
+            .max_proxies_per_request
+            .is_some_and(|max| tried.len() >= max)
+        {
+            return Err(exhausted());
+        }
+        let domain = self.domain_key(url.host_str().unwrap_or_default());
+        let mut guard = self.inner.lock().await;
+        let blacklisted = guard
+            .blacklist
+            .contains(&domain, &BlacklistKey::Proxy(hint.to_string()));
+        if !blacklisted && !tried.iter().any(|proxy| proxy == hint) {
+            return Ok(hint.to_string());
+        }
+        untried_domain_proxy(&mut guard, &domain, tried).ok_or_else(exhausted)
+    }
 
-            let client = self.client_pool.client(proxy.as_deref()).await?;
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            skip(self, body, binding, cancel),
+            fields(%method, %url, request_id = tracing::field::Empty)
+        )
+    )]
+    async fn execute(
+        &self,
+        method: Method,
+        url: Url,
+        body: Option<RequestBody>,
+        resource: ResourceType,
+        binding: Option<&SessionBinding>,
+        cancel: Option<&CancellationToken>,
+    ) -> CloudScraperResult<ScraperResponse> {
+        let request_id = Uuid::new_v4();
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("request_id", tracing::field::display(request_id));
 
-            if delay > Duration::from_millis(0) {
-                sleep(delay).await;
+        // Taken before the global slot so a saturated domain does not hold
+        // slots other domains could use.
+        let _domain_slot = match &self.concurrency {
+            Some(limits) if !self.is_passthrough(&url) => {
+                let domain = self.domain_key(url.host_str().unwrap_or_default());
+                Some(until_cancelled(cancel, limits.acquire(&domain)).await?)
             }
+            _ => None,
+        };
 
-            let req_headers = to_reqwest_headers(&headers_http)?;
-            let mut builder = client
-                .request(method.clone(), url.clone())
-                .headers(req_headers);
-            if let Some(ref body) = body {
-                builder = builder.body(body.clone());
+        // Held until this call returns, so retries keep the same slot.
+        let _slot = match &self.request_slots {
+            Some(slots) => Some(
+                until_cancelled(cancel, slots.acquire())
+                    .await?
+                    .expect("request slots are never closed"),
+            ),
+            None => None,
+        };
+
+        if self.is_passthrough(&url) {
+            return until_cancelled(cancel, self.send_passthrough(request_id, method, url, body))
+                .await?;
+        }
+
+        self.begin_request().await?;
+        let mut forced_proxy: Option<String> = None;
+        let mut tried_proxies: Vec<String> = Vec::new();
+        let mut attempt = 0usize;
+        let mut loop_tracker = ChallengeLoopTracker::default();
+        let mut encountered: Vec<ChallengeType> = Vec::new();
+        let mut status_retries = 0usize;
+        let mut solved: Option<ChallengeType> = None;
+        self.refresh_expiring_clearance(&url).await;
+
+        loop {
+            attempt += 1;
+            if cancel.is_some_and(CancellationToken::is_cancelled) {
+                return Err(CloudScraperError::Cancelled);
             }
 
-            let started = Instant::now();
-            let resp = builder.send().await?;
-            let latency = started.elapsed();
+            let Exchange {
+                headers: headers_http,
+                proxy,
+                delay,
+                latency,
+                final_url,
+                status,
+                response_headers: http_headers,
+                body: body_bytes,
+                context,
+            } = until_cancelled(
+                cancel,
+                self.send_once(
+                    request_id,
+                    Outgoing {
+                        method: &method,
+                        url: &url,
+                        body: body.as_ref(),
+                        resource,
+                    },
+                    forced_proxy.take(),
+                    binding,
+                ),
+            )
+            .await??;
+            if let Some(proxy) = &proxy
+                && !tried_proxies.contains(proxy)
+            {
+                tried_proxies.push(proxy.clone());
+            }
+            self.note_bot_cookie(&url, &http_headers);
+            if !self.config.response_interceptors.is_empty() {
+                let response = ScraperResponse::new(
+                    status,
+                    http_headers.clone(),
+                    Bytes::from(body_bytes.clone()),
+                    final_url.clone(),
+                );
+                if self.interceptor_wants_retry(&response) {
+                    self.record_outcome(
+                        request_id,
+                        Some(status_failure(status, proxy.as_deref())),
+                        status,
+                        latency,
+                        delay,
+                        &final_url,
+                    )
+                    .await;
+                    self.schedule_intercepted_retry(request_id, attempt, response)?;
+                    continue;
+                }
+            }
 
-            let final_url = resp.url().clone();
-            let status = resp.status().as_u16();
-            let headers_raw = resp.headers().clone();
-            let body_bytes = resp.bytes().await?.to_vec();
-            let body_text = String::from_utf8_lossy(&body_bytes).to_string();
+            let body_text = decode_body(&body_bytes, &http_headers);
+            let user_agent = headers_http
+                .get(http::header::USER_AGENT)
+                .and_then(|value| value.to_str().ok());
 
-            let http_headers = reqwest_to_http(&headers_raw)?;
             let challenge_response = ChallengeResponse {
                 url: &final_url,
                 status,
@@ -542,107 +2744,206 @@ impl CloudScraper {
                 request_method: &method,
             };
 
-            self.events
-                .dispatch(ScraperEvent::PostResponse(PostResponseEvent {
-                    url: final_url.clone(),
-                    method: method.clone(),
-                    status,
-                    latency,
-                    timestamp: chrono::Utc::now(),
-                }));
-
-            let result = {
-                let mut guard = self.inner.lock().await;
-                let CloudScraperInner {
-                    pipeline,
-                    proxy_manager,
-                    current_proxy,
-                    tls_manager,
-                    fingerprint,
-                    ..
-                } = &mut *guard;
+            let result = until_cancelled(
+                cancel,
+                self.evaluate_challenge(&challenge_response, None, user_agent),
+            )
+            .await?;
 
-                pipeline
-                    .evaluate(
-                        &challenge_response,
-                        PipelineContext {
-                            proxy_pool: proxy_manager.as_mut().map(|pm| pm as &mut dyn ProxyPool),
-                            current_proxy: current_proxy.as_deref(),
-                            failure_recorder: Some(&self.state),
-                            fingerprint_manager: fingerprint.as_mut().map(|fp| {
-                                fp as &mut dyn crate::challenges::solvers::FingerprintManager
-                            }),
-                            tls_manager: tls_manager
-                                .as_mut()
-                                .map(|tls| tls as &mut dyn TlsProfileManager),
-                        },
-                    )
-                    .await
-            };
+            // Only a challenge page means Cloudflare raised its guard; blocks,
+            // rate limits and origin failures inside the window do not.
+            let under_attack = result
+                .detection()
+                .is_some_and(|detection| detection.challenge_type.is_solvable_page())
+                && self.note_escalation(&url).await;
 
             match result {
+                ChallengePipelineResult::NoChallenge
+                    if self.config.retry_statuses.contains(&status)
+                        && status_retries < self.config.max_status_retries =>
+                {
+                    self.record_outcome(
+                        request_id,
+                        Some(status_failure(status, proxy.as_deref())),
+                        status,
+                        latency,
+                        delay,
+                        &final_url,
+                    )
+                    .await;
+                    let wait = match retry_after(&http_headers, chrono::Utc::now()) {
+                        Some(wait) => wait.min(self.config.max_retry_after),
+                        None => self
+                            .config
+                            .status_retry_backoff
+                            .saturating_mul(1 << status_retries.min(16)),
+                    };
+                    status_retries += 1;
+                    self.events.dispatch(ScraperEvent::Retry(RetryEvent {
+                        request_id,
+                        domain: url.host_str().unwrap_or_default().to_string(),
+                        attempt: (attempt + 1) as u32,
+                        reason: format!("origin answered {status}"),
+                        scheduled_after: wait,
+                        timestamp: chrono::Utc::now(),
+                    }));
+                    until_cancelled(cancel, sleep(wait)).await?;
+                    continue;
+                }
                 ChallengePipelineResult::NoChallenge => {
-                    self.record_outcome(true, status, latency, delay, &final_url)
+                    self.record_outcome(request_id, None, status, latency, delay, &final_url)
                         .await;
-                    let response = ScraperResponse::new(
-                        status,
-                        http_headers.clone(),
-                        Bytes::from(body_bytes),
-                        final_url,
-                    );
-                    return Ok(response);
+                    self.note_concurrency(&url, status == 429);
+                    self.note_clear(&url, &http_headers);
+                    let body_bytes = Bytes::from(body_bytes);
+                    let cached = self.cache.as_ref().and_then(|cache| {
+                        cache.resolve(&method, &url, status, &http_headers, &body_bytes)
+                    });
+                    let response = match cached {
+                        Some(cached) => ScraperResponse::new(
+                            cached.status,
+                            cached.headers,
+                            cached.body,
+                            final_url,
+                        ),
+                        None => ScraperResponse::new(status, http_headers, body_bytes, final_url),
+                    };
+                    return Ok(response.with_context(context).with_challenge_solved(solved));
                 }
                 ChallengePipelineResult::Submission {
                     detection,
                     submission,
                 } => {
-                    let (response, challenge_latency) = self
-                        .handle_submission(
+                    let (detection, submission) = until_cancelled(
+                        cancel,
+                        self.refresh_stale_token(
+                            &challenge_response,
+                            user_agent,
+                            detection,
+                            submission,
+                        ),
+                    )
+                    .await?;
+                    let challenge_type = detection.challenge_type;
+                    loop_tracker.observe(&detection);
+                    let (response, challenge_latency, challenge_wait, cleared) = until_cancelled(
+                        cancel,
+                        self.handle_submission(
+                            request_id,
                             submission,
                             detection,
-                            &method,
-                            &url,
-                            headers_http.clone(),
-                            body.clone(),
-                        )
-                        .await?;
+                            under_attack,
+                            Route::new(proxy.as_deref(), binding),
+                            OriginalRequest::new(method.clone(), url.clone())
+                                .with_headers(headers_http.clone())
+                                .with_body(body.as_ref().map(|body| body.bytes.clone())),
+                            &mut loop_tracker,
+                        ),
+                    )
+                    .await??;
                     self.record_outcome(
-                        response.status() < 500,
+                        request_id,
+                        (!cleared).then(|| {
+                            status_failure(response.status(), proxy.as_deref())
+                                .with_challenge_type(challenge_type)
+                        }),
                         response.status(),
                         latency + challenge_latency,
-                        delay,
+                        delay + challenge_wait,
                         response.url(),
                     )
                     .await;
-                    return Ok(response);
+                    if cleared {
+                        self.note_clear(&url, response.headers());
+                        solved = Some(challenge_type);
+                    } else {
+                        self.note_challenged(&url, challenge_type);
+                        if loop_tracker.repeats >= self.config.challenge_loop_threshold
+                            && let Some((challenge_type, _)) = loop_tracker.last
+                        {
+                            return Err(CloudScraperError::ChallengeLoop {
+                                challenge_type,
+                                attempts: loop_tracker.repeats,
+                                last_response: Box::new(response.with_context(context)),
+                            });
+                        }
+                    }
+                    if self.interceptor_wants_retry(&response) {
+                        self.schedule_intercepted_retry(request_id, attempt, response)?;
+                        continue;
+                    }
+                    return Ok(response.with_context(context).with_challenge_solved(solved));
                 }
                 ChallengePipelineResult::Mitigation { detection, plan } => {
-                    self.record_outcome(false, status, latency, delay, &final_url)
-                        .await;
+                    self.record_outcome(
+                        request_id,
+                        Some(
+                            status_failure(status, proxy.as_deref())
+                                .with_challenge_type(detection.challenge_type),
+                        ),
+                        status,
+                        latency,
+                        delay,
+                        &final_url,
+                    )
+                    .await;
+                    if detection.challenge_type == ChallengeType::RateLimit {
+                        self.note_concurrency(&url, true);
+                    }
+                    self.note_challenged(&url, detection.challenge_type);
+                    encountered.push(detection.challenge_type);
                     self.events
                         .dispatch(ScraperEvent::Challenge(ChallengeEvent {
+                            request_id,
                             domain: detection.url.clone(),
                             challenge_type: format!("{:?}", detection.challenge_type),
                             success: false,
-                            metadata: vec![
+                            under_attack,
+                            metadata: [
                                 ("reason".into(), plan.reason.clone()),
                                 ("pattern".into(), detection.pattern_id.clone()),
-                            ],
+                            ]
+                            .into_iter()
+                            .chain(cloudflare_diagnostics(&http_headers))
+                            .collect(),
                             timestamp: chrono::Utc::now(),
                         }));
 
-                    if let Some(wait) = plan.wait {
-                        sleep(wait).await;
+                    let repeats = loop_tracker.observe(&detection);
+                    if repeats >= self.config.challenge_loop_threshold {
+                        return Err(CloudScraperError::ChallengeLoop {
+                            challenge_type: detection.challenge_type,
+                            attempts: repeats,
+                            last_response: Box::new(
+                                ScraperResponse::new(
+                                    status,
+                                    http_headers,
+                                    Bytes::from(body_bytes),
+                                    final_url,
+                                )
+                                .with_context(context),
+                            ),
+                        });
                     }
 
-                    if let Some(ref proxy_hint) = plan.new_proxy {
-                        forced_proxy = Some(proxy_hint.clone());
+                    if let Some(wait) = plan.wait {
+                        until_cancelled(cancel, sleep(wait)).await?;
                     }
 
-                    let should_retry =
-                        plan.should_retry && attempt < self.config.max_challenge_attempts;
-                    if should_retry {
+                    if plan.should_retry && attempt >= self.config.max_challenge_attempts {
+                        return Err(CloudScraperError::ChallengeExhausted {
+                            attempts: attempt,
+                            encountered,
+                            plan: Box::new(plan),
+                        });
+                    }
+                    if plan.should_retry {
+                        if let Some(ref proxy_hint) = plan.new_proxy {
+                            forced_proxy =
+                                Some(self.untried_proxy(&url, proxy_hint, &tried_proxies).await?);
+                        }
                         self.events.dispatch(ScraperEvent::Retry(RetryEvent {
+                            request_id,
                             domain: detection.url,
                             attempt: (attempt + 1) as u32,
                             reason: plan.reason.clone(),
@@ -655,23 +2956,73 @@ impl CloudScraper {
                     }
                 }
                 ChallengePipelineResult::Unsupported { detection, reason } => {
-                    self.record_outcome(false, status, latency, delay, &final_url)
-                        .await;
+                    self.record_outcome(
+                        request_id,
+                        Some(
+                            status_failure(status, proxy.as_deref())
+                                .with_challenge_type(detection.challenge_type),
+                        ),
+                        status,
+                        latency,
+                        delay,
+                        &final_url,
+                    )
+                    .await;
+                    self.note_challenged(&url, detection.challenge_type);
+                    if let Some(dir) = &self.config.dump_unsupported_to {
+                        match write_challenge_dump(
+                            dir,
+                            request_id,
+                            &challenge_response,
+                            &body_bytes,
+                            &detection,
+                            &reason,
+                        )
+                        .await
+                        {
+                            Ok(path) => log::info!(
+                                "[{request_id}] unsupported challenge dumped to {}",
+                                path.display()
+                            ),
+                            Err(err) => log::warn!(
+                                "[{request_id}] failed to dump unsupported challenge: {err}"
+                            ),
+                        }
+                    }
                     self.events
                         .dispatch(ScraperEvent::Challenge(ChallengeEvent {
+                            request_id,
                             domain: detection.url,
                             challenge_type: detection.pattern_name,
                             success: false,
-                            metadata: vec![("reason".into(), reason.to_string())],
+                            under_attack,
+                            metadata: [("reason".into(), reason.to_string())]
+                                .into_iter()
+                                .chain(cloudflare_diagnostics(&http_headers))
+                                .collect(),
                             timestamp: chrono::Utc::now(),
                         }));
                     return Err(CloudScraperError::Unsupported(reason));
                 }
                 ChallengePipelineResult::Failed { detection, error } => {
-                    self.record_outcome(false, status, latency, delay, &final_url)
-                        .await;
+                    let mut failure = status_failure(status, proxy.as_deref())
+                        .with_challenge_type(detection.challenge_type);
+                    if error.is_captcha_failure() {
+                        failure = failure.captcha_failure();
+                    }
+                    self.record_outcome(
+                        request_id,
+                        Some(failure),
+                        status,
+                        latency,
+                        delay,
+                        &final_url,
+                    )
+                    .await;
+                    self.note_challenged(&url, detection.challenge_type);
                     self.events
                         .dispatch(ScraperEvent::Error(crate::modules::events::ErrorEvent {
+                            request_id,
                             domain: detection.url,
                             error: error.to_string(),
                             timestamp: chrono::Utc::now(),
@@ -682,182 +3033,953 @@ impl CloudScraper {
         }
     }
 
-    async fn handle_submission(
-        &self,
-        submission: ChallengeSubmission,
-        detection: ChallengeDetection,
-        method: &Method,
-        url: &Url,
-        headers: HeaderMap,
-        body: Option<Vec<u8>>,
-    ) -> CloudScraperResult<(ScraperResponse, Duration)> {
-        let original = OriginalRequest::new(method.clone(), url.clone())
-            .with_headers(headers)
-            .with_body(body);
-
-        let started = Instant::now();
-        let result =
-            execute_challenge_submission(self.challenge_client.clone(), submission, original).await;
-        let challenge_latency = started.elapsed();
-
-        let success = result.is_ok();
-        {
-            let mut guard = self.inner.lock().await;
-            guard
-                .pipeline
-                .record_outcome(&detection.pattern_id, success);
-        }
+    /// Whether `domain` challenged its last request, holds a valid clearance,
+    /// or is due a `__cf_bm` refresh.
+    ///
+    /// Lets schedulers route known-hard domains to a slower lane before
+    /// probing them again.
+    pub fn domain_challenge_status(&self, domain: &str) -> ChallengeStatus {
+        self.state.challenge_status(&self.domain_key(domain))
+    }
 
-        let final_response = result?;
-        let response = ScraperResponse::new(
-            final_response.status,
-            final_response.headers.clone(),
-            Bytes::from(final_response.body.clone()),
-            final_response.url.clone(),
-        );
+    /// When `domain`'s `cf_clearance` cookie expires, if known.
+    pub fn clearance_expiry(&self, domain: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.state.clearance_expiry(&self.domain_key(domain))
+    }
 
-        self.events
-            .dispatch(ScraperEvent::Challenge(ChallengeEvent {
-                domain: detection.url,
-                challenge_type: detection.pattern_name,
-                success,
-                metadata: vec![
-                    ("pattern".into(), detection.pattern_id),
-                    ("status".into(), final_response.status.to_string()),
-                ],
-                timestamp: chrono::Utc::now(),
-            }));
+    /// Key per-domain subsystems track `host` under; see [`StateScope`].
+    fn domain_key<'a>(&self, host: &'a str) -> Cow<'a, str> {
+        self.config.state_scope.key(host)
+    }
 
-        self.events
-            .dispatch(ScraperEvent::PostResponse(PostResponseEvent {
-                url: response.url().clone(),
-                method: method.clone(),
-                status: response.status(),
-                latency: challenge_latency,
-                timestamp: chrono::Utc::now(),
-            }));
+    /// Drop a clearance that expires within the refresh margin, so the next
+    /// request meets the challenge now rather than mid-crawl.
+    async fn refresh_expiring_clearance(&self, url: &Url) {
+        let margin = self.config.clearance_refresh_margin;
+        if margin.is_zero() {
+            return;
+        }
+        let domain = self.domain_key(url.host_str().unwrap_or_default());
+        let Some(expires) = self.state.clearance_expiry(&domain) else {
+            return;
+        };
+        let due = expires - chrono::Duration::from_std(margin).unwrap_or(chrono::Duration::MAX);
+        if due > chrono::Utc::now() {
+            return;
+        }
+        log::debug!("{domain} clearance expires at {expires}; re-solving the challenge");
+        self.client_pool.expire_cookie(url, CLEARANCE_COOKIE).await;
+    }
 
-        Ok((response, challenge_latency))
+    fn note_bot_cookie(&self, url: &Url, headers: &HeaderMap) {
+        let now = chrono::Utc::now();
+        if let Some(expires) = bot_management_expiry(headers, now) {
+            self.state.update(
+                &self.domain_key(url.host_str().unwrap_or_default()),
+                |state| state.note_bot_cookie(now, expires),
+            );
+        }
     }
 
-    async fn record_outcome(
-        &self,
-        success: bool,
-        status: u16,
-        latency: Duration,
-        delay: Duration,
-        url: &Url,
-    ) {
-        let domain = url.host_str().unwrap_or_default();
-        if success {
-            self.state.record_success(domain);
+    /// Feed adaptive concurrency with how `url` answered.
+    fn note_concurrency(&self, url: &Url, rate_limited: bool) {
+        let Some(limits) = &self.concurrency else {
+            return;
+        };
+        let host = url.host_str().unwrap_or_default();
+        let domain = self.domain_key(host);
+        if rate_limited {
+            limits.record_rate_limited(&domain);
         } else {
-            self.state
-                .record_failure(domain, format!("status_{status}"));
+            limits.record_success(&domain);
         }
-
-        if let Some(ref collector) = self.metrics {
-            collector.record_response(domain, status, latency);
+        if let Some(metrics) = &self.metrics {
+            metrics.record_concurrency(host, limits.current(&domain));
         }
+    }
+
+    fn note_clear(&self, url: &Url, headers: &HeaderMap) {
+        let until = clearance_expiry(headers, chrono::Utc::now());
+        self.state.update(
+            &self.domain_key(url.host_str().unwrap_or_default()),
+            |state| state.mark_clear(until),
+        );
+    }
 
+    /// Detect an Under Attack Mode escalation: a challenge while the domain's
+    /// clearance is still valid. Escalated domains switch to Research pacing
+    /// until a clean streak steps them back.
+    async fn note_escalation(&self, url: &Url) -> bool {
+        let domain = self.domain_key(url.host_str().unwrap_or_default());
+        if !self.state.note_escalation(&domain) {
+            return false;
+        }
+        log::warn!("{domain} challenged inside its clearance window; assuming Under Attack Mode");
         let mut guard = self.inner.lock().await;
         if let Some(timing) = guard.adaptive_timing.as_mut() {
-            let outcome = TimingOutcome {
-                success,
-                response_time: latency,
-                applied_delay: delay,
-            };
-            timing.record_outcome(domain, &outcome);
-        }
-
-        if let Some(anti) = guard.anti_detection.as_mut() {
-            anti.record_response(domain, status, latency);
+            timing.escalate_domain(&domain, BehaviorProfile::Research);
         }
+        true
+    }
 
-        if let Some(perf) = guard.performance_monitor.as_mut()
-            && let Some(report) = perf.record(domain, latency, success)
-            && !report.alerts.is_empty()
-        {
-            log::warn!("performance alerts: {:#?}", report.alerts);
+    fn note_challenged(&self, url: &Url, challenge_type: ChallengeType) {
+        if challenge_type == ChallengeType::OriginError {
+            return;
         }
+        self.state.update(
+            &self.domain_key(url.host_str().unwrap_or_default()),
+            |state| state.mark_challenged(challenge_type),
+        );
+    }
 
-        if let Some(ml) = guard.ml_optimizer.as_mut() {
-            let mut features = FeatureVector::new();
-            features.insert("latency".into(), latency.as_secs_f64());
-            features.insert("delay".into(), delay.as_secs_f64());
-            ml.record_attempt(domain, features, success, Some(delay.as_secs_f64()));
-        }
+    fn interceptor_wants_retry(&self, response: &ScraperResponse) -> bool {
+        self.config
+            .response_interceptors
+            .iter()
+            .any(|interceptor| interceptor(response) == ResponseAction::Retry)
     }
 
-    async fn prepare_request(
+    /// Announce a retry requested by a response interceptor, or give up once
+    /// the attempt budget is spent.
+    fn schedule_intercepted_retry(
         &self,
-        method: &Method,
-        url: &Url,
-        body_size: usize,
-        forced_proxy: Option<String>,
-    ) -> CloudScraperResult<(HeaderMap, AntiDetectionContext, Option<String>, Duration)> {
-        let mut headers = self.base_headers_http.clone();
-        if let Some(state) = self.state.get(url.host_str().unwrap_or("")) {
-            for (name, value) in state.sticky_headers {
-                let header_name = HeaderName::from_bytes(name.as_bytes())
-                    .map_err(|_| CloudScraperError::InvalidHeader(name.clone()))?;
-                let header_value = HeaderValue::from_str(&value)
-                    .map_err(|_| CloudScraperError::InvalidHeader(name.clone()))?;
-                headers.insert(header_name, header_value);
-            }
+        request_id: Uuid,
+        attempt: usize,
+        response: ScraperResponse,
+    ) -> CloudScraperResult<()> {
+        if attempt >= self.config.max_challenge_attempts {
+            return Err(CloudScraperError::InterceptorRetry {
+                attempts: attempt,
+                last_response: Box::new(response),
+            });
         }
+        self.events.dispatch(ScraperEvent::Retry(RetryEvent {
+            request_id,
+            domain: response.url().host_str().unwrap_or_default().to_string(),
+            attempt: (attempt + 1) as u32,
+            reason: "response interceptor requested retry".into(),
+            scheduled_after: Duration::ZERO,
+            timestamp: chrono::Utc::now(),
+        }));
+        Ok(())
+    }
 
-        let mut anti_ctx =
-            AntiDetectionContext::new(url.clone(), method.clone()).with_headers(headers.clone());
-        anti_ctx.set_body_size(body_size);
+    /// Perform a request without running challenge detection or solving.
+    ///
+    /// Headers, proxy selection and timing are applied exactly as in
+    /// [`CloudScraper::request`], but the response is returned verbatim — a
+    /// Cloudflare challenge page comes back as-is instead of being solved. Use
+    /// this when clearance is already known to be present and detection or
+    /// captcha spend is unwanted.
+    pub async fn request_raw(
+        &self,
+        method: Method,
+        url: Url,
+        body: Option<Vec<u8>>,
+    ) -> CloudScraperResult<ScraperResponse> {
+        let request_id = Uuid::new_v4();
+        let body = body.map(RequestBody::from);
+        self.begin_request().await?;
+        let exchange = self
+            .send_once(
+                request_id,
+                Outgoing {
+                    method: &method,
+                    url: &url,
+                    body: body.as_ref(),
+                    resource: ResourceType::Document,
+                },
+                None,
+                None,
+            )
+            .await?;
+        self.record_outcome(
+            request_id,
+            (exchange.status >= 400)
+                .then(|| status_failure(exchange.status, exchange.proxy.as_deref())),
+            exchange.status,
+            exchange.latency,
+            exchange.delay,
+            &exchange.final_url,
+        )
+        .await;
+
+        Ok(ScraperResponse::new(
+            exchange.status,
+            exchange.response_headers,
+            Bytes::from(exchange.body),
+            exchange.final_url,
+        )
+        .with_context(exchange.context))
+    }
+
+    fn is_passthrough(&self, url: &Url) -> bool {
+        let Some(host) = url.host_str() else {
+            return false;
+        };
+        self.config.passthrough_domains.iter().any(|domain| {
+            host == domain
+                || host
+                    .strip_suffix(domain.as_str())
+                    .is_some_and(|prefix| prefix.ends_with('.'))
+        })
+    }
+
+    /// Send a request to a passthrough host over the direct client.
+    async fn send_passthrough(
+        &self,
+        request_id: Uuid,
+        method: Method,
+        url: Url,
+        body: Option<RequestBody>,
+    ) -> CloudScraperResult<ScraperResponse> {
+        let mut headers = self.inner.lock().await.base_headers.clone();
+        if let Some(body) = &body {
+            body.apply_content_type(&mut headers)?;
+        }
+        self.events
+            .dispatch(ScraperEvent::PreRequest(PreRequestEvent {
+                request_id,
+                url: url.clone(),
+                method: method.clone(),
+                headers: headers.clone(),
+                timestamp: chrono::Utc::now(),
+            }));
+
+        let client = self.client_pool.client(Route::default()).await?;
+        let mut builder = client
+            .request(method.clone(), url)
+            .headers(to_reqwest_headers(&headers)?);
+        if let Some(body) = body {
+            builder = builder.body(body.bytes);
+        }
+        let started = Instant::now();
+        let resp = builder.send().await?;
+        let latency = started.elapsed();
+
+        let status = resp.status().as_u16();
+        let final_url = resp.url().clone();
+        let response_headers = reqwest_to_http(resp.headers())?;
+        let body = resp.bytes().await?;
+
+        self.events
+            .dispatch(ScraperEvent::PostResponse(PostResponseEvent {
+                request_id,
+                url: final_url.clone(),
+                method,
+                status,
+                diagnostics: cloudflare_diagnostics(&response_headers),
+                headers: response_headers.clone(),
+                latency,
+                delay: Duration::ZERO,
+                timestamp: chrono::Utc::now(),
+            }));
+
+        Ok(ScraperResponse::new(
+            status,
+            response_headers,
+            body,
+            final_url,
+        ))
+    }
+
+    /// Prepare, pace and send a single request, returning the raw exchange.
+    async fn send_once(
+        &self,
+        request_id: Uuid,
+        outgoing: Outgoing<'_>,
+        forced_proxy: Option<String>,
+        binding: Option<&SessionBinding>,
+    ) -> CloudScraperResult<Exchange> {
+        let Outgoing {
+            method,
+            url,
+            body,
+            resource,
+        } = outgoing;
+        let (mut headers_http, anti_ctx, mut context) = self
+            .prepare_request(
+                method,
+                url,
+                body.map(|b| b.bytes.len()).unwrap_or(0),
+                forced_proxy,
+                binding,
+            )
+            .await?;
+        let mut proxy = context.proxy.take();
+        let mut delay = context.delay;
+
+        if let Some(accept) = resource.accept() {
+            headers_http.insert(http::header::ACCEPT, HeaderValue::from_static(accept));
+        }
 
-        let mut proxy = forced_proxy;
-        let mut delay = Duration::from_millis(0);
+        if let Some(ref ct) = self.config.content_type {
+            headers_http.insert(
+                HeaderName::from_static("content-type"),
+                HeaderValue::from_str(ct)
+                    .map_err(|_| CloudScraperError::InvalidHeader("content-type".into()))?,
+            );
+        }
+        if let Some(body) = body {
+            body.apply_content_type(&mut headers_http)?;
+        }
 
+        if let Some(hint) = anti_ctx.delay_hint()
+            && hint > delay
         {
-            let mut guard = self.inner.lock().await;
+            delay = hint;
+        }
 
-            if let Some(ref mut generator) = guard.fingerprint
-                && let Some(domain) = url.host_str()
-            {
-                let fp = generator.generate_for(domain);
-                anti_ctx.set_user_agent(fp.user_agent.clone());
-                headers.insert(
-                    HeaderName::from_static("user-agent"),
-                    HeaderValue::from_str(&fp.user_agent)
-                        .map_err(|_| CloudScraperError::InvalidHeader("user-agent".into()))?,
-                );
-                headers.insert(
-                    HeaderName::from_static("accept-language"),
-                    HeaderValue::from_str(&fp.accept_language)
-                        .map_err(|_| CloudScraperError::InvalidHeader("accept-language".into()))?,
-                );
+        if let Some(cache) = &self.cache {
+            cache.add_validators(method, url, &mut headers_http);
+        }
+
+        for interceptor in &self.config.request_interceptors {
+            interceptor(&mut headers_http, url, method);
+        }
+
+        self.events
+            .dispatch(ScraperEvent::PreRequest(PreRequestEvent {
+                request_id,
+                url: url.clone(),
+                method: method.clone(),
+                headers: headers_http.clone(),
+                timestamp: chrono::Utc::now(),
+            }));
+
+        for interceptor in &self.config.delay_interceptors {
+            delay = interceptor(url, delay);
+        }
+
+        let (response, latency) = match &self.player {
+            Some(player) => (player.replay(method, url)?, Duration::ZERO),
+            None => {
+                let mut failed_proxies = Vec::new();
+                let mut retries = 0;
+                loop {
+                    let client = self
+                        .client_pool
+                        .client(Route::new(proxy.as_deref(), binding))
+                        .await?;
+
+                    if delay > Duration::from_millis(0) {
+                        sleep(delay).await;
+                    }
+
+                    let req_headers = to_reqwest_headers(&headers_http)?;
+                    let mut builder = client
+                        .request(method.clone(), url.clone())
+                        .headers(req_headers);
+                    if let Some(body) = body {
+                        builder = builder.body(body.bytes.clone());
+                    }
+
+                    let started = Instant::now();
+                    let fetched = async {
+                        let resp = builder.send().await?;
+                        let status = resp.status().as_u16();
+                        let final_url = resp.url().clone();
+                        let headers = resp.headers().clone();
+                        let body = resp.bytes().await?.to_vec();
+                        Ok::<_, reqwest::Error>((status, final_url, headers, body))
+                    }
+                    .await;
+                    match fetched {
+                        Ok((status, final_url, headers, body)) => {
+                            let response = ReplayedResponse {
+                                status,
+                                url: final_url,
+                                headers: reqwest_to_http(&headers)?,
+                                body,
+                            };
+                            break (response, started.elapsed());
+                        }
+                        Err(err) if is_transient(&err, method) => {
+                            let next = self
+                                .proxy_after_transient_failure(
+                                    url,
+                                    proxy.take(),
+                                    binding,
+                                    &mut failed_proxies,
+                                )
+                                .await;
+                            if retries >= self.config.max_transient_retries {
+                                return Err(err.into());
+                            }
+                            retries += 1;
+                            log::warn!("[{request_id}] transient network error, retrying: {err}");
+                            self.events.dispatch(ScraperEvent::Retry(RetryEvent {
+                                request_id,
+                                domain: url.host_str().unwrap_or_default().to_string(),
+                                attempt: retries as u32 + 1,
+                                reason: format!("transient network error: {err}"),
+                                scheduled_after: delay,
+                                timestamp: chrono::Utc::now(),
+                            }));
+                            proxy = next;
+                        }
+                        Err(err) => return Err(err.into()),
+                    }
+                }
             }
+        };
+
+        if let Some(recorder) = &self.recorder {
+            recorder.record(cassette::interaction(
+                method,
+                url,
+                &headers_http,
+                body.map(|b| b.bytes.as_slice()),
+                &response,
+            ));
+        }
+
+        let ReplayedResponse {
+            status,
+            url: final_url,
+            headers: response_headers,
+            body,
+        } = response;
+
+        self.events
+            .dispatch(ScraperEvent::PostResponse(PostResponseEvent {
+                request_id,
+                url: final_url.clone(),
+                method: method.clone(),
+                status,
+                diagnostics: cloudflare_diagnostics(&response_headers),
+                headers: response_headers.clone(),
+                latency,
+                delay,
+                timestamp: chrono::Utc::now(),
+            }));
+
+        context.proxy = proxy.clone();
+        context.delay = delay;
+        Ok(Exchange {
+            headers: headers_http,
+            proxy,
+            delay,
+            latency,
+            final_url,
+            status,
+            response_headers,
+            body,
+            context,
+        })
+    }
+
+    /// Report `proxy` after a transient network error and pick the one to
+    /// retry through: a proxy this request has not failed on yet, or the same
+    /// one when none is left or the request is pinned to a session.
+    async fn proxy_after_transient_failure(
+        &self,
+        url: &Url,
+        proxy: Option<String>,
+        binding: Option<&SessionBinding>,
+        failed: &mut Vec<String>,
+    ) -> Option<String> {
+        let proxy = proxy?;
+        let mut guard = self.inner.lock().await;
+        let Some(manager) = guard.proxy_manager.as_mut() else {
+            return Some(proxy);
+        };
+        manager.report_failure(&proxy);
+        if binding.is_some_and(|binding| binding.proxy.is_some()) {
+            return Some(proxy);
+        }
+        failed.push(proxy.clone());
+        let domain = self.domain_key(url.host_str().unwrap_or_default());
+        let next = untried_domain_proxy(&mut guard, &domain, failed).unwrap_or(proxy);
+        guard.current_proxy = Some(next.clone());
+        if url.host_str().is_some() {
+            guard
+                .domain_proxies
+                .insert(domain.into_owned(), next.clone());
+        }
+        Some(next)
+    }
+
+    /// Challenge client bound to `route`, so Cloudflare sees the submission
+    /// arrive the same way as the request that drew the challenge.
+    async fn submission_client(
+        &self,
+        route: Route<'_>,
+    ) -> CloudScraperResult<Arc<dyn ChallengeHttpClient>> {
+        let client: Arc<dyn ChallengeHttpClient> = match &self.player {
+            Some(player) => player.clone(),
+            None => Arc::new(ReqwestChallengeHttpClient::from_client(
+                self.client_pool.challenge_client(route).await?,
+            )),
+        };
+        Ok(match &self.recorder {
+            Some(recorder) => Arc::new(RecordingChallengeClient::new(client, recorder.clone())),
+            None => client,
+        })
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            skip_all,
+            fields(%request_id, pattern = %detection.pattern_id)
+        )
+    )]
+    #[allow(clippy::too_many_arguments)]
+    async fn handle_submission(
+        &self,
+        request_id: Uuid,
+        mut submission: ChallengeSubmission,
+        detection: ChallengeDetection,
+        under_attack: bool,
+        route: Route<'_>,
+        original: OriginalRequest,
+        loop_tracker: &mut ChallengeLoopTracker,
+    ) -> CloudScraperResult<(ScraperResponse, Duration, Duration, bool)> {
+        let method = original.method.clone();
+        let user_agent = original
+            .headers
+            .get(http::header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let interceptors = &self.config.submission_interceptors;
+        intercept_submission(interceptors, &mut submission);
+        let started = Instant::now();
+        let result = execute_challenge_stages(
+            self.submission_client(route).await?,
+            submission,
+            original,
+            self.config.post_clearance,
+            &PipelineStages {
+                inner: &self.inner,
+                interceptors,
+                user_agent: user_agent.as_deref(),
+                loop_tracker: StdMutex::new(&mut *loop_tracker),
+                loop_threshold: self.config.challenge_loop_threshold,
+            },
+            self.config.max_challenge_stages,
+        )
+        .await;
 
-            if let Some(ref mut anti) = guard.anti_detection {
-                anti.prepare_request(url.host_str().unwrap_or(""), &mut anti_ctx);
-                headers = anti_ctx.headers.clone();
+        let (final_response, wait) = match result {
+            Ok(staged) => {
+                if staged.stages > 1 {
+                    log::debug!("challenge cleared after {} stages", staged.stages);
+                }
+                (staged.response, staged.waited)
             }
+            Err(err) => {
+                let mut guard = self.inner.lock().await;
+                guard.pipeline.record_outcome(&detection.pattern_id, false);
+                return Err(err.into());
+            }
+        };
+        let challenge_latency = started.elapsed().saturating_sub(wait);
+
+        let success = self
+            .verify_clearance(&final_response, &method, loop_tracker)
+            .await;
+        {
+            let mut guard = self.inner.lock().await;
+            guard
+                .pipeline
+                .record_outcome(&detection.pattern_id, success);
+        }
+        let response = ScraperResponse::new(
+            final_response.status,
+            final_response.headers.clone(),
+            Bytes::from(final_response.body.clone()),
+            final_response.url.clone(),
+        );
+
+        self.events
+            .dispatch(ScraperEvent::Challenge(ChallengeEvent {
+                request_id,
+                domain: detection.url,
+                challenge_type: detection.pattern_name,
+                success,
+                under_attack,
+                metadata: [
+                    ("pattern".into(), detection.pattern_id),
+                    ("status".into(), final_response.status.to_string()),
+                ]
+                .into_iter()
+                .chain(cloudflare_diagnostics(&final_response.headers))
+                .collect(),
+                timestamp: chrono::Utc::now(),
+            }));
+
+        self.events
+            .dispatch(ScraperEvent::PostResponse(PostResponseEvent {
+                request_id,
+                url: response.url().clone(),
+                method: method.clone(),
+                status: response.status(),
+                headers: response.headers().clone(),
+                latency: challenge_latency,
+                delay: wait,
+                diagnostics: cloudflare_diagnostics(response.headers()),
+                timestamp: chrono::Utc::now(),
+            }));
+
+        Ok((response, challenge_latency, wait, success))
+    }
+
+    /// Confirm a submission actually cleared the challenge.
+    ///
+    /// A response that is re-detected as a challenge always counts as a
+    /// failure, and towards `loop_tracker`. Otherwise the clearance cookie is
+    /// authoritative; without it we only accept a non-error response whose
+    /// body carries no challenge markers.
+    async fn verify_clearance(
+        &self,
+        response: &ChallengeHttpResponse,
+        method: &Method,
+        loop_tracker: &mut ChallengeLoopTracker,
+    ) -> bool {
+        let body = response.text();
+        let challenge_response = ChallengeResponse {
+            url: &response.url,
+            status: response.status,
+            headers: &response.headers,
+            body: &body,
+            request_method: method,
+        };
+
+        // Classified without recording, so verification leaves detection
+        // history and stats alone.
+        let rechallenged = self
+            .inner
+            .lock()
+            .await
+            .pipeline
+            .detector()
+            .classify(&challenge_response);
+        if let Some(detection) = rechallenged {
+            loop_tracker.observe(&detection);
+            return false;
+        }
+
+        confirms_clearance(response.status, &response.headers, &body)
+    }
+
+    /// Learn from a response; `failure` is `None` when it was a success.
+    async fn record_outcome(
+        &self,
+        request_id: Uuid,
+        failure: Option<FailureContext>,
+        status: u16,
+        latency: Duration,
+        delay: Duration,
+        url: &Url,
+    ) {
+        let host = url.host_str().unwrap_or_default();
+        let domain = &*self.domain_key(host);
+        let success = failure.is_none();
+        self.state
+            .record_outcome_detailed(domain, Some(latency), Some(delay), failure);
+
+        if let Some(ref collector) = self.metrics {
+            collector.record_response(host, status, latency, delay);
+        }
+
+        let alerts = self
+            .inner
+            .lock()
+            .await
+            .learn(domain, status, latency, delay, success);
+        if !alerts.is_empty() {
+            log::warn!("[{request_id}] performance alerts: {alerts:#?}");
+        }
+    }
+
+    async fn prepare_request(
+        &self,
+        method: &Method,
+        url: &Url,
+        body_size: usize,
+        forced_proxy: Option<String>,
+        binding: Option<&SessionBinding>,
+    ) -> CloudScraperResult<(HeaderMap, AntiDetectionContext, RequestContext)> {
+        let mut guard = self.inner.lock().await;
+        let prepared = guard.prepare(
+            self.prepare_scope(),
+            method,
+            url,
+            body_size,
+            forced_proxy,
+            binding,
+        )?;
+        guard.current_proxy = prepared.2.proxy.clone();
+        Ok(prepared)
+    }
+
+    fn prepare_scope(&self) -> PrepareScope<'_> {
+        PrepareScope {
+            state: &self.state,
+            state_scope: self.config.state_scope,
+            accept_languages: &self.config.accept_languages,
+        }
+    }
+
+    /// Count one logical request towards user-agent rotation, rotating first
+    /// when it is due.
+    ///
+    /// Only called once per request, never for its retries. Requests already
+    /// in flight keep the clients they hold; the next request the budget
+    /// runs out on rotates, however many others are in flight.
+    async fn begin_request(&self) -> CloudScraperResult<()> {
+        let mut guard = self.inner.lock().await;
+        if let Some(setup) = self.maybe_rotate_user_agent(&mut guard)? {
+            // Still under the lock, so no request starts before the clients
+            // present the new browser.
+            self.client_pool.rekey(setup).await?;
+        }
+        Ok(())
+    }
+
+    /// Re-select the base user-agent profile once the configured request budget is spent.
+    ///
+    /// The fingerprint generator and TLS manager are re-aligned to the new
+    /// browser so the rotated identity stays coherent across layers. Returns
+    /// the client setup for the new browser when a rotation happened.
+    fn maybe_rotate_user_agent(
+        &self,
+        inner: &mut CloudScraperInner,
+    ) -> CloudScraperResult<Option<ClientSetup>> {
+        let Some(every) = self.config.rotate_user_agent_every else {
+            return Ok(None);
+        };
+        if self.config.user_agent.custom.is_some() {
+            return Ok(None);
+        }
+
+        inner.requests_since_ua_rotation += 1;
+        if inner.requests_since_ua_rotation <= every {
+            return Ok(None);
+        }
+        inner.requests_since_ua_rotation = 1;
+
+        let current = inner
+            .base_headers
+            .get(http::header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
 
-            if proxy.is_none() {
-                let next = guard.proxy_manager.as_mut().and_then(|pm| pm.next_proxy());
-                guard.current_proxy = next.clone();
-                proxy = next;
-            } else {
-                guard.current_proxy = proxy.clone();
+        let mut profile =
+            get_user_agent_profile_with_rng(self.config.user_agent.clone(), &mut inner.rng)?;
+        for _ in 0..UA_ROTATION_ATTEMPTS {
+            if profile.headers.get("User-Agent") != current.as_ref() {
+                break;
             }
+            profile =
+                get_user_agent_profile_with_rng(self.config.user_agent.clone(), &mut inner.rng)?;
+        }
+        inner.base_headers = to_http_headers(&profile)?;
 
-            if let Some(ref mut timing) = guard.adaptive_timing {
-                let request = TimingRequest::new(request_kind(method), body_size);
-                delay = timing.calculate_delay(url.host_str().unwrap_or(""), &request);
+        let browser = profile
+            .headers
+            .get("User-Agent")
+            .and_then(|ua| BrowserType::from_user_agent(ua));
+        if let Some(browser) = browser {
+            if let Some(generator) = inner.fingerprint.as_mut() {
+                generator.set_browser(browser);
+                generator.reset();
+            }
+            if let Some(tls) = inner.tls_manager.as_mut() {
+                tls.reset_for_browser(browser);
             }
         }
 
-        Ok((headers, anti_ctx, proxy, delay))
+        let spoofed_browser = browser.filter(|_| inner.fingerprint.is_some());
+        ClientSetup::new(&profile, inner.tls_manager.as_ref(), spoofed_browser).map(Some)
+    }
+}
+
+/// Request handle bound to one proxy and browser identity.
+///
+/// Obtained from [`CloudScraper::session`]. Mitigation plans that suggest a
+/// different proxy are not applied inside a session; the binding always wins.
+pub struct Session<'a> {
+    scraper: &'a CloudScraper,
+    binding: SessionBinding,
+}
+
+impl Session<'_> {
+    /// Proxy every request of this session is routed through, if any.
+    pub fn proxy(&self) -> Option<&str> {
+        self.binding.proxy.as_deref()
+    }
+
+    /// Perform an HTTP GET request within the session.
+    pub async fn get(&self, url: &str) -> CloudScraperResult<ScraperResponse> {
+        let url = Url::parse(url)?;
+        self.request(Method::GET, url, None).await
+    }
+
+    /// Perform an HTTP POST request within the session.
+    pub async fn post(&self, url: &str, body: Vec<u8>) -> CloudScraperResult<ScraperResponse> {
+        let url = Url::parse(url)?;
+        self.request(Method::POST, url, Some(body)).await
+    }
+
+    /// Perform an arbitrary HTTP request within the session.
+    pub async fn request(
+        &self,
+        method: Method,
+        url: Url,
+        body: Option<Vec<u8>>,
+    ) -> CloudScraperResult<ScraperResponse> {
+        self.scraper
+            .execute(
+                method,
+                url,
+                body.map(RequestBody::from),
+                ResourceType::Document,
+                Some(&self.binding),
+                None,
+            )
+            .await
+    }
+}
+
+/// The request [`CloudScraper::send_once`] puts on the wire.
+struct Outgoing<'a> {
+    method: &'a Method,
+    url: &'a Url,
+    body: Option<&'a RequestBody>,
+    resource: ResourceType,
+}
+
+/// A request body, buffered so every attempt sends the same bytes.
+struct RequestBody {
+    bytes: Vec<u8>,
+    /// Replaces the configured `Content-Type`, e.g. to carry a multipart
+    /// boundary.
+    content_type: Option<String>,
+}
+
+impl From<Vec<u8>> for RequestBody {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self {
+            bytes,
+            content_type: None,
+        }
+    }
+}
+
+impl RequestBody {
+    fn apply_content_type(&self, headers: &mut HeaderMap) -> CloudScraperResult<()> {
+        if let Some(content_type) = &self.content_type {
+            headers.insert(
+                http::header::CONTENT_TYPE,
+                HeaderValue::from_str(content_type)
+                    .map_err(|_| CloudScraperError::InvalidHeader("content-type".into()))?,
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Identity pinned for the lifetime of a [`Session`].
+pub(crate) struct SessionBinding {
+    proxy: Option<String>,
+    fingerprint: StdMutex<Option<BrowserFingerprint>>,
+    /// Base headers and TLS setup current when the session opened; user-agent
+    /// rotation does not reach them.
+    base_headers: HeaderMap,
+    setup: ClientSetup,
+    clients: StdMutex<HashMap<(Option<String>, bool), reqwest::Client>>,
+}
+
+impl SessionBinding {
+    fn fingerprint_or(&self, generate: impl FnOnce() -> BrowserFingerprint) -> BrowserFingerprint {
+        let mut slot = self
+            .fingerprint
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        slot.get_or_insert_with(generate).clone()
+    }
+}
+
+/// Outcome of a single prepared request sent over the wire.
+struct Exchange {
+    headers: HeaderMap,
+    /// Proxy the request went through, reused for any challenge submission.
+    proxy: Option<String>,
+    delay: Duration,
+    latency: Duration,
+    final_url: Url,
+    status: u16,
+    response_headers: HeaderMap,
+    body: Vec<u8>,
+    /// Identity the request was sent with.
+    context: RequestContext,
+}
+
+/// Browsers keep about six connections per HTTP/1.1 host.
+const DEFAULT_POOL_MAX_IDLE_PER_HOST: usize = 6;
+
+/// Firefox's default keep-alive timeout.
+const DEFAULT_POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(115);
+
+const DEFAULT_STATUS_RETRY_BACKOFF: Duration = Duration::from_secs(1);
+const DEFAULT_MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// Comfortably inside [`DEFAULT_POOL_IDLE_TIMEOUT`].
+const DEFAULT_WARM_INTERVAL: Duration = Duration::from_secs(60);
+const WARM_PING_TIMEOUT: Duration = Duration::from_secs(10);
+
+const DEFAULT_IDENTITY_BLACKLIST_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+const DEFAULT_CLEARANCE_REFRESH_MARGIN: Duration = Duration::from_secs(120);
+
+/// How many times rotation re-rolls the profile pool to avoid repeating the current UA.
+const UA_ROTATION_ATTEMPTS: usize = 5;
+
+/// Fresh fingerprints or TLS profiles tried before giving up on avoiding a
+/// blacklisted one.
+const IDENTITY_REROLL_ATTEMPTS: usize = 8;
+
+/// Extra solves spent on a captcha token that would arrive stale; after
+/// these the last token is submitted anyway.
+const MAX_STALE_TOKEN_RESOLVES: usize = 2;
+
+/// Storage keys written by [`CloudScraper::save_to`].
+const SNAPSHOT_KEYS: [&str; 4] = [
+    STATE_STORAGE_KEY,
+    DETECTOR_STORAGE_KEY,
+    ML_STORAGE_KEY,
+    PROXY_STORAGE_KEY,
+];
+
+/// What [`CloudScraper::recommend_profile`] weighs for a domain.
+struct ProfileSignals {
+    success_rate: f32,
+    challenge_rate: f32,
+    failure_streak: u32,
+    under_attack: bool,
+    latency: Duration,
+}
+
+fn recommended_profile(signals: ProfileSignals) -> BehaviorProfile {
+    if signals.under_attack
+        || signals.success_rate < 0.8
+        || signals.challenge_rate >= 0.2
+        || signals.failure_streak >= 3
+    {
+        BehaviorProfile::Research
+    } else if signals.success_rate >= 0.95
+        && signals.challenge_rate < 0.05
+        && signals.latency <= Duration::from_secs(1)
+    {
+        BehaviorProfile::Focused
+    } else {
+        BehaviorProfile::Casual
     }
 }
 
-fn request_kind(method: &Method) -> RequestKind {
+pub(crate) fn request_kind(method: &Method) -> RequestKind {
     match *method {
         Method::GET => RequestKind::Get,
         Method::POST => RequestKind::Post,
@@ -870,9 +3992,16 @@ fn request_kind(method: &Method) -> RequestKind {
     }
 }
 
+fn to_reqwest_tls_version(version: TlsVersion) -> reqwest::tls::Version {
+    match version {
+        TlsVersion::Tls12 => reqwest::tls::Version::TLS_1_2,
+        TlsVersion::Tls13 => reqwest::tls::Version::TLS_1_3,
+    }
+}
+
 fn to_http_headers(profile: &UserAgentProfile) -> CloudScraperResult<HeaderMap> {
     let mut headers = HeaderMap::new();
-    for (name, value) in &profile.headers {
+    for (name, value) in &profile.ordered_headers {
         let header_name = HeaderName::from_bytes(name.as_bytes())
             .map_err(|_| CloudScraperError::InvalidHeader(name.clone()))?;
         let header_value = HeaderValue::from_str(value)
@@ -894,14 +4023,2668 @@ fn to_reqwest_headers(headers: &HeaderMap) -> CloudScraperResult<reqwest::header
     Ok(map)
 }
 
-fn reqwest_to_http(headers: &reqwest::header::HeaderMap) -> CloudScraperResult<HeaderMap> {
-    let mut map = HeaderMap::new();
-    for (name, value) in headers.iter() {
-        let header_name = HeaderName::from_bytes(name.as_str().as_bytes())
-            .map_err(|_| CloudScraperError::InvalidHeader(name.to_string()))?;
-        let header_value = HeaderValue::from_bytes(value.as_bytes())
+/// Detections closer than this in confidence count as the same challenge.
+const LOOP_CONFIDENCE_TOLERANCE: f32 = 0.1;
+
+/// Counts consecutive detections of the same challenge within one request.
+#[derive(Default)]
+struct ChallengeLoopTracker {
+    last: Option<(ChallengeType, f32)>,
+    repeats: usize,
+}
+
+impl ChallengeLoopTracker {
+    /// Record a detection and return how many times in a row it has been seen.
+    fn observe(&mut self, detection: &ChallengeDetection) -> usize {
+        self.repeats = self.next_count(detection);
+        self.last = Some((detection.challenge_type, detection.confidence));
+        self.repeats
+    }
+
+    /// What [`observe`](Self::observe) would return for `detection`.
+    fn next_count(&self, detection: &ChallengeDetection) -> usize {
+        let same = self.last.is_some_and(|(challenge_type, confidence)| {
+            challenge_type == detection.challenge_type
+                && (confidence - detection.confidence).abs() <= LOOP_CONFIDENCE_TOLERANCE
+        });
+        if same { self.repeats + 1 } else { 1 }
+    }
+}
+
+/// Ensure every enabled identity layer claims the same browser engine as the
+/// user agent.
+fn check_coherence(
+    config: &CloudScraperConfig,
+    ua_browser: Option<BrowserType>,
+) -> CloudScraperResult<()> {
+    let Some(ua_browser) = ua_browser else {
+        return Err(CloudScraperError::IncoherentConfig(
+            "user agent does not identify a known browser".into(),
+        ));
+    };
+
+    let mut problems = Vec::new();
+    let tls_browser = config.tls_config.preferred_browser;
+    if config.enable_tls_fingerprinting && !ua_browser.is_compatible_with(tls_browser) {
+        problems.push(format!(
+            "user agent is {ua_browser:?} but the TLS profile prefers {tls_browser:?}"
+        ));
+    }
+    if config.enable_spoofing
+        && let Some(spoofing) = config.spoofing_browser
+        && !ua_browser.is_compatible_with(spoofing)
+    {
+        problems.push(format!(
+            "user agent is {ua_browser:?} but fingerprints spoof {spoofing:?}"
+        ));
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(CloudScraperError::IncoherentConfig(problems.join("; ")))
+    }
+}
+
+/// Whether a response no challenge was detected in shows the domain let us
+/// through: the clearance cookie is authoritative, otherwise only a
+/// non-error response whose body carries no challenge markers counts.
+pub(crate) fn confirms_clearance(status: u16, headers: &HeaderMap, body: &str) -> bool {
+    has_clearance_cookie(headers) || (status < 400 && !looks_like_challenge_page(body))
+}
+
+/// Failure record for a response with `status`, sent through `proxy`.
+pub(crate) fn status_failure(status: u16, proxy: Option<&str>) -> FailureContext {
+    let failure = FailureContext::new(format!("status_{status}")).with_status(status);
+    match proxy {
+        Some(proxy) => failure.with_proxy(proxy),
+        None => failure,
+    }
+}
+
+/// Run blocking I/O on tokio's blocking thread pool.
+async fn run_blocking<T, E>(
+    work: impl FnOnce() -> Result<T, E> + Send + 'static,
+) -> CloudScraperResult<T>
+where
+    T: Send + 'static,
+    E: Into<CloudScraperError> + Send + 'static,
+{
+    tokio::task::spawn_blocking(work)
+        .await
+        .map_err(std::io::Error::other)?
+        .map_err(Into::into)
+}
+
+/// Drive `future` to completion unless `cancel` fires first.
+async fn until_cancelled<F: Future>(
+    cancel: Option<&CancellationToken>,
+    future: F,
+) -> CloudScraperResult<F::Output> {
+    match cancel {
+        Some(token) => tokio::select! {
+            biased;
+            _ = token.cancelled() => Err(CloudScraperError::Cancelled),
+            output = future => Ok(output),
+        },
+        None => Ok(future.await),
+    }
+}
+
+/// Stable per-domain pick from `languages` when no fingerprint drives it.
+pub(crate) fn domain_accept_language<'a>(
+    languages: &'a [String],
+    domain: Option<&str>,
+) -> Option<&'a str> {
+    if languages.is_empty() {
+        return None;
+    }
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    domain.unwrap_or_default().hash(&mut hasher);
+    let index = (hasher.finish() % languages.len() as u64) as usize;
+    Some(languages[index].as_str())
+}
+
+/// Persist an unsupported challenge response for offline pattern work.
+async fn write_challenge_dump(
+    dir: &Path,
+    request_id: Uuid,
+    response: &ChallengeResponse<'_>,
+    raw_body: &[u8],
+    detection: &ChallengeDetection,
+    reason: &UnsupportedReason,
+) -> std::io::Result<PathBuf> {
+    tokio::fs::create_dir_all(dir).await?;
+    let timestamp = chrono::Utc::now().format("%Y%m%dT%H%M%S%.3fZ");
+    let path = dir.join(format!("unsupported-{timestamp}-{request_id}.txt"));
+
+    let mut contents = format!(
+        "url: {}\nmethod: {}\nstatus: {}\nreason: {reason}\npattern: {} ({})\nconfidence: {:.2}\n\n",
+        response.url, response.request_method, response.status, detection.pattern_name,
+        detection.pattern_id, detection.confidence,
+    )
+    .into_bytes();
+    for (name, value) in response.headers {
+        contents.extend_from_slice(name.as_str().as_bytes());
+        contents.extend_from_slice(b": ");
+        contents.extend_from_slice(value.as_bytes());
+        contents.push(b'\n');
+    }
+    contents.push(b'\n');
+    contents.extend_from_slice(raw_body);
+
+    tokio::fs::write(&path, contents).await?;
+    Ok(path)
+}
+
+/// Connect failures (DNS included), timeouts and dropped connections are
+/// often down to one proxy or a brief network blip, so they are retried.
+///
+/// Only a connect failure proves the request never left; after a timeout or
+/// a dropped connection the origin may already have acted on it, so those
+/// are retried for idempotent methods only.
+fn is_transient(error: &reqwest::Error, method: &Method) -> bool {
+    if error.is_connect() {
+        return true;
+    }
+    if !method.is_idempotent() {
+        return false;
+    }
+    if error.is_timeout() {
+        return true;
+    }
+    let mut source = std::error::Error::source(error);
+    while let Some(cause) = source {
+        if let Some(io) = cause.downcast_ref::<std::io::Error>()
+            && matches!(
+                io.kind(),
+                std::io::ErrorKind::ConnectionReset
+                    | std::io::ErrorKind::ConnectionAborted
+                    | std::io::ErrorKind::BrokenPipe
+                    | std::io::ErrorKind::UnexpectedEof
+            )
+        {
+            return true;
+        }
+        source = cause.source();
+    }
+    false
+}
+
+fn reqwest_to_http(headers: &reqwest::header::HeaderMap) -> CloudScraperResult<HeaderMap> {
+    let mut map = HeaderMap::new();
+    for (name, value) in headers.iter() {
+        let header_name = HeaderName::from_bytes(name.as_str().as_bytes())
             .map_err(|_| CloudScraperError::InvalidHeader(name.to_string()))?;
-        map.insert(header_name, header_value);
+        let header_value = HeaderValue::from_bytes(value.as_bytes())
+            .map_err(|_| CloudScraperError::InvalidHeader(name.to_string()))?;
+        map.append(header_name, header_value);
     }
     Ok(map)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::challenges::detectors::ResponseStrategy;
+    use crate::multipart::MultipartPart;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[derive(Clone)]
+    struct MockResponse {
+        status: u16,
+        headers: Vec<(String, String)>,
+        body: String,
+    }
+
+    impl MockResponse {
+        fn ok(body: &str) -> Self {
+            Self {
+                status: 200,
+                headers: Vec::new(),
+                body: body.to_string(),
+            }
+        }
+    }
+
+    /// Minimal HTTP/1.1 server replaying canned responses in order (the last one repeats).
+    struct MockServer {
+        url: Url,
+        requests: Arc<StdMutex<Vec<String>>>,
+    }
+
+    impl MockServer {
+        async fn start(responses: Vec<MockResponse>) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+            let requests = Arc::new(StdMutex::new(Vec::new()));
+            let recorded = requests.clone();
+
+            tokio::spawn(async move {
+                let mut served = 0usize;
+                loop {
+                    let Ok((mut socket, _)) = listener.accept().await else {
+                        break;
+                    };
+                    let raw = read_request(&mut socket).await;
+                    recorded.lock().unwrap().push(raw);
+
+                    let response = responses[served.min(responses.len() - 1)].clone();
+                    served += 1;
+
+                    let mut head = format!(
+                        "HTTP/1.1 {} Mock\r\ncontent-length: {}\r\nconnection: close\r\n",
+                        response.status,
+                        response.body.len()
+                    );
+                    for (name, value) in &response.headers {
+                        head.push_str(&format!("{name}: {value}\r\n"));
+                    }
+                    head.push_str("\r\n");
+                    let _ = socket.write_all(head.as_bytes()).await;
+                    let _ = socket.write_all(response.body.as_bytes()).await;
+                    let _ = socket.shutdown().await;
+                }
+            });
+
+            Self { url, requests }
+        }
+
+        fn requests(&self) -> Vec<String> {
+            self.requests.lock().unwrap().clone()
+        }
+    }
+
+    async fn read_request(socket: &mut tokio::net::TcpStream) -> String {
+        let mut buffer = Vec::new();
+        let mut chunk = [0u8; 4096];
+        loop {
+            let read = socket.read(&mut chunk).await.unwrap_or(0);
+            if read == 0 {
+                break;
+            }
+            buffer.extend_from_slice(&chunk[..read]);
+            let text = String::from_utf8_lossy(&buffer);
+            if let Some(end) = text.find("\r\n\r\n") {
+                let length = request_header(&text, "content-length")
+                    .and_then(|value| value.parse::<usize>().ok())
+                    .unwrap_or(0);
+                if buffer.len() >= end + 4 + length {
+                    break;
+                }
+            }
+        }
+        String::from_utf8_lossy(&buffer).to_string()
+    }
+
+    fn request_header(raw: &str, name: &str) -> Option<String> {
+        raw.split("\r\n\r\n")
+            .next()?
+            .lines()
+            .skip(1)
+            .filter_map(|line| line.split_once(':'))
+            .find(|(key, _)| key.trim().eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.trim().to_string())
+    }
+
+    const TURNSTILE_PAGE: &str = r#"<html><body>
+        <div class="cf-turnstile" data-sitekey="0123456789ABCDEFGHIJ0123456789ABCDEFGHIJ"></div>
+        <script src="https://challenges.cloudflare.com/turnstile/v0/api.js"></script>
+        </body></html>"#;
+
+    /// Builder without the subsystems that inject delays or rewrite headers.
+    fn quiet_builder() -> CloudScraperBuilder {
+        CloudScraper::builder()
+            .disable_adaptive_timing()
+            .disable_anti_detection()
+            .disable_spoofing()
+            .disable_ml_optimization()
+            .disable_performance_monitoring()
+    }
+
+    #[tokio::test]
+    async fn rotates_user_agent_after_configured_requests() {
+        let server = MockServer::start(vec![MockResponse::ok("ok")]).await;
+        let scraper = quiet_builder()
+            .with_deterministic_seed(3)
+            .with_user_agent_rotation(2)
+            .build()
+            .unwrap();
+
+        for _ in 0..3 {
+            scraper.get(server.url.as_str()).await.unwrap();
+        }
+
+        let agents: Vec<String> = server
+            .requests()
+            .iter()
+            .map(|raw| request_header(raw, "user-agent").unwrap())
+            .collect();
+        assert_eq!(agents.len(), 3);
+        assert_eq!(agents[0], agents[1]);
+        assert_ne!(agents[1], agents[2]);
+
+        let preferred = {
+            let inner = scraper.inner.lock().await;
+            inner
+                .tls_manager
+                .as_ref()
+                .unwrap()
+                .preferred_profile()
+                .cipher_suites
+                .clone()
+        };
+        let setup = scraper.client_pool.setup.lock().unwrap();
+        assert_eq!(setup.cipher_suites, preferred);
+    }
+
+    #[tokio::test]
+    async fn status_retries_do_not_advance_user_agent_rotation() {
+        let server = MockServer::start(vec![
+            MockResponse {
+                status: 503,
+                headers: Vec::new(),
+                body: "busy".into(),
+            },
+            MockResponse::ok("ok"),
+        ])
+        .await;
+        let scraper = quiet_builder()
+            .with_deterministic_seed(3)
+            .with_user_agent_rotation(2)
+            .with_retry_statuses([503], 1)
+            .with_status_retry_backoff(Duration::ZERO)
+            .build()
+            .unwrap();
+
+        for _ in 0..3 {
+            scraper.get(server.url.as_str()).await.unwrap();
+        }
+
+        let agents: Vec<String> = server
+            .requests()
+            .iter()
+            .map(|raw| request_header(raw, "user-agent").unwrap())
+            .collect();
+        assert_eq!(agents.len(), 4);
+        assert!(agents[..3].iter().all(|agent| *agent == agents[0]));
+        assert_ne!(agents[2], agents[3]);
+    }
+
+    #[tokio::test]
+    async fn user_agent_rotates_while_other_requests_are_in_flight() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let agents = Arc::new(StdMutex::new(Vec::new()));
+        let seen = agents.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let seen = seen.clone();
+                tokio::spawn(async move {
+                    let raw = read_request(&mut socket).await;
+                    if raw.starts_with("GET /slow") {
+                        sleep(Duration::from_millis(500)).await;
+                    } else {
+                        seen.lock()
+                            .unwrap()
+                            .push(request_header(&raw, "user-agent").unwrap());
+                    }
+                    let _ = socket
+                        .write_all(
+                            b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok",
+                        )
+                        .await;
+                    let _ = socket.shutdown().await;
+                });
+            }
+        });
+        let scraper = quiet_builder()
+            .with_deterministic_seed(3)
+            .with_user_agent_rotation(2)
+            .build()
+            .unwrap();
+
+        let slow_url = format!("{base}/slow");
+        let slow = scraper.get(&slow_url);
+        let fast = async {
+            sleep(Duration::from_millis(50)).await;
+            for _ in 0..3 {
+                scraper.get(&format!("{base}/fast")).await.unwrap();
+            }
+        };
+        let (slow, ()) = tokio::join!(slow, fast);
+        slow.unwrap();
+
+        let agents = agents.lock().unwrap();
+        assert_eq!(agents.len(), 3);
+        // The slow request is still in flight when the budget runs out.
+        assert_ne!(agents[0], agents[1]);
+        assert_eq!(agents[1], agents[2]);
+    }
+
+    #[tokio::test]
+    async fn same_seed_prepares_identical_requests() {
+        let server = MockServer::start(vec![MockResponse::ok("ok")]).await;
+        let run = |seed: u64| {
+            let url = server.url.clone();
+            async move {
+                let scraper = CloudScraper::builder()
+                    .with_deterministic_seed(seed)
+                    .with_max_adaptive_delay(Duration::ZERO)
+                    .with_user_agent_rotation(2)
+                    .build()
+                    .unwrap();
+                for path in ["a", "b", "c"] {
+                    scraper.get(url.join(path).unwrap().as_str()).await.unwrap();
+                }
+            }
+        };
+
+        run(7).await;
+        run(7).await;
+        run(8).await;
+        let requests = server.requests();
+        assert_eq!(requests.len(), 9);
+        assert_eq!(requests[..3], requests[3..6]);
+        assert_ne!(requests[..3], requests[6..]);
+    }
+
+    #[tokio::test]
+    async fn raw_request_returns_challenge_page_verbatim() {
+        let challenge = MockResponse {
+            status: 403,
+            headers: vec![("server".into(), "cloudflare".into())],
+            body: TURNSTILE_PAGE.into(),
+        };
+        let server = MockServer::start(vec![challenge]).await;
+        let scraper = quiet_builder().build().unwrap();
+
+        let response = scraper
+            .request_raw(Method::GET, server.url.clone(), None)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 403);
+        assert!(response.text().await.unwrap().contains("cf-turnstile"));
+
+        let solved = scraper.get(server.url.as_str()).await;
+        assert!(matches!(solved, Err(CloudScraperError::Unsupported(_))));
+    }
+
+    #[derive(Default)]
+    struct RecordingHandler(StdMutex<Vec<ScraperEvent>>);
+
+    impl EventHandler for RecordingHandler {
+        fn handle(&self, event: &ScraperEvent) {
+            self.0.lock().unwrap().push(event.clone());
+        }
+    }
+
+    #[tokio::test]
+    async fn events_share_a_correlation_id_per_request() {
+        let server = MockServer::start(vec![MockResponse::ok("ok")]).await;
+        let recorder = Arc::new(RecordingHandler::default());
+        let scraper = quiet_builder()
+            .with_event_handler(recorder.clone())
+            .build()
+            .unwrap();
+
+        scraper.get(server.url.as_str()).await.unwrap();
+        scraper.get(server.url.as_str()).await.unwrap();
+
+        let ids: Vec<Uuid> = recorder
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .map(ScraperEvent::request_id)
+            .collect();
+        assert_eq!(ids.len(), 4);
+        assert_eq!(ids[0], ids[1]);
+        assert_eq!(ids[2], ids[3]);
+        assert_ne!(ids[0], ids[2]);
+    }
+
+    #[tokio::test]
+    async fn deserializes_json_bodies() {
+        #[derive(serde::Deserialize)]
+        struct Payload {
+            id: u32,
+            name: String,
+        }
+
+        let response = ScraperResponse::new(
+            200,
+            HeaderMap::new(),
+            Bytes::from_static(br#"{"id": 7, "name": "widget"}"#),
+            Url::parse("https://example.com/api").unwrap(),
+        );
+        let payload: Payload = response.json().await.unwrap();
+        assert_eq!(payload.id, 7);
+        assert_eq!(payload.name, "widget");
+
+        let invalid = ScraperResponse::new(
+            200,
+            HeaderMap::new(),
+            Bytes::from_static(b"<html></html>"),
+            Url::parse("https://example.com/api").unwrap(),
+        );
+        assert!(matches!(
+            invalid.json::<Payload>().await,
+            Err(CloudScraperError::Deserialize(_))
+        ));
+    }
+
+    #[test]
+    fn exposes_header_helpers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("server", HeaderValue::from_static("cloudflare"));
+        headers.insert("cf-ray", HeaderValue::from_static("8a1b2c3d4e5f6789-AMS"));
+        headers.insert(
+            "content-type",
+            HeaderValue::from_static("text/html; charset=utf-8"),
+        );
+        let response = ScraperResponse::new(
+            200,
+            headers,
+            Bytes::new(),
+            Url::parse("https://example.com/").unwrap(),
+        );
+
+        assert_eq!(response.cf_ray(), Some("8a1b2c3d4e5f6789-AMS"));
+        assert_eq!(response.content_type(), Some("text/html; charset=utf-8"));
+        assert_eq!(response.header("Server"), Some("cloudflare"));
+        assert!(response.is_cloudflare());
+
+        let plain = ScraperResponse::new(
+            200,
+            HeaderMap::new(),
+            Bytes::new(),
+            Url::parse("https://example.com/").unwrap(),
+        );
+        assert_eq!(plain.cf_ray(), None);
+        assert_eq!(plain.content_type(), None);
+        assert_eq!(plain.header("x-missing"), None);
+        assert!(!plain.is_cloudflare());
+    }
+
+    #[tokio::test]
+    async fn session_pins_a_single_proxy() {
+        let first = MockServer::start(vec![MockResponse::ok("first")]).await;
+        let second = MockServer::start(vec![MockResponse::ok("second")]).await;
+        let scraper = quiet_builder()
+            .with_proxies([first.url.to_string(), second.url.to_string()])
+            .build()
+            .unwrap();
+
+        let session = scraper.session().await;
+        let pinned = session.proxy().unwrap().to_string();
+        for _ in 0..3 {
+            session.get("http://origin.test/page").await.unwrap();
+        }
+
+        let (used, unused) = if pinned == first.url.as_str() {
+            (&first, &second)
+        } else {
+            (&second, &first)
+        };
+        assert_eq!(used.requests().len(), 3);
+        assert!(unused.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn session_keeps_its_browser_across_user_agent_rotation() {
+        let server = MockServer::start(vec![MockResponse::ok("ok")]).await;
+        let scraper = quiet_builder()
+            .with_deterministic_seed(3)
+            .with_user_agent_rotation(1)
+            .build()
+            .unwrap();
+
+        let session = scraper.session().await;
+        session.get(server.url.as_str()).await.unwrap();
+        for _ in 0..3 {
+            scraper.get(server.url.as_str()).await.unwrap();
+        }
+        session.get(server.url.as_str()).await.unwrap();
+
+        let agents: Vec<String> = server
+            .requests()
+            .iter()
+            .map(|raw| request_header(raw, "user-agent").unwrap())
+            .collect();
+        assert_eq!(agents[0], agents[4]);
+        assert!(agents[1..4].iter().any(|agent| *agent != agents[0]));
+        let user_agent = |setup: &ClientSetup| setup.base_headers[http::header::USER_AGENT].clone();
+        assert_eq!(
+            user_agent(&session.binding.setup).to_str().unwrap(),
+            agents[0]
+        );
+        assert_ne!(
+            user_agent(&scraper.client_pool.current_setup()),
+            user_agent(&session.binding.setup)
+        );
+        assert!(!session.binding.clients.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn connect_failure_retries_through_another_proxy() {
+        let dead = {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            format!("http://{}/", listener.local_addr().unwrap())
+        };
+        let live = MockServer::start(vec![MockResponse::ok("through live proxy")]).await;
+        let scraper = quiet_builder()
+            .with_proxies([dead.clone(), live.url.to_string()])
+            .build()
+            .unwrap();
+
+        let response = scraper.get("http://origin.test/page").await.unwrap();
+        assert_eq!(response.text().await.unwrap(), "through live proxy");
+        assert_eq!(live.requests().len(), 1);
+        let report = scraper
+            .inner
+            .lock()
+            .await
+            .proxy_manager
+            .as_ref()
+            .unwrap()
+            .health_report();
+        assert_eq!(report.details[&dead].failures, 1);
+    }
+
+    #[tokio::test]
+    async fn dumps_unsupported_challenge_pages() {
+        let challenge = MockResponse {
+            status: 403,
+            headers: vec![("server".into(), "cloudflare".into())],
+            body: TURNSTILE_PAGE.into(),
+        };
+        let server = MockServer::start(vec![challenge]).await;
+        let dir = std::env::temp_dir().join(format!("cloudscraper-dump-{}", Uuid::new_v4()));
+        let scraper = quiet_builder()
+            .with_unsupported_dump_dir(&dir)
+            .build()
+            .unwrap();
+
+        let result = scraper.get(server.url.as_str()).await;
+        assert!(matches!(result, Err(CloudScraperError::Unsupported(_))));
+
+        let dumps: Vec<PathBuf> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(dumps.len(), 1);
+        let contents = std::fs::read_to_string(&dumps[0]).unwrap();
+        assert!(contents.contains("status: 403"));
+        assert!(contents.contains("server: cloudflare"));
+        assert!(contents.contains("cf-turnstile"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn honors_patterns_registered_on_custom_detector() {
+        let wall = MockResponse {
+            status: 403,
+            headers: vec![("server".into(), "cloudflare".into())],
+            body: "<html><body><p>custom-wall-marker</p></body></html>".into(),
+        };
+        let server = MockServer::start(vec![wall]).await;
+
+        let passthrough = quiet_builder().build().unwrap();
+        let response = passthrough.get(server.url.as_str()).await.unwrap();
+        assert_eq!(response.status(), 403);
+
+        let mut detector = ChallengeDetector::new();
+        detector
+            .add_adaptive_pattern(
+                server.url.host_str().unwrap(),
+                "Custom wall",
+                vec!["custom-wall-marker"],
+                ChallengeType::Unknown,
+                ResponseStrategy::DelayRetry,
+            )
+            .unwrap();
+        let scraper = quiet_builder().with_detector(detector).build().unwrap();
+        let result = scraper.get(server.url.as_str()).await;
+        assert!(matches!(
+            result,
+            Err(CloudScraperError::Unsupported(
+                UnsupportedReason::UnknownChallenge
+            ))
+        ));
+    }
+
+    #[tokio::test]
+    async fn replays_recorded_cassette_without_network() {
+        let server = MockServer::start(vec![
+            MockResponse::ok("first"),
+            MockResponse {
+                status: 201,
+                headers: vec![("content-type".into(), "application/json".into())],
+                body: r#"{"created":true}"#.into(),
+            },
+        ])
+        .await;
+        let cassette = std::env::temp_dir().join(format!("cloudscraper-{}.json", Uuid::new_v4()));
+        let post_url = server.url.join("items").unwrap();
+
+        let recording = quiet_builder().record_to(&cassette).build().unwrap();
+        recording.get(server.url.as_str()).await.unwrap();
+        recording
+            .request(Method::POST, post_url.clone(), Some(b"name=x".to_vec()))
+            .await
+            .unwrap();
+        assert_eq!(server.requests().len(), 2);
+        assert!(!cassette.exists());
+        recording.finish_recording().await.unwrap();
+
+        let replay = quiet_builder().replay_from(&cassette).build().unwrap();
+        let first = replay.get(server.url.as_str()).await.unwrap();
+        assert_eq!(first.text().await.unwrap(), "first");
+        let created = replay.request(Method::POST, post_url, None).await.unwrap();
+        assert_eq!(created.status(), 201);
+        assert_eq!(created.content_type(), Some("application/json"));
+        assert_eq!(server.requests().len(), 2);
+
+        let exhausted = replay.get(server.url.as_str()).await;
+        assert!(matches!(
+            exhausted,
+            Err(CloudScraperError::Cassette(CassetteError::NoMatch { .. }))
+        ));
+        std::fs::remove_file(&cassette).unwrap();
+    }
+
+    #[tokio::test]
+    async fn cancels_a_hanging_request() {
+        // Connections queue in the backlog but are never answered.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        let scraper = quiet_builder().build().unwrap();
+
+        let token = CancellationToken::new();
+        let trigger = token.clone();
+        tokio::spawn(async move {
+            sleep(Duration::from_millis(100)).await;
+            trigger.cancel();
+        });
+
+        let result = tokio::time::timeout(
+            Duration::from_secs(5),
+            scraper.request_with_cancel(Method::GET, url.clone(), None, &token),
+        )
+        .await
+        .expect("cancellation should end the request");
+        assert!(matches!(result, Err(CloudScraperError::Cancelled)));
+
+        let again = scraper
+            .request_with_cancel(Method::GET, url, None, &token)
+            .await;
+        assert!(matches!(again, Err(CloudScraperError::Cancelled)));
+        drop(listener);
+    }
+
+    #[tokio::test]
+    async fn exposes_learned_timing_per_domain() {
+        let server = MockServer::start(vec![MockResponse::ok("ok")]).await;
+        let cap = Duration::from_millis(20);
+        let scraper = CloudScraper::builder()
+            .disable_anti_detection()
+            .disable_spoofing()
+            .disable_ml_optimization()
+            .disable_performance_monitoring()
+            .with_max_adaptive_delay(cap)
+            .build()
+            .unwrap();
+        let domain = server.url.host_str().unwrap().to_string();
+        assert!(scraper.timing_snapshot(&domain).await.is_none());
+
+        for _ in 0..3 {
+            scraper.get(server.url.as_str()).await.unwrap();
+        }
+
+        let snapshot = scraper.timing_snapshot(&domain).await.unwrap();
+        assert_eq!(snapshot.consecutive_failures, 0);
+        assert!((snapshot.success_rate - 1.0).abs() < f32::EPSILON);
+        let learned = snapshot.optimal_timing.unwrap();
+        assert!(learned <= cap);
+        assert_eq!(scraper.optimal_delay(&domain).await, Some(learned));
+        assert!(scraper.optimal_delay("unseen.example").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn native_tls_backend_is_always_selectable() {
+        let server = MockServer::start(vec![MockResponse::ok("native")]).await;
+        let scraper = quiet_builder()
+            .with_tls_backend(TlsBackend::NativeTls)
+            .build()
+            .unwrap();
+        let response = scraper.get(server.url.as_str()).await.unwrap();
+        assert_eq!(response.text().await.unwrap(), "native");
+    }
+
+    #[tokio::test]
+    async fn unavailable_backends_fail_with_tls_error() {
+        let server = MockServer::start(vec![MockResponse::ok("ok")]).await;
+        let mut backends = vec![TlsBackend::Boring];
+        if !cfg!(feature = "rustls") {
+            backends.push(TlsBackend::Rustls);
+        }
+        for backend in backends {
+            let result = match quiet_builder().with_tls_backend(backend).build() {
+                Ok(scraper) => scraper.get(server.url.as_str()).await.map(|_| ()),
+                Err(err) => Err(err),
+            };
+            assert!(matches!(result, Err(CloudScraperError::Tls(_))));
+        }
+    }
+
+    fn custom_agent(user_agent: &str) -> UserAgentOptions {
+        UserAgentOptions {
+            custom: Some(user_agent.into()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn strict_coherence_accepts_matching_identity() {
+        let chrome = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36";
+        let built = CloudScraper::builder()
+            .with_user_agent_options(custom_agent(chrome))
+            .with_spoofing_browser(BrowserType::Edge)
+            .strict_coherence()
+            .build();
+        assert!(built.is_ok());
+    }
+
+    #[test]
+    fn strict_coherence_rejects_mixed_identity() {
+        let firefox =
+            "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:121.0) Gecko/20100101 Firefox/121.0";
+        let built = CloudScraper::builder()
+            .with_user_agent_options(custom_agent(firefox))
+            .with_spoofing_browser(BrowserType::Safari)
+            .strict_coherence()
+            .build();
+        let Err(CloudScraperError::IncoherentConfig(message)) = built else {
+            panic!("expected an incoherent configuration error");
+        };
+        assert!(message.contains("TLS profile prefers Chrome"));
+        assert!(message.contains("fingerprints spoof Safari"));
+    }
+
+    #[tokio::test]
+    async fn reports_challenge_loops() {
+        let limited = MockResponse {
+            status: 429,
+            headers: vec![
+                ("server".into(), "cloudflare".into()),
+                ("retry-after".into(), "0".into()),
+            ],
+            body: "<title>Rate Limited</title> You are being rate limited".into(),
+        };
+        let server = MockServer::start(vec![limited]).await;
+        let scraper = quiet_builder()
+            .with_max_challenge_attempts(5)
+            .with_challenge_loop_threshold(2)
+            .build()
+            .unwrap();
+
+        let result = scraper.get(server.url.as_str()).await;
+        let Err(CloudScraperError::ChallengeLoop {
+            challenge_type,
+            attempts,
+            last_response,
+        }) = result
+        else {
+            panic!("expected a challenge loop, got {result:?}");
+        };
+        assert_eq!(challenge_type, ChallengeType::RateLimit);
+        assert_eq!(attempts, 2);
+        assert_eq!(last_response.status(), 429);
+        assert!(last_response.text().await.unwrap().contains("rate limited"));
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn challenge_served_back_after_each_submission_is_a_loop() {
+        let challenge = MockResponse {
+            status: 403,
+            headers: vec![("server".into(), "cloudflare".into())],
+            body: TURNSTILE_PAGE.into(),
+        };
+        let server = MockServer::start(vec![challenge]).await;
+        let scraper = quiet_builder()
+            .with_challenge_loop_threshold(3)
+            .with_max_challenge_stages(5)
+            .build()
+            .unwrap();
+        {
+            let mut guard = scraper.inner.lock().await;
+            let turnstile = TurnstileSolver::new()
+                .with_delay_range(Duration::ZERO, Duration::ZERO)
+                .with_captcha_provider(Arc::new(TokenProvider));
+            guard.pipeline =
+                ChallengePipeline::new(ChallengeDetector::default()).with_turnstile(turnstile);
+        }
+
+        let result = scraper.get(server.url.as_str()).await;
+        let Err(CloudScraperError::ChallengeLoop {
+            challenge_type,
+            attempts,
+            last_response,
+        }) = result
+        else {
+            panic!("expected a challenge loop, got {result:?}");
+        };
+        assert_eq!(challenge_type, ChallengeType::Turnstile);
+        assert_eq!(attempts, 3);
+        assert_eq!(last_response.status(), 403);
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn emits_accept_language_from_configured_set() {
+        let server = MockServer::start(vec![MockResponse::ok("ok")]).await;
+        let languages = ["fr-FR,fr;q=0.9,en;q=0.5", "es-ES,es;q=0.8"];
+        let spoofed = CloudScraper::builder()
+            .disable_adaptive_timing()
+            .disable_anti_detection()
+            .disable_ml_optimization()
+            .disable_performance_monitoring()
+            .with_accept_languages(languages)
+            .build()
+            .unwrap();
+        let plain = quiet_builder()
+            .with_accept_languages(languages)
+            .build()
+            .unwrap();
+
+        for scraper in [&spoofed, &plain, &spoofed] {
+            scraper.get(server.url.as_str()).await.unwrap();
+        }
+
+        let emitted: Vec<String> = server
+            .requests()
+            .iter()
+            .map(|raw| request_header(raw, "accept-language").unwrap())
+            .collect();
+        assert_eq!(emitted.len(), 3);
+        assert!(
+            emitted
+                .iter()
+                .all(|value| languages.contains(&value.as_str()))
+        );
+        assert_eq!(emitted[0], emitted[2]);
+    }
+
+    #[tokio::test]
+    async fn request_interceptor_headers_reach_the_server() {
+        let server = MockServer::start(vec![MockResponse::ok("ok")]).await;
+        let scraper = CloudScraper::builder()
+            .disable_adaptive_timing()
+            .disable_ml_optimization()
+            .disable_performance_monitoring()
+            .with_request_interceptor(Box::new(|headers, url, method| {
+                let token = format!("Bearer {}-{}", method, url.path());
+                headers.insert("authorization", HeaderValue::from_str(&token).unwrap());
+                headers.insert("user-agent", HeaderValue::from_static("interceptor/1.0"));
+            }))
+            .build()
+            .unwrap();
+
+        scraper.get(&format!("{}orders", server.url)).await.unwrap();
+
+        let raw = &server.requests()[0];
+        assert_eq!(
+            request_header(raw, "authorization").as_deref(),
+            Some("Bearer GET-/orders")
+        );
+        assert_eq!(
+            request_header(raw, "user-agent").as_deref(),
+            Some("interceptor/1.0")
+        );
+    }
+
+    #[tokio::test]
+    async fn response_interceptor_retries_soft_blocks() {
+        let server = MockServer::start(vec![
+            MockResponse::ok("<p>You are blocked (ref SOFT-403)</p>"),
+            MockResponse::ok("welcome"),
+        ])
+        .await;
+        let scraper = quiet_builder()
+            .with_response_interceptor(Box::new(|response| {
+                if String::from_utf8_lossy(response.body()).contains("SOFT-403") {
+                    ResponseAction::Retry
+                } else {
+                    ResponseAction::Continue
+                }
+            }))
+            .build()
+            .unwrap();
+
+        let response = scraper.get(server.url.as_str()).await.unwrap();
+        assert_eq!(response.text().await.unwrap(), "welcome");
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    /// Keep-alive server counting accepted connections.
+    async fn keep_alive_server() -> (Url, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let accepted = connections.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                accepted.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(async move {
+                    while !read_request(&mut socket).await.is_empty() {
+                        let reply = "HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok";
+                        if socket.write_all(reply.as_bytes()).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+        (url, connections)
+    }
+
+    #[tokio::test]
+    async fn applies_connection_pool_settings_to_clients() {
+        let (url, connections) = keep_alive_server().await;
+        let pooled = quiet_builder().build().unwrap();
+        for _ in 0..3 {
+            pooled.get(url.as_str()).await.unwrap();
+        }
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+
+        let (url, connections) = keep_alive_server().await;
+        let unpooled = quiet_builder()
+            .with_connection_pool(0, None)
+            .build()
+            .unwrap();
+        for _ in 0..3 {
+            unpooled.get(url.as_str()).await.unwrap();
+        }
+        assert_eq!(connections.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn fresh_connection_strategy_disables_pooling() {
+        let (url, connections) = keep_alive_server().await;
+        let fresh = quiet_builder()
+            .with_connection_strategy(ConnectionStrategy::FreshPerRequest)
+            .build()
+            .unwrap();
+        assert_eq!(fresh.client_pool.max_idle_per_host, 0);
+        for _ in 0..3 {
+            fresh.get(url.as_str()).await.unwrap();
+        }
+        assert_eq!(connections.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn tracks_domain_challenge_status() {
+        let mut challenge = MockResponse::ok(TURNSTILE_PAGE);
+        challenge.status = 403;
+        challenge.headers = vec![("server".into(), "cloudflare".into())];
+        let mut cleared = MockResponse::ok("welcome");
+        cleared.headers = vec![(
+            "set-cookie".into(),
+            "cf_clearance=granted; Max-Age=1800; path=/".into(),
+        )];
+        let server = MockServer::start(vec![challenge, cleared]).await;
+        let host = server.url.host_str().unwrap().to_string();
+        let scraper = quiet_builder().build().unwrap();
+
+        assert_eq!(
+            scraper.domain_challenge_status(&host),
+            ChallengeStatus::Unknown
+        );
+
+        assert!(scraper.get(server.url.as_str()).await.is_err());
+        assert_eq!(
+            scraper.domain_challenge_status(&host),
+            ChallengeStatus::Challenged(ChallengeType::Turnstile)
+        );
+
+        scraper.get(server.url.as_str()).await.unwrap();
+        match scraper.domain_challenge_status(&host) {
+            ChallengeStatus::Clear { until: Some(until) } => {
+                assert!(until > chrono::Utc::now() + chrono::Duration::minutes(29));
+            }
+            other => panic!("expected clearance with expiry, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn challenge_inside_clearance_window_escalates_to_under_attack() {
+        let mut cleared = MockResponse::ok("welcome");
+        cleared.headers = vec![(
+            "set-cookie".into(),
+            "cf_clearance=granted; Max-Age=1800; path=/".into(),
+        )];
+        let mut challenge = MockResponse::ok(TURNSTILE_PAGE);
+        challenge.status = 403;
+        challenge.headers = vec![("server".into(), "cloudflare".into())];
+        let server = MockServer::start(vec![cleared, challenge]).await;
+        let host = server.url.host_str().unwrap().to_string();
+        let recorder = Arc::new(RecordingHandler::default());
+        let scraper = CloudScraper::builder()
+            .disable_anti_detection()
+            .disable_spoofing()
+            .disable_ml_optimization()
+            .disable_performance_monitoring()
+            .with_max_adaptive_delay(Duration::from_millis(20))
+            .with_event_handler(recorder.clone())
+            .build()
+            .unwrap();
+
+        scraper.get(server.url.as_str()).await.unwrap();
+        assert!(scraper.get(server.url.as_str()).await.is_err());
+
+        let flagged = recorder.0.lock().unwrap().iter().any(
+            |event| matches!(event, ScraperEvent::Challenge(challenge) if challenge.under_attack),
+        );
+        assert!(flagged);
+        assert!(scraper.state.get(&host).unwrap().under_attack());
+        let guard = scraper.inner.lock().await;
+        let timing = guard.adaptive_timing.as_ref().unwrap();
+        assert_eq!(timing.domain_profile(&host), BehaviorProfile::Research);
+    }
+
+    #[tokio::test]
+    async fn origin_errors_do_not_escalate_a_cleared_domain() {
+        let mut cleared = MockResponse::ok("welcome");
+        cleared.headers = vec![(
+            "set-cookie".into(),
+            "cf_clearance=granted; Max-Age=1800; path=/".into(),
+        )];
+        let origin_error = MockResponse {
+            status: 522,
+            headers: vec![("server".into(), "cloudflare".into())],
+            body: "Connection timed out".into(),
+        };
+        let server = MockServer::start(vec![cleared, origin_error]).await;
+        let host = server.url.host_str().unwrap().to_string();
+        let scraper = CloudScraper::builder()
+            .disable_anti_detection()
+            .disable_spoofing()
+            .disable_ml_optimization()
+            .disable_performance_monitoring()
+            .with_max_adaptive_delay(Duration::from_millis(20))
+            .build()
+            .unwrap();
+        scraper.inner.lock().await.pipeline = ChallengePipeline::new(ChallengeDetector::default())
+            .with_origin_error(
+                OriginErrorHandler::new().with_delay_range(Duration::ZERO, Duration::ZERO),
+            );
+
+        scraper.get(server.url.as_str()).await.unwrap();
+        assert!(scraper.get(server.url.as_str()).await.is_err());
+
+        assert!(matches!(
+            scraper.domain_challenge_status(&host),
+            ChallengeStatus::Clear { .. }
+        ));
+        assert!(!scraper.state.get(&host).unwrap().under_attack());
+        let guard = scraper.inner.lock().await;
+        let timing = guard.adaptive_timing.as_ref().unwrap();
+        assert_ne!(timing.domain_profile(&host), BehaviorProfile::Research);
+    }
+
+    #[tokio::test]
+    async fn rate_limits_do_not_escalate_a_cleared_domain() {
+        let mut cleared = MockResponse::ok("welcome");
+        cleared.headers = vec![(
+            "set-cookie".into(),
+            "cf_clearance=granted; Max-Age=1800; path=/".into(),
+        )];
+        let rate_limited = MockResponse {
+            status: 429,
+            headers: vec![("server".into(), "cloudflare".into())],
+            body: r#"<span class="cf-error-code">1015</span> You are being rate limited"#.into(),
+        };
+        let server = MockServer::start(vec![cleared, rate_limited]).await;
+        let host = server.url.host_str().unwrap().to_string();
+        let scraper = CloudScraper::builder()
+            .disable_anti_detection()
+            .disable_spoofing()
+            .disable_ml_optimization()
+            .disable_performance_monitoring()
+            .with_max_adaptive_delay(Duration::from_millis(20))
+            .build()
+            .unwrap();
+        scraper.inner.lock().await.pipeline = ChallengePipeline::new(ChallengeDetector::default())
+            .with_rate_limit(
+                RateLimitHandler::new().with_delay_range(Duration::ZERO, Duration::ZERO),
+            );
+
+        scraper.get(server.url.as_str()).await.unwrap();
+        assert!(scraper.get(server.url.as_str()).await.is_err());
+
+        assert!(!scraper.state.get(&host).unwrap().under_attack());
+        let guard = scraper.inner.lock().await;
+        let timing = guard.adaptive_timing.as_ref().unwrap();
+        assert_ne!(timing.domain_profile(&host), BehaviorProfile::Research);
+    }
+
+    #[tokio::test]
+    async fn capabilities_report_a_missing_captcha_provider() {
+        let scraper = quiet_builder().build().unwrap();
+        let capabilities = scraper.capabilities().await;
+        assert!(capabilities.can_solve(ChallengeType::JavaScriptV1));
+        assert!(capabilities.can_solve(ChallengeType::ManagedV3));
+        assert!(!capabilities.can_solve(ChallengeType::Turnstile));
+        assert_eq!(
+            capabilities.missing_reason(ChallengeType::Turnstile),
+            Some(UnsupportedReason::MissingDependency("captcha_provider"))
+        );
+
+        let scraper = quiet_builder()
+            .with_captcha_provider(Arc::new(crate::AntiCaptchaProvider::new("key")))
+            .build()
+            .unwrap();
+        let capabilities = scraper.capabilities().await;
+        assert!(capabilities.can_solve(ChallengeType::Turnstile));
+        assert!(capabilities.missing.is_empty());
+    }
+
+    #[tokio::test]
+    async fn exports_a_har_log_of_each_exchange() {
+        let server = MockServer::start(vec![MockResponse::ok("ok")]).await;
+        let scraper = quiet_builder().enable_har_log().build().unwrap();
+        scraper.get(server.url.as_str()).await.unwrap();
+
+        let har: serde_json::Value = serde_json::from_str(&scraper.export_har()).unwrap();
+        assert_eq!(har["log"]["version"], "1.2");
+        let entries = har["log"]["entries"].as_array().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0]["request"]["method"], "GET");
+        assert_eq!(entries[0]["request"]["url"], server.url.as_str());
+        assert!(
+            !entries[0]["request"]["headers"]
+                .as_array()
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(entries[0]["response"]["status"], 200);
+        assert!(entries[0]["timings"]["wait"].as_f64().unwrap() >= 0.0);
+
+        let disabled = quiet_builder().build().unwrap();
+        let empty: serde_json::Value = serde_json::from_str(&disabled.export_har()).unwrap();
+        assert!(empty["log"]["entries"].as_array().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn seeded_sticky_headers_reach_the_first_request() {
+        let server = MockServer::start(vec![MockResponse::ok("ok")]).await;
+        let host = server.url.host_str().unwrap().to_string();
+        let scraper = quiet_builder()
+            .with_domain_seed(
+                &host,
+                HashMap::from([("cf_clearance".to_string(), "resumed".to_string())]),
+                HashMap::from([("x-resume-token".to_string(), "abc123".to_string())]),
+            )
+            .build()
+            .unwrap();
+
+        scraper.get(server.url.as_str()).await.unwrap();
+
+        let requests = server.requests();
+        assert_eq!(
+            request_header(&requests[0], "x-resume-token").as_deref(),
+            Some("abc123")
+        );
+        let state = scraper.state.get(&host).unwrap();
+        assert_eq!(state.cookies["cf_clearance"], "resumed");
+    }
+
+    #[tokio::test]
+    async fn passthrough_domains_skip_pacing_and_detection() {
+        let challenge = MockResponse {
+            status: 403,
+            headers: vec![("server".into(), "cloudflare".into())],
+            body: TURNSTILE_PAGE.into(),
+        };
+        let server = MockServer::start(vec![challenge]).await;
+        let recorder = Arc::new(RecordingHandler::default());
+        let scraper = CloudScraper::builder()
+            .with_passthrough_domains([server.url.host_str().unwrap()])
+            .with_event_handler(recorder.clone())
+            .build()
+            .unwrap();
+
+        let response = scraper.get(server.url.as_str()).await.unwrap();
+
+        assert_eq!(response.status(), 403);
+        let events = recorder.0.lock().unwrap();
+        assert!(
+            events
+                .iter()
+                .all(|event| !matches!(event, ScraperEvent::Challenge(_)))
+        );
+        assert!(events.iter().any(|event| matches!(
+            event,
+            ScraperEvent::PostResponse(post) if post.delay == Duration::ZERO
+        )));
+        assert_eq!(
+            scraper.domain_challenge_status(server.url.host_str().unwrap()),
+            ChallengeStatus::Unknown
+        );
+    }
+
+    #[tokio::test]
+    async fn rotate_identity_changes_fingerprint_tls_and_proxy() {
+        let first = MockServer::start(vec![MockResponse::ok("first")]).await;
+        let second = MockServer::start(vec![MockResponse::ok("second")]).await;
+        let scraper = CloudScraper::builder()
+            .disable_adaptive_timing()
+            .disable_anti_detection()
+            .disable_ml_optimization()
+            .disable_performance_monitoring()
+            .with_proxies([first.url.to_string(), second.url.to_string()])
+            .build()
+            .unwrap();
+        let domain = "origin.test";
+        let url = "http://origin.test/page";
+
+        scraper.get(url).await.unwrap();
+        let (fingerprint, ja3, proxy) = {
+            let mut guard = scraper.inner.lock().await;
+            let fingerprint = guard.fingerprint.as_mut().unwrap().generate_for(domain);
+            let ja3 = guard
+                .tls_manager
+                .as_mut()
+                .unwrap()
+                .current_profile(domain)
+                .ja3;
+            (fingerprint, ja3, guard.domain_proxies[domain].clone())
+        };
+
+        scraper.rotate_identity(domain).await;
+        scraper.get(url).await.unwrap();
+
+        let mut guard = scraper.inner.lock().await;
+        let rotated = guard.fingerprint.as_mut().unwrap().generate_for(domain);
+        assert_ne!(rotated.canvas_fingerprint, fingerprint.canvas_fingerprint);
+        let rotated_ja3 = guard
+            .tls_manager
+            .as_mut()
+            .unwrap()
+            .current_profile(domain)
+            .ja3;
+        assert_ne!(rotated_ja3, ja3);
+        assert_ne!(guard.domain_proxies[domain], proxy);
+    }
+
+    struct TokenProvider;
+
+    #[async_trait::async_trait]
+    impl CaptchaProvider for TokenProvider {
+        fn name(&self) -> &'static str {
+            "token"
+        }
+
+        async fn solve(&self, _task: &crate::CaptchaTask) -> crate::CaptchaResult {
+            Ok(crate::CaptchaSolution::new("token"))
+        }
+    }
+
+    #[tokio::test]
+    async fn challenge_submission_reuses_the_request_proxy() {
+        let challenge = MockResponse {
+            status: 403,
+            headers: vec![("server".into(), "cloudflare".into())],
+            body: TURNSTILE_PAGE.into(),
+        };
+        let first = MockServer::start(vec![challenge.clone(), MockResponse::ok("welcome")]).await;
+        let second = MockServer::start(vec![challenge, MockResponse::ok("welcome")]).await;
+        let scraper = quiet_builder()
+            .with_proxies([first.url.to_string(), second.url.to_string()])
+            .build()
+            .unwrap();
+        {
+            let mut guard = scraper.inner.lock().await;
+            let turnstile = TurnstileSolver::new()
+                .with_delay_range(Duration::ZERO, Duration::ZERO)
+                .with_captcha_provider(Arc::new(TokenProvider));
+            guard.pipeline =
+                ChallengePipeline::new(ChallengeDetector::default()).with_turnstile(turnstile);
+        }
+
+        scraper.get("http://origin.test/page").await.unwrap();
+
+        let (used, unused) = if first.requests().is_empty() {
+            (second, first)
+        } else {
+            (first, second)
+        };
+        assert!(unused.requests().is_empty());
+        let requests = used.requests();
+        assert!(requests.len() >= 2, "{requests:?}");
+        assert!(requests[0].starts_with("GET http://origin.test/page"));
+        assert!(requests[1].starts_with("POST http://origin.test/"));
+    }
+
+    struct FailingProvider;
+
+    #[async_trait::async_trait]
+    impl CaptchaProvider for FailingProvider {
+        fn name(&self) -> &'static str {
+            "failing"
+        }
+
+        async fn solve(&self, _task: &crate::CaptchaTask) -> crate::CaptchaResult {
+            Err(crate::CaptchaError::Provider("no workers".into()))
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_captcha_solve_is_recorded_with_its_context() {
+        let proxy = MockServer::start(vec![MockResponse {
+            status: 403,
+            headers: vec![("server".into(), "cloudflare".into())],
+            body: TURNSTILE_PAGE.into(),
+        }])
+        .await;
+        let scraper = quiet_builder()
+            .with_proxies([proxy.url.to_string()])
+            .build()
+            .unwrap();
+        scraper.inner.lock().await.pipeline = ChallengePipeline::new(ChallengeDetector::default())
+            .with_turnstile(
+                TurnstileSolver::new()
+                    .with_delay_range(Duration::ZERO, Duration::ZERO)
+                    .with_captcha_provider(Arc::new(FailingProvider)),
+            );
+
+        assert!(scraper.get("http://origin.test/page").await.is_err());
+        let state = scraper.state.get("origin.test").unwrap();
+        let failure = state.recent_errors.back().unwrap();
+        assert_eq!(failure.code, Some(403));
+        assert_eq!(failure.challenge_type, Some(ChallengeType::Turnstile));
+        assert_eq!(failure.proxy.as_deref(), Some(proxy.url.as_str()));
+        assert!(failure.captcha_failure);
+    }
+
+    #[tokio::test]
+    async fn access_denied_stops_after_max_proxies_per_request() {
+        let denied = MockResponse {
+            status: 403,
+            headers: vec![("server".into(), "cloudflare".into())],
+            body: r#"<span class="cf-error-code">1020</span> Access denied"#.into(),
+        };
+        let mut servers = Vec::new();
+        for _ in 0..4 {
+            servers.push(MockServer::start(vec![denied.clone()]).await);
+        }
+        let scraper = quiet_builder()
+            .with_proxies(servers.iter().map(|server| server.url.to_string()))
+            .with_max_proxies_per_request(2)
+            .with_max_challenge_attempts(10)
+            .with_challenge_loop_threshold(10)
+            .build()
+            .unwrap();
+        scraper.inner.lock().await.pipeline = ChallengePipeline::new(ChallengeDetector::default())
+            .with_access_denied(
+                AccessDeniedHandler::new().with_delay_range(Duration::ZERO, Duration::ZERO),
+            );
+
+        match scraper.get("http://origin.test/page").await {
+            Err(CloudScraperError::ProxiesExhausted { tried }) => {
+                assert_eq!(tried.len(), 2);
+                assert_ne!(tried[0], tried[1]);
+            }
+            other => panic!("expected proxies exhausted, got {other:?}"),
+        }
+        let used = servers
+            .iter()
+            .filter(|server| !server.requests().is_empty())
+            .count();
+        assert_eq!(used, 2);
+    }
+
+    #[tokio::test]
+    async fn access_denied_rotation_skips_blacklisted_proxies() {
+        let denied = MockResponse {
+            status: 403,
+            headers: vec![("server".into(), "cloudflare".into())],
+            body: r#"<span class="cf-error-code">1020</span> Access denied"#.into(),
+        };
+        let mut servers = Vec::new();
+        for _ in 0..3 {
+            servers.push(MockServer::start(vec![denied.clone()]).await);
+        }
+        let scraper = quiet_builder()
+            .with_proxies(servers.iter().map(|server| server.url.to_string()))
+            .with_max_challenge_attempts(10)
+            .with_challenge_loop_threshold(10)
+            .build()
+            .unwrap();
+        scraper.inner.lock().await.pipeline = ChallengePipeline::new(ChallengeDetector::default())
+            .with_access_denied(
+                AccessDeniedHandler::new().with_delay_range(Duration::ZERO, Duration::ZERO),
+            );
+        for server in &servers[1..] {
+            scraper
+                .blacklist_identity("origin.test", BlacklistKey::Proxy(server.url.to_string()))
+                .await;
+        }
+
+        match scraper.get("http://origin.test/page").await {
+            Err(CloudScraperError::ProxiesExhausted { tried }) => {
+                assert_eq!(tried, vec![servers[0].url.to_string()]);
+            }
+            other => panic!("expected proxies exhausted, got {other:?}"),
+        }
+        assert!(
+            servers[1..]
+                .iter()
+                .all(|server| server.requests().is_empty())
+        );
+    }
+
+    #[tokio::test]
+    async fn submission_interceptor_patches_the_submitted_form() {
+        let server = MockServer::start(vec![
+            MockResponse {
+                status: 403,
+                headers: vec![("server".into(), "cloudflare".into())],
+                body: TURNSTILE_PAGE.into(),
+            },
+            MockResponse::ok("welcome"),
+        ])
+        .await;
+        let scraper = quiet_builder()
+            .with_submission_interceptor(Box::new(|submission| {
+                submission
+                    .form_fields
+                    .insert("site_quirk".into(), "1".into());
+            }))
+            .build()
+            .unwrap();
+        scraper.inner.lock().await.pipeline = ChallengePipeline::new(ChallengeDetector::default())
+            .with_turnstile(
+                TurnstileSolver::new()
+                    .with_delay_range(Duration::ZERO, Duration::ZERO)
+                    .with_captcha_provider(Arc::new(TokenProvider)),
+            );
+
+        scraper.get(server.url.as_str()).await.unwrap();
+
+        let requests = server.requests();
+        let post = requests
+            .iter()
+            .find(|request| request.starts_with("POST "))
+            .expect("challenge submission");
+        assert!(post.contains("site_quirk=1"), "{post}");
+    }
+
+    struct ExpiringTokenProvider(std::sync::atomic::AtomicUsize);
+
+    #[async_trait::async_trait]
+    impl CaptchaProvider for ExpiringTokenProvider {
+        fn name(&self) -> &'static str {
+            "expiring"
+        }
+
+        async fn solve(&self, _task: &crate::CaptchaTask) -> crate::CaptchaResult {
+            let call = self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+            let ttl = if call == 1 { 1 } else { 300 };
+            Ok(crate::CaptchaSolution::new(format!("token-{call}"))
+                .with_expiry(Duration::from_secs(ttl)))
+        }
+    }
+
+    #[tokio::test]
+    async fn short_lived_captcha_token_is_solved_again() {
+        let server = MockServer::start(vec![
+            MockResponse {
+                status: 403,
+                headers: vec![("server".into(), "cloudflare".into())],
+                body: TURNSTILE_PAGE.into(),
+            },
+            MockResponse::ok("welcome"),
+        ])
+        .await;
+        let scraper = quiet_builder().build().unwrap();
+        let provider = Arc::new(ExpiringTokenProvider(Default::default()));
+        scraper.inner.lock().await.pipeline = ChallengePipeline::new(ChallengeDetector::default())
+            .with_turnstile(
+                TurnstileSolver::new()
+                    .with_delay_range(Duration::ZERO, Duration::ZERO)
+                    .with_captcha_provider(provider.clone()),
+            );
+
+        scraper.get(server.url.as_str()).await.unwrap();
+
+        assert_eq!(provider.0.load(std::sync::atomic::Ordering::SeqCst), 2);
+        let guard = scraper.inner.lock().await;
+        assert_eq!(guard.pipeline.detector().detection_history().count(), 1);
+        drop(guard);
+        let requests = server.requests();
+        let post = requests
+            .iter()
+            .find(|request| request.starts_with("POST "))
+            .expect("challenge submission");
+        assert!(post.contains("cf-turnstile-response=token-2"), "{post}");
+    }
+
+    #[tokio::test]
+    async fn not_modified_is_answered_from_the_cache() {
+        let server = MockServer::start(vec![
+            MockResponse {
+                status: 200,
+                headers: vec![("etag".into(), "\"v1\"".into())],
+                body: "cached body".into(),
+            },
+            MockResponse {
+                status: 304,
+                headers: Vec::new(),
+                body: String::new(),
+            },
+        ])
+        .await;
+        let scraper = quiet_builder()
+            .enable_cache(CacheConfig::default())
+            .build()
+            .unwrap();
+
+        let first = scraper.get(server.url.as_str()).await.unwrap();
+        let second = scraper.get(server.url.as_str()).await.unwrap();
+
+        assert_eq!(second.status(), 200);
+        assert_eq!(second.text().await.unwrap(), first.text().await.unwrap());
+        let requests = server.requests();
+        assert!(!requests[0].to_ascii_lowercase().contains("if-none-match"));
+        assert!(
+            requests[1]
+                .to_ascii_lowercase()
+                .contains("if-none-match: \"v1\""),
+            "{}",
+            requests[1]
+        );
+    }
+
+    #[tokio::test]
+    async fn concurrent_requests_respect_the_global_limit() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let active = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let (server_active, server_peak) = (active.clone(), peak.clone());
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let (active, peak) = (server_active.clone(), server_peak.clone());
+                tokio::spawn(async move {
+                    read_request(&mut socket).await;
+                    let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    sleep(Duration::from_millis(100)).await;
+                    active.fetch_sub(1, Ordering::SeqCst);
+                    let _ = socket
+                        .write_all(
+                            b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok",
+                        )
+                        .await;
+                    let _ = socket.shutdown().await;
+                });
+            }
+        });
+        let scraper = quiet_builder()
+            .with_max_concurrent_requests(2)
+            .build()
+            .unwrap();
+
+        let get = || scraper.get(&url);
+        let results = tokio::join!(get(), get(), get(), get(), get(), get());
+        for result in <[_; 6]>::from(results) {
+            result.unwrap();
+        }
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn dropped_connections_are_retried_for_idempotent_methods_only() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let seen = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                read_request(&mut socket).await;
+                seen.fetch_add(1, Ordering::SeqCst);
+                // Reset rather than close, as a proxy dropping the request would.
+                socket.set_linger(Some(Duration::ZERO)).unwrap();
+                drop(socket);
+            }
+        });
+        let scraper = quiet_builder()
+            .with_max_transient_retries(2)
+            .build()
+            .unwrap();
+
+        let post = scraper.request(
+            Method::POST,
+            Url::parse(&url).unwrap(),
+            Some(b"order=1".to_vec()),
+        );
+        assert!(post.await.is_err());
+        assert_eq!(requests.swap(0, Ordering::SeqCst), 1);
+
+        assert!(scraper.get(&url).await.is_err());
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn accept_header_follows_the_resource_type() {
+        let server = MockServer::start(vec![MockResponse::ok("ok")]).await;
+        let scraper = quiet_builder().build().unwrap();
+        for resource in [
+            ResourceType::Document,
+            ResourceType::Api,
+            ResourceType::Image,
+        ] {
+            scraper
+                .request_as(Method::GET, server.url.clone(), None, resource)
+                .await
+                .unwrap();
+        }
+
+        let accepts: Vec<String> = server
+            .requests()
+            .iter()
+            .map(|raw| request_header(raw, "accept").unwrap_or_default())
+            .collect();
+        assert!(accepts[0].starts_with("text/html"), "{accepts:?}");
+        assert!(accepts[1].starts_with("application/json"), "{accepts:?}");
+        assert!(accepts[2].starts_with("image/"), "{accepts:?}");
+    }
+
+    #[tokio::test]
+    async fn failing_domain_recommends_research() {
+        let scraper = CloudScraper::builder()
+            .disable_anti_detection()
+            .disable_spoofing()
+            .disable_ml_optimization()
+            .disable_performance_monitoring()
+            .with_behavior_profile(BehaviorProfile::Casual)
+            .build()
+            .unwrap();
+        assert_eq!(
+            scraper.recommend_profile("example.com").await,
+            BehaviorProfile::Casual
+        );
+
+        scraper.state.record_success("fast.example.com");
+        scraper.state.record_success("example.com");
+        for _ in 0..4 {
+            scraper.state.record_failure("example.com", "status_503");
+        }
+        assert_eq!(
+            scraper.recommend_profile("fast.example.com").await,
+            BehaviorProfile::Focused
+        );
+        assert_eq!(
+            scraper.adopt_recommended_profile("Example.com").await,
+            BehaviorProfile::Research
+        );
+        let guard = scraper.inner.lock().await;
+        let timing = guard.adaptive_timing.as_ref().unwrap();
+        assert_eq!(
+            timing.domain_profile("example.com"),
+            BehaviorProfile::Research
+        );
+    }
+
+    #[tokio::test]
+    async fn delay_interceptor_sets_the_wait() {
+        let server = MockServer::start(vec![MockResponse::ok("done")]).await;
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = seen.clone();
+        let scraper = quiet_builder()
+            .with_delay_interceptor(Box::new(move |url, delay| {
+                recorded
+                    .lock()
+                    .unwrap()
+                    .push((url.path().to_string(), delay));
+                delay + Duration::from_millis(250)
+            }))
+            .build()
+            .unwrap();
+
+        let started = Instant::now();
+        scraper.get(&format!("{}page", server.url)).await.unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(250));
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![("/page".to_string(), Duration::ZERO)]
+        );
+        let stats = &scraper.metrics().unwrap().snapshot().domains[0];
+        assert_eq!(
+            stats.average_injected_delay,
+            Some(Duration::from_millis(250))
+        );
+    }
+
+    struct RecordingInterpreter(StdMutex<Vec<String>>);
+
+    impl JavascriptInterpreter for RecordingInterpreter {
+        fn solve_challenge(
+            &self,
+            _page_html: &str,
+            _host: &str,
+        ) -> Result<String, crate::external_deps::interpreters::InterpreterError> {
+            Ok("42".into())
+        }
+
+        fn solve_challenge_as(
+            &self,
+            page_html: &str,
+            host: &str,
+            user_agent: &str,
+        ) -> Result<String, crate::external_deps::interpreters::InterpreterError> {
+            self.0.lock().unwrap().push(user_agent.to_string());
+            self.solve_challenge(page_html, host)
+        }
+    }
+
+    const IUAM_V1_PAGE: &str = include_str!("../tests/fixtures/iuam_v1.html");
+
+    fn iuam_v1_challenge() -> MockResponse {
+        let mut challenge = MockResponse::ok(IUAM_V1_PAGE);
+        challenge.status = 503;
+        challenge.headers = vec![("server".into(), "cloudflare".into())];
+        challenge
+    }
+
+    #[tokio::test]
+    async fn spoofed_user_agent_reaches_header_and_interpreter() {
+        let mut cleared = MockResponse::ok("welcome");
+        cleared.headers = vec![(
+            "set-cookie".into(),
+            "cf_clearance=granted; Max-Age=1800; path=/".into(),
+        )];
+        let server = MockServer::start(vec![iuam_v1_challenge(), cleared]).await;
+        let host = server.url.host_str().unwrap().to_string();
+        let interpreter = Arc::new(RecordingInterpreter(StdMutex::new(Vec::new())));
+        let scraper = CloudScraper::builder()
+            .disable_adaptive_timing()
+            .disable_anti_detection()
+            .disable_ml_optimization()
+            .disable_performance_monitoring()
+            .with_spoofing_browser(BrowserType::Firefox)
+            .with_interpreter(interpreter.clone())
+            .build()
+            .unwrap();
+
+        scraper.get(server.url.as_str()).await.unwrap();
+
+        let fingerprint = scraper
+            .inner
+            .lock()
+            .await
+            .fingerprint
+            .as_mut()
+            .unwrap()
+            .generate_for(&host);
+        assert_eq!(fingerprint.browser_type(), Some(BrowserType::Firefox));
+        let header = request_header(&server.requests()[0], "user-agent").unwrap();
+        assert_eq!(header, fingerprint.user_agent);
+        assert_eq!(*interpreter.0.lock().unwrap(), vec![fingerprint.user_agent]);
+    }
+
+    #[tokio::test]
+    async fn expiring_clearance_is_solved_again_up_front() {
+        let mut short = MockResponse::ok("first");
+        short.headers = vec![(
+            "set-cookie".into(),
+            "cf_clearance=short; Max-Age=60; path=/".into(),
+        )];
+        let mut renewed = MockResponse::ok("welcome");
+        renewed.headers = vec![(
+            "set-cookie".into(),
+            "cf_clearance=renewed; Max-Age=1800; path=/".into(),
+        )];
+        let server = MockServer::start(vec![short, iuam_v1_challenge(), renewed]).await;
+        let host = server.url.host_str().unwrap().to_string();
+        let scraper = quiet_builder()
+            .with_interpreter(Arc::new(RecordingInterpreter(StdMutex::new(Vec::new()))))
+            .build()
+            .unwrap();
+
+        scraper.get(server.url.as_str()).await.unwrap();
+        let first_expiry = scraper.clearance_expiry(&host).unwrap();
+        assert!(first_expiry <= chrono::Utc::now() + chrono::Duration::seconds(60));
+
+        let response = scraper.get(server.url.as_str()).await.unwrap();
+        assert_eq!(response.text().await.unwrap(), "welcome");
+        let requests = server.requests();
+        assert!(
+            request_header(&requests[1], "cookie")
+                .is_none_or(|cookie| !cookie.contains("cf_clearance"))
+        );
+        assert!(requests.len() >= 3);
+        let renewed_expiry = scraper.clearance_expiry(&host).unwrap();
+        assert!(renewed_expiry > chrono::Utc::now() + chrono::Duration::minutes(25));
+    }
+
+    #[tokio::test]
+    async fn solves_saved_challenge_page_offline() {
+        let scraper = quiet_builder()
+            .with_interpreter(Arc::new(RecordingInterpreter(StdMutex::new(Vec::new()))))
+            .build()
+            .unwrap();
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/iuam_v1.html");
+        let url = Url::parse("https://example.com/").unwrap();
+
+        match scraper.solve_local(&path, &url, 503).await.unwrap() {
+            PipelinePrepareResult::Prepared {
+                detection,
+                submission,
+            } => {
+                assert_eq!(detection.challenge_type, ChallengeType::JavaScriptV1);
+                assert_eq!(submission.form_fields["jschl_answer"], "42");
+                assert_eq!(submission.url.path(), "/cdn-cgi/l/chk_jschl");
+            }
+            other => panic!("expected a prepared submission, got {other:?}"),
+        }
+        assert!(matches!(
+            scraper
+                .solve_local(&path.with_file_name("missing.html"), &url, 503)
+                .await,
+            Err(CloudScraperError::Io(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn registrable_domain_scope_shares_clearance_between_subdomains() {
+        let cleared = || MockResponse {
+            status: 200,
+            headers: vec![
+                (
+                    "set-cookie".into(),
+                    format!("{CLEARANCE_COOKIE}=granted; Path=/"),
+                ),
+                ("set-cookie".into(), "session=private; Path=/".into()),
+            ],
+            body: "cleared".into(),
+        };
+        let run = |scope: StateScope| async move {
+            let proxy = MockServer::start(vec![cleared(), MockResponse::ok("page")]).await;
+            let scraper = quiet_builder()
+                .with_state_scope(scope)
+                .with_proxies([proxy.url.to_string()])
+                .build()
+                .unwrap();
+            scraper.get("http://a.example.com/").await.unwrap();
+            scraper.get("http://b.example.com/").await.unwrap();
+            let cookie = request_header(&proxy.requests()[1], "cookie");
+            (scraper, cookie)
+        };
+
+        let (scraper, cookie) = run(StateScope::RegistrableDomain).await;
+        assert_eq!(cookie, Some(format!("{CLEARANCE_COOKIE}=granted")));
+        assert!(matches!(
+            scraper.domain_challenge_status("b.example.com"),
+            ChallengeStatus::Clear { .. }
+        ));
+        assert_eq!(scraper.state.get("example.com").unwrap().outcomes, 2);
+
+        let (_, cookie) = run(StateScope::Host).await;
+        assert_eq!(cookie, None);
+    }
+
+    #[tokio::test]
+    async fn expiring_a_cookie_removes_its_parent_domain_copy() {
+        let proxy = MockServer::start(vec![
+            MockResponse {
+                status: 200,
+                headers: vec![(
+                    "set-cookie".into(),
+                    format!("{CLEARANCE_COOKIE}=granted; Domain=.example.com; Path=/"),
+                )],
+                body: "cleared".into(),
+            },
+            MockResponse::ok("page"),
+        ])
+        .await;
+        let scraper = quiet_builder()
+            .with_proxies([proxy.url.to_string()])
+            .build()
+            .unwrap();
+        let url = Url::parse("http://www.example.com/").unwrap();
+
+        scraper.get(url.as_str()).await.unwrap();
+        scraper.get(url.as_str()).await.unwrap();
+        scraper
+            .client_pool
+            .expire_cookie(&url, CLEARANCE_COOKIE)
+            .await;
+        scraper.get(url.as_str()).await.unwrap();
+
+        let requests = proxy.requests();
+        assert_eq!(
+            request_header(&requests[1], "cookie"),
+            Some(format!("{CLEARANCE_COOKIE}=granted"))
+        );
+        assert_eq!(request_header(&requests[2], "cookie"), None);
+    }
+
+    #[tokio::test]
+    async fn exhausted_attempts_report_challenges_met() {
+        let limited = MockResponse {
+            status: 429,
+            headers: vec![
+                ("server".into(), "cloudflare".into()),
+                ("retry-after".into(), "0".into()),
+            ],
+            body: "<title>Rate Limited</title> You are being rate limited".into(),
+        };
+        let banned = MockResponse {
+            status: 403,
+            headers: vec![("server".into(), "cloudflare".into())],
+            body: r#"<span class="cf-error-code">1010</span> Bot management"#.into(),
+        };
+        let server = MockServer::start(vec![limited.clone(), limited, banned]).await;
+        let scraper = quiet_builder()
+            .with_max_challenge_attempts(3)
+            .with_challenge_loop_threshold(10)
+            .build()
+            .unwrap();
+        scraper.inner.lock().await.pipeline = ChallengePipeline::new(ChallengeDetector::default())
+            .with_rate_limit(RateLimitHandler::new())
+            .with_bot_management(
+                BotManagementHandler::new().with_delay_range(Duration::ZERO, Duration::ZERO),
+            );
+
+        let err = scraper.get(server.url.as_str()).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "gave up after 3 attempts: RateLimit, RateLimit, BotManagement"
+        );
+        let CloudScraperError::ChallengeExhausted {
+            attempts,
+            encountered,
+            ..
+        } = err
+        else {
+            panic!("expected exhausted attempts, got {err:?}");
+        };
+        assert_eq!(attempts, 3);
+        assert_eq!(encountered.len(), 3);
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn cloudflare_diagnostic_headers_reach_events() {
+        let limited = MockResponse {
+            status: 429,
+            headers: vec![
+                ("server".into(), "cloudflare".into()),
+                ("retry-after".into(), "0".into()),
+                ("cf-mitigated".into(), "challenge".into()),
+                ("cf-cache-status".into(), "DYNAMIC".into()),
+                ("server-timing".into(), "cfL4;desc=\"?proto=TCP\"".into()),
+            ],
+            body: "<title>Rate Limited</title> You are being rate limited".into(),
+        };
+        let server = MockServer::start(vec![limited]).await;
+        let recorder = Arc::new(RecordingHandler::default());
+        let scraper = quiet_builder()
+            .with_max_challenge_attempts(1)
+            .with_event_handler(recorder.clone())
+            .build()
+            .unwrap();
+        assert!(scraper.get(server.url.as_str()).await.is_err());
+
+        let expected = vec![
+            ("cf-mitigated".to_string(), "challenge".to_string()),
+            ("cf-cache-status".to_string(), "DYNAMIC".to_string()),
+            (
+                "server-timing".to_string(),
+                "cfL4;desc=\"?proto=TCP\"".to_string(),
+            ),
+        ];
+        let events = recorder.0.lock().unwrap();
+        let post = events
+            .iter()
+            .find_map(|event| match event {
+                ScraperEvent::PostResponse(post) => Some(post),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(post.diagnostics, expected);
+        let challenge = events
+            .iter()
+            .find_map(|event| match event {
+                ScraperEvent::Challenge(challenge) => Some(challenge),
+                _ => None,
+            })
+            .unwrap();
+        for pair in &expected {
+            assert!(challenge.metadata.contains(pair));
+        }
+
+        let response =
+            ScraperResponse::new(403, post.headers.clone(), Bytes::new(), server.url.clone());
+        assert_eq!(response.cf_mitigated(), Some("challenge"));
+        assert_eq!(response.cf_cache_status(), Some("DYNAMIC"));
+        assert!(response.server_timing().unwrap().starts_with("cfL4"));
+    }
+
+    #[tokio::test]
+    async fn sustained_challenges_escalate_the_domain_profile() {
+        let limited = MockResponse {
+            status: 429,
+            headers: vec![
+                ("server".into(), "cloudflare".into()),
+                ("retry-after".into(), "0".into()),
+            ],
+            body: "<title>Rate Limited</title> You are being rate limited".into(),
+        };
+        let server = MockServer::start(vec![limited]).await;
+        let scraper = CloudScraper::builder()
+            .disable_anti_detection()
+            .disable_spoofing()
+            .disable_ml_optimization()
+            .disable_performance_monitoring()
+            .with_max_adaptive_delay(Duration::ZERO)
+            .with_behavior_profile(BehaviorProfile::Focused)
+            .enable_auto_profile_escalation()
+            .with_max_challenge_attempts(6)
+            .with_challenge_loop_threshold(10)
+            .build()
+            .unwrap();
+
+        assert!(scraper.get(server.url.as_str()).await.is_err());
+        let host = server.url.host_str().unwrap();
+        let guard = scraper.inner.lock().await;
+        let timing = guard.adaptive_timing.as_ref().unwrap();
+        assert_eq!(timing.domain_profile(host), BehaviorProfile::Research);
+    }
+
+    #[tokio::test]
+    async fn multipart_upload_is_replayed_after_a_challenge() {
+        let limited = MockResponse {
+            status: 429,
+            headers: vec![
+                ("server".into(), "cloudflare".into()),
+                ("retry-after".into(), "0".into()),
+            ],
+            body: "<title>Rate Limited</title> You are being rate limited".into(),
+        };
+        let ok = MockResponse {
+            status: 200,
+            headers: vec![],
+            body: "uploaded".into(),
+        };
+        let server = MockServer::start(vec![limited, ok]).await;
+        let scraper = quiet_builder().build().unwrap();
+        let form = MultipartForm::new()
+            .text("note", "hello")
+            .part(MultipartPart::file(
+                "upload",
+                "report.csv",
+                &b"a,b\n1,2"[..],
+            ));
+        let boundary = form.boundary().to_string();
+
+        let response = scraper
+            .post_multipart(server.url.as_str(), form)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        for raw in &requests {
+            assert_eq!(
+                request_header(raw, "content-type").unwrap(),
+                format!("multipart/form-data; boundary={boundary}")
+            );
+            assert!(raw.contains("filename=\"report.csv\""), "{raw}");
+            assert!(raw.ends_with(&format!("--{boundary}--\r\n")), "{raw}");
+        }
+    }
+
+    #[tokio::test]
+    async fn warmer_pings_warm_domains_periodically() {
+        let server = MockServer::start(vec![MockResponse {
+            status: 200,
+            headers: vec![],
+            body: String::new(),
+        }])
+        .await;
+        let scraper = quiet_builder()
+            .with_warm_domains(vec![server.url.clone()])
+            .with_warm_interval(Duration::from_millis(50))
+            .build()
+            .unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while server.requests().len() < 3 {
+            assert!(Instant::now() < deadline, "warmer did not ping");
+            sleep(Duration::from_millis(20)).await;
+        }
+        assert!(server.requests().iter().all(|raw| raw.starts_with("HEAD ")));
+
+        drop(scraper);
+        sleep(Duration::from_millis(50)).await;
+        let pinged = server.requests().len();
+        sleep(Duration::from_millis(200)).await;
+        assert_eq!(server.requests().len(), pinged);
+    }
+
+    #[tokio::test]
+    async fn warm_pings_use_the_domain_identity_and_bound_proxy() {
+        let first = MockServer::start(vec![MockResponse::ok("page")]).await;
+        let second = MockServer::start(vec![MockResponse::ok("page")]).await;
+        let origin = Url::parse("http://origin.test/").unwrap();
+        let scraper = CloudScraper::builder()
+            .disable_adaptive_timing()
+            .disable_anti_detection()
+            .disable_ml_optimization()
+            .disable_performance_monitoring()
+            .with_proxies([first.url.to_string(), second.url.to_string()])
+            .with_warm_domains(vec![origin])
+            .with_warm_interval(Duration::from_millis(30))
+            .build()
+            .unwrap();
+
+        sleep(Duration::from_millis(100)).await;
+        assert!(first.requests().is_empty() && second.requests().is_empty());
+
+        let response = scraper.get("http://origin.test/page").await.unwrap();
+        let (bound, other) = if first.requests().is_empty() {
+            (&second, &first)
+        } else {
+            (&first, &second)
+        };
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let pings = loop {
+            let pings: Vec<String> = bound
+                .requests()
+                .into_iter()
+                .filter(|raw| raw.starts_with("HEAD "))
+                .collect();
+            if pings.len() >= 2 {
+                break pings;
+            }
+            assert!(Instant::now() < deadline, "warmer did not ping");
+            sleep(Duration::from_millis(20)).await;
+        };
+        assert!(other.requests().is_empty());
+        for ping in &pings {
+            assert_eq!(
+                request_header(ping, "user-agent").as_deref(),
+                response.context().user_agent.as_deref()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn response_context_records_the_identity_used() {
+        let proxy = MockServer::start(vec![MockResponse::ok("page")]).await;
+        let scraper = CloudScraper::builder()
+            .disable_anti_detection()
+            .disable_ml_optimization()
+            .disable_performance_monitoring()
+            .with_max_adaptive_delay(Duration::ZERO)
+            .with_behavior_profile(BehaviorProfile::Research)
+            .with_proxies([proxy.url.to_string()])
+            .build()
+            .unwrap();
+
+        let response = scraper.get("http://origin.test/page").await.unwrap();
+        let context = response.context();
+        assert_eq!(context.proxy.as_deref(), Some(proxy.url.as_str()));
+        assert_eq!(context.behavior_profile, Some(BehaviorProfile::Research));
+        assert_eq!(context.delay, Duration::ZERO);
+        assert_eq!(
+            context.user_agent.as_deref(),
+            request_header(&proxy.requests()[0], "user-agent").as_deref()
+        );
+        assert_eq!(context.ja3_hash.as_ref().map(String::len), Some(32));
+        assert!(context.canvas_fingerprint.is_some());
+    }
+
+    #[tokio::test]
+    async fn reporting_the_ja3_hash_does_not_rotate_the_tls_profile() {
+        let server = MockServer::start(vec![MockResponse::ok("ok")]).await;
+        let host = server.url.host_str().unwrap().to_string();
+        let scraper = quiet_builder().build().unwrap();
+
+        let mut hashes = Vec::new();
+        for _ in 0..12 {
+            let response = scraper.get(server.url.as_str()).await.unwrap();
+            hashes.push(response.context().ja3_hash.clone().unwrap());
+        }
+
+        hashes.dedup();
+        assert_eq!(hashes.len(), 1);
+        let report = scraper.identity_report(&host).await;
+        assert_eq!(report.tls_profile.unwrap().ja3_hash(), hashes[0]);
+    }
+
+    #[tokio::test]
+    async fn flaky_origin_status_is_retried() {
+        let bad_gateway = MockResponse {
+            status: 502,
+            headers: vec![],
+            body: "bad gateway".into(),
+        };
+        let server = MockServer::start(vec![bad_gateway, MockResponse::ok("page")]).await;
+        let handler = Arc::new(RecordingHandler::default());
+        let scraper = quiet_builder()
+            .with_retry_statuses([500, 502, 503], 2)
+            .with_status_retry_backoff(Duration::ZERO)
+            .with_event_handler(handler.clone())
+            .build()
+            .unwrap();
+
+        let response = scraper.get(server.url.as_str()).await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(server.requests().len(), 2);
+        let retries = handler
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|event| matches!(event, ScraperEvent::Retry(_)))
+            .count();
+        assert_eq!(retries, 1);
+    }
+
+    #[tokio::test]
+    async fn status_retry_caps_a_long_retry_after() {
+        let unavailable = MockResponse {
+            status: 503,
+            headers: vec![("retry-after".into(), "3600".into())],
+            body: "down for maintenance".into(),
+        };
+        let server = MockServer::start(vec![unavailable, MockResponse::ok("page")]).await;
+        let handler = Arc::new(RecordingHandler::default());
+        let scraper = quiet_builder()
+            .with_retry_statuses([503], 1)
+            .with_max_retry_after(Duration::from_millis(10))
+            .with_event_handler(handler.clone())
+            .build()
+            .unwrap();
+
+        let response = scraper.get(server.url.as_str()).await.unwrap();
+        assert_eq!(response.status(), 200);
+        let waits: Vec<Duration> = handler
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|event| match event {
+                ScraperEvent::Retry(retry) => Some(retry.scheduled_after),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(waits, [Duration::from_millis(10)]);
+    }
+
+    #[tokio::test]
+    async fn solved_challenge_is_reported_on_the_response() {
+        let server = MockServer::start(vec![
+            MockResponse::ok("clean"),
+            MockResponse {
+                status: 403,
+                headers: vec![("server".into(), "cloudflare".into())],
+                body: TURNSTILE_PAGE.into(),
+            },
+            MockResponse::ok("welcome"),
+        ])
+        .await;
+        let scraper = quiet_builder().build().unwrap();
+        scraper.inner.lock().await.pipeline = ChallengePipeline::new(ChallengeDetector::default())
+            .with_turnstile(
+                TurnstileSolver::new()
+                    .with_delay_range(Duration::ZERO, Duration::ZERO)
+                    .with_captcha_provider(Arc::new(TokenProvider)),
+            );
+
+        let clean = scraper.get(server.url.as_str()).await.unwrap();
+        assert_eq!(clean.challenge_solved(), None);
+        let solved = scraper.get(server.url.as_str()).await.unwrap();
+        assert_eq!(solved.challenge_solved(), Some(ChallengeType::Turnstile));
+        assert_eq!(solved.text().await.unwrap(), "welcome");
+    }
+
+    #[tokio::test]
+    async fn rate_limit_halves_the_domain_concurrency() {
+        let limited = MockResponse {
+            status: 429,
+            headers: vec![
+                ("server".into(), "cloudflare".into()),
+                ("retry-after".into(), "0".into()),
+            ],
+            body: "<title>Rate Limited</title> You are being rate limited".into(),
+        };
+        let server = MockServer::start(vec![limited, MockResponse::ok("page")]).await;
+        let scraper = quiet_builder()
+            .enable_adaptive_concurrency(AimdConfig {
+                initial: 4,
+                ..Default::default()
+            })
+            .build()
+            .unwrap();
+        let host = server.url.host_str().unwrap();
+        assert_eq!(scraper.current_concurrency(host), Some(4));
+
+        scraper.get(server.url.as_str()).await.unwrap();
+        assert_eq!(scraper.current_concurrency(host), Some(2));
+        let stats = scraper.metrics().unwrap().snapshot();
+        let stats = stats.domains.iter().find(|d| d.domain == host).unwrap();
+        assert_eq!(stats.concurrency_limit, Some(2));
+        let saved = scraper.state.get(host).unwrap().concurrency_allowance;
+        assert!(saved.is_some_and(|allowance| (2.0..3.0).contains(&allowance)));
+        assert_eq!(
+            quiet_builder().build().unwrap().current_concurrency(host),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn blacklisted_proxy_is_skipped_only_on_its_domain() {
+        let first = MockServer::start(vec![MockResponse::ok("first")]).await;
+        let second = MockServer::start(vec![MockResponse::ok("second")]).await;
+        let scraper = quiet_builder()
+            .with_proxies([first.url.to_string(), second.url.to_string()])
+            .build()
+            .unwrap();
+        scraper
+            .blacklist_identity("origin.test", BlacklistKey::Proxy(first.url.to_string()))
+            .await;
+
+        for _ in 0..4 {
+            let response = scraper.get("http://origin.test/page").await.unwrap();
+            assert_eq!(
+                response.context().proxy.as_deref(),
+                Some(second.url.as_str())
+            );
+        }
+        assert!(first.requests().is_empty());
+
+        for _ in 0..2 {
+            scraper.get("http://elsewhere.test/page").await.unwrap();
+        }
+        assert_eq!(first.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn blacklisted_pinned_user_agent_fails_the_request() {
+        let pinned = "Mozilla/5.0 (X11; Linux x86_64; rv:121.0) Gecko/20100101 Firefox/121.0";
+        let server = MockServer::start(vec![MockResponse::ok("ok")]).await;
+        let host = server.url.host_str().unwrap().to_string();
+        let scraper = CloudScraper::builder()
+            .with_user_agent_options(custom_agent(pinned))
+            .disable_adaptive_timing()
+            .disable_anti_detection()
+            .disable_ml_optimization()
+            .disable_performance_monitoring()
+            .build()
+            .unwrap();
+        scraper
+            .blacklist_identity(&host, BlacklistKey::UserAgent(pinned.into()))
+            .await;
+
+        match scraper.get(server.url.as_str()).await {
+            Err(CloudScraperError::IdentityBlacklisted { domain, component }) => {
+                assert_eq!(domain, host);
+                assert_eq!(component, "fingerprint");
+            }
+            other => panic!("expected a blacklisted identity, got {other:?}"),
+        }
+        assert!(server.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn custom_user_agent_aligns_the_spoofed_identity() {
+        let firefox_linux =
+            "Mozilla/5.0 (X11; Linux x86_64; rv:121.0) Gecko/20100101 Firefox/121.0";
+        let scraper = CloudScraper::builder()
+            .with_user_agent_options(custom_agent(firefox_linux))
+            .build()
+            .unwrap();
+        let mut guard = scraper.inner.lock().await;
+        let fingerprint = guard
+            .fingerprint
+            .as_mut()
+            .unwrap()
+            .generate_for("example.com");
+        assert_eq!(fingerprint.platform, "Linux x86_64");
+        assert_eq!(fingerprint.user_agent, firefox_linux);
+        assert_eq!(
+            guard
+                .tls_manager
+                .as_ref()
+                .unwrap()
+                .preferred_profile()
+                .browser,
+            BrowserType::Firefox
+        );
+    }
+
+    #[tokio::test]
+    async fn explicit_spoofing_browser_is_kept_over_a_custom_user_agent() {
+        let firefox_linux =
+            "Mozilla/5.0 (X11; Linux x86_64; rv:121.0) Gecko/20100101 Firefox/121.0";
+        let scraper = CloudScraper::builder()
+            .with_user_agent_options(custom_agent(firefox_linux))
+            .with_spoofing_browser(BrowserType::Safari)
+            .build()
+            .unwrap();
+        let mut guard = scraper.inner.lock().await;
+        let fingerprint = guard
+            .fingerprint
+            .as_mut()
+            .unwrap()
+            .generate_for("example.com");
+        assert_eq!(fingerprint.browser_type(), Some(BrowserType::Safari));
+        assert_eq!(
+            guard
+                .tls_manager
+                .as_ref()
+                .unwrap()
+                .preferred_profile()
+                .browser,
+            BrowserType::Safari
+        );
+        drop(guard);
+
+        let strict = CloudScraper::builder()
+            .with_user_agent_options(custom_agent(firefox_linux))
+            .with_spoofing_browser(BrowserType::Safari)
+            .strict_coherence()
+            .build();
+        assert!(matches!(
+            strict,
+            Err(CloudScraperError::IncoherentConfig(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn identity_reports_diff_two_domains() {
+        let server = MockServer::start(vec![MockResponse::ok("ok")]).await;
+        let scraper = CloudScraper::builder()
+            .disable_adaptive_timing()
+            .disable_anti_detection()
+            .build()
+            .unwrap();
+        let unseen = scraper.identity_report("unseen.test").await;
+        assert!(unseen.fingerprint.is_none() && unseen.tls_profile.is_none());
+
+        scraper.get(server.url.as_str()).await.unwrap();
+        let host = server.url.host_str().unwrap();
+        let seen = scraper.identity_report(host).await;
+        assert!(seen.fingerprint.is_some() && seen.tls_profile.is_some());
+        assert!(seen.diff(&seen).is_empty());
+
+        let fields: Vec<&str> = unseen.diff(&seen).iter().map(|diff| diff.field).collect();
+        assert_eq!(fields, ["fingerprint", "tls_profile"]);
+    }
+
+    #[tokio::test]
+    async fn saves_and_restores_through_a_shared_backend() {
+        let storage = Arc::new(MemoryStorage::new());
+        let original = quiet_builder().build().unwrap();
+        original.state.record_failure("example.com", "blocked");
+        original.save_to(storage.clone()).await.unwrap();
+        assert!(storage.keys().contains(&STATE_STORAGE_KEY.to_string()));
+        assert!(storage.keys().contains(&DETECTOR_STORAGE_KEY.to_string()));
+
+        let restored = quiet_builder().build().unwrap();
+        restored.load_from(storage).await.unwrap();
+        assert!(restored.state.get("example.com").is_some());
+        assert!(restored.inner.try_lock().is_ok());
+    }
+
+    #[tokio::test]
+    async fn clearance_from_a_submission_is_sent_on_the_next_request() {
+        let server = MockServer::start(vec![
+            MockResponse {
+                status: 403,
+                headers: vec![
+                    ("server".into(), "cloudflare".into()),
+                    ("set-cookie".into(), "__cf_bm=bm; Path=/".into()),
+                ],
+                body: TURNSTILE_PAGE.into(),
+            },
+            MockResponse {
+                status: 200,
+                headers: vec![("set-cookie".into(), "cf_clearance=cleared; Path=/".into())],
+                body: "welcome".into(),
+            },
+            MockResponse::ok("again"),
+        ])
+        .await;
+        let scraper = quiet_builder().build().unwrap();
+        {
+            let mut guard = scraper.inner.lock().await;
+            let turnstile = TurnstileSolver::new()
+                .with_delay_range(Duration::ZERO, Duration::ZERO)
+                .with_captcha_provider(Arc::new(TokenProvider));
+            guard.pipeline =
+                ChallengePipeline::new(ChallengeDetector::default()).with_turnstile(turnstile);
+        }
+
+        scraper.get(server.url.as_str()).await.unwrap();
+        scraper.get(server.url.as_str()).await.unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3, "{requests:?}");
+        assert!(requests[1].starts_with("POST"));
+        let submitted = request_header(&requests[1], "cookie").unwrap_or_default();
+        assert!(submitted.contains("__cf_bm=bm"), "{submitted}");
+        let next = request_header(&requests[2], "cookie").unwrap_or_default();
+        assert!(next.contains("cf_clearance=cleared"), "{next}");
+    }
+}
@@ -6,6 +6,7 @@ use async_trait::async_trait;
 pub struct CapSolverProvider {
     pub api_key: String,
     pub config: CaptchaConfig,
+    client: reqwest::Client,
 }
 
 impl CapSolverProvider {
@@ -13,15 +14,35 @@ impl CapSolverProvider {
         Self {
             api_key: api_key.into(),
             config: CaptchaConfig::default(),
+            client: reqwest::Client::new(),
         }
     }
 
-    pub fn with_config(api_key: impl Into<String>, config: CaptchaConfig) -> Self {
+    /// Fails when `config.provider_proxy` is not a valid proxy URL.
+    pub fn with_config(
+        api_key: impl Into<String>,
+        config: CaptchaConfig,
+    ) -> Result<Self, CaptchaError> {
+        let client = config.http_client()?;
+        Ok(Self::with_client(api_key, config, client))
+    }
+
+    /// Use a pre-built client for vendor API calls; `provider_proxy` is ignored.
+    pub fn with_client(
+        api_key: impl Into<String>,
+        config: CaptchaConfig,
+        client: reqwest::Client,
+    ) -> Self {
         Self {
             api_key: api_key.into(),
             config,
+            client,
         }
     }
+
+    pub fn client(&self) -> &reqwest::Client {
+        &self.client
+    }
 }
 
 #[async_trait]
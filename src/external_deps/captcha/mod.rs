@@ -17,6 +17,8 @@ use std::collections::HashMap;
 use std::time::Duration;
 
 use async_trait::async_trait;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use thiserror::Error;
 use url::Url;
 
@@ -25,6 +27,14 @@ use url::Url;
 pub struct CaptchaConfig {
     pub timeout: Duration,
     pub poll_interval: Duration,
+    /// Maximum random offset applied to each poll so requests to the vendor
+    /// API don't land on a fixed cadence.
+    pub poll_jitter: Duration,
+    /// Proxy for calls to the vendor API itself, e.g. `http://proxy:3128`.
+    pub provider_proxy: Option<String>,
+    /// A token with less lifetime than this left at submission time is
+    /// solved again instead of being sent.
+    pub min_token_ttl_before_submit: Duration,
 }
 
 impl Default for CaptchaConfig {
@@ -32,17 +42,52 @@ impl Default for CaptchaConfig {
         Self {
             timeout: Duration::from_secs(120),
             poll_interval: Duration::from_secs(2),
+            poll_jitter: Duration::from_millis(500),
+            provider_proxy: None,
+            min_token_ttl_before_submit: Duration::from_secs(10),
         }
     }
 }
 
+impl CaptchaConfig {
+    /// Delay before the next poll: `poll_interval` shifted by up to
+    /// `poll_jitter` either way, drawn from `rng` — usually
+    /// [`CaptchaTask::poll_rng`].
+    pub fn next_poll_delay<R: Rng + ?Sized>(&self, rng: &mut R) -> Duration {
+        if self.poll_jitter.is_zero() {
+            return self.poll_interval;
+        }
+        let jitter = rng.gen_range(Duration::ZERO..=self.poll_jitter * 2);
+        (self.poll_interval + jitter).saturating_sub(self.poll_jitter)
+    }
+
+    /// HTTP client for vendor API calls, routed through `provider_proxy`.
+    pub fn http_client(&self) -> Result<reqwest::Client, CaptchaError> {
+        let mut builder = reqwest::Client::builder();
+        if let Some(proxy) = &self.provider_proxy {
+            let proxy = reqwest::Proxy::all(proxy).map_err(|err| {
+                CaptchaError::Configuration(format!("invalid provider proxy '{proxy}': {err}"))
+            })?;
+            builder = builder.proxy(proxy);
+        }
+        builder
+            .build()
+            .map_err(|err| CaptchaError::Configuration(err.to_string()))
+    }
+}
+
 /// Details describing the captcha Cloudflare issued.
 #[derive(Debug, Clone)]
 pub struct CaptchaTask {
     pub site_key: String,
     pub page_url: Url,
     pub action: Option<String>,
+    /// Turnstile `cData` customer payload, for providers that accept it.
+    pub c_data: Option<String>,
     pub data: HashMap<String, String>,
+    /// Seeds the poll jitter; solvers draw it from the scraper's generator
+    /// so a seeded scraper polls on a reproducible schedule.
+    pub poll_seed: Option<u64>,
 }
 
 impl CaptchaTask {
@@ -51,7 +96,9 @@ impl CaptchaTask {
             site_key: site_key.into(),
             page_url,
             action: None,
+            c_data: None,
             data: HashMap::new(),
+            poll_seed: None,
         }
     }
 
@@ -60,10 +107,29 @@ impl CaptchaTask {
         self
     }
 
+    pub fn with_c_data(mut self, c_data: impl Into<String>) -> Self {
+        self.c_data = Some(c_data.into());
+        self
+    }
+
     pub fn insert_metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
         self.data.insert(key.into(), value.into());
         self
     }
+
+    pub fn with_poll_seed(mut self, seed: u64) -> Self {
+        self.poll_seed = Some(seed);
+        self
+    }
+
+    /// Generator for [`CaptchaConfig::next_poll_delay`], entropy-seeded when
+    /// the task carries no `poll_seed`.
+    pub fn poll_rng(&self) -> StdRng {
+        match self.poll_seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        }
+    }
 }
 
 /// Resolved captcha token and optional metadata.
@@ -118,3 +184,76 @@ pub enum CaptchaError {
     #[error("captcha error: {0}")]
     Other(String),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn provider_client_routes_through_provider_proxy() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = CaptchaConfig {
+            provider_proxy: Some(format!("http://{}", listener.local_addr().unwrap())),
+            ..CaptchaConfig::default()
+        };
+        let provider = AntiCaptchaProvider::with_config("key", config).unwrap();
+
+        let proxy = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0u8; 4096];
+            let read = stream.read(&mut buf).await.unwrap();
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .await
+                .unwrap();
+            String::from_utf8_lossy(&buf[..read]).into_owned()
+        });
+
+        let response = provider
+            .client()
+            .get("http://api.vendor.test/getBalance")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        let request = proxy.await.unwrap();
+        assert!(
+            request.starts_with("GET http://api.vendor.test/getBalance "),
+            "{request}"
+        );
+    }
+
+    #[test]
+    fn seeded_tasks_poll_on_a_reproducible_schedule() {
+        let config = CaptchaConfig::default();
+        let url = Url::parse("https://example.com/").unwrap();
+        let task = CaptchaTask::new("key", url).with_poll_seed(7);
+        let schedule = |task: &CaptchaTask| {
+            let mut rng = task.poll_rng();
+            (0..5)
+                .map(|_| config.next_poll_delay(&mut rng))
+                .collect::<Vec<_>>()
+        };
+
+        let delays = schedule(&task);
+        assert_eq!(delays, schedule(&task));
+        assert!(delays.iter().all(|delay| {
+            *delay >= config.poll_interval - config.poll_jitter
+                && *delay <= config.poll_interval + config.poll_jitter
+        }));
+    }
+
+    #[test]
+    fn invalid_provider_proxy_is_a_configuration_error() {
+        let config = CaptchaConfig {
+            provider_proxy: Some("not a proxy".into()),
+            ..CaptchaConfig::default()
+        };
+        assert!(matches!(
+            TwoCaptchaProvider::with_config("key", config),
+            Err(CaptchaError::Configuration(_))
+        ));
+    }
+}
@@ -1,9 +1,38 @@
+use std::panic::{AssertUnwindSafe, catch_unwind};
+
 use boa_engine::{Context, Source};
 use once_cell::sync::Lazy;
 use regex::{Regex, RegexBuilder};
 
+use crate::modules::spoofing::OperatingSystem;
+
 use super::{InterpreterError, InterpreterResult, JavascriptInterpreter};
 
+/// `navigator.userAgent` when the caller does not supply one.
+const DEFAULT_USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64)";
+
+/// Panics Boa 0.21: the index's `valueOf` shrinks the buffer underneath
+/// `Atomics.store`.
+#[cfg(test)]
+pub(crate) const BOA_PANIC_SCRIPT: &str = "var buffer = new ArrayBuffer(16, { maxByteLength: 16 }); \
+     Atomics.store(new Uint8Array(buffer), { valueOf() { buffer.resize(1); return 15; } }, 1);";
+
+/// `navigator.platform` matching the OS `user_agent` names.
+fn navigator_platform(user_agent: &str) -> &'static str {
+    OperatingSystem::from_user_agent(user_agent)
+        .map_or("Win32", OperatingSystem::navigator_platform)
+}
+
+/// Run `eval` so a Boa panic surfaces as an error instead of unwinding
+/// through the caller. The solvers additionally keep evaluation off the async
+/// runtime by calling in from `spawn_blocking`.
+fn run_isolated<T>(eval: impl FnOnce() -> InterpreterResult<T>) -> InterpreterResult<T> {
+    // Each evaluation builds its own `Context`, so nothing half-updated by a
+    // panic outlives it.
+    catch_unwind(AssertUnwindSafe(eval))
+        .unwrap_or_else(|_| Err(InterpreterError::Execution("interpreter panicked".into())))
+}
+
 /// Default interpreter backed by the Boa JavaScript engine.
 #[derive(Debug, Default)]
 pub struct BoaJavascriptInterpreter;
@@ -28,7 +57,9 @@ impl BoaJavascriptInterpreter {
             .collect()
     }
 
-    fn build_prelude(&self, host: &str) -> String {
+    fn build_prelude(&self, host: &str, user_agent: &str, platform: &str) -> String {
+        let user_agent = serde_json::to_string(user_agent).unwrap_or_else(|_| "\"\"".into());
+        let platform = serde_json::to_string(platform).unwrap_or_else(|_| "\"\"".into());
         format!(
             r#"
 var __host = "{host}";
@@ -41,10 +72,10 @@ var location = {{
 }};
 var window = {{ location: location }};
 var navigator = {{
-    userAgent: "Mozilla/5.0 (Windows NT 10.0; Win64; x64)",
+    userAgent: {user_agent},
     language: "en-US",
     languages: ["en-US", "en"],
-    platform: "Win32"
+    platform: {platform}
 }};
 window.navigator = navigator;
 var history = {{ replaceState: function() {{}} }};
@@ -176,6 +207,30 @@ var btoa = function(str) {{
 
 impl JavascriptInterpreter for BoaJavascriptInterpreter {
     fn solve_challenge(&self, page_html: &str, host: &str) -> InterpreterResult<String> {
+        self.solve_challenge_as(page_html, host, DEFAULT_USER_AGENT)
+    }
+
+    fn solve_challenge_as(
+        &self,
+        page_html: &str,
+        host: &str,
+        user_agent: &str,
+    ) -> InterpreterResult<String> {
+        run_isolated(|| self.eval_challenge(page_html, host, user_agent))
+    }
+
+    fn execute(&self, script: &str, host: &str) -> InterpreterResult<String> {
+        run_isolated(|| self.eval_script(script, host))
+    }
+}
+
+impl BoaJavascriptInterpreter {
+    fn eval_challenge(
+        &self,
+        page_html: &str,
+        host: &str,
+        user_agent: &str,
+    ) -> InterpreterResult<String> {
         let scripts = self.extract_scripts(page_html);
         if scripts.is_empty() {
             return Err(InterpreterError::Execution(
@@ -184,7 +239,7 @@ impl JavascriptInterpreter for BoaJavascriptInterpreter {
         }
 
         let mut context = Context::default();
-        let prelude = self.build_prelude(host);
+        let prelude = self.build_prelude(host, user_agent, navigator_platform(user_agent));
 
         context
             .eval(Source::from_bytes(&prelude))
@@ -210,9 +265,13 @@ impl JavascriptInterpreter for BoaJavascriptInterpreter {
         self.read_answer(&mut context)
     }
 
-    fn execute(&self, script: &str, host: &str) -> InterpreterResult<String> {
+    fn eval_script(&self, script: &str, host: &str) -> InterpreterResult<String> {
         let mut context = Context::default();
-        let prelude = self.build_prelude(host);
+        let prelude = self.build_prelude(
+            host,
+            DEFAULT_USER_AGENT,
+            navigator_platform(DEFAULT_USER_AGENT),
+        );
 
         context
             .eval(Source::from_bytes(&prelude))
@@ -260,6 +319,24 @@ mod tests {
         assert_eq!(answer, "15.0000000000");
     }
 
+    #[test]
+    fn navigator_platform_follows_the_user_agent() {
+        let html = r#"
+            <form id="challenge-form"><input type="hidden" id="jschl_answer" /></form>
+            <script>
+                document.getElementById('jschl_answer').value =
+                    navigator.platform === "MacIntel" ? 1 : 0;
+            </script>
+        "#;
+        let mac = "Mozilla/5.0 (Macintosh; Intel Mac OS X 13_1) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.2 Safari/605.1.15";
+
+        let interpreter = BoaJavascriptInterpreter::new();
+        let answer = interpreter
+            .solve_challenge_as(html, "example.com", mac)
+            .unwrap();
+        assert_eq!(answer, "1.0000000000");
+    }
+
     #[test]
     fn error_when_missing_script() {
         let html = "<html><body>No script</body></html>";
@@ -269,4 +346,17 @@ mod tests {
             .unwrap_err();
         assert!(matches!(err, InterpreterError::Execution(_)));
     }
+
+    #[test]
+    fn panics_become_execution_errors() {
+        let interpreter = BoaJavascriptInterpreter::new();
+        let err = interpreter
+            .execute(BOA_PANIC_SCRIPT, "example.com")
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            InterpreterError::Execution(message) if message == "interpreter panicked"
+        ));
+        assert_eq!(interpreter.execute("1 + 2", "example.com").unwrap(), "3");
+    }
 }
@@ -5,8 +5,12 @@
 
 mod boa;
 
+#[cfg(test)]
+pub(crate) use boa::BOA_PANIC_SCRIPT;
 pub use boa::BoaJavascriptInterpreter;
 
+use std::sync::Arc;
+
 use thiserror::Error;
 
 /// Abstraction over JavaScript runtimes capable of solving Cloudflare logic.
@@ -15,6 +19,19 @@ pub trait JavascriptInterpreter: Send + Sync {
     /// 10 decimal places.
     fn solve_challenge(&self, page_html: &str, host: &str) -> Result<String, InterpreterError>;
 
+    /// Like [`solve_challenge`](Self::solve_challenge), with
+    /// `navigator.userAgent` reporting `user_agent` so the page sees the same
+    /// browser as the request headers. Runtimes without a navigator ignore it.
+    fn solve_challenge_as(
+        &self,
+        page_html: &str,
+        host: &str,
+        user_agent: &str,
+    ) -> Result<String, InterpreterError> {
+        let _ = user_agent;
+        self.solve_challenge(page_html, host)
+    }
+
     /// Execute raw JavaScript within a pre-constructed environment.
     fn execute(&self, script: &str, host: &str) -> Result<String, InterpreterError> {
         let _ = (script, host);
@@ -33,3 +50,19 @@ pub enum InterpreterError {
 
 /// Convenience alias for runtime results.
 pub type InterpreterResult<T> = Result<T, InterpreterError>;
+
+/// Run `eval` against `interpreter` on tokio's blocking pool, so evaluation
+/// never stalls the runtime and a panicking interpreter surfaces as
+/// `InterpreterError::Execution("interpreter panicked")`.
+pub(crate) async fn evaluate_blocking<F>(
+    interpreter: &Arc<dyn JavascriptInterpreter>,
+    eval: F,
+) -> InterpreterResult<String>
+where
+    F: FnOnce(&dyn JavascriptInterpreter) -> InterpreterResult<String> + Send + 'static,
+{
+    let interpreter = interpreter.clone();
+    tokio::task::spawn_blocking(move || eval(interpreter.as_ref()))
+        .await
+        .unwrap_or_else(|_| Err(InterpreterError::Execution("interpreter panicked".into())))
+}
@@ -31,6 +31,9 @@
 //! ```
 
 mod cloudscraper;
+mod multipart;
+mod scraper_core;
+mod serde_millis;
 
 pub mod challenges;
 pub mod external_deps;
@@ -38,29 +41,43 @@ pub mod modules;
 
 pub use crate::cloudscraper::{
     CloudScraper, CloudScraperBuilder, CloudScraperConfig, CloudScraperError, CloudScraperResult,
-    ScraperResponse,
+    ConnectionStrategy, DelayInterceptor, IdentityReport, RequestContext, RequestInterceptor,
+    ResourceType, ResponseAction, ResponseInterceptor, ScraperResponse, Session,
+    SubmissionInterceptor,
 };
+pub use crate::multipart::{MultipartForm, MultipartPart};
+pub use crate::scraper_core::{CoreDecision, CoreResponse, PreparedRequest, ScraperCore};
+
+pub use tokio_util::sync::CancellationToken;
 
 pub use crate::challenges::core::{
     ChallengeExecutionError, ChallengeHttpClient, ChallengeHttpClientError, ChallengeHttpResponse,
-    ChallengeResponse, ChallengeSubmission, OriginalRequest, ReqwestChallengeHttpClient,
-    execute_challenge_submission,
+    ChallengeResponse, ChallengeStageSolver, ChallengeSubmission, DecayingRate, LearningRate,
+    OriginalRequest, PostClearanceStrategy, ReqwestChallengeHttpClient, StagedChallengeResponse,
+    decode_body, execute_challenge_stages, execute_challenge_submission,
+    execute_challenge_submission_with,
 };
 
+#[cfg(feature = "hot-reload")]
+pub use crate::challenges::detectors::PatternWatcher;
 pub use crate::challenges::detectors::{
-    ChallengeDetection, ChallengeDetector, ChallengeType, ResponseStrategy,
+    AdaptivePatternInfo, ChallengeDetection, ChallengeDetector, ChallengeType, PatternDefinition,
+    PatternError, ResponseStrategy,
 };
 
+pub use crate::challenges::parse::{ParseError, ParsedChallenge, parse_challenge};
 pub use crate::challenges::pipeline::{
-    ChallengePipeline, ChallengePipelineResult, PipelineContext, PipelineError, UnsupportedReason,
+    Capabilities, ChallengePipeline, ChallengePipelineResult, DynChallengeSolver, PipelineContext,
+    PipelineError, PipelinePrepareResult, SolverOutput, UnsupportedReason, solve_challenge_page,
 };
 
 pub use crate::challenges::solvers::{
-    FailureRecorder, FingerprintManager, MitigationPlan, TlsProfileManager,
+    FailureContext, FailureRecorder, FingerprintManager, MitigationPlan, TlsProfileManager,
 };
 
 pub use crate::challenges::user_agents::{
     UserAgentError, UserAgentOptions, UserAgentProfile, get_user_agent_profile,
+    get_user_agent_profile_with_rng,
 };
 
 pub use crate::external_deps::captcha::{
@@ -73,15 +90,17 @@ pub use crate::external_deps::interpreters::{
 };
 
 pub use crate::modules::{
-    AdaptiveTimingStrategy, AntiDetectionContext, AntiDetectionStrategy, BehaviorProfile,
-    BrowserFingerprint, BrowserProfile, BrowserType, ChallengeEvent, ConsistencyLevel,
-    DefaultAdaptiveTiming, DefaultAntiDetection, DefaultTLSManager, DomainState, DomainStats,
-    DomainTimingSnapshot, ErrorEvent, EventDispatcher, EventHandler, FeatureVector,
-    FingerprintGenerator, GlobalStats, LoggingHandler, MLOptimizer, MetricsCollector,
-    MetricsHandler, MetricsSnapshot, PerformanceConfig, PerformanceMonitor, PerformanceReport,
-    PostResponseEvent, PreRequestEvent, ProxyConfig, ProxyHealthReport, ProxyManager, RequestKind,
-    RetryEvent, RotationStrategy, ScraperEvent, StateManager, StrategyRecommendation, TLSConfig,
-    TimingOutcome, TimingRequest,
+    AdaptiveConcurrency, AdaptiveTimingStrategy, AimdConfig, AntiDetectionContext,
+    AntiDetectionStrategy, BehaviorProfile, BlacklistKey, BrowserFingerprint, BrowserProfile,
+    BrowserType, CacheConfig, ChallengeEvent, ChallengeStatus, ConcurrencyPermit, ConsistencyLevel,
+    DefaultAdaptiveTiming, DefaultAntiDetection, DefaultTLSManager, DomainSeed, DomainState,
+    DomainStats, DomainTimingSnapshot, ErrorEvent, EventDispatcher, EventHandler, FeatureVector,
+    FieldDiff, FileStorage, FingerprintGenerator, GlobalStats, HarLog, LoggingHandler, MLOptimizer,
+    MemoryStorage, MetricsCollector, MetricsHandler, MetricsSnapshot, OperatingSystem,
+    PerformanceConfig, PerformanceMonitor, PerformanceReport, PostResponseEvent, PreRequestEvent,
+    ProxyConfig, ProxyHealthReport, ProxyManager, RequestKind, RetryEvent, RotationStrategy,
+    ScraperEvent, StateManager, StateScope, StorageBackend, StorageError, StrategyRecommendation,
+    TLSConfig, TimingOutcome, TimingRequest, TlsBackend,
 };
 
 /// Library version
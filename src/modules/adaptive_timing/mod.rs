@@ -4,10 +4,30 @@
 //! adjustments, and per-domain learning.
 
 use chrono::{DateTime, Local, Timelike};
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, VecDeque};
 use std::time::{Duration, Instant};
 
+use crate::challenges::core::{DEFAULT_SUCCESS_HALF_LIFE, DecayingRate, LearningRate};
+
+/// Hard ceiling applied to every computed delay unless overridden.
+pub const DEFAULT_ABSOLUTE_MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Default schedule of the per-domain response time and delay averages.
+pub const DEFAULT_TIMING_LEARNING_RATE: LearningRate = LearningRate::Fixed(0.1);
+
+/// Successes in a row before fast mode starts shrinking delays.
+const FAST_MODE_MIN_STREAK: u32 = 10;
+/// Success rate a domain must hold for fast mode to keep shrinking delays.
+const FAST_MODE_MIN_SUCCESS_RATE: f32 = 0.98;
+
+/// Failures in a row that escalate a domain's profile one step.
+const ESCALATION_FAILURE_STREAK: u32 = 3;
+/// Successes in a row that undo a domain's latest escalation.
+const DEESCALATION_SUCCESS_STREAK: u32 = 20;
+
 /// Behaviour profiles that control the high-level timing envelope.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum BehaviorProfile {
@@ -17,6 +37,17 @@ pub enum BehaviorProfile {
     Mobile,
 }
 
+impl BehaviorProfile {
+    /// Next more cautious profile: Focused, then Casual, then Research.
+    pub fn escalated(self) -> Option<Self> {
+        match self {
+            BehaviorProfile::Focused => Some(BehaviorProfile::Casual),
+            BehaviorProfile::Casual | BehaviorProfile::Mobile => Some(BehaviorProfile::Research),
+            BehaviorProfile::Research => None,
+        }
+    }
+}
+
 /// High-level request kinds for timing adjustments.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum RequestKind {
@@ -84,11 +115,13 @@ pub struct TimingOutcome {
 }
 
 /// Snapshot of learned state for observability.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct DomainTimingSnapshot {
     pub success_rate: f32,
     pub consecutive_failures: u8,
+    #[serde(with = "crate::serde_millis::duration")]
     pub average_response_time: Duration,
+    #[serde(with = "crate::serde_millis::option_duration")]
     pub optimal_timing: Option<Duration>,
 }
 
@@ -106,32 +139,57 @@ pub trait AdaptiveTimingStrategy: Send + Sync {
 pub struct DefaultAdaptiveTiming {
     profiles: HashMap<BehaviorProfile, TimingProfile>,
     active_profile: BehaviorProfile,
+    domain_profiles: HashMap<String, BehaviorProfile>,
     domain_state: HashMap<String, DomainTimingState>,
     global_history: VecDeque<bool>,
     last_global_request: Option<Instant>,
+    success_half_life: Duration,
+    learning_rate: LearningRate,
+    profile_escalation: bool,
+    absolute_max_delay: Duration,
+    aggressiveness: f32,
+    rng: StdRng,
 }
 
 #[derive(Debug, Clone)]
 struct DomainTimingState {
-    success_rate: f32,
+    outcomes: DecayingRate,
     consecutive_failures: u8,
+    success_streak: u32,
+    /// Share of the delay above `min_delay` kept in fast mode; 1.0 is off.
+    fast_factor: f32,
     average_response_time: f32,
     optimal_timing: Option<f32>,
     last_request: Option<Instant>,
     recent_delays: VecDeque<f32>,
+    /// Outcomes folded into the averages so far.
+    updates: u64,
+    /// Failures since the last escalation or success.
+    escalation_streak: u32,
+    /// Profiles the domain was escalated from, most recent last.
+    escalated_from: Vec<BehaviorProfile>,
 }
 
-impl Default for DomainTimingState {
-    fn default() -> Self {
+impl DomainTimingState {
+    fn new(success_half_life: Duration) -> Self {
         Self {
-            success_rate: 1.0,
+            outcomes: DecayingRate::new(success_half_life),
             consecutive_failures: 0,
+            success_streak: 0,
+            fast_factor: 1.0,
             average_response_time: 1.0,
             optimal_timing: None,
             last_request: None,
             recent_delays: VecDeque::with_capacity(32),
+            updates: 0,
+            escalation_streak: 0,
+            escalated_from: Vec::new(),
         }
     }
+
+    fn success_rate(&self) -> f32 {
+        self.outcomes.rate().unwrap_or(1.0) as f32
+    }
 }
 
 impl DefaultAdaptiveTiming {
@@ -189,20 +247,105 @@ impl DefaultAdaptiveTiming {
         Self {
             profiles,
             active_profile: BehaviorProfile::Casual,
+            domain_profiles: HashMap::new(),
             domain_state: HashMap::new(),
             global_history: VecDeque::with_capacity(128),
             last_global_request: None,
+            success_half_life: DEFAULT_SUCCESS_HALF_LIFE,
+            learning_rate: DEFAULT_TIMING_LEARNING_RATE,
+            profile_escalation: false,
+            absolute_max_delay: DEFAULT_ABSOLUTE_MAX_DELAY,
+            aggressiveness: 0.0,
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    /// Draw delay jitter from `rng` instead of an entropy-seeded generator.
+    pub fn with_rng(mut self, rng: StdRng) -> Self {
+        self.rng = rng;
+        self
+    }
+
+    /// Enable fast mode: after a sustained success streak each further success
+    /// shrinks the delay toward the profile's `min_delay`, and any failure
+    /// snaps it back. `0.0` (the default) disables it, `1.0` shrinks fastest.
+    pub fn set_aggressiveness(&mut self, aggressiveness: f32) {
+        self.aggressiveness = aggressiveness.clamp(0.0, 1.0);
+        if self.aggressiveness == 0.0 {
+            for state in self.domain_state.values_mut() {
+                state.fast_factor = 1.0;
+            }
         }
     }
 
-    fn profile(&self) -> TimingProfile {
+    pub fn aggressiveness(&self) -> f32 {
+        self.aggressiveness
+    }
+
+    /// Ceiling applied after every other adjustment, regardless of profile.
+    pub fn with_absolute_max_delay(mut self, max_delay: Duration) -> Self {
+        self.absolute_max_delay = max_delay;
+        self
+    }
+
+    pub fn absolute_max_delay(&self) -> Duration {
+        self.absolute_max_delay
+    }
+
+    /// Age at which a domain outcome counts half as much towards its success rate.
+    pub fn with_success_half_life(mut self, half_life: Duration) -> Self {
+        self.success_half_life = half_life;
+        self
+    }
+
+    /// Schedule of the per-domain response time and delay averages.
+    pub fn with_learning_rate(mut self, learning_rate: LearningRate) -> Self {
+        self.learning_rate = learning_rate;
+        self
+    }
+
+    /// Step a domain's profile up with [`BehaviorProfile::escalated`] after
+    /// repeated failures, and back down after a long clean streak.
+    pub fn set_profile_escalation(&mut self, enabled: bool) {
+        self.profile_escalation = enabled;
+    }
+
+    /// Pace `domain` with `profile` instead of the active one.
+    pub fn set_domain_profile(&mut self, domain: &str, profile: BehaviorProfile) {
+        if self.profiles.contains_key(&profile) {
+            self.domain_profiles.insert(domain.to_string(), profile);
+        }
+    }
+
+    /// Pace `domain` with `profile` until a clean streak steps it back to
+    /// the profile it had before.
+    pub fn escalate_domain(&mut self, domain: &str, profile: BehaviorProfile) {
+        let current = self.domain_profile(domain);
+        if current == profile || !self.profiles.contains_key(&profile) {
+            return;
+        }
+        self.ensure_domain_state(domain)
+            .escalated_from
+            .push(current);
+        self.set_domain_profile(domain, profile);
+    }
+
+    /// Profile pacing `domain`: its override, else the active profile.
+    pub fn domain_profile(&self, domain: &str) -> BehaviorProfile {
+        self.domain_profiles
+            .get(domain)
+            .copied()
+            .unwrap_or(self.active_profile)
+    }
+
+    fn profile(&self, domain: &str) -> TimingProfile {
         self.profiles
-            .get(&self.active_profile)
+            .get(&self.domain_profile(domain))
             .copied()
             .expect("profile missing")
     }
 
-    fn circadian_multiplier() -> f32 {
+    fn circadian_multiplier(rng: &mut StdRng) -> f32 {
         let now: DateTime<Local> = Local::now();
         let hour = now.hour() as i32;
         let base = match hour {
@@ -227,16 +370,22 @@ impl DefaultAdaptiveTiming {
             23 => 0.3,
             _ => 0.5,
         };
-        let mut rng = rand::thread_rng();
         base * rng.gen_range(0.85..=1.15)
     }
 
     fn ensure_domain_state(&mut self, domain: &str) -> &mut DomainTimingState {
-        self.domain_state.entry(domain.to_string()).or_default()
+        let half_life = self.success_half_life;
+        self.domain_state
+            .entry(domain.to_string())
+            .or_insert_with(|| DomainTimingState::new(half_life))
     }
 
-    fn apply_human_jitter(mut delay: f32, profile: TimingProfile, content_length: usize) -> f32 {
-        let mut rng = rand::thread_rng();
+    fn apply_human_jitter(
+        rng: &mut StdRng,
+        mut delay: f32,
+        profile: TimingProfile,
+        content_length: usize,
+    ) -> f32 {
         // Reading delay heuristics
         if content_length > 500 {
             let words = (content_length as f32 / 5.0).max(1.0);
@@ -278,16 +427,21 @@ impl AdaptiveTimingStrategy for DefaultAdaptiveTiming {
     }
 
     fn calculate_delay(&mut self, domain: &str, request: &TimingRequest) -> Duration {
-        let profile = self.profile();
-        let state = self.ensure_domain_state(domain);
+        let profile = self.profile(domain);
+        let half_life = self.success_half_life;
+        let rng = &mut self.rng;
+        let state = self
+            .domain_state
+            .entry(domain.to_string())
+            .or_insert_with(|| DomainTimingState::new(half_life));
 
         let mut delay = profile.base_delay * request.kind.delay_multiplier();
-        let mut rng = rand::thread_rng();
         let variance = rng.gen_range(1.0 - profile.variance_factor..=1.0 + profile.variance_factor);
         delay *= variance;
 
-        if state.success_rate < profile.success_rate_threshold {
-            let delta = profile.success_rate_threshold - state.success_rate;
+        let success_rate = state.success_rate();
+        if success_rate < profile.success_rate_threshold {
+            let delta = profile.success_rate_threshold - success_rate;
             delay *= 1.0 + delta.max(0.05);
         }
 
@@ -303,9 +457,9 @@ impl AdaptiveTimingStrategy for DefaultAdaptiveTiming {
         let response_factor = state.average_response_time.clamp(0.6, 1.5);
         delay *= response_factor;
 
-        delay = Self::apply_human_jitter(delay, profile, request.content_length);
+        delay = Self::apply_human_jitter(rng, delay, profile, request.content_length);
 
-        let circadian = Self::circadian_multiplier().max(0.2);
+        let circadian = Self::circadian_multiplier(rng).max(0.2);
         delay /= circadian;
 
         let now = Instant::now();
@@ -316,28 +470,58 @@ impl AdaptiveTimingStrategy for DefaultAdaptiveTiming {
             }
         }
 
+        if state.fast_factor < 1.0 {
+            delay = profile.min_delay + (delay - profile.min_delay).max(0.0) * state.fast_factor;
+        }
+
         state.last_request = Some(now);
         self.last_global_request = Some(now);
 
-        Duration::from_secs_f32(profile.clamp(delay))
+        Duration::from_secs_f32(profile.clamp(delay)).min(self.absolute_max_delay)
     }
 
     fn record_outcome(&mut self, domain: &str, outcome: &TimingOutcome) {
+        let aggressiveness = self.aggressiveness;
+        let learning_rate = self.learning_rate;
+        let escalation = self.profile_escalation;
+        let mut escalate = false;
+        let mut restore = None;
         let state = self.ensure_domain_state(domain);
-        let alpha = 0.1;
-        let success_value = if outcome.success { 1.0 } else { 0.0 };
-
-        state.success_rate = (1.0 - alpha) * state.success_rate + alpha * success_value;
+        let alpha = learning_rate.rate(state.updates) as f32;
+        state.updates += 1;
+        state.outcomes.record(outcome.success);
 
         if outcome.success {
             state.consecutive_failures = 0;
+            state.success_streak = state.success_streak.saturating_add(1);
+            if aggressiveness > 0.0
+                && state.success_streak >= FAST_MODE_MIN_STREAK
+                && state.success_rate() > FAST_MODE_MIN_SUCCESS_RATE
+            {
+                state.fast_factor *= 1.0 - 0.25 * aggressiveness;
+            }
             let applied = outcome.applied_delay.as_secs_f32().min(10.0);
             state.optimal_timing = Some(match state.optimal_timing {
                 None => applied,
-                Some(prev) => (0.9 * prev) + (0.1 * applied),
+                Some(prev) => (1.0 - alpha) * prev + alpha * applied,
             });
+            state.escalation_streak = 0;
+            // Also undoes escalations requested through `escalate_domain`.
+            if state
+                .success_streak
+                .is_multiple_of(DEESCALATION_SUCCESS_STREAK)
+            {
+                restore = state.escalated_from.pop();
+            }
         } else {
             state.consecutive_failures = state.consecutive_failures.saturating_add(1).min(5);
+            state.success_streak = 0;
+            state.fast_factor = 1.0;
+            state.escalation_streak += 1;
+            if escalation && state.escalation_streak >= ESCALATION_FAILURE_STREAK {
+                state.escalation_streak = 0;
+                escalate = true;
+            }
         }
 
         let response_time = outcome.response_time.as_secs_f32().min(30.0);
@@ -355,13 +539,25 @@ impl AdaptiveTimingStrategy for DefaultAdaptiveTiming {
             self.global_history.pop_front();
         }
         self.global_history.push_back(outcome.success);
+
+        let current = self.domain_profile(domain);
+        if let Some(previous) = restore {
+            log::debug!("{domain} clean again; pacing it as {previous:?}");
+            self.set_domain_profile(domain, previous);
+        } else if escalate && let Some(next) = current.escalated() {
+            log::info!("{domain} keeps failing; escalating from {current:?} to {next:?}");
+            self.ensure_domain_state(domain)
+                .escalated_from
+                .push(current);
+            self.set_domain_profile(domain, next);
+        }
     }
 
     fn snapshot(&self, domain: &str) -> Option<DomainTimingSnapshot> {
         self.domain_state
             .get(domain)
             .map(|state| DomainTimingSnapshot {
-                success_rate: state.success_rate,
+                success_rate: state.success_rate(),
                 consecutive_failures: state.consecutive_failures,
                 average_response_time: Duration::from_secs_f32(state.average_response_time),
                 optimal_timing: state.optimal_timing.map(Duration::from_secs_f32),
@@ -395,4 +591,137 @@ mod tests {
         // After successive successes the delay should tend to decrease a bit.
         assert!(delay2 <= delay1 * 2);
     }
+
+    #[test]
+    fn absolute_max_delay_caps_stacked_penalties() {
+        let cap = Duration::from_millis(1500);
+        let mut timing = DefaultAdaptiveTiming::new().with_absolute_max_delay(cap);
+        timing.set_behavior_profile(BehaviorProfile::Research);
+        for _ in 0..20 {
+            timing.record_outcome(
+                "example.com",
+                &TimingOutcome {
+                    success: false,
+                    response_time: Duration::from_secs(30),
+                    applied_delay: Duration::from_secs(10),
+                },
+            );
+        }
+
+        let request = TimingRequest::new(RequestKind::Post, 5_000_000);
+        for _ in 0..50 {
+            assert!(timing.calculate_delay("example.com", &request) <= cap);
+        }
+    }
+
+    #[test]
+    fn snapshot_round_trips_through_json() {
+        let snapshot = DomainTimingSnapshot {
+            success_rate: 0.75,
+            consecutive_failures: 2,
+            average_response_time: Duration::from_millis(1250),
+            optimal_timing: Some(Duration::from_millis(900)),
+        };
+        let json = serde_json::to_string(&snapshot).unwrap();
+        assert!(json.contains("\"average_response_time\":1250"));
+        let restored: DomainTimingSnapshot = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, snapshot);
+    }
+
+    #[test]
+    fn aggressive_mode_converges_to_min_delay() {
+        let mut timing = DefaultAdaptiveTiming::new();
+        timing.set_aggressiveness(1.0);
+        let request = TimingRequest::new(RequestKind::Get, 0);
+        let min_delay = timing.profile("example.com").min_delay;
+        let success = TimingOutcome {
+            success: true,
+            response_time: Duration::from_millis(300),
+            applied_delay: Duration::from_secs(1),
+        };
+
+        for _ in 0..50 {
+            timing.calculate_delay("example.com", &request);
+            timing.record_outcome("example.com", &success);
+        }
+        let delay = timing.calculate_delay("example.com", &request);
+        assert!(delay.as_secs_f32() <= min_delay * 1.05, "{delay:?}");
+
+        timing.record_outcome(
+            "example.com",
+            &TimingOutcome {
+                success: false,
+                ..success
+            },
+        );
+        assert_eq!(timing.domain_state["example.com"].fast_factor, 1.0);
+    }
+
+    #[test]
+    fn failure_streaks_escalate_and_clean_streaks_step_back() {
+        let mut timing = DefaultAdaptiveTiming::new();
+        timing.set_behavior_profile(BehaviorProfile::Focused);
+        timing.set_profile_escalation(true);
+        let outcome = |success| TimingOutcome {
+            success,
+            response_time: Duration::from_millis(200),
+            applied_delay: Duration::ZERO,
+        };
+
+        for _ in 0..ESCALATION_FAILURE_STREAK {
+            timing.record_outcome("example.com", &outcome(false));
+        }
+        assert_eq!(
+            timing.domain_profile("example.com"),
+            BehaviorProfile::Casual
+        );
+        for _ in 0..ESCALATION_FAILURE_STREAK * 2 {
+            timing.record_outcome("example.com", &outcome(false));
+        }
+        assert_eq!(
+            timing.domain_profile("example.com"),
+            BehaviorProfile::Research
+        );
+        assert_eq!(timing.domain_profile("other.com"), BehaviorProfile::Focused);
+
+        for _ in 0..DEESCALATION_SUCCESS_STREAK {
+            timing.record_outcome("example.com", &outcome(true));
+        }
+        assert_eq!(
+            timing.domain_profile("example.com"),
+            BehaviorProfile::Casual
+        );
+        for _ in 0..DEESCALATION_SUCCESS_STREAK * 2 {
+            timing.record_outcome("example.com", &outcome(true));
+        }
+        assert_eq!(
+            timing.domain_profile("example.com"),
+            BehaviorProfile::Focused
+        );
+    }
+
+    #[test]
+    fn escalated_domain_steps_back_after_a_clean_streak() {
+        let mut timing = DefaultAdaptiveTiming::new();
+        timing.set_behavior_profile(BehaviorProfile::Focused);
+        let success = TimingOutcome {
+            success: true,
+            response_time: Duration::from_millis(200),
+            applied_delay: Duration::ZERO,
+        };
+
+        timing.escalate_domain("example.com", BehaviorProfile::Research);
+        for _ in 1..DEESCALATION_SUCCESS_STREAK {
+            timing.record_outcome("example.com", &success);
+        }
+        assert_eq!(
+            timing.domain_profile("example.com"),
+            BehaviorProfile::Research
+        );
+        timing.record_outcome("example.com", &success);
+        assert_eq!(
+            timing.domain_profile("example.com"),
+            BehaviorProfile::Focused
+        );
+    }
 }
@@ -4,8 +4,9 @@
 //! layer that prepares requests before they hit the network.
 
 use http::{HeaderMap, HeaderName, HeaderValue, Method};
-use rand::Rng;
-use std::collections::{HashMap, VecDeque};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::time::{Duration, Instant};
 use url::Url;
 
@@ -16,10 +17,20 @@ pub struct AntiDetectionConfig {
     pub inject_noise_headers: bool,
     pub header_noise_range: (usize, usize),
     pub burst_window: Duration,
+    /// Starting per-domain burst limit; each domain then learns its own.
     pub max_requests_per_window: usize,
+    /// Ceiling for a learned per-domain burst limit.
+    pub max_burst_limit: usize,
+    /// Successes in a row before a domain's burst limit grows by one.
+    pub burst_growth_streak: u32,
     pub cooldown: Duration,
     pub failure_cooldown: Duration,
     pub jitter_range: (f32, f32),
+    /// Headers the randomizer and noise injector never touch.
+    pub preserve_headers: HashSet<HeaderName>,
+    /// Also leave alone any header already set when the request reaches
+    /// anti-detection, e.g. by a sticky header or the caller.
+    pub preserve_existing_headers: bool,
 }
 
 impl Default for AntiDetectionConfig {
@@ -30,13 +41,25 @@ impl Default for AntiDetectionConfig {
             header_noise_range: (1, 3),
             burst_window: Duration::from_secs(30),
             max_requests_per_window: 10,
+            max_burst_limit: 40,
+            burst_growth_streak: 20,
             cooldown: Duration::from_secs(3),
             failure_cooldown: Duration::from_secs(20),
             jitter_range: (0.85, 1.25),
+            preserve_headers: HashSet::new(),
+            preserve_existing_headers: false,
         }
     }
 }
 
+impl AntiDetectionConfig {
+    /// Whether `name` must be left as found in `headers`.
+    pub fn preserves(&self, name: &HeaderName, headers: &HeaderMap) -> bool {
+        self.preserve_headers.contains(name)
+            || (self.preserve_existing_headers && headers.contains_key(name))
+    }
+}
+
 /// Context object mutated by anti-detection strategies before dispatch.
 #[derive(Debug, Clone)]
 pub struct AntiDetectionContext {
@@ -92,25 +115,31 @@ pub trait AntiDetectionStrategy: Send + Sync {
 pub struct DefaultAntiDetection {
     config: AntiDetectionConfig,
     per_domain: HashMap<String, DomainAntiDetection>,
+    rng: StdRng,
 }
 
 #[derive(Debug)]
 struct DomainAntiDetection {
     recent_requests: VecDeque<Instant>,
     failure_streak: u8,
+    success_streak: u32,
+    /// Learned burst limit; `None` until the first adjustment.
+    burst_limit: Option<usize>,
     cooldown_until: Option<Instant>,
     rolling_latency: VecDeque<f32>,
     fingerprint_salt: u32,
 }
 
-impl Default for DomainAntiDetection {
-    fn default() -> Self {
+impl DomainAntiDetection {
+    fn new(fingerprint_salt: u32) -> Self {
         Self {
             recent_requests: VecDeque::with_capacity(32),
             failure_streak: 0,
+            success_streak: 0,
+            burst_limit: None,
             cooldown_until: None,
             rolling_latency: VecDeque::with_capacity(32),
-            fingerprint_salt: rand::thread_rng().r#gen(),
+            fingerprint_salt,
         }
     }
 }
@@ -120,15 +149,33 @@ impl DefaultAntiDetection {
         Self {
             config,
             per_domain: HashMap::new(),
+            rng: StdRng::from_entropy(),
         }
     }
 
+    /// Draw header randomisation and jitter from `rng`.
+    pub fn with_rng(mut self, rng: StdRng) -> Self {
+        self.rng = rng;
+        self
+    }
+
     pub fn config(&self) -> &AntiDetectionConfig {
         &self.config
     }
 
+    /// Requests `domain` may make per burst window before being throttled.
+    pub fn burst_limit(&self, domain: &str) -> usize {
+        self.per_domain
+            .get(domain)
+            .and_then(|state| state.burst_limit)
+            .unwrap_or(self.config.max_requests_per_window)
+    }
+
     fn state_mut(&mut self, domain: &str) -> &mut DomainAntiDetection {
-        self.per_domain.entry(domain.to_string()).or_default()
+        let rng = &mut self.rng;
+        self.per_domain
+            .entry(domain.to_string())
+            .or_insert_with(|| DomainAntiDetection::new(rng.r#gen()))
     }
 
     fn prune_old_requests(state: &mut DomainAntiDetection, window: Duration) {
@@ -144,8 +191,8 @@ impl DefaultAntiDetection {
         ctx: &mut AntiDetectionContext,
     ) {
         Self::prune_old_requests(state, config.burst_window);
-        if state.recent_requests.len() > config.max_requests_per_window && ctx.delay_hint.is_none()
-        {
+        let limit = state.burst_limit.unwrap_or(config.max_requests_per_window);
+        if state.recent_requests.len() > limit && ctx.delay_hint.is_none() {
             ctx.delay_hint = Some(config.cooldown);
         }
     }
@@ -163,6 +210,7 @@ impl DefaultAntiDetection {
     }
 
     fn randomize_headers(
+        rng: &mut StdRng,
         config: &AntiDetectionConfig,
         state: &DomainAntiDetection,
         ctx: &mut AntiDetectionContext,
@@ -171,7 +219,6 @@ impl DefaultAntiDetection {
             return;
         }
 
-        let mut rng = rand::thread_rng();
         // Rotate a few headers that commonly trigger fingerprinting.
         static TARGET_HEADERS: &[&str] = &[
             "accept-language",
@@ -183,26 +230,32 @@ impl DefaultAntiDetection {
         for header in TARGET_HEADERS {
             if let Ok(name) = HeaderName::from_lowercase(header.as_bytes())
                 && rng.gen_bool(0.3)
+                && !config.preserves(&name, &ctx.headers)
             {
-                let value = random_header_value(&mut rng, state.fingerprint_salt);
+                let value = random_header_value(rng, state.fingerprint_salt);
                 ctx.headers.insert(name, value);
             }
         }
 
-        if let Some(agent) = &ctx.user_agent {
-            let name = HeaderName::from_static("user-agent");
+        let name = HeaderName::from_static("user-agent");
+        if let Some(agent) = &ctx.user_agent
+            && !config.preserves(&name, &ctx.headers)
+        {
             let value = HeaderValue::from_str(agent)
                 .unwrap_or_else(|_| HeaderValue::from_static("Mozilla/5.0"));
             ctx.headers.insert(name, value);
         }
     }
 
-    fn inject_noise_headers(config: &AntiDetectionConfig, ctx: &mut AntiDetectionContext) {
+    fn inject_noise_headers(
+        rng: &mut StdRng,
+        config: &AntiDetectionConfig,
+        ctx: &mut AntiDetectionContext,
+    ) {
         if !config.inject_noise_headers {
             return;
         }
 
-        let mut rng = rand::thread_rng();
         let (min, max) = config.header_noise_range;
         let upper = max.max(min);
         let count = rng.gen_range(min..=upper);
@@ -213,6 +266,7 @@ impl DefaultAntiDetection {
                 .collect();
             let name = format!("x-cf-client-{}", token);
             if let Ok(header_name) = HeaderName::from_bytes(name.as_bytes())
+                && !config.preserves(&header_name, &ctx.headers)
                 && let Ok(header_value) =
                     HeaderValue::from_str(&format!("{}-{}", rng.r#gen::<u32>(), ctx.body_size))
             {
@@ -226,36 +280,53 @@ impl AntiDetectionStrategy for DefaultAntiDetection {
     fn prepare_request(&mut self, domain: &str, ctx: &mut AntiDetectionContext) {
         let config = self.config.clone();
         {
-            let state = self.state_mut(domain);
+            let rng = &mut self.rng;
+            let state = self
+                .per_domain
+                .entry(domain.to_string())
+                .or_insert_with(|| DomainAntiDetection::new(rng.r#gen()));
             state.recent_requests.push_back(Instant::now());
             Self::enforce_burst_limits(&config, state, ctx);
             Self::maybe_apply_cooldown(state, ctx);
-            Self::randomize_headers(&config, state, ctx);
+            Self::randomize_headers(rng, &config, state, ctx);
         }
 
-        Self::inject_noise_headers(&config, ctx);
+        Self::inject_noise_headers(&mut self.rng, &config, ctx);
 
         // Apply jitter hint so that timing layer can increase randomness.
-        let jitter = {
-            let mut rng = rand::thread_rng();
-            rng.gen_range(config.jitter_range.0..=config.jitter_range.1)
-        };
+        let jitter = self
+            .rng
+            .gen_range(config.jitter_range.0..=config.jitter_range.1);
         ctx.metadata
             .insert("anti_detection_jitter".into(), format!("{:.3}", jitter));
     }
 
     fn record_response(&mut self, domain: &str, status: u16, latency: Duration) {
-        let failure_cooldown = self.config.failure_cooldown;
+        let config = self.config.clone();
         let state = self.state_mut(domain);
         let success = status < 500;
 
         if !success {
             state.failure_streak = state.failure_streak.saturating_add(1);
-            state.cooldown_until = Some(Instant::now() + failure_cooldown);
+            state.cooldown_until = Some(Instant::now() + config.failure_cooldown);
         } else {
             state.failure_streak = 0;
         }
 
+        // Grow the burst limit slowly on sustained success; halve it on a
+        // failure or challenge status.
+        let limit = state.burst_limit.unwrap_or(config.max_requests_per_window);
+        if success && !matches!(status, 403 | 429) {
+            state.success_streak = state.success_streak.saturating_add(1);
+            if state.success_streak >= config.burst_growth_streak.max(1) {
+                state.success_streak = 0;
+                state.burst_limit = Some((limit + 1).min(config.max_burst_limit.max(limit)));
+            }
+        } else {
+            state.success_streak = 0;
+            state.burst_limit = Some((limit / 2).max(1));
+        }
+
         if state.rolling_latency.len() == 32 {
             state.rolling_latency.pop_front();
         }
@@ -304,4 +375,48 @@ mod tests {
         strategy.prepare_request("example.com", &mut ctx3);
         assert!(ctx3.delay_hint.is_some());
     }
+
+    #[test]
+    fn learns_burst_limit_per_domain() {
+        let mut strategy = DefaultAntiDetection::new(AntiDetectionConfig {
+            max_requests_per_window: 4,
+            max_burst_limit: 6,
+            burst_growth_streak: 3,
+            ..Default::default()
+        });
+        let latency = Duration::from_millis(100);
+
+        for _ in 0..6 {
+            strategy.record_response("example.com", 200, latency);
+        }
+        assert_eq!(strategy.burst_limit("example.com"), 6);
+        for _ in 0..6 {
+            strategy.record_response("example.com", 200, latency);
+        }
+        assert_eq!(strategy.burst_limit("example.com"), 6);
+
+        strategy.record_response("example.com", 429, latency);
+        assert_eq!(strategy.burst_limit("example.com"), 3);
+        assert_eq!(strategy.burst_limit("other.org"), 4);
+    }
+
+    #[test]
+    fn preserved_headers_survive_randomization() {
+        let accept_language = HeaderName::from_static("accept-language");
+        let mut strategy = DefaultAntiDetection::new(AntiDetectionConfig {
+            preserve_headers: HashSet::from([accept_language.clone()]),
+            ..Default::default()
+        })
+        .with_rng(StdRng::seed_from_u64(7));
+        let url = Url::parse("https://example.com").unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(accept_language.clone(), HeaderValue::from_static("de-DE"));
+        for _ in 0..50 {
+            let mut ctx =
+                AntiDetectionContext::new(url.clone(), Method::GET).with_headers(headers.clone());
+            strategy.prepare_request("example.com", &mut ctx);
+            assert_eq!(ctx.headers[&accept_language], "de-DE");
+        }
+    }
 }
@@ -0,0 +1,99 @@
+//! Per-domain identity blacklists.
+//!
+//! When a proxy, TLS profile or browser fingerprint is known to be banned on
+//! a domain, blacklisting it there keeps the scraper from presenting it to
+//! that domain again until the entry expires. Other domains are unaffected.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// One burned component of a request identity.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum BlacklistKey {
+    /// Proxy endpoint, as configured.
+    Proxy(String),
+    /// JA3 hash of a TLS profile.
+    Ja3(String),
+    /// Canvas fingerprint hash.
+    Canvas(String),
+    /// Exact `User-Agent` string.
+    UserAgent(String),
+}
+
+/// Blacklisted identity components by domain, each expiring after a TTL.
+#[derive(Debug)]
+pub struct IdentityBlacklist {
+    ttl: Duration,
+    domains: HashMap<String, HashMap<BlacklistKey, Instant>>,
+}
+
+impl IdentityBlacklist {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            domains: HashMap::new(),
+        }
+    }
+
+    /// Blacklist `key` on `domain` for the configured TTL, restarting it if
+    /// the key was already listed.
+    pub fn add(&mut self, domain: &str, key: BlacklistKey) {
+        self.domains
+            .entry(domain.to_string())
+            .or_default()
+            .insert(key, Instant::now() + self.ttl);
+    }
+
+    pub fn contains(&self, domain: &str, key: &BlacklistKey) -> bool {
+        self.domains
+            .get(domain)
+            .and_then(|keys| keys.get(key))
+            .is_some_and(|until| *until > Instant::now())
+    }
+
+    /// Proxies currently blacklisted on `domain`.
+    pub fn proxies(&self, domain: &str) -> Vec<String> {
+        let now = Instant::now();
+        self.domains
+            .get(domain)
+            .into_iter()
+            .flatten()
+            .filter_map(|(key, until)| match key {
+                BlacklistKey::Proxy(proxy) if *until > now => Some(proxy.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Drop expired entries.
+    pub fn prune(&mut self) {
+        let now = Instant::now();
+        self.domains.retain(|_, keys| {
+            keys.retain(|_, until| *until > now);
+            !keys.is_empty()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_are_scoped_to_their_domain_and_expire() {
+        let mut blacklist = IdentityBlacklist::new(Duration::from_millis(30));
+        let proxy = BlacklistKey::Proxy("http://p1:8080".into());
+        blacklist.add("example.com", proxy.clone());
+        blacklist.add("example.com", BlacklistKey::Ja3("abc".into()));
+
+        assert!(blacklist.contains("example.com", &proxy));
+        assert!(!blacklist.contains("other.com", &proxy));
+        assert_eq!(blacklist.proxies("example.com"), vec!["http://p1:8080"]);
+
+        std::thread::sleep(Duration::from_millis(40));
+        assert!(!blacklist.contains("example.com", &proxy));
+        assert!(blacklist.proxies("example.com").is_empty());
+        blacklist.prune();
+        assert!(blacklist.domains.is_empty());
+    }
+}
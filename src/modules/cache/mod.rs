@@ -0,0 +1,213 @@
+//! Conditional-request cache for repeat fetches.
+//!
+//! Successful `GET` responses carrying an `ETag` or `Last-Modified` header are
+//! kept per URL. Later requests for the same URL send `If-None-Match` /
+//! `If-Modified-Since`, and a `304 Not Modified` is answered from the stored
+//! body. The scraper only offers responses that cleared challenge evaluation,
+//! so challenge pages never enter the cache.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use bytes::Bytes;
+use http::header::{
+    CACHE_CONTROL, ETAG, HeaderValue, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+};
+use http::{HeaderMap, Method};
+use url::Url;
+
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    /// Stored responses; the oldest entry is evicted past this.
+    pub max_entries: usize,
+    /// Larger bodies are never stored.
+    pub max_body_bytes: usize,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            max_entries: 256,
+            max_body_bytes: 8 * 1024 * 1024,
+        }
+    }
+}
+
+/// A stored response, returned in place of a `304`.
+#[derive(Debug, Clone)]
+pub struct CachedResponse {
+    pub status: u16,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+impl CachedResponse {
+    fn validators(&self) -> (Option<&HeaderValue>, Option<&HeaderValue>) {
+        (self.headers.get(ETAG), self.headers.get(LAST_MODIFIED))
+    }
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    entries: HashMap<String, CachedResponse>,
+    /// Insertion order, oldest first.
+    order: VecDeque<String>,
+}
+
+/// Bounded store of revalidatable responses keyed by URL.
+#[derive(Debug)]
+pub struct ResponseCache {
+    config: CacheConfig,
+    state: Mutex<CacheState>,
+}
+
+impl ResponseCache {
+    pub fn new(config: CacheConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(CacheState::default()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.state
+            .lock()
+            .expect("cache lock poisoned")
+            .entries
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        let mut state = self.state.lock().expect("cache lock poisoned");
+        state.entries.clear();
+        state.order.clear();
+    }
+
+    /// Add `If-None-Match` / `If-Modified-Since` for a cached `url`.
+    pub fn add_validators(&self, method: &Method, url: &Url, headers: &mut HeaderMap) {
+        if method != Method::GET {
+            return;
+        }
+        let state = self.state.lock().expect("cache lock poisoned");
+        let Some(entry) = state.entries.get(url.as_str()) else {
+            return;
+        };
+        let (etag, last_modified) = entry.validators();
+        if let Some(etag) = etag {
+            headers.insert(IF_NONE_MATCH, etag.clone());
+        }
+        if let Some(last_modified) = last_modified {
+            headers.insert(IF_MODIFIED_SINCE, last_modified.clone());
+        }
+    }
+
+    /// Resolve a response that cleared challenge evaluation: a `304` is
+    /// swapped for the stored response, a revalidatable `200` is stored.
+    /// Returns `None` when the response should be passed on unchanged.
+    pub fn resolve(
+        &self,
+        method: &Method,
+        url: &Url,
+        status: u16,
+        headers: &HeaderMap,
+        body: &Bytes,
+    ) -> Option<CachedResponse> {
+        if method != Method::GET {
+            return None;
+        }
+        let mut state = self.state.lock().expect("cache lock poisoned");
+        match status {
+            304 => state.entries.get(url.as_str()).cloned(),
+            200 => {
+                let cacheable = (headers.contains_key(ETAG) || headers.contains_key(LAST_MODIFIED))
+                    && body.len() <= self.config.max_body_bytes
+                    && !no_store(headers);
+                if cacheable {
+                    self.insert(
+                        &mut state,
+                        url.as_str().to_string(),
+                        CachedResponse {
+                            status,
+                            headers: headers.clone(),
+                            body: body.clone(),
+                        },
+                    );
+                } else if state.entries.remove(url.as_str()).is_some() {
+                    state.order.retain(|key| key != url.as_str());
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+
+    fn insert(&self, state: &mut CacheState, key: String, response: CachedResponse) {
+        if state.entries.insert(key.clone(), response).is_none() {
+            state.order.push_back(key);
+        }
+        while state.entries.len() > self.config.max_entries.max(1) {
+            match state.order.pop_front() {
+                Some(oldest) => {
+                    state.entries.remove(&oldest);
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+fn no_store(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(CACHE_CONTROL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|directive| directive.trim().eq_ignore_ascii_case("no-store"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn etagged(tag: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(ETAG, HeaderValue::from_static(tag));
+        headers
+    }
+
+    #[test]
+    fn evicts_oldest_and_skips_no_store() {
+        let cache = ResponseCache::new(CacheConfig {
+            max_entries: 2,
+            ..Default::default()
+        });
+        let body = Bytes::from_static(b"body");
+        for path in ["a", "b", "c"] {
+            let url = Url::parse(&format!("https://example.com/{path}")).unwrap();
+            cache.resolve(&Method::GET, &url, 200, &etagged("\"v1\""), &body);
+        }
+        assert_eq!(cache.len(), 2);
+
+        let mut headers = HeaderMap::new();
+        let first = Url::parse("https://example.com/a").unwrap();
+        cache.add_validators(&Method::GET, &first, &mut headers);
+        assert!(headers.is_empty());
+        let last = Url::parse("https://example.com/c").unwrap();
+        cache.add_validators(&Method::GET, &last, &mut headers);
+        assert_eq!(headers[IF_NONE_MATCH], "\"v1\"");
+
+        let mut private = etagged("\"v2\"");
+        private.insert(CACHE_CONTROL, HeaderValue::from_static("private, no-store"));
+        cache.resolve(&Method::GET, &last, 200, &private, &body);
+        assert_eq!(cache.len(), 1);
+        assert!(
+            cache
+                .resolve(&Method::GET, &last, 304, &HeaderMap::new(), &body)
+                .is_none()
+        );
+    }
+}
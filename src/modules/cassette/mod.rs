@@ -0,0 +1,420 @@
+//! Request/response recording for deterministic replay.
+//!
+//! A cassette is a JSON file holding every exchange in the order it happened.
+//! Recording collects exchanges in memory as requests complete and writes the
+//! file once finished; replay serves the recorded responses back, matching on
+//! method and URL, without touching the network.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use http::{HeaderMap, HeaderName, HeaderValue, Method};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use url::Url;
+
+use crate::challenges::core::{
+    ChallengeHttpClient, ChallengeHttpClientError, ChallengeHttpResponse,
+};
+
+/// Errors raised while loading, saving or replaying a cassette.
+#[derive(Debug, Error)]
+pub enum CassetteError {
+    #[error("cassette io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("malformed cassette: {0}")]
+    Format(#[from] serde_json::Error),
+    #[error("invalid recorded body: {0}")]
+    Body(#[from] base64::DecodeError),
+    #[error("invalid recorded {0}")]
+    Invalid(String),
+    #[error("no recorded interaction for {method} {url}")]
+    NoMatch { method: String, url: String },
+}
+
+/// Body stored as text when it is valid UTF-8, base64 otherwise.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordedBody {
+    Text(String),
+    Base64(String),
+}
+
+impl RecordedBody {
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        match std::str::from_utf8(bytes) {
+            Ok(text) => RecordedBody::Text(text.to_string()),
+            Err(_) => RecordedBody::Base64(BASE64.encode(bytes)),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, CassetteError> {
+        match self {
+            RecordedBody::Text(text) => Ok(text.clone().into_bytes()),
+            RecordedBody::Base64(encoded) => Ok(BASE64.decode(encoded)?),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<RecordedBody>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    pub status: u16,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: RecordedBody,
+}
+
+/// A single request and the response it produced.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interaction {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+/// Decoded form of a [`RecordedResponse`].
+#[derive(Debug, Clone)]
+pub struct ReplayedResponse {
+    pub status: u16,
+    pub url: Url,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+/// Ordered collection of interactions persisted as JSON.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Cassette {
+    pub interactions: Vec<Interaction>,
+}
+
+impl Cassette {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, CassetteError> {
+        let raw = std::fs::read(path)?;
+        Ok(serde_json::from_slice(&raw)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), CassetteError> {
+        let raw = serde_json::to_vec_pretty(self)?;
+        std::fs::write(path, raw)?;
+        Ok(())
+    }
+}
+
+/// Buffers interactions as they complete and writes them to a cassette file
+/// on [`CassetteRecorder::finish`] or drop.
+#[derive(Debug)]
+pub struct CassetteRecorder {
+    path: PathBuf,
+    state: Mutex<RecorderState>,
+}
+
+#[derive(Debug, Default)]
+struct RecorderState {
+    cassette: Cassette,
+    /// Interactions already written to `path`.
+    saved: usize,
+}
+
+impl CassetteRecorder {
+    /// Start a new, empty cassette at `path`.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            state: Mutex::new(RecorderState::default()),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Buffer one exchange; nothing touches the disk until [`Self::finish`].
+    pub fn record(&self, interaction: Interaction) {
+        let mut state = self.state.lock().expect("cassette lock poisoned");
+        state.cassette.interactions.push(interaction);
+    }
+
+    /// Write every buffered exchange to the cassette file.
+    ///
+    /// Blocks on file I/O, and only touches the file when something was
+    /// recorded since the last call.
+    pub fn finish(&self) -> Result<(), CassetteError> {
+        let mut state = self.state.lock().expect("cassette lock poisoned");
+        let recorded = state.cassette.interactions.len();
+        if state.saved == recorded {
+            return Ok(());
+        }
+        state.cassette.save(&self.path)?;
+        state.saved = recorded;
+        Ok(())
+    }
+}
+
+impl Drop for CassetteRecorder {
+    fn drop(&mut self) {
+        if let Err(err) = self.finish() {
+            log::warn!("failed to write cassette {}: {err}", self.path.display());
+        }
+    }
+}
+
+/// Serves recorded responses in order, matching on method and URL.
+#[derive(Debug)]
+pub struct CassettePlayer {
+    remaining: Mutex<Vec<Interaction>>,
+}
+
+impl CassettePlayer {
+    pub fn new(cassette: Cassette) -> Self {
+        Self {
+            remaining: Mutex::new(cassette.interactions),
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, CassetteError> {
+        Ok(Self::new(Cassette::load(path)?))
+    }
+
+    /// Take the next unused response recorded for `method` and `url`.
+    pub fn replay(&self, method: &Method, url: &Url) -> Result<ReplayedResponse, CassetteError> {
+        let mut remaining = self.remaining.lock().expect("cassette lock poisoned");
+        let index = remaining
+            .iter()
+            .position(|interaction| {
+                interaction.request.method == method.as_str()
+                    && interaction.request.url == url.as_str()
+            })
+            .ok_or_else(|| CassetteError::NoMatch {
+                method: method.to_string(),
+                url: url.to_string(),
+            })?;
+        let response = remaining.remove(index).response;
+
+        Ok(ReplayedResponse {
+            status: response.status,
+            url: Url::parse(&response.url)
+                .map_err(|err| CassetteError::Invalid(format!("url: {err}")))?,
+            headers: pairs_to_headers(&response.headers)?,
+            body: response.body.to_bytes()?,
+        })
+    }
+
+    /// Number of recorded interactions not yet served.
+    pub fn remaining(&self) -> usize {
+        self.remaining.lock().expect("cassette lock poisoned").len()
+    }
+}
+
+/// Build an [`Interaction`] from raw request and response parts.
+pub fn interaction(
+    method: &Method,
+    url: &Url,
+    request_headers: &HeaderMap,
+    request_body: Option<&[u8]>,
+    response: &ReplayedResponse,
+) -> Interaction {
+    Interaction {
+        request: RecordedRequest {
+            method: method.to_string(),
+            url: url.to_string(),
+            headers: headers_to_pairs(request_headers),
+            body: request_body.map(RecordedBody::from_bytes),
+        },
+        response: RecordedResponse {
+            status: response.status,
+            url: response.url.to_string(),
+            headers: headers_to_pairs(&response.headers),
+            body: RecordedBody::from_bytes(&response.body),
+        },
+    }
+}
+
+fn headers_to_pairs(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            (
+                name.as_str().to_string(),
+                String::from_utf8_lossy(value.as_bytes()).into_owned(),
+            )
+        })
+        .collect()
+}
+
+fn pairs_to_headers(pairs: &[(String, String)]) -> Result<HeaderMap, CassetteError> {
+    let mut headers = HeaderMap::new();
+    for (name, value) in pairs {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|_| CassetteError::Invalid(format!("header name '{name}'")))?;
+        let value = HeaderValue::from_str(value)
+            .map_err(|_| CassetteError::Invalid(format!("header value for '{name}'")))?;
+        headers.append(name, value);
+    }
+    Ok(headers)
+}
+
+fn encode_form(fields: &HashMap<String, String>) -> Vec<u8> {
+    url::form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(fields)
+        .finish()
+        .into_bytes()
+}
+
+fn to_challenge_response(response: ReplayedResponse) -> ChallengeHttpResponse {
+    let is_redirect = (300..400).contains(&response.status);
+    ChallengeHttpResponse {
+        status: response.status,
+        headers: response.headers,
+        body: response.body,
+        url: response.url,
+        is_redirect,
+    }
+}
+
+#[async_trait]
+impl ChallengeHttpClient for CassettePlayer {
+    async fn send_form(
+        &self,
+        method: &Method,
+        url: &Url,
+        _headers: &HeaderMap,
+        _form_fields: &HashMap<String, String>,
+        _allow_redirects: bool,
+    ) -> Result<ChallengeHttpResponse, ChallengeHttpClientError> {
+        self.replay(method, url)
+            .map(to_challenge_response)
+            .map_err(|err| ChallengeHttpClientError::Transport(err.to_string()))
+    }
+
+    async fn send_with_body(
+        &self,
+        method: &Method,
+        url: &Url,
+        _headers: &HeaderMap,
+        _body: Option<&[u8]>,
+        _allow_redirects: bool,
+    ) -> Result<ChallengeHttpResponse, ChallengeHttpClientError> {
+        self.replay(method, url)
+            .map(to_challenge_response)
+            .map_err(|err| ChallengeHttpClientError::Transport(err.to_string()))
+    }
+}
+
+/// Challenge client wrapper that records every exchange it forwards.
+pub struct RecordingChallengeClient {
+    inner: Arc<dyn ChallengeHttpClient>,
+    recorder: Arc<CassetteRecorder>,
+}
+
+impl RecordingChallengeClient {
+    pub fn new(inner: Arc<dyn ChallengeHttpClient>, recorder: Arc<CassetteRecorder>) -> Self {
+        Self { inner, recorder }
+    }
+
+    fn record(
+        &self,
+        method: &Method,
+        url: &Url,
+        headers: &HeaderMap,
+        body: Option<&[u8]>,
+        response: &ChallengeHttpResponse,
+    ) {
+        let replayed = ReplayedResponse {
+            status: response.status,
+            url: response.url.clone(),
+            headers: response.headers.clone(),
+            body: response.body.clone(),
+        };
+        self.recorder
+            .record(interaction(method, url, headers, body, &replayed));
+    }
+}
+
+#[async_trait]
+impl ChallengeHttpClient for RecordingChallengeClient {
+    async fn send_form(
+        &self,
+        method: &Method,
+        url: &Url,
+        headers: &HeaderMap,
+        form_fields: &HashMap<String, String>,
+        allow_redirects: bool,
+    ) -> Result<ChallengeHttpResponse, ChallengeHttpClientError> {
+        let response = self
+            .inner
+            .send_form(method, url, headers, form_fields, allow_redirects)
+            .await?;
+        self.record(
+            method,
+            url,
+            headers,
+            Some(&encode_form(form_fields)),
+            &response,
+        );
+        Ok(response)
+    }
+
+    async fn send_with_body(
+        &self,
+        method: &Method,
+        url: &Url,
+        headers: &HeaderMap,
+        body: Option<&[u8]>,
+        allow_redirects: bool,
+    ) -> Result<ChallengeHttpResponse, ChallengeHttpClientError> {
+        let response = self
+            .inner
+            .send_with_body(method, url, headers, body, allow_redirects)
+            .await?;
+        self.record(method, url, headers, body, &response);
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bodies_round_trip_through_json() {
+        let binary = vec![0xff, 0x00, 0x9c];
+        let cassette = Cassette {
+            interactions: vec![Interaction {
+                request: RecordedRequest {
+                    method: "GET".into(),
+                    url: "https://example.com/".into(),
+                    headers: vec![("accept".into(), "*/*".into())],
+                    body: None,
+                },
+                response: RecordedResponse {
+                    status: 200,
+                    url: "https://example.com/".into(),
+                    headers: vec![("set-cookie".into(), "a=1".into())],
+                    body: RecordedBody::from_bytes(&binary),
+                },
+            }],
+        };
+
+        let json = serde_json::to_string(&cassette).unwrap();
+        let player = CassettePlayer::new(serde_json::from_str(&json).unwrap());
+        let url = Url::parse("https://example.com/").unwrap();
+        let replayed = player.replay(&Method::GET, &url).unwrap();
+        assert_eq!(replayed.body, binary);
+        assert_eq!(replayed.headers["set-cookie"], "a=1");
+        assert!(matches!(
+            player.replay(&Method::GET, &url),
+            Err(CassetteError::NoMatch { .. })
+        ));
+    }
+}
@@ -0,0 +1,245 @@
+//! Per-domain adaptive concurrency.
+//!
+//! Each domain starts with a small in-flight allowance. Every successful
+//! response grows it by `increase / allowance`, about `increase` per full
+//! window of requests, and a rate limit halves it (additive increase,
+//! multiplicative decrease). Requests beyond the allowance wait for a slot,
+//! so the scraper settles on the parallelism a site tolerates. With a
+//! [`StateManager`] attached, allowances are saved with the domain state and
+//! picked up again after a restart.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::Notify;
+
+use crate::modules::state::StateManager;
+
+#[derive(Debug, Clone)]
+pub struct AimdConfig {
+    /// Allowance of a domain before any feedback.
+    pub initial: usize,
+    /// Allowance never grows past this.
+    pub max: usize,
+    /// Growth per window of successful requests.
+    pub increase: f64,
+}
+
+impl Default for AimdConfig {
+    fn default() -> Self {
+        Self {
+            initial: 1,
+            max: 16,
+            increase: 1.0,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct DomainLimit {
+    allowance: f64,
+    in_flight: usize,
+}
+
+impl DomainLimit {
+    fn slots(&self) -> usize {
+        slots(self.allowance)
+    }
+}
+
+fn slots(allowance: f64) -> usize {
+    (allowance.floor() as usize).max(1)
+}
+
+#[derive(Debug)]
+struct Shared {
+    config: AimdConfig,
+    domains: Mutex<HashMap<String, DomainLimit>>,
+    released: Notify,
+}
+
+/// AIMD in-flight limits keyed by domain. Clones share the same limits.
+#[derive(Debug, Clone)]
+pub struct AdaptiveConcurrency {
+    shared: Arc<Shared>,
+    state: Option<StateManager>,
+}
+
+impl AdaptiveConcurrency {
+    pub fn new(config: AimdConfig) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                config,
+                domains: Mutex::new(HashMap::new()),
+                released: Notify::new(),
+            }),
+            state: None,
+        }
+    }
+
+    /// Save allowances to `state` and start domains from the allowance saved
+    /// there.
+    pub fn with_state(mut self, state: StateManager) -> Self {
+        self.state = Some(state);
+        self
+    }
+
+    /// Wait for a free slot on `domain`; the slot is held until the permit
+    /// is dropped.
+    pub async fn acquire(&self, domain: &str) -> ConcurrencyPermit {
+        loop {
+            let released = self.shared.released.notified();
+            {
+                let mut domains = self.lock();
+                let limit = self.entry(&mut domains, domain);
+                if limit.in_flight < limit.slots() {
+                    limit.in_flight += 1;
+                    return ConcurrencyPermit {
+                        shared: Arc::clone(&self.shared),
+                        domain: domain.to_string(),
+                    };
+                }
+            }
+            released.await;
+        }
+    }
+
+    /// Grow `domain`'s allowance after a successful response.
+    pub fn record_success(&self, domain: &str) {
+        let config = &self.shared.config;
+        let mut domains = self.lock();
+        let limit = self.entry(&mut domains, domain);
+        let before = limit.slots();
+        limit.allowance =
+            (limit.allowance + config.increase / limit.allowance).min(config.max.max(1) as f64);
+        if limit.slots() > before {
+            self.shared.released.notify_waiters();
+        }
+        let allowance = limit.allowance;
+        drop(domains);
+        self.persist(domain, allowance);
+    }
+
+    /// Halve `domain`'s allowance after a rate limit.
+    pub fn record_rate_limited(&self, domain: &str) {
+        let mut domains = self.lock();
+        let limit = self.entry(&mut domains, domain);
+        limit.allowance = (limit.allowance / 2.0).max(1.0);
+        log::debug!(
+            "{domain} rate limited; concurrency lowered to {}",
+            limit.slots()
+        );
+        let allowance = limit.allowance;
+        drop(domains);
+        self.persist(domain, allowance);
+    }
+
+    /// Requests `domain` may currently have in flight.
+    pub fn current(&self, domain: &str) -> usize {
+        let allowance = self.lock().get(domain).map(|limit| limit.allowance);
+        slots(allowance.unwrap_or_else(|| self.initial(domain)))
+    }
+
+    /// Allowance of a domain not tracked yet: the saved one, else the
+    /// configured initial allowance.
+    fn initial(&self, domain: &str) -> f64 {
+        let config = &self.shared.config;
+        let saved = self
+            .state
+            .as_ref()
+            .and_then(|state| state.get(domain))
+            .and_then(|state| state.concurrency_allowance);
+        let max = config.max.max(1) as f64;
+        match saved {
+            Some(allowance) => allowance.clamp(1.0, max),
+            None => config.initial.clamp(1, config.max.max(1)) as f64,
+        }
+    }
+
+    fn persist(&self, domain: &str, allowance: f64) {
+        if let Some(state) = &self.state {
+            state.update(domain, |state| {
+                state.concurrency_allowance = Some(allowance)
+            });
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, DomainLimit>> {
+        self.shared
+            .domains
+            .lock()
+            .expect("concurrency lock poisoned")
+    }
+
+    fn entry<'a>(
+        &self,
+        domains: &'a mut HashMap<String, DomainLimit>,
+        domain: &str,
+    ) -> &'a mut DomainLimit {
+        domains
+            .entry(domain.to_string())
+            .or_insert_with(|| DomainLimit {
+                allowance: self.initial(domain),
+                in_flight: 0,
+            })
+    }
+}
+
+/// A held slot from [`AdaptiveConcurrency::acquire`].
+#[derive(Debug)]
+pub struct ConcurrencyPermit {
+    shared: Arc<Shared>,
+    domain: String,
+}
+
+impl Drop for ConcurrencyPermit {
+    fn drop(&mut self) {
+        if let Ok(mut domains) = self.shared.domains.lock()
+            && let Some(limit) = domains.get_mut(&self.domain)
+        {
+            limit.in_flight = limit.in_flight.saturating_sub(1);
+        }
+        self.shared.released.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn grows_on_success_halves_on_rate_limit_and_queues_excess() {
+        let limits = AdaptiveConcurrency::new(AimdConfig::default());
+        assert_eq!(limits.current("example.com"), 1);
+        for _ in 0..10 {
+            limits.record_success("example.com");
+        }
+        assert_eq!(limits.current("example.com"), 4);
+        limits.record_rate_limited("example.com");
+        assert_eq!(limits.current("example.com"), 2);
+        assert_eq!(limits.current("other.com"), 1);
+
+        let held = limits.acquire("other.com").await;
+        let waiting = tokio::time::timeout(Duration::from_millis(50), limits.acquire("other.com"));
+        assert!(waiting.await.is_err());
+        drop(held);
+        let next = tokio::time::timeout(Duration::from_millis(50), limits.acquire("other.com"));
+        assert!(next.await.is_ok());
+    }
+
+    #[test]
+    fn allowances_survive_through_the_state_and_reads_track_nothing() {
+        let state = StateManager::new();
+        let limits = AdaptiveConcurrency::new(AimdConfig::default()).with_state(state.clone());
+        for _ in 0..10 {
+            limits.record_success("example.com");
+        }
+        assert_eq!(limits.current("other.com"), 1);
+        assert!(!limits.lock().contains_key("other.com"));
+        assert!(state.get("other.com").is_none());
+
+        let restarted = AdaptiveConcurrency::new(AimdConfig::default()).with_state(state);
+        assert_eq!(restarted.current("example.com"), 4);
+    }
+}
@@ -8,12 +8,33 @@ use http::{HeaderMap, Method};
 use std::sync::Arc;
 use std::time::Duration;
 use url::Url;
+use uuid::Uuid;
 
 use super::metrics::MetricsCollector;
 
+/// Response headers Cloudflare sets to explain how it handled a request.
+pub const CLOUDFLARE_DIAGNOSTIC_HEADERS: &[&str] =
+    &["cf-mitigated", "cf-cache-status", "server-timing"];
+
+/// The [`CLOUDFLARE_DIAGNOSTIC_HEADERS`] present in `headers`, in that order.
+pub fn cloudflare_diagnostics(headers: &HeaderMap) -> Vec<(String, String)> {
+    CLOUDFLARE_DIAGNOSTIC_HEADERS
+        .iter()
+        .filter_map(|name| {
+            let values: Vec<&str> = headers
+                .get_all(*name)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .collect();
+            (!values.is_empty()).then(|| (name.to_string(), values.join(", ")))
+        })
+        .collect()
+}
+
 /// Structured pre-request event.
 #[derive(Debug, Clone)]
 pub struct PreRequestEvent {
+    pub request_id: Uuid,
     pub url: Url,
     pub method: Method,
     pub headers: HeaderMap,
@@ -23,24 +44,38 @@ pub struct PreRequestEvent {
 /// Structured post-response event.
 #[derive(Debug, Clone)]
 pub struct PostResponseEvent {
+    pub request_id: Uuid,
     pub url: Url,
     pub method: Method,
     pub status: u16,
+    /// Response headers.
+    pub headers: HeaderMap,
+    /// Time on the wire, excluding `delay`.
     pub latency: Duration,
+    /// Deliberate wait applied before the request was sent.
+    pub delay: Duration,
+    /// See [`cloudflare_diagnostics`].
+    pub diagnostics: Vec<(String, String)>,
     pub timestamp: DateTime<Utc>,
 }
 
 #[derive(Debug, Clone)]
 pub struct ChallengeEvent {
+    pub request_id: Uuid,
     pub domain: String,
     pub challenge_type: String,
     pub success: bool,
+    /// The domain challenged again while its clearance was still valid,
+    /// which is how Cloudflare's Under Attack Mode shows up.
+    pub under_attack: bool,
+    /// Includes the challenge response's [`cloudflare_diagnostics`].
     pub metadata: Vec<(String, String)>,
     pub timestamp: DateTime<Utc>,
 }
 
 #[derive(Debug, Clone)]
 pub struct ErrorEvent {
+    pub request_id: Uuid,
     pub domain: String,
     pub error: String,
     pub timestamp: DateTime<Utc>,
@@ -48,6 +83,7 @@ pub struct ErrorEvent {
 
 #[derive(Debug, Clone)]
 pub struct RetryEvent {
+    pub request_id: Uuid,
     pub domain: String,
     pub attempt: u32,
     pub reason: String,
@@ -64,6 +100,19 @@ pub enum ScraperEvent {
     Retry(RetryEvent),
 }
 
+impl ScraperEvent {
+    /// Correlation id of the logical request that produced this event.
+    pub fn request_id(&self) -> Uuid {
+        match self {
+            ScraperEvent::PreRequest(event) => event.request_id,
+            ScraperEvent::PostResponse(event) => event.request_id,
+            ScraperEvent::Challenge(event) => event.request_id,
+            ScraperEvent::Error(event) => event.request_id,
+            ScraperEvent::Retry(event) => event.request_id,
+        }
+    }
+}
+
 /// Trait implemented by event handlers.
 pub trait EventHandler: Send + Sync {
     fn handle(&self, event: &ScraperEvent);
@@ -101,11 +150,12 @@ impl EventHandler for LoggingHandler {
     fn handle(&self, event: &ScraperEvent) {
         match event {
             ScraperEvent::PreRequest(pre) => {
-                log::debug!("-> {} {}", pre.method, pre.url);
+                log::debug!("[{}] -> {} {}", pre.request_id, pre.method, pre.url);
             }
             ScraperEvent::PostResponse(post) => {
                 log::debug!(
-                    "<- {} {} -> {} ({:.2}s)",
+                    "[{}] <- {} {} -> {} ({:.2}s)",
+                    post.request_id,
                     post.method,
                     post.url,
                     post.status,
@@ -114,18 +164,25 @@ impl EventHandler for LoggingHandler {
             }
             ScraperEvent::Challenge(challenge) => {
                 log::info!(
-                    "challenge {} ({}) success={}",
+                    "[{}] challenge {} ({}) success={}",
+                    challenge.request_id,
                     challenge.domain,
                     challenge.challenge_type,
                     challenge.success
                 );
             }
             ScraperEvent::Error(error) => {
-                log::warn!("warning {} -> {}", error.domain, error.error);
+                log::warn!(
+                    "[{}] warning {} -> {}",
+                    error.request_id,
+                    error.domain,
+                    error.error
+                );
             }
             ScraperEvent::Retry(retry) => {
                 log::info!(
-                    "retry {} attempt {} after {:.2}s",
+                    "[{}] retry {} attempt {} after {:.2}s",
+                    retry.request_id,
                     retry.domain,
                     retry.attempt,
                     retry.scheduled_after.as_secs_f64()
@@ -155,6 +212,7 @@ impl EventHandler for MetricsHandler {
                     post.url.host_str().unwrap_or(""),
                     post.status,
                     post.latency,
+                    post.delay,
                 );
             }
             ScraperEvent::Error(error) => {
@@ -183,6 +241,7 @@ mod tests {
         let counter = Arc::new(CountingHandler(std::sync::Mutex::new(0)));
         dispatcher.register_handler(counter.clone());
         dispatcher.dispatch(ScraperEvent::Error(ErrorEvent {
+            request_id: Uuid::new_v4(),
             domain: "example.com".into(),
             error: "timeout".into(),
             timestamp: Utc::now(),
@@ -0,0 +1,251 @@
+//! HTTP Archive (HAR 1.2) capture built from scraper events.
+//!
+//! [`HarLog`] is an event handler: it pairs each `PreRequest` with the
+//! `PostResponse` that follows and annotates the entry with any challenge
+//! events for the same request. Bodies are not captured. Challenge details go
+//! in the custom `_challenges` field, which HAR viewers ignore.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use http::HeaderMap;
+use serde_json::{Value, json};
+use uuid::Uuid;
+
+use super::events::{
+    ChallengeEvent, EventHandler, PostResponseEvent, PreRequestEvent, ScraperEvent,
+};
+
+#[derive(Debug)]
+struct HarEntry {
+    request_id: Uuid,
+    started: DateTime<Utc>,
+    request: Option<PreRequestEvent>,
+    response: PostResponseEvent,
+    challenges: Vec<ChallengeEvent>,
+}
+
+#[derive(Debug, Default)]
+struct HarState {
+    pending: HashMap<Uuid, PreRequestEvent>,
+    entries: Vec<HarEntry>,
+}
+
+/// Event handler accumulating a HAR log of every exchange.
+#[derive(Debug, Default)]
+pub struct HarLog {
+    state: Mutex<HarState>,
+}
+
+impl HarLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of completed exchanges captured so far.
+    pub fn len(&self) -> usize {
+        self.state.lock().expect("har lock poisoned").entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The log as a HAR 1.2 document.
+    pub fn to_json(&self) -> Value {
+        let state = self.state.lock().expect("har lock poisoned");
+        let entries: Vec<Value> = state.entries.iter().map(entry_json).collect();
+        json!({
+            "log": {
+                "version": "1.2",
+                "creator": { "name": "cloudscraper-rs", "version": crate::VERSION },
+                "entries": entries,
+            }
+        })
+    }
+
+    /// The log serialized as pretty-printed HAR JSON.
+    pub fn export(&self) -> String {
+        serde_json::to_string_pretty(&self.to_json()).unwrap_or_default()
+    }
+}
+
+impl EventHandler for HarLog {
+    fn handle(&self, event: &ScraperEvent) {
+        let mut state = self.state.lock().expect("har lock poisoned");
+        match event {
+            ScraperEvent::PreRequest(pre) => {
+                state.pending.insert(pre.request_id, pre.clone());
+            }
+            ScraperEvent::PostResponse(post) => {
+                let request = state.pending.remove(&post.request_id);
+                let started = request.as_ref().map_or_else(
+                    || {
+                        post.timestamp
+                            - chrono::Duration::from_std(post.latency + post.delay)
+                                .unwrap_or_default()
+                    },
+                    |pre| pre.timestamp,
+                );
+                state.entries.push(HarEntry {
+                    request_id: post.request_id,
+                    started,
+                    request,
+                    response: post.clone(),
+                    challenges: Vec::new(),
+                });
+            }
+            ScraperEvent::Challenge(challenge) => {
+                if let Some(entry) = state
+                    .entries
+                    .iter_mut()
+                    .rev()
+                    .find(|entry| entry.request_id == challenge.request_id)
+                {
+                    entry.challenges.push(challenge.clone());
+                }
+            }
+            ScraperEvent::Error(_) | ScraperEvent::Retry(_) => {}
+        }
+    }
+}
+
+fn entry_json(entry: &HarEntry) -> Value {
+    let response = &entry.response;
+    let (method, url, request_headers) = match &entry.request {
+        Some(pre) => (&pre.method, &pre.url, headers_json(&pre.headers)),
+        None => (&response.method, &response.url, Vec::new()),
+    };
+    let query: Vec<Value> = url
+        .query_pairs()
+        .map(|(name, value)| json!({ "name": name, "value": value }))
+        .collect();
+    let mime_type = response
+        .headers
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let redirect_url = response
+        .headers
+        .get(http::header::LOCATION)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let blocked = millis(response.delay);
+    let wait = millis(response.latency);
+    let challenges: Vec<Value> = entry
+        .challenges
+        .iter()
+        .map(|challenge| {
+            let metadata: serde_json::Map<String, Value> = challenge
+                .metadata
+                .iter()
+                .map(|(key, value)| (key.clone(), Value::from(value.as_str())))
+                .collect();
+            json!({
+                "type": challenge.challenge_type,
+                "success": challenge.success,
+                "underAttack": challenge.under_attack,
+                "metadata": metadata,
+            })
+        })
+        .collect();
+
+    json!({
+        "startedDateTime": entry.started.to_rfc3339(),
+        "time": blocked + wait,
+        "request": {
+            "method": method.as_str(),
+            "url": url.as_str(),
+            "httpVersion": "HTTP/1.1",
+            "cookies": [],
+            "headers": request_headers,
+            "queryString": query,
+            "headersSize": -1,
+            "bodySize": -1,
+        },
+        "response": {
+            "status": response.status,
+            "statusText": http::StatusCode::from_u16(response.status)
+                .ok()
+                .and_then(|status| status.canonical_reason())
+                .unwrap_or_default(),
+            "httpVersion": "HTTP/1.1",
+            "cookies": [],
+            "headers": headers_json(&response.headers),
+            "content": { "size": 0, "mimeType": mime_type },
+            "redirectURL": redirect_url,
+            "headersSize": -1,
+            "bodySize": -1,
+        },
+        "cache": {},
+        "timings": { "blocked": blocked, "send": 0, "wait": wait, "receive": 0 },
+        "_requestId": entry.request_id.to_string(),
+        "_challenges": challenges,
+    })
+}
+
+fn headers_json(headers: &HeaderMap) -> Vec<Value> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            json!({
+                "name": name.as_str(),
+                "value": String::from_utf8_lossy(value.as_bytes()),
+            })
+        })
+        .collect()
+}
+
+fn millis(duration: std::time::Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::Method;
+    use std::time::Duration;
+    use url::Url;
+
+    #[test]
+    fn annotates_entries_with_challenges() {
+        let har = HarLog::new();
+        let request_id = Uuid::new_v4();
+        let url = Url::parse("https://example.com/page?q=1").unwrap();
+        har.handle(&ScraperEvent::PreRequest(PreRequestEvent {
+            request_id,
+            url: url.clone(),
+            method: Method::GET,
+            headers: HeaderMap::new(),
+            timestamp: Utc::now(),
+        }));
+        har.handle(&ScraperEvent::PostResponse(PostResponseEvent {
+            request_id,
+            url,
+            method: Method::GET,
+            status: 403,
+            headers: HeaderMap::new(),
+            latency: Duration::from_millis(40),
+            delay: Duration::from_millis(10),
+            diagnostics: Vec::new(),
+            timestamp: Utc::now(),
+        }));
+        har.handle(&ScraperEvent::Challenge(ChallengeEvent {
+            request_id,
+            domain: "example.com".into(),
+            challenge_type: "Turnstile".into(),
+            success: false,
+            under_attack: false,
+            metadata: vec![("reason".into(), "no provider".into())],
+            timestamp: Utc::now(),
+        }));
+
+        let log = har.to_json();
+        let entry = &log["log"]["entries"][0];
+        assert_eq!(entry["request"]["queryString"][0]["value"], "1");
+        assert_eq!(entry["response"]["statusText"], "Forbidden");
+        assert_eq!(entry["time"], 50.0);
+        assert_eq!(entry["_challenges"][0]["metadata"]["reason"], "no provider");
+    }
+}
@@ -1,22 +1,36 @@
 //! Metrics collection utilities.
 //!
 //! Provides aggregated global and per-domain statistics with latency
-//! percentiles for observability.
+//! percentiles for observability. Wire latency (time on the network) and
+//! injected delay (deliberate waits before sending) are tracked separately.
 
 use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
+#[cfg(feature = "metrics-server")]
+mod server;
+
 /// Aggregated metrics across all domains.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct GlobalStats {
     pub started_at: DateTime<Utc>,
     pub total_requests: u64,
     pub successes: u64,
     pub failures: u64,
+    /// Wire latency, excluding injected delays.
+    #[serde(with = "crate::serde_millis::option_duration")]
     pub average_latency: Option<Duration>,
+    #[serde(with = "crate::serde_millis::option_duration")]
     pub p95_latency: Option<Duration>,
+    /// Deliberate waits (adaptive timing, challenge delays) before sending.
+    #[serde(with = "crate::serde_millis::option_duration")]
+    pub average_injected_delay: Option<Duration>,
+    #[serde(with = "crate::serde_millis::option_duration")]
+    pub p95_injected_delay: Option<Duration>,
 }
 
 impl Default for GlobalStats {
@@ -28,26 +42,40 @@ impl Default for GlobalStats {
             failures: 0,
             average_latency: None,
             p95_latency: None,
+            average_injected_delay: None,
+            p95_injected_delay: None,
         }
     }
 }
 
 /// Domain-scoped metrics snapshot.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct DomainStats {
     pub domain: String,
     pub total_requests: u64,
     pub successes: u64,
     pub failures: u64,
+    /// Wire latency, excluding injected delays.
+    #[serde(with = "crate::serde_millis::option_duration")]
     pub average_latency: Option<Duration>,
+    #[serde(with = "crate::serde_millis::option_duration")]
     pub p95_latency: Option<Duration>,
+    /// Deliberate waits (adaptive timing, challenge delays) before sending.
+    #[serde(with = "crate::serde_millis::option_duration")]
+    pub average_injected_delay: Option<Duration>,
+    #[serde(with = "crate::serde_millis::option_duration")]
+    pub p95_injected_delay: Option<Duration>,
     pub consecutive_failures: u32,
     pub last_status: Option<u16>,
+    /// In-flight allowance under adaptive concurrency, when enabled.
+    #[serde(default)]
+    pub concurrency_limit: Option<usize>,
 }
 
 impl DomainStats {
     fn from_accumulator(domain: &str, acc: &DomainAccumulator) -> Self {
-        let (avg, p95) = acc.latency_stats();
+        let (avg, p95) = window_stats(&acc.latencies);
+        let (avg_delay, p95_delay) = window_stats(&acc.delays);
         Self {
             domain: domain.to_string(),
             total_requests: acc.total_requests,
@@ -55,27 +83,127 @@ impl DomainStats {
             failures: acc.failures,
             average_latency: avg,
             p95_latency: p95,
+            average_injected_delay: avg_delay,
+            p95_injected_delay: p95_delay,
             consecutive_failures: acc.consecutive_failures,
             last_status: acc.last_status,
+            concurrency_limit: acc.concurrency_limit,
         }
     }
 }
 
-#[derive(Debug, Clone)]
+/// Durations serialize as integer milliseconds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct MetricsSnapshot {
     pub global: GlobalStats,
     pub domains: Vec<DomainStats>,
 }
 
+impl MetricsSnapshot {
+    /// Serialize for shipping to an external collector.
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(self)
+    }
+
+    /// Render in the Prometheus text exposition format. Durations are in
+    /// seconds; per-domain series carry a `domain` label.
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        let global = &self.global;
+        let counters = [
+            ("requests_total", "Requests sent.", global.total_requests),
+            ("successes_total", "Responses below 500.", global.successes),
+            (
+                "failures_total",
+                "Errors and 5xx responses.",
+                global.failures,
+            ),
+        ];
+        for (name, help, value) in counters {
+            let _ = writeln!(out, "# HELP cloudscraper_{name} {help}");
+            let _ = writeln!(out, "# TYPE cloudscraper_{name} counter");
+            let _ = writeln!(out, "cloudscraper_{name} {value}");
+        }
+        let gauges = [
+            (
+                "latency_average_seconds",
+                "Average wire latency.",
+                global.average_latency,
+            ),
+            (
+                "latency_p95_seconds",
+                "95th percentile wire latency.",
+                global.p95_latency,
+            ),
+            (
+                "injected_delay_average_seconds",
+                "Average injected delay.",
+                global.average_injected_delay,
+            ),
+            (
+                "injected_delay_p95_seconds",
+                "95th percentile injected delay.",
+                global.p95_injected_delay,
+            ),
+        ];
+        for (name, help, value) in gauges {
+            if let Some(value) = value {
+                let _ = writeln!(out, "# HELP cloudscraper_{name} {help}");
+                let _ = writeln!(out, "# TYPE cloudscraper_{name} gauge");
+                let _ = writeln!(out, "cloudscraper_{name} {}", value.as_secs_f64());
+            }
+        }
+
+        let domain_series: [(&str, &str, DomainValue); 5] = [
+            ("domain_requests_total", "counter", |d| {
+                Some(d.total_requests as f64)
+            }),
+            ("domain_failures_total", "counter", |d| {
+                Some(d.failures as f64)
+            }),
+            ("domain_consecutive_failures", "gauge", |d| {
+                Some(d.consecutive_failures as f64)
+            }),
+            ("domain_latency_average_seconds", "gauge", |d| {
+                d.average_latency.map(|latency| latency.as_secs_f64())
+            }),
+            ("domain_concurrency_limit", "gauge", |d| {
+                d.concurrency_limit.map(|limit| limit as f64)
+            }),
+        ];
+        for (name, kind, value) in domain_series {
+            let _ = writeln!(out, "# TYPE cloudscraper_{name} {kind}");
+            for domain in &self.domains {
+                if let Some(value) = value(domain) {
+                    let label = escape_label(&domain.domain);
+                    let _ = writeln!(out, "cloudscraper_{name}{{domain=\"{label}\"}} {value}");
+                }
+            }
+        }
+        out
+    }
+}
+
+type DomainValue = fn(&DomainStats) -> Option<f64>;
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
 #[derive(Debug)]
 struct DomainAccumulator {
     total_requests: u64,
     successes: u64,
     failures: u64,
     latencies: VecDeque<Duration>,
+    delays: VecDeque<Duration>,
     max_window: usize,
     consecutive_failures: u32,
     last_status: Option<u16>,
+    concurrency_limit: Option<usize>,
 }
 
 impl DomainAccumulator {
@@ -85,13 +213,15 @@ impl DomainAccumulator {
             successes: 0,
             failures: 0,
             latencies: VecDeque::with_capacity(max_window),
+            delays: VecDeque::with_capacity(max_window),
             max_window,
             consecutive_failures: 0,
             last_status: None,
+            concurrency_limit: None,
         }
     }
 
-    fn record(&mut self, status: u16, latency: Duration) {
+    fn record(&mut self, status: u16, latency: Duration, delay: Duration) {
         self.total_requests += 1;
         self.last_status = Some(status);
 
@@ -103,22 +233,44 @@ impl DomainAccumulator {
             self.consecutive_failures = self.consecutive_failures.saturating_add(1);
         }
 
-        if self.latencies.len() == self.max_window {
-            self.latencies.pop_front();
-        }
-        self.latencies.push_back(latency);
+        push_window(&mut self.latencies, latency, self.max_window);
+        push_window(&mut self.delays, delay, self.max_window);
+    }
+}
+
+fn push_window(window: &mut VecDeque<Duration>, sample: Duration, max_window: usize) {
+    if window.len() == max_window {
+        window.pop_front();
+    }
+    window.push_back(sample);
+}
+
+/// Average and p95 of a sample window.
+fn window_stats(window: &VecDeque<Duration>) -> (Option<Duration>, Option<Duration>) {
+    if window.is_empty() {
+        return (None, None);
+    }
+    let mut samples: Vec<_> = window.iter().cloned().collect();
+    samples.sort_unstable();
+    let avg = samples.iter().map(|d| d.as_secs_f64()).sum::<f64>() / samples.len() as f64;
+    (Some(Duration::from_secs_f64(avg)), percentile_95(&samples))
+}
+
+/// 95th percentile of already sorted samples.
+fn percentile_95(sorted: &[Duration]) -> Option<Duration> {
+    if sorted.is_empty() {
+        return None;
     }
+    let idx = ((sorted.len() as f64 * 0.95).ceil() as usize).saturating_sub(1);
+    Some(sorted[idx])
+}
 
-    fn latency_stats(&self) -> (Option<Duration>, Option<Duration>) {
-        if self.latencies.is_empty() {
-            return (None, None);
+fn blend(average: Option<Duration>, sample: Duration) -> Duration {
+    match average {
+        Some(avg) => {
+            Duration::from_secs_f64((avg.as_secs_f64() * 0.9) + (sample.as_secs_f64() * 0.1))
         }
-        let mut samples: Vec<_> = self.latencies.iter().cloned().collect();
-        samples.sort_unstable();
-        let avg = samples.iter().map(|d| d.as_secs_f64()).sum::<f64>() / samples.len() as f64;
-        let p95_index = ((samples.len() as f64 * 0.95).ceil() as usize).saturating_sub(1);
-        let p95 = samples[p95_index];
-        (Some(Duration::from_secs_f64(avg)), Some(p95))
+        None => sample,
     }
 }
 
@@ -164,7 +316,15 @@ impl MetricsCollector {
         }
     }
 
-    pub fn record_response(&self, domain: &str, status: u16, latency: Duration) {
+    /// Record a response, keeping time on the wire (`network_latency`) apart
+    /// from the deliberate wait applied before sending (`applied_delay`).
+    pub fn record_response(
+        &self,
+        domain: &str,
+        status: u16,
+        network_latency: Duration,
+        applied_delay: Duration,
+    ) {
         let mut guard = self.inner.lock().expect("metrics lock poisoned");
         guard.global.total_requests += 1;
         if status < 500 {
@@ -173,28 +333,30 @@ impl MetricsCollector {
             guard.global.failures += 1;
         }
 
-        if let Some(avg) = guard.global.average_latency {
-            let blended = (avg.as_secs_f64() * 0.9) + (latency.as_secs_f64() * 0.1);
-            guard.global.average_latency = Some(Duration::from_secs_f64(blended));
-        } else {
-            guard.global.average_latency = Some(latency);
-        }
+        guard.global.average_latency = Some(blend(guard.global.average_latency, network_latency));
+        guard.global.average_injected_delay =
+            Some(blend(guard.global.average_injected_delay, applied_delay));
 
         let acc = guard.accumulator_mut(domain);
-        acc.record(status, latency);
+        acc.record(status, network_latency, applied_delay);
 
         // Update global p95 from all samples (approximation using domain 95th blending).
-        let mut percentile_samples: Vec<_> = guard
+        let mut latencies: Vec<_> = guard
             .domains
             .values()
             .flat_map(|domain| domain.latencies.iter())
             .cloned()
             .collect();
-        percentile_samples.sort_unstable();
-        if !percentile_samples.is_empty() {
-            let idx = ((percentile_samples.len() as f64 * 0.95).ceil() as usize).saturating_sub(1);
-            guard.global.p95_latency = Some(percentile_samples[idx]);
-        }
+        latencies.sort_unstable();
+        let mut delays: Vec<_> = guard
+            .domains
+            .values()
+            .flat_map(|domain| domain.delays.iter())
+            .cloned()
+            .collect();
+        delays.sort_unstable();
+        guard.global.p95_latency = percentile_95(&latencies);
+        guard.global.p95_injected_delay = percentile_95(&delays);
     }
 
     pub fn record_error(&self, domain: &str) {
@@ -208,6 +370,12 @@ impl MetricsCollector {
         acc.last_status = Some(0);
     }
 
+    /// Record the in-flight allowance adaptive concurrency settled on.
+    pub fn record_concurrency(&self, domain: &str, limit: usize) {
+        let mut guard = self.inner.lock().expect("metrics lock poisoned");
+        guard.accumulator_mut(domain).concurrency_limit = Some(limit);
+    }
+
     pub fn snapshot(&self) -> MetricsSnapshot {
         let guard = self.inner.lock().expect("metrics lock poisoned");
         let domains = guard
@@ -236,8 +404,18 @@ mod tests {
     #[test]
     fn records_success_and_failure() {
         let metrics = MetricsCollector::new();
-        metrics.record_response("example.com", 200, Duration::from_millis(150));
-        metrics.record_response("example.com", 503, Duration::from_millis(800));
+        metrics.record_response(
+            "example.com",
+            200,
+            Duration::from_millis(150),
+            Duration::ZERO,
+        );
+        metrics.record_response(
+            "example.com",
+            503,
+            Duration::from_millis(800),
+            Duration::ZERO,
+        );
         metrics.record_error("example.com");
 
         let snapshot = metrics.snapshot();
@@ -250,4 +428,61 @@ mod tests {
         assert_eq!(domain.successes, 1);
         assert_eq!(domain.failures, 2);
     }
+
+    #[test]
+    fn tracks_wire_latency_and_injected_delay_independently() {
+        let metrics = MetricsCollector::new();
+        metrics.record_response(
+            "example.com",
+            200,
+            Duration::from_millis(100),
+            Duration::from_secs(4),
+        );
+        metrics.record_response(
+            "example.com",
+            200,
+            Duration::from_millis(100),
+            Duration::from_secs(4),
+        );
+
+        let snapshot = metrics.snapshot();
+        let domain = &snapshot.domains[0];
+        assert_eq!(domain.average_latency, Some(Duration::from_millis(100)));
+        assert_eq!(domain.p95_injected_delay, Some(Duration::from_secs(4)));
+        assert_eq!(
+            snapshot.global.p95_latency,
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(
+            snapshot.global.average_injected_delay,
+            Some(Duration::from_secs(4))
+        );
+    }
+
+    #[test]
+    fn snapshot_round_trips_through_json() {
+        let metrics = MetricsCollector::new();
+        metrics.record_response(
+            "example.com",
+            200,
+            Duration::from_millis(150),
+            Duration::from_millis(40),
+        );
+        metrics.record_error("other.com");
+        let snapshot = metrics.snapshot();
+
+        let json = snapshot.to_json().unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["global"]["average_latency"], 150);
+        assert_eq!(value["global"]["average_injected_delay"], 40);
+
+        let restored: MetricsSnapshot = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, snapshot);
+        let stats: DomainStats =
+            serde_json::from_str(&serde_json::to_string(&snapshot.domains[0]).unwrap()).unwrap();
+        assert_eq!(stats, snapshot.domains[0]);
+        let global: GlobalStats =
+            serde_json::from_str(&serde_json::to_string(&snapshot.global).unwrap()).unwrap();
+        assert_eq!(global, snapshot.global);
+    }
 }
@@ -0,0 +1,130 @@
+//! Tiny HTTP endpoint for a [`MetricsCollector`], behind the
+//! `metrics-server` feature.
+//!
+//! Serves `/metrics` in the Prometheus text format and `/snapshot.json` as
+//! the serialized [`MetricsSnapshot`](super::MetricsSnapshot). Anything else
+//! is a `404`.
+
+use std::convert::Infallible;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use bytes::Bytes;
+use http::header::CONTENT_TYPE;
+use http::{Method, Request, Response, StatusCode};
+use http_body_util::Full;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper_util::rt::TokioIo;
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+
+use super::MetricsCollector;
+
+const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+impl MetricsCollector {
+    /// Bind `addr` and serve the collector until the returned task is aborted.
+    pub async fn serve(&self, addr: SocketAddr) -> io::Result<JoinHandle<()>> {
+        let listener = TcpListener::bind(addr).await?;
+        Ok(self.serve_listener(listener))
+    }
+
+    /// Serve the collector on an already bound listener.
+    pub fn serve_listener(&self, listener: TcpListener) -> JoinHandle<()> {
+        let collector = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let stream = match listener.accept().await {
+                    Ok((stream, _)) => stream,
+                    Err(err) => {
+                        log::warn!("metrics server failed to accept a connection: {err}");
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        continue;
+                    }
+                };
+                let collector = collector.clone();
+                tokio::spawn(async move {
+                    let service = service_fn(move |request| {
+                        let response = respond(&collector, &request);
+                        async move { Ok::<_, Infallible>(response) }
+                    });
+                    if let Err(err) = http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await
+                    {
+                        log::debug!("metrics connection ended with an error: {err}");
+                    }
+                });
+            }
+        })
+    }
+}
+
+fn respond<B>(collector: &MetricsCollector, request: &Request<B>) -> Response<Full<Bytes>> {
+    let (status, content_type, body) = match (request.method(), request.uri().path()) {
+        (&Method::GET, "/metrics") => (
+            StatusCode::OK,
+            PROMETHEUS_CONTENT_TYPE,
+            collector.snapshot().to_prometheus(),
+        ),
+        (&Method::GET, "/snapshot.json") => match collector.snapshot().to_json() {
+            Ok(json) => (StatusCode::OK, "application/json", json),
+            Err(err) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "text/plain",
+                err.to_string(),
+            ),
+        },
+        _ => (StatusCode::NOT_FOUND, "text/plain", "not found".to_string()),
+    };
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, content_type)
+        .body(Full::new(Bytes::from(body)))
+        .expect("static response parts are valid")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn serves_prometheus_text_and_json_snapshot() {
+        let metrics = MetricsCollector::new();
+        metrics.record_response(
+            "example.com",
+            200,
+            Duration::from_millis(250),
+            Duration::ZERO,
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let server = metrics.serve_listener(listener);
+
+        let client = reqwest::Client::new();
+        let response = client.get(format!("{base}/metrics")).send().await.unwrap();
+        assert_eq!(response.status(), 200);
+        let text = response.text().await.unwrap();
+        assert!(text.contains("cloudscraper_requests_total 1\n"));
+        assert!(
+            text.contains(
+                "cloudscraper_domain_latency_average_seconds{domain=\"example.com\"} 0.25"
+            )
+        );
+
+        let response = client
+            .get(format!("{base}/snapshot.json"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        let snapshot: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(snapshot["global"]["total_requests"], 1);
+
+        let missing = client.get(format!("{base}/other")).send().await.unwrap();
+        assert_eq!(missing.status(), 404);
+        server.abort();
+    }
+}
@@ -3,8 +3,17 @@
 //! Learns correlations between recorded features and bypass success rates so
 //! adaptive strategies can make informed recommendations.
 
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Mutex;
+
+use crate::challenges::core::LearningRate;
+use crate::modules::storage::{StorageBackend, StorageError, load_json, store_json};
+
+/// [`StorageBackend`] key of the [`MLOptimizer`] snapshot.
+pub const ML_STORAGE_KEY: &str = "ml";
 
 /// Feature vector represented as numeric values.
 pub type FeatureVector = HashMap<String, f64>;
@@ -39,18 +48,23 @@ pub struct StrategyRecommendation {
     pub notes: Vec<String>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct AttemptRecord {
     features: FeatureVector,
     success: bool,
     delay_used: Option<f64>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 struct DomainModel {
     attempts: VecDeque<AttemptRecord>,
     weights: HashMap<String, f64>,
     success_rate: f64,
+    /// Outcomes folded into `success_rate`.
+    #[serde(default)]
+    updates: u64,
+    /// Taken from the loading optimizer's config.
+    #[serde(skip)]
     window_size: usize,
 }
 
@@ -60,6 +74,7 @@ impl DomainModel {
             attempts: VecDeque::with_capacity(window_size),
             weights: HashMap::new(),
             success_rate: 1.0,
+            updates: 0,
             window_size,
         }
     }
@@ -77,6 +92,9 @@ impl DomainModel {
 pub struct MLOptimizer {
     config: MLConfig,
     domains: HashMap<String, DomainModel>,
+    /// Replaces the fixed `learning_rate` when set.
+    learning_rate_schedule: Option<LearningRate>,
+    rng: Mutex<StdRng>,
 }
 
 impl MLOptimizer {
@@ -84,9 +102,23 @@ impl MLOptimizer {
         Self {
             domains: HashMap::new(),
             config,
+            learning_rate_schedule: None,
+            rng: Mutex::new(StdRng::from_entropy()),
         }
     }
 
+    /// Draw exploration jitter from `rng`.
+    pub fn with_rng(mut self, rng: StdRng) -> Self {
+        self.rng = Mutex::new(rng);
+        self
+    }
+
+    /// Weight outcomes by `schedule` instead of the fixed `learning_rate`.
+    pub fn with_learning_rate_schedule(mut self, schedule: LearningRate) -> Self {
+        self.learning_rate_schedule = Some(schedule);
+        self
+    }
+
     fn model_mut(&mut self, domain: &str) -> &mut DomainModel {
         self.domains
             .entry(domain.to_string())
@@ -101,8 +133,12 @@ impl MLOptimizer {
         success: bool,
         delay_used: Option<f64>,
     ) {
-        let alpha = self.config.learning_rate;
+        let schedule = self
+            .learning_rate_schedule
+            .unwrap_or(LearningRate::Fixed(self.config.learning_rate));
         let model = self.model_mut(domain);
+        let alpha = schedule.rate(model.updates);
+        model.updates += 1;
         model.push(AttemptRecord {
             features,
             success,
@@ -164,7 +200,7 @@ impl MLOptimizer {
             return None;
         }
 
-        let mut rng = rand::thread_rng();
+        let mut rng = self.rng.lock().expect("ml rng poisoned");
         let mut notes = Vec::new();
         let confidence = model.success_rate;
 
@@ -211,6 +247,29 @@ impl MLOptimizer {
     pub fn clear_domain(&mut self, domain: &str) {
         self.domains.remove(domain);
     }
+
+    /// Save every domain's samples and weights under [`ML_STORAGE_KEY`].
+    pub fn save_to(&self, storage: &dyn StorageBackend) -> Result<(), StorageError> {
+        store_json(storage, ML_STORAGE_KEY, &self.domains)
+    }
+
+    /// Restore a snapshot written by [`save_to`](Self::save_to), replacing
+    /// the models of the domains it covers. Samples beyond this optimizer's
+    /// window are dropped, oldest first. Returns whether one was found.
+    pub fn load_from(&mut self, storage: &dyn StorageBackend) -> Result<bool, StorageError> {
+        let Some(models) = load_json::<HashMap<String, DomainModel>>(storage, ML_STORAGE_KEY)?
+        else {
+            return Ok(false);
+        };
+        let window_size = self.config.window_size;
+        for (domain, mut model) in models {
+            model.window_size = window_size;
+            let excess = model.attempts.len().saturating_sub(window_size);
+            model.attempts.drain(..excess);
+            self.domains.insert(domain, model);
+        }
+        Ok(true)
+    }
 }
 
 impl Default for MLOptimizer {
@@ -239,4 +298,17 @@ mod tests {
         let rec = recommendation.unwrap();
         assert!(rec.feature_weights.contains_key("timing"));
     }
+
+    #[test]
+    fn learning_rate_schedule_is_opt_in() {
+        let fail_once = |optimizer: &mut MLOptimizer| {
+            optimizer.record_attempt("example.com", FeatureVector::new(), false, None);
+            optimizer.domains["example.com"].success_rate
+        };
+
+        assert!((fail_once(&mut MLOptimizer::default()) - 0.85).abs() < 1e-9);
+        let mut scheduled =
+            MLOptimizer::default().with_learning_rate_schedule(LearningRate::decaying(0.15));
+        assert!((fail_once(&mut scheduled) - 0.5).abs() < 1e-9);
+    }
 }
@@ -5,13 +5,19 @@
 
 pub mod adaptive_timing;
 pub mod anti_detection;
+pub mod blacklist;
+pub mod cache;
+pub mod cassette;
+pub mod concurrency;
 pub mod events;
+pub mod har;
 pub mod metrics;
 pub mod ml;
 pub mod performance;
 pub mod proxy;
 pub mod spoofing;
 pub mod state;
+pub mod storage;
 pub mod tls;
 
 // Re-export commonly used types
@@ -22,14 +28,27 @@ pub use adaptive_timing::{
 pub use anti_detection::{
     AntiDetectionConfig, AntiDetectionContext, AntiDetectionStrategy, DefaultAntiDetection,
 };
+pub use blacklist::{BlacklistKey, IdentityBlacklist};
+pub use cache::{CacheConfig, CachedResponse, ResponseCache};
+pub use cassette::{
+    Cassette, CassetteError, CassettePlayer, CassetteRecorder, Interaction, RecordedBody,
+};
+pub use concurrency::{AdaptiveConcurrency, AimdConfig, ConcurrencyPermit};
 pub use events::{
     ChallengeEvent, ErrorEvent, EventDispatcher, EventHandler, LoggingHandler, MetricsHandler,
     PostResponseEvent, PreRequestEvent, RetryEvent, ScraperEvent,
 };
+pub use har::HarLog;
 pub use metrics::{DomainStats, GlobalStats, MetricsCollector, MetricsSnapshot};
 pub use ml::{FeatureVector, MLConfig, MLOptimizer, StrategyRecommendation};
 pub use performance::{PerformanceConfig, PerformanceMonitor, PerformanceReport};
 pub use proxy::{ProxyConfig, ProxyHealthReport, ProxyManager, RotationStrategy};
-pub use spoofing::{BrowserFingerprint, BrowserType, ConsistencyLevel, FingerprintGenerator};
-pub use state::{DomainState, StateManager};
-pub use tls::{BrowserProfile, DefaultTLSManager, TLSConfig};
+pub use spoofing::{
+    BrowserFingerprint, BrowserType, ConsistencyLevel, FieldDiff, FingerprintGenerator,
+    OperatingSystem,
+};
+pub use state::{ChallengeStatus, DomainSeed, DomainState, StateManager, StateScope};
+pub use storage::{FileStorage, MemoryStorage, StorageBackend, StorageError};
+pub use tls::{
+    BrowserProfile, DefaultTLSManager, TLSConfig, TlsBackend, TlsProfileError, TlsVersion,
+};
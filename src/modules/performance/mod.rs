@@ -3,6 +3,7 @@
 //! Tracks latency and error trends, then surfaces alerts when thresholds are
 //! exceeded.
 
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, VecDeque};
 use std::time::Duration;
 
@@ -25,9 +26,11 @@ impl Default for PerformanceConfig {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PerformanceReport {
+    #[serde(with = "crate::serde_millis::option_duration")]
     pub global_latency: Option<Duration>,
+    #[serde(with = "crate::serde_millis::named_durations")]
     pub slow_domains: Vec<(String, Duration)>,
     pub error_domains: Vec<(String, f64)>,
     pub alerts: Vec<String>,
@@ -237,4 +240,20 @@ mod tests {
         let report = monitor.snapshot();
         assert!(!report.slow_domains.is_empty());
     }
+
+    #[test]
+    fn report_round_trips_through_json() {
+        let mut monitor = PerformanceMonitor::new(PerformanceConfig {
+            latency_threshold: Duration::from_millis(200),
+            min_samples: 2,
+            ..Default::default()
+        });
+        monitor.record("example.com", Duration::from_millis(500), true);
+        monitor.record("example.com", Duration::from_millis(700), false);
+        let report = monitor.snapshot();
+
+        let json = serde_json::to_string(&report).unwrap();
+        let restored: PerformanceReport = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, report);
+    }
 }
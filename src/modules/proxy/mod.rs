@@ -3,13 +3,21 @@
 //! Tracks proxy performance, bans unhealthy endpoints, and selects the next
 //! candidate based on the chosen rotation strategy.
 
-use rand::Rng;
+use chrono::{DateTime, Utc};
+use rand::rngs::StdRng;
 use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::time::{Duration, Instant};
 
+use crate::challenges::core::{DEFAULT_SUCCESS_HALF_LIFE, DecayingRate};
 use crate::challenges::solvers::access_denied::ProxyPool;
+use crate::modules::storage::{StorageBackend, StorageError, load_json, store_json};
+
+/// [`StorageBackend`] key of the [`ProxyManager`] snapshot.
+pub const PROXY_STORAGE_KEY: &str = "proxies";
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RotationStrategy {
@@ -26,6 +34,11 @@ pub struct ProxyConfig {
     pub ban_time: Duration,
     pub failure_threshold: u32,
     pub cooldown: Duration,
+    /// Age at which an outcome counts half as much towards the proxy score.
+    pub success_half_life: Duration,
+    /// Distinct proxies a single request may try before failing with
+    /// `ProxiesExhausted`; `None` leaves it bounded only by the retry limit.
+    pub max_proxies_per_request: Option<usize>,
 }
 
 impl Default for ProxyConfig {
@@ -35,11 +48,13 @@ impl Default for ProxyConfig {
             ban_time: Duration::from_secs(300),
             failure_threshold: 3,
             cooldown: Duration::from_secs(60),
+            success_half_life: DEFAULT_SUCCESS_HALF_LIFE,
+            max_proxies_per_request: None,
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProxyHealthReport {
     pub total_proxies: usize,
     pub available_proxies: usize,
@@ -47,12 +62,23 @@ pub struct ProxyHealthReport {
     pub details: HashMap<String, ProxyStats>,
 }
 
-#[derive(Debug, Clone, Default)]
+/// Instants serialize as their age in milliseconds; the `_at` wall-clock
+/// twins survive a restart unchanged and are what persisted history should
+/// compare.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ProxyStats {
     pub successes: u64,
     pub failures: u64,
+    #[serde(with = "crate::serde_millis::option_instant_age")]
     pub last_used: Option<Instant>,
+    #[serde(with = "crate::serde_millis::option_instant_age")]
     pub last_failure: Option<Instant>,
+    #[serde(default)]
+    pub last_used_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub last_failure_at: Option<DateTime<Utc>>,
+    /// Time-decayed success ratio used for scoring.
+    pub recent: DecayingRate,
 }
 
 #[derive(Debug, Clone)]
@@ -71,12 +97,7 @@ impl ProxyEntry {
     }
 
     fn score(&self) -> f64 {
-        let total = self.stats.successes + self.stats.failures;
-        let success_rate = if total == 0 {
-            1.0
-        } else {
-            self.stats.successes as f64 / total as f64
-        };
+        let success_rate = self.stats.recent.rate().unwrap_or(1.0);
         let recency = self
             .stats
             .last_used
@@ -93,7 +114,7 @@ pub struct ProxyManager {
     config: ProxyConfig,
     proxies: Vec<ProxyEntry>,
     current_index: usize,
-    rng: rand::rngs::ThreadRng,
+    rng: StdRng,
 }
 
 impl ProxyManager {
@@ -102,10 +123,16 @@ impl ProxyManager {
             config,
             proxies: Vec::new(),
             current_index: 0,
-            rng: rand::thread_rng(),
+            rng: StdRng::from_entropy(),
         }
     }
 
+    /// Drive random and weighted rotation from `rng`.
+    pub fn with_rng(mut self, rng: StdRng) -> Self {
+        self.rng = rng;
+        self
+    }
+
     pub fn load<I>(&mut self, proxies: I)
     where
         I: IntoIterator,
@@ -124,17 +151,86 @@ impl ProxyManager {
         }
         self.proxies.push(ProxyEntry {
             endpoint,
-            stats: ProxyStats::default(),
+            stats: ProxyStats {
+                recent: DecayingRate::new(self.config.success_half_life),
+                ..ProxyStats::default()
+            },
             banned_until: None,
         });
     }
 
+    /// Replace the pool with `proxies`, keeping stats and bans for endpoints
+    /// that remain. Returns the endpoints that were dropped.
+    pub fn reload<I>(&mut self, proxies: I) -> Vec<String>
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        let mut previous = std::mem::take(&mut self.proxies);
+        for proxy in proxies {
+            let endpoint = proxy.into();
+            if self.proxies.iter().any(|entry| entry.endpoint == endpoint) {
+                continue;
+            }
+            match previous.iter().position(|entry| entry.endpoint == endpoint) {
+                Some(index) => self.proxies.push(previous.swap_remove(index)),
+                None => self.add_proxy(endpoint),
+            }
+        }
+        self.current_index = 0;
+        previous.into_iter().map(|entry| entry.endpoint).collect()
+    }
+
+    /// Seed pooled endpoints with stats saved from an earlier
+    /// [`health_report`](Self::health_report); unknown endpoints are ignored.
+    pub fn restore_stats(&mut self, details: &HashMap<String, ProxyStats>) {
+        for entry in &mut self.proxies {
+            if let Some(stats) = details.get(&entry.endpoint) {
+                entry.stats = stats.clone();
+            }
+        }
+    }
+
+    /// Save per-proxy stats under [`PROXY_STORAGE_KEY`].
+    pub fn save_to(&self, storage: &dyn StorageBackend) -> Result<(), StorageError> {
+        store_json(storage, PROXY_STORAGE_KEY, &self.health_report().details)
+    }
+
+    /// [`restore_stats`](Self::restore_stats) from a snapshot written by
+    /// [`save_to`](Self::save_to). Returns whether one was found.
+    pub fn load_from(&mut self, storage: &dyn StorageBackend) -> Result<bool, StorageError> {
+        let Some(details) = load_json(storage, PROXY_STORAGE_KEY)? else {
+            return Ok(false);
+        };
+        self.restore_stats(&details);
+        Ok(true)
+    }
+
     pub fn remove_proxy(&mut self, proxy: &str) {
         self.proxies.retain(|entry| entry.endpoint != proxy);
     }
 
     pub fn next_proxy(&mut self) -> Option<String> {
-        if self.proxies.is_empty() {
+        self.select_proxy(&[], false)
+    }
+
+    /// Like [`next_proxy`](Self::next_proxy) but never returns `excluded`
+    /// while another proxy is available.
+    pub fn next_proxy_except(&mut self, excluded: &str) -> Option<String> {
+        self.select_proxy(&[excluded], false)
+    }
+
+    /// A proxy not in `excluded`, or `None` once every proxy is excluded.
+    pub fn next_untried_proxy(&mut self, excluded: &[String]) -> Option<String> {
+        let excluded: Vec<&str> = excluded.iter().map(String::as_str).collect();
+        self.select_proxy(&excluded, true)
+    }
+
+    /// Rotate past `excluded`; unless `strict`, fall back to an excluded
+    /// proxy when nothing else is available.
+    fn select_proxy(&mut self, excluded: &[&str], strict: bool) -> Option<String> {
+        let allowed = |entry: &ProxyEntry| !strict || !excluded.contains(&entry.endpoint.as_str());
+        if !self.proxies.iter().any(allowed) {
             return None;
         }
 
@@ -151,12 +247,21 @@ impl ProxyManager {
                 available_indices.push(idx);
             }
         }
+        let is_excluded = |entry: &ProxyEntry| excluded.contains(&entry.endpoint.as_str());
+        if strict
+            || available_indices
+                .iter()
+                .any(|&idx| !is_excluded(&self.proxies[idx]))
+        {
+            available_indices.retain(|&idx| !is_excluded(&self.proxies[idx]));
+        }
 
         let selected_index = if available_indices.is_empty() {
             let index = self
                 .proxies
                 .iter()
                 .enumerate()
+                .filter(|(_, entry)| allowed(entry))
                 .min_by_key(|(_, entry)| entry.banned_until.unwrap_or(now))
                 .map(|(idx, _)| idx)?;
             let entry = &mut self.proxies[index];
@@ -210,6 +315,7 @@ impl ProxyManager {
 
         let entry = &mut self.proxies[selected_index];
         entry.stats.last_used = Some(Instant::now());
+        entry.stats.last_used_at = Some(Utc::now());
         Some(entry.endpoint.clone())
     }
 
@@ -220,6 +326,7 @@ impl ProxyManager {
             .find(|entry| entry.endpoint == proxy)
         {
             entry.stats.successes += 1;
+            entry.stats.recent.record(true);
             entry.banned_until = None;
         }
     }
@@ -231,7 +338,9 @@ impl ProxyManager {
             .find(|entry| entry.endpoint == proxy)
         {
             entry.stats.failures += 1;
+            entry.stats.recent.record(false);
             entry.stats.last_failure = Some(Instant::now());
+            entry.stats.last_failure_at = Some(Utc::now());
             if entry.stats.failures % self.config.failure_threshold as u64 == 0 {
                 entry.banned_until = Some(Instant::now() + self.config.ban_time);
             }
@@ -277,7 +386,7 @@ impl ProxyPool for ProxyManager {
 }
 
 fn weighted_choice_index(
-    rng: &mut rand::rngs::ThreadRng,
+    rng: &mut StdRng,
     proxies: &[ProxyEntry],
     indices: &[usize],
 ) -> Option<usize> {
@@ -332,4 +441,127 @@ mod tests {
         let report = manager.health_report();
         assert_eq!(report.banned_proxies, 1);
     }
+
+    #[test]
+    fn stale_success_history_loses_to_recent_failures() {
+        let mut manager = ProxyManager::new(ProxyConfig {
+            rotation_strategy: RotationStrategy::Smart,
+            success_half_life: Duration::from_secs(600),
+            ..Default::default()
+        });
+        manager.load(["http://veteran:8080", "http://newcomer:8080"]);
+
+        let long_ago = Instant::now();
+        let now = long_ago + Duration::from_secs(6 * 3600);
+        let veteran = &mut manager.proxies[0].stats;
+        for _ in 0..100 {
+            veteran.successes += 1;
+            veteran.recent.record_at(true, long_ago);
+        }
+        for _ in 0..5 {
+            veteran.failures += 1;
+            veteran.recent.record_at(false, now);
+        }
+        let newcomer = &mut manager.proxies[1].stats;
+        for success in [true, true, true, false] {
+            newcomer.recent.record_at(success, now);
+        }
+
+        let veteran = &manager.proxies[0].stats;
+        let lifetime_rate =
+            veteran.successes as f64 / (veteran.successes + veteran.failures) as f64;
+        assert!(lifetime_rate > 0.9);
+        assert!(manager.proxies[0].score() < manager.proxies[1].score());
+        assert_eq!(
+            manager.next_proxy().as_deref(),
+            Some("http://newcomer:8080")
+        );
+    }
+
+    #[test]
+    fn reload_keeps_stats_for_surviving_proxies() {
+        let mut manager = ProxyManager::default();
+        manager.load(["http://kept:8080", "http://dropped:8080"]);
+        manager.report_success("http://kept:8080");
+        manager.report_success("http://kept:8080");
+        manager.report_failure("http://kept:8080");
+
+        let removed = manager.reload(["http://kept:8080", "http://fresh:8080"]);
+        assert_eq!(removed, vec!["http://dropped:8080".to_string()]);
+
+        let report = manager.health_report();
+        assert_eq!(report.total_proxies, 2);
+        let kept = &report.details["http://kept:8080"];
+        assert_eq!((kept.successes, kept.failures), (2, 1));
+        assert_eq!(report.details["http://fresh:8080"].successes, 0);
+    }
+
+    #[test]
+    fn health_report_round_trips_through_json() {
+        let mut manager = ProxyManager::default();
+        manager.load(["http://1.1.1.1:8080", "http://2.2.2.2:8080"]);
+        let proxy = manager.next_proxy().unwrap();
+        manager.report_success(&proxy);
+        manager.report_failure(&proxy);
+        let report = manager.health_report();
+
+        let json = serde_json::to_string(&report).unwrap();
+        let restored: ProxyHealthReport = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.total_proxies, report.total_proxies);
+        assert_eq!(restored.available_proxies, report.available_proxies);
+        let stats = &restored.details[&proxy];
+        assert_eq!((stats.successes, stats.failures), (1, 1));
+        assert!(stats.last_used.is_some() && stats.last_failure.is_some());
+        let drift = stats.recent.rate().unwrap() - report.details[&proxy].recent.rate().unwrap();
+        assert!(drift.abs() < 1e-9);
+    }
+
+    #[test]
+    fn next_proxy_except_skips_the_excluded_endpoint() {
+        let mut manager = ProxyManager::default();
+        manager.load(["http://1.1.1.1:8080", "http://2.2.2.2:8080"]);
+        assert_eq!(manager.next_proxy().unwrap(), "http://1.1.1.1:8080");
+        // Sequential rotation would hand out 2.2.2.2 next.
+        assert_eq!(
+            manager.next_proxy_except("http://2.2.2.2:8080").as_deref(),
+            Some("http://1.1.1.1:8080")
+        );
+        manager.remove_proxy("http://1.1.1.1:8080");
+        manager.remove_proxy("http://2.2.2.2:8080");
+        manager.add_proxy("http://3.3.3.3:8080");
+        assert_eq!(
+            manager.next_proxy_except("http://3.3.3.3:8080").as_deref(),
+            Some("http://3.3.3.3:8080")
+        );
+    }
+
+    #[test]
+    fn wall_clock_stats_survive_a_restart() {
+        let mut manager = ProxyManager::default();
+        manager.load(["http://1.1.1.1:8080"]);
+        let proxy = manager.next_proxy().unwrap();
+        manager.report_failure(&proxy);
+        let saved = serde_json::to_string(&manager.health_report().details).unwrap();
+
+        let details: HashMap<String, ProxyStats> = serde_json::from_str(&saved).unwrap();
+        let mut restarted = ProxyManager::default();
+        restarted.load(["http://1.1.1.1:8080", "http://2.2.2.2:8080"]);
+        restarted.restore_stats(&details);
+
+        let report = restarted.health_report();
+        let restored = &report.details[&proxy];
+        let original = &manager.health_report().details[&proxy];
+        assert_eq!(restored.failures, 1);
+        assert_eq!(restored.last_used_at, original.last_used_at);
+        assert_eq!(restored.last_failure_at, original.last_failure_at);
+        assert!(report.details["http://2.2.2.2:8080"].last_used_at.is_none());
+
+        // Stats saved before the wall-clock fields existed still load.
+        let mut legacy = serde_json::to_value(original).unwrap();
+        let fields = legacy.as_object_mut().unwrap();
+        fields.remove("last_used_at");
+        fields.remove("last_failure_at");
+        let legacy: ProxyStats = serde_json::from_value(legacy).unwrap();
+        assert!(legacy.last_used.is_some() && legacy.last_used_at.is_none());
+    }
 }
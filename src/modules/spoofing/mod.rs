@@ -4,8 +4,10 @@
 //! present stable client identities when required.
 
 use chrono::{DateTime, Utc};
-use rand::{Rng, seq::SliceRandom};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng, seq::SliceRandom};
 use std::collections::HashMap;
+use std::fmt;
 
 use crate::challenges::solvers::FingerprintManager;
 
@@ -19,6 +21,134 @@ pub enum BrowserType {
     MobileSafari,
 }
 
+impl BrowserType {
+    /// Best-effort classification of a User-Agent string.
+    pub fn from_user_agent(user_agent: &str) -> Option<Self> {
+        let ua = user_agent.to_ascii_lowercase();
+        let mobile = ua.contains("mobile") || ua.contains("android") || ua.contains("iphone");
+        if ua.contains("firefox/") || ua.contains("fxios/") {
+            Some(BrowserType::Firefox)
+        } else if ua.contains("edg/") {
+            Some(BrowserType::Edge)
+        } else if ua.contains("chrome/") || ua.contains("crios/") {
+            Some(if mobile {
+                BrowserType::MobileChrome
+            } else {
+                BrowserType::Chrome
+            })
+        } else if ua.contains("safari/") {
+            Some(if mobile {
+                BrowserType::MobileSafari
+            } else {
+                BrowserType::Safari
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Whether two browsers share a rendering engine, and therefore a
+    /// plausible TLS and fingerprint surface.
+    pub fn is_compatible_with(self, other: BrowserType) -> bool {
+        self.engine() == other.engine()
+    }
+
+    fn engine(self) -> &'static str {
+        match self {
+            BrowserType::Chrome | BrowserType::Edge | BrowserType::MobileChrome => "chromium",
+            BrowserType::Firefox => "gecko",
+            BrowserType::Safari | BrowserType::MobileSafari => "webkit",
+        }
+    }
+}
+
+/// Operating system a User-Agent claims, which fixes `navigator.platform`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OperatingSystem {
+    Windows,
+    MacOs,
+    Linux,
+    Android,
+    Ios,
+}
+
+impl OperatingSystem {
+    /// Best-effort classification of a User-Agent string.
+    pub fn from_user_agent(user_agent: &str) -> Option<Self> {
+        let ua = user_agent.to_ascii_lowercase();
+        if ua.contains("iphone") || ua.contains("ipad") || ua.contains("ipod") {
+            Some(OperatingSystem::Ios)
+        } else if ua.contains("android") {
+            Some(OperatingSystem::Android)
+        } else if ua.contains("windows") {
+            Some(OperatingSystem::Windows)
+        } else if ua.contains("mac os x") || ua.contains("macintosh") {
+            Some(OperatingSystem::MacOs)
+        } else if ua.contains("linux") || ua.contains("x11") || ua.contains("cros") {
+            Some(OperatingSystem::Linux)
+        } else {
+            None
+        }
+    }
+
+    /// `navigator.platform` browsers report on this OS.
+    pub fn navigator_platform(self) -> &'static str {
+        match self {
+            OperatingSystem::Windows => "Win32",
+            OperatingSystem::MacOs => "MacIntel",
+            OperatingSystem::Linux => "Linux x86_64",
+            OperatingSystem::Android => "Linux armv8l",
+            OperatingSystem::Ios => "iPhone",
+        }
+    }
+
+    /// WebGL `(vendor, renderer)` pairs GPUs commonly report on this OS.
+    fn webgl(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            OperatingSystem::Windows => &[
+                ("Google Inc.", "ANGLE (NVIDIA GeForce RTX 3080)"),
+                ("Google Inc.", "ANGLE (Intel(R) UHD Graphics 630)"),
+            ],
+            OperatingSystem::MacOs => &[("Apple", "Apple GPU"), ("Apple", "Apple M2")],
+            OperatingSystem::Linux => &[
+                ("Intel", "Mesa Intel(R) UHD Graphics 630"),
+                ("AMD", "AMD Radeon RX 6800 (radeonsi)"),
+            ],
+            OperatingSystem::Android => &[("Qualcomm", "Adreno (TM) 730"), ("ARM", "Mali-G710")],
+            OperatingSystem::Ios => &[("Apple", "Apple A16 GPU"), ("Apple", "Apple A15 GPU")],
+        }
+    }
+}
+
+/// A field two identities disagree on, as reported by the `diff` methods.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldDiff {
+    pub field: &'static str,
+    pub left: String,
+    pub right: String,
+}
+
+impl FieldDiff {
+    /// Push a diff for `field` onto `diffs` when the values differ.
+    pub(crate) fn compare(
+        diffs: &mut Vec<FieldDiff>,
+        field: &'static str,
+        left: impl Into<String>,
+        right: impl Into<String>,
+    ) {
+        let (left, right) = (left.into(), right.into());
+        if left != right {
+            diffs.push(FieldDiff { field, left, right });
+        }
+    }
+}
+
+impl fmt::Display for FieldDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} -> {}", self.field, self.left, self.right)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct BrowserFingerprint {
     pub user_agent: String,
@@ -33,6 +163,84 @@ pub struct BrowserFingerprint {
     pub created_at: DateTime<Utc>,
 }
 
+impl BrowserFingerprint {
+    /// Browser the fingerprint's user agent identifies, so TLS and script
+    /// layers can present the same one.
+    pub fn browser_type(&self) -> Option<BrowserType> {
+        BrowserType::from_user_agent(&self.user_agent)
+    }
+
+    /// Fields that differ from `other`, in declaration order; the creation
+    /// time is ignored.
+    pub fn diff(&self, other: &BrowserFingerprint) -> Vec<FieldDiff> {
+        let resolution = |(width, height): (u16, u16)| format!("{width}x{height}");
+        let mut diffs = Vec::new();
+        FieldDiff::compare(
+            &mut diffs,
+            "user_agent",
+            &self.user_agent,
+            &other.user_agent,
+        );
+        FieldDiff::compare(
+            &mut diffs,
+            "accept_language",
+            &self.accept_language,
+            &other.accept_language,
+        );
+        FieldDiff::compare(&mut diffs, "platform", &self.platform, &other.platform);
+        FieldDiff::compare(
+            &mut diffs,
+            "screen_resolution",
+            resolution(self.screen_resolution),
+            resolution(other.screen_resolution),
+        );
+        FieldDiff::compare(&mut diffs, "timezone", &self.timezone, &other.timezone);
+        FieldDiff::compare(
+            &mut diffs,
+            "webgl_vendor",
+            &self.webgl_vendor,
+            &other.webgl_vendor,
+        );
+        FieldDiff::compare(
+            &mut diffs,
+            "webgl_renderer",
+            &self.webgl_renderer,
+            &other.webgl_renderer,
+        );
+        FieldDiff::compare(
+            &mut diffs,
+            "canvas_fingerprint",
+            &self.canvas_fingerprint,
+            &other.canvas_fingerprint,
+        );
+        FieldDiff::compare(
+            &mut diffs,
+            "audio_fingerprint",
+            &self.audio_fingerprint,
+            &other.audio_fingerprint,
+        );
+        diffs
+    }
+
+    /// Present `user_agent` with its OS's platform, redrawing the WebGL
+    /// vendor and renderer when the template was for another OS.
+    fn with_pinned_user_agent(mut self, user_agent: Option<&str>, rng: &mut StdRng) -> Self {
+        if let Some(user_agent) = user_agent {
+            if let Some(os) = OperatingSystem::from_user_agent(user_agent) {
+                self.platform = os.navigator_platform().to_string();
+                if OperatingSystem::from_user_agent(&self.user_agent) != Some(os)
+                    && let Some((vendor, renderer)) = os.webgl().choose(rng)
+                {
+                    self.webgl_vendor = vendor.to_string();
+                    self.webgl_renderer = renderer.to_string();
+                }
+            }
+            self.user_agent = user_agent.to_string();
+        }
+        self
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum ConsistencyLevel {
     None,
@@ -47,6 +255,10 @@ pub struct FingerprintGenerator {
     consistency: ConsistencyLevel,
     cache: HashMap<String, BrowserFingerprint>,
     global: Option<BrowserFingerprint>,
+    accept_languages: Vec<String>,
+    /// User agent every fingerprint presents instead of the template's.
+    user_agent: Option<String>,
+    rng: StdRng,
 }
 
 impl FingerprintGenerator {
@@ -56,14 +268,56 @@ impl FingerprintGenerator {
             consistency: ConsistencyLevel::Domain,
             cache: HashMap::new(),
             global: None,
+            accept_languages: Vec::new(),
+            user_agent: None,
+            rng: StdRng::from_entropy(),
         }
     }
 
+    /// Generate fingerprints from `rng` instead of an entropy-seeded generator.
+    pub fn with_rng(mut self, rng: StdRng) -> Self {
+        self.rng = rng;
+        self
+    }
+
     pub fn with_consistency(mut self, level: ConsistencyLevel) -> Self {
         self.consistency = level;
         self
     }
 
+    /// Draw `Accept-Language` from `languages` instead of the browser templates.
+    ///
+    /// Values whose primary language matches the fingerprint timezone are
+    /// preferred; the rest are only used when none match.
+    pub fn with_accept_languages(mut self, languages: Vec<String>) -> Self {
+        self.accept_languages = languages;
+        self.reset();
+        self
+    }
+
+    /// Present `user_agent` in every fingerprint, with the platform of the OS
+    /// it names, instead of the templates' own.
+    pub fn with_user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = Some(user_agent.into());
+        self.reset();
+        self
+    }
+
+    /// Fingerprint `domain` is currently presented with, without generating
+    /// one; always `None` without consistency.
+    pub fn peek(&self, domain: &str) -> Option<&BrowserFingerprint> {
+        match self.consistency {
+            ConsistencyLevel::None => None,
+            ConsistencyLevel::Global => self.cache.get(domain).or(self.global.as_ref()),
+            ConsistencyLevel::Domain => self.cache.get(domain),
+        }
+    }
+
+    /// Browser whose templates fingerprints are drawn from.
+    pub fn browser(&self) -> BrowserType {
+        self.browser
+    }
+
     pub fn set_browser(&mut self, browser: BrowserType) {
         if self.browser != browser {
             self.cache.clear();
@@ -74,18 +328,37 @@ impl FingerprintGenerator {
 
     pub fn generate_for(&mut self, domain: &str) -> BrowserFingerprint {
         match self.consistency {
-            ConsistencyLevel::None => Self::random_fingerprint(self.browser),
+            ConsistencyLevel::None => {
+                Self::random_fingerprint(&mut self.rng, self.browser, &self.accept_languages)
+                    .with_pinned_user_agent(self.user_agent.as_deref(), &mut self.rng)
+            }
             ConsistencyLevel::Global => {
+                if let Some(fingerprint) = self.cache.get(domain) {
+                    return fingerprint.clone();
+                }
                 if self.global.is_none() {
-                    self.global = Some(Self::random_fingerprint(self.browser));
+                    self.global = Some(
+                        Self::random_fingerprint(
+                            &mut self.rng,
+                            self.browser,
+                            &self.accept_languages,
+                        )
+                        .with_pinned_user_agent(self.user_agent.as_deref(), &mut self.rng),
+                    );
                 }
                 self.global.clone().unwrap()
             }
             ConsistencyLevel::Domain => {
                 let browser = self.browser;
+                let languages = &self.accept_languages;
+                let rng = &mut self.rng;
+                let user_agent = self.user_agent.as_deref();
                 self.cache
                     .entry(domain.to_string())
-                    .or_insert_with(|| Self::random_fingerprint(browser))
+                    .or_insert_with(|| {
+                        Self::random_fingerprint(rng, browser, languages)
+                            .with_pinned_user_agent(user_agent, rng)
+                    })
                     .clone()
             }
         }
@@ -95,44 +368,75 @@ impl FingerprintGenerator {
         self.cache.remove(domain);
     }
 
-    fn random_fingerprint(browser: BrowserType) -> BrowserFingerprint {
+    /// Draw a fresh identity for `domain` in place of the current one. Under
+    /// [`ConsistencyLevel::Global`] it replaces the shared identity for
+    /// `domain` only.
+    pub fn regenerate_for(&mut self, domain: &str) -> BrowserFingerprint {
+        let fingerprint =
+            Self::random_fingerprint(&mut self.rng, self.browser, &self.accept_languages)
+                .with_pinned_user_agent(self.user_agent.as_deref(), &mut self.rng);
+        if !matches!(self.consistency, ConsistencyLevel::None) {
+            self.cache.insert(domain.to_string(), fingerprint.clone());
+        }
+        fingerprint
+    }
+
+    /// Drop every cached identity so the next request generates a fresh one.
+    pub fn reset(&mut self) {
+        self.cache.clear();
+        self.global = None;
+    }
+
+    fn random_fingerprint(
+        rng: &mut StdRng,
+        browser: BrowserType,
+        accept_languages: &[String],
+    ) -> BrowserFingerprint {
         let templates = templates_for_browser(browser);
-        let mut rng = rand::thread_rng();
-        let template = templates.choose(&mut rng).unwrap_or(&templates[0]);
+        let template = templates.choose(rng).unwrap_or(&templates[0]);
 
         let screen_resolution = template
             .screen_resolutions
-            .choose(&mut rng)
+            .choose(rng)
             .copied()
             .unwrap_or((1920, 1080));
 
         let timezone = template
             .timezones
-            .choose(&mut rng)
+            .choose(rng)
             .cloned()
             .unwrap_or_else(|| "UTC".to_string());
 
         let webgl_vendor = template
             .webgl_vendors
-            .choose(&mut rng)
+            .choose(rng)
             .cloned()
             .unwrap_or_else(|| "Google Inc.".into());
         let webgl_renderer = template
             .webgl_renderers
-            .choose(&mut rng)
+            .choose(rng)
             .cloned()
             .unwrap_or_else(|| "ANGLE (NVIDIA GeForce GTX 1660)".into());
 
         let canvas_seed: u64 = rng.r#gen();
         let audio_seed: u64 = rng.r#gen();
 
+        let accept_language = if accept_languages.is_empty() {
+            template.accept_languages.choose(rng).cloned()
+        } else {
+            let local: Vec<&String> = accept_languages
+                .iter()
+                .filter(|language| language_matches_timezone(language, &timezone))
+                .collect();
+            match local.choose(rng) {
+                Some(language) => Some((*language).clone()),
+                None => accept_languages.choose(rng).cloned(),
+            }
+        };
+
         BrowserFingerprint {
             user_agent: template.user_agent.clone(),
-            accept_language: template
-                .accept_languages
-                .choose(&mut rng)
-                .cloned()
-                .unwrap_or_else(|| "en-US,en;q=0.9".into()),
+            accept_language: accept_language.unwrap_or_else(|| "en-US,en;q=0.9".into()),
             platform: template.platform.clone(),
             screen_resolution,
             timezone,
@@ -224,6 +528,44 @@ fn templates_for_browser(browser: BrowserType) -> Vec<FingerprintTemplate> {
     }
 }
 
+/// Primary language commonly paired with an IANA timezone, when known.
+fn timezone_language(timezone: &str) -> Option<&'static str> {
+    const LANGUAGES: &[(&str, &str)] = &[
+        ("Europe/London", "en"),
+        ("Europe/Dublin", "en"),
+        ("Europe/Berlin", "de"),
+        ("Europe/Vienna", "de"),
+        ("Europe/Zurich", "de"),
+        ("Europe/Paris", "fr"),
+        ("Europe/Madrid", "es"),
+        ("America/Mexico_City", "es"),
+        ("America/Buenos_Aires", "es"),
+        ("Europe/Rome", "it"),
+        ("Europe/Amsterdam", "nl"),
+        ("Europe/Lisbon", "pt"),
+        ("America/Sao_Paulo", "pt"),
+        ("Asia/Tokyo", "ja"),
+        ("Asia/Seoul", "ko"),
+        ("Asia/Shanghai", "zh"),
+    ];
+    if let Some((_, language)) = LANGUAGES.iter().find(|(zone, _)| *zone == timezone) {
+        return Some(language);
+    }
+    let region = timezone.split('/').next().unwrap_or_default();
+    matches!(region, "America" | "Australia" | "Pacific").then_some("en")
+}
+
+/// Whether the first language in an `Accept-Language` value fits `timezone`.
+fn language_matches_timezone(accept_language: &str, timezone: &str) -> bool {
+    let Some(expected) = timezone_language(timezone) else {
+        return false;
+    };
+    accept_language
+        .split([',', ';', '-'])
+        .next()
+        .is_some_and(|primary| primary.trim().eq_ignore_ascii_case(expected))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -237,4 +579,99 @@ mod tests {
         assert_eq!(fp1.user_agent, fp2.user_agent);
         assert_ne!(fp1.canvas_fingerprint, fp3.canvas_fingerprint);
     }
+
+    #[test]
+    fn regenerating_under_global_consistency_overrides_one_domain() {
+        let mut generator =
+            FingerprintGenerator::default().with_consistency(ConsistencyLevel::Global);
+        let shared = generator.generate_for("a.example");
+        let fresh = generator.regenerate_for("a.example");
+
+        assert_ne!(fresh.canvas_fingerprint, shared.canvas_fingerprint);
+        assert_eq!(
+            generator.generate_for("a.example").canvas_fingerprint,
+            fresh.canvas_fingerprint
+        );
+        assert_eq!(
+            generator.generate_for("b.example").canvas_fingerprint,
+            shared.canvas_fingerprint
+        );
+    }
+
+    #[test]
+    fn classifies_user_agents() {
+        let firefox = "Mozilla/5.0 (X11; Linux x86_64; rv:121.0) Gecko/20100101 Firefox/121.0";
+        let android = "Mozilla/5.0 (Linux; Android 13) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Mobile Safari/537.36";
+        assert_eq!(
+            BrowserType::from_user_agent(firefox),
+            Some(BrowserType::Firefox)
+        );
+        assert_eq!(
+            BrowserType::from_user_agent(android),
+            Some(BrowserType::MobileChrome)
+        );
+        assert_eq!(BrowserType::from_user_agent("curl/8.0"), None);
+    }
+
+    #[test]
+    fn prefers_accept_language_matching_timezone() {
+        let languages = vec![
+            "de-DE,de;q=0.9,en;q=0.6".to_string(),
+            "ja-JP,ja;q=0.9".to_string(),
+        ];
+        let mut generator =
+            FingerprintGenerator::new(BrowserType::Chrome).with_accept_languages(languages.clone());
+        for index in 0..20 {
+            let fp = generator.generate_for(&format!("site{index}.example"));
+            assert!(languages.contains(&fp.accept_language));
+            if fp.timezone == "Asia/Tokyo" {
+                assert!(fp.accept_language.starts_with("ja"));
+            }
+            if fp.timezone == "Europe/Berlin" {
+                assert!(fp.accept_language.starts_with("de"));
+            }
+        }
+    }
+
+    #[test]
+    fn diff_reports_the_fields_that_changed() {
+        let chrome = FingerprintGenerator::new(BrowserType::Chrome).generate_for("example.com");
+        let safari = FingerprintGenerator::new(BrowserType::Safari).generate_for("example.com");
+        assert!(chrome.diff(&chrome.clone()).is_empty());
+
+        let diffs = chrome.diff(&safari);
+        let fields: Vec<&str> = diffs.iter().map(|diff| diff.field).collect();
+        for field in [
+            "user_agent",
+            "platform",
+            "screen_resolution",
+            "webgl_renderer",
+        ] {
+            assert!(fields.contains(&field), "{field} missing from {fields:?}");
+        }
+        assert!(fields.contains(&"canvas_fingerprint"));
+        let platform = diffs.iter().find(|diff| diff.field == "platform").unwrap();
+        assert_eq!(
+            (platform.left.as_str(), platform.right.as_str()),
+            ("Win32", "MacIntel")
+        );
+        assert_eq!(platform.to_string(), "platform: Win32 -> MacIntel");
+    }
+
+    #[test]
+    fn pinned_user_agent_redraws_webgl_for_its_os() {
+        let windows = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36";
+        let mut generator = FingerprintGenerator::new(BrowserType::Safari)
+            .with_user_agent(windows)
+            .with_rng(StdRng::seed_from_u64(7));
+        let fingerprint = generator.generate_for("example.com");
+        assert_eq!(fingerprint.platform, "Win32");
+        assert!(
+            OperatingSystem::Windows.webgl().contains(&(
+                fingerprint.webgl_vendor.as_str(),
+                fingerprint.webgl_renderer.as_str()
+            )),
+            "{fingerprint:?}"
+        );
+    }
 }
@@ -4,16 +4,39 @@
 //! staying lightweight for async callers.
 
 use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::{HashMap, VecDeque};
 use std::sync::{Arc, RwLock};
 use std::time::Duration;
 
-use crate::challenges::solvers::FailureRecorder;
+use crate::challenges::core::LearningRate;
+use crate::challenges::detectors::ChallengeType;
+use crate::challenges::solvers::{FailureContext, FailureRecorder};
+use crate::modules::storage::{StorageBackend, StorageError, load_json, store_json};
+
+mod scope;
+
+pub use scope::StateScope;
 
 const ERROR_HISTORY_LIMIT: usize = 50;
 const RECENT_DELAY_LIMIT: usize = 32;
 
+/// Default schedule of the [`TimingState`] averages.
+pub const DEFAULT_STATE_LEARNING_RATE: LearningRate = LearningRate::Fixed(0.05);
+
+/// How long before `__cf_bm` expires a domain is reported as needing refresh.
+pub const BOT_COOKIE_REFRESH_MARGIN: Duration = Duration::from_secs(120);
+
+/// [`StorageBackend`] key of the [`StateManager`] snapshot.
+pub const STATE_STORAGE_KEY: &str = "state";
+
+/// `DomainState::metadata` key recording an Under Attack Mode escalation.
+pub const UNDER_ATTACK_METADATA_KEY: &str = "under_attack";
+
+/// Clean responses in a row that lift an Under Attack Mode escalation.
+pub const UNDER_ATTACK_RECOVERY_STREAK: u32 = 20;
+
 fn chrono_duration(duration: Duration) -> chrono::Duration {
     chrono::Duration::from_std(duration).unwrap_or_else(|_| {
         let millis = duration.as_millis().min(i64::MAX as u128);
@@ -28,6 +51,9 @@ pub struct TimingState {
     pub consecutive_failures: u8,
     pub optimal_delay: Option<Duration>,
     pub recent_delays: VecDeque<Duration>,
+    /// Outcomes folded into the averages so far.
+    pub updates: u64,
+    pub learning_rate: LearningRate,
 }
 
 impl Default for TimingState {
@@ -38,6 +64,8 @@ impl Default for TimingState {
             consecutive_failures: 0,
             optimal_delay: None,
             recent_delays: VecDeque::with_capacity(RECENT_DELAY_LIMIT),
+            updates: 0,
+            learning_rate: DEFAULT_STATE_LEARNING_RATE,
         }
     }
 }
@@ -49,9 +77,9 @@ impl TimingState {
         response_time: Duration,
         applied_delay: Duration,
     ) {
+        let alpha = self.learning_rate.rate(self.updates) as f32;
         self.apply_boolean_outcome(success);
 
-        let alpha = 0.05;
         let response_secs = response_time.as_secs_f32();
         if self.avg_response_time_secs <= 0.0 {
             self.avg_response_time_secs = response_secs;
@@ -78,7 +106,8 @@ impl TimingState {
     }
 
     pub fn apply_boolean_outcome(&mut self, success: bool) {
-        let alpha = 0.05;
+        let alpha = self.learning_rate.rate(self.updates) as f32;
+        self.updates += 1;
         let target = if success { 1.0 } else { 0.0 };
         self.success_rate = (1.0 - alpha) * self.success_rate + alpha * target;
 
@@ -255,11 +284,42 @@ impl MlStrategyState {
     }
 }
 
+/// Whether a domain is currently known to challenge requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChallengeStatus {
+    /// Last response was clean; `until` is the clearance cookie expiry, if known.
+    Clear { until: Option<DateTime<Utc>> },
+    /// Last response was this challenge and it was not cleared.
+    Challenged(ChallengeType),
+    /// The `__cf_bm` cookie expires at `bot_cookie_expires` or already has;
+    /// expect a challenge unless the domain is visited to refresh it.
+    NeedsRefresh { bot_cookie_expires: DateTime<Utc> },
+    /// Never requested, or the known clearance has expired.
+    #[default]
+    Unknown,
+}
+
 #[derive(Debug, Clone)]
 pub struct DomainErrorRecord {
     pub timestamp: DateTime<Utc>,
     pub code: Option<u16>,
     pub message: String,
+    pub challenge_type: Option<ChallengeType>,
+    pub proxy: Option<String>,
+    pub captcha_failure: bool,
+}
+
+impl From<FailureContext> for DomainErrorRecord {
+    fn from(context: FailureContext) -> Self {
+        Self {
+            timestamp: context.timestamp,
+            code: context.status,
+            message: context.reason,
+            challenge_type: context.challenge_type,
+            proxy: context.proxy,
+            captcha_failure: context.captcha_failure,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -268,6 +328,9 @@ pub struct DomainState {
     pub last_error: Option<String>,
     pub failure_streak: u32,
     pub success_streak: u32,
+    /// Outcomes recorded and challenges seen, for challenge frequency.
+    pub outcomes: u32,
+    pub challenges: u32,
     pub timing: TimingState,
     pub timing_pattern: TimingPatternState,
     pub burst: BurstState,
@@ -278,6 +341,12 @@ pub struct DomainState {
     pub cookies: HashMap<String, String>,
     pub sticky_headers: HashMap<String, String>,
     pub metadata: HashMap<String, Value>,
+    pub challenge: ChallengeStatus,
+    /// When the current `__cf_bm` cookie was issued and expires.
+    pub bot_cookie_issued: Option<DateTime<Utc>>,
+    pub bot_cookie_expires: Option<DateTime<Utc>>,
+    /// In-flight allowance learned by adaptive concurrency.
+    pub concurrency_allowance: Option<f64>,
 }
 
 impl Default for DomainState {
@@ -287,6 +356,8 @@ impl Default for DomainState {
             last_error: None,
             failure_streak: 0,
             success_streak: 0,
+            outcomes: 0,
+            challenges: 0,
             timing: TimingState::default(),
             timing_pattern: TimingPatternState::default(),
             burst: BurstState::default(),
@@ -297,6 +368,10 @@ impl Default for DomainState {
             cookies: HashMap::new(),
             sticky_headers: HashMap::new(),
             metadata: HashMap::new(),
+            challenge: ChallengeStatus::Unknown,
+            bot_cookie_issued: None,
+            bot_cookie_expires: None,
+            concurrency_allowance: None,
         }
     }
 }
@@ -310,25 +385,65 @@ impl DomainState {
         self.record_outcome(false, None, None, Some(error.into()));
     }
 
+    /// Like [`record_failure`](Self::record_failure), keeping the context in
+    /// `recent_errors`.
+    pub fn record_failure_detailed(&mut self, context: FailureContext) {
+        self.register_outcome(false, None, None, Some(context.into()));
+    }
+
     pub fn record_outcome(
         &mut self,
         success: bool,
         response_time: Option<Duration>,
         applied_delay: Option<Duration>,
         error: Option<String>,
+    ) {
+        let record = (!success).then(|| {
+            FailureContext::new(error.unwrap_or_else(|| "unknown error".to_string())).into()
+        });
+        self.register_outcome(success, response_time, applied_delay, record);
+    }
+
+    /// Like [`record_outcome`](Self::record_outcome), keeping a failure's
+    /// context in `recent_errors`; `None` records a success.
+    pub fn record_outcome_detailed(
+        &mut self,
+        response_time: Option<Duration>,
+        applied_delay: Option<Duration>,
+        failure: Option<FailureContext>,
+    ) {
+        let success = failure.is_none();
+        self.register_outcome(
+            success,
+            response_time,
+            applied_delay,
+            failure.map(Into::into),
+        );
+    }
+
+    fn register_outcome(
+        &mut self,
+        success: bool,
+        response_time: Option<Duration>,
+        applied_delay: Option<Duration>,
+        error: Option<DomainErrorRecord>,
     ) {
         let now = Utc::now();
+        self.outcomes = self.outcomes.saturating_add(1);
         if success {
             self.success_streak = self.success_streak.saturating_add(1);
             self.failure_streak = 0;
             self.last_success = Some(now);
             self.last_error = None;
             self.recent_errors.clear();
+            if self.success_streak >= UNDER_ATTACK_RECOVERY_STREAK && self.under_attack() {
+                self.set_under_attack(false);
+            }
         } else {
             self.failure_streak = self.failure_streak.saturating_add(1);
             self.success_streak = 0;
             if let Some(ref err) = error {
-                self.last_error = Some(err.clone());
+                self.last_error = Some(err.message.clone());
             }
         }
 
@@ -341,9 +456,8 @@ impl DomainState {
             }
         }
 
-        if !success {
-            let message = error.unwrap_or_else(|| "unknown error".to_string());
-            self.push_error(None, message);
+        if let Some(record) = error {
+            self.push_record(record);
         }
     }
 
@@ -358,13 +472,14 @@ impl DomainState {
     }
 
     pub fn push_error(&mut self, code: Option<u16>, message: impl Into<String>) {
-        let msg = message.into();
-        self.last_error = Some(msg.clone());
-        self.recent_errors.push_back(DomainErrorRecord {
-            timestamp: Utc::now(),
-            code,
-            message: msg,
-        });
+        let mut context = FailureContext::new(message);
+        context.status = code;
+        self.push_record(context.into());
+    }
+
+    fn push_record(&mut self, record: DomainErrorRecord) {
+        self.last_error = Some(record.message.clone());
+        self.recent_errors.push_back(record);
         if self.recent_errors.len() > ERROR_HISTORY_LIMIT {
             self.recent_errors.pop_front();
         }
@@ -382,6 +497,104 @@ impl DomainState {
         self.metadata.insert(key.into(), value);
     }
 
+    /// Challenge status as of `now`; an expired clearance reads as `Unknown`,
+    /// and a `__cf_bm` cookie within [`BOT_COOKIE_REFRESH_MARGIN`] of expiry
+    /// as `NeedsRefresh` unless the domain is already challenging.
+    pub fn challenge_status_at(&self, now: DateTime<Utc>) -> ChallengeStatus {
+        let status = self.clearance_status_at(now);
+        match (status, self.bot_cookie_expires) {
+            (ChallengeStatus::Challenged(_), _) | (_, None) => status,
+            (_, Some(expires)) if expires - chrono_duration(BOT_COOKIE_REFRESH_MARGIN) <= now => {
+                ChallengeStatus::NeedsRefresh {
+                    bot_cookie_expires: expires,
+                }
+            }
+            _ => status,
+        }
+    }
+
+    fn clearance_status_at(&self, now: DateTime<Utc>) -> ChallengeStatus {
+        match self.challenge {
+            ChallengeStatus::Clear { until: Some(until) } if until <= now => {
+                ChallengeStatus::Unknown
+            }
+            status => status,
+        }
+    }
+
+    /// When the current clearance expires, if known; an expired one is kept.
+    pub fn clearance_expiry(&self) -> Option<DateTime<Utc>> {
+        match self.challenge {
+            ChallengeStatus::Clear { until } => until,
+            _ => None,
+        }
+    }
+
+    /// Record a `__cf_bm` cookie issued at `issued` and valid until `expires`.
+    pub fn note_bot_cookie(&mut self, issued: DateTime<Utc>, expires: DateTime<Utc>) {
+        self.bot_cookie_issued = Some(issued);
+        self.bot_cookie_expires = Some(expires);
+    }
+
+    /// Record a clean response, keeping a still-valid clearance expiry unless
+    /// a newer one is provided.
+    pub fn mark_clear(&mut self, until: Option<DateTime<Utc>>) {
+        let until = until.or(match self.clearance_status_at(Utc::now()) {
+            ChallengeStatus::Clear { until } => until,
+            _ => None,
+        });
+        self.challenge = ChallengeStatus::Clear { until };
+    }
+
+    pub fn mark_challenged(&mut self, challenge_type: ChallengeType) {
+        self.challenge = ChallengeStatus::Challenged(challenge_type);
+        self.challenges = self.challenges.saturating_add(1);
+    }
+
+    /// Share of recorded outcomes that met a challenge.
+    pub fn challenge_rate(&self) -> f32 {
+        match self.outcomes.max(self.challenges) {
+            0 => 0.0,
+            total => self.challenges as f32 / total as f32,
+        }
+    }
+
+    /// Flag an Under Attack Mode escalation if a challenge arrives while the
+    /// clearance is still valid; returns whether it did.
+    ///
+    /// The escalation is kept under [`UNDER_ATTACK_METADATA_KEY`] with the time
+    /// it was last seen, how often it happened and whether it is still
+    /// active; [`UNDER_ATTACK_RECOVERY_STREAK`] clean responses lift it.
+    pub fn note_escalation(&mut self, now: DateTime<Utc>) -> bool {
+        let ChallengeStatus::Clear { until: Some(_) } = self.challenge_status_at(now) else {
+            return false;
+        };
+        let count = self
+            .metadata
+            .get(UNDER_ATTACK_METADATA_KEY)
+            .and_then(|entry| entry.get("count"))
+            .and_then(Value::as_u64)
+            .unwrap_or(0);
+        self.set_metadata(
+            UNDER_ATTACK_METADATA_KEY,
+            serde_json::json!({ "since": now.to_rfc3339(), "count": count + 1, "active": true }),
+        );
+        true
+    }
+
+    /// Whether this domain is escalated to Under Attack Mode.
+    pub fn under_attack(&self) -> bool {
+        self.metadata
+            .get(UNDER_ATTACK_METADATA_KEY)
+            .is_some_and(|entry| entry.get("active").and_then(Value::as_bool) != Some(false))
+    }
+
+    fn set_under_attack(&mut self, active: bool) {
+        if let Some(Value::Object(entry)) = self.metadata.get_mut(UNDER_ATTACK_METADATA_KEY) {
+            entry.insert("active".into(), Value::Bool(active));
+        }
+    }
+
     pub fn mark_request(&mut self) {
         let now = Utc::now();
         self.timing_pattern.mark_request(now);
@@ -398,19 +611,95 @@ impl DomainState {
     }
 }
 
+/// The part of a [`DomainState`] worth keeping across restarts; timing and
+/// session windows are rebuilt from live traffic.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct PersistedDomain {
+    cookies: HashMap<String, String>,
+    sticky_headers: HashMap<String, String>,
+    metadata: HashMap<String, Value>,
+    last_success: Option<DateTime<Utc>>,
+    failure_streak: u32,
+    success_streak: u32,
+    outcomes: u32,
+    challenges: u32,
+    clearance_until: Option<DateTime<Utc>>,
+    bot_cookie_issued: Option<DateTime<Utc>>,
+    bot_cookie_expires: Option<DateTime<Utc>>,
+    concurrency_allowance: Option<f64>,
+}
+
+impl PersistedDomain {
+    fn capture(state: &DomainState) -> Self {
+        Self {
+            cookies: state.cookies.clone(),
+            sticky_headers: state.sticky_headers.clone(),
+            metadata: state.metadata.clone(),
+            last_success: state.last_success,
+            failure_streak: state.failure_streak,
+            success_streak: state.success_streak,
+            outcomes: state.outcomes,
+            challenges: state.challenges,
+            clearance_until: state.clearance_expiry(),
+            bot_cookie_issued: state.bot_cookie_issued,
+            bot_cookie_expires: state.bot_cookie_expires,
+            concurrency_allowance: state.concurrency_allowance,
+        }
+    }
+
+    fn apply(self, state: &mut DomainState) {
+        state.cookies.extend(self.cookies);
+        state.sticky_headers.extend(self.sticky_headers);
+        state.metadata.extend(self.metadata);
+        state.last_success = self.last_success;
+        state.failure_streak = self.failure_streak;
+        state.success_streak = self.success_streak;
+        state.outcomes = self.outcomes;
+        state.challenges = self.challenges;
+        if let Some(until) = self.clearance_until {
+            state.challenge = ChallengeStatus::Clear { until: Some(until) };
+        }
+        state.bot_cookie_issued = self.bot_cookie_issued;
+        state.bot_cookie_expires = self.bot_cookie_expires;
+        state.concurrency_allowance = self.concurrency_allowance;
+    }
+}
+
+/// Cookies and sticky headers known for a domain before the first request.
+#[derive(Debug, Clone, Default)]
+pub struct DomainSeed {
+    pub cookies: HashMap<String, String>,
+    pub headers: HashMap<String, String>,
+}
+
 /// Thread-safe state manager.
 #[derive(Clone, Debug)]
 pub struct StateManager {
     inner: Arc<RwLock<HashMap<String, DomainState>>>,
+    learning_rate: LearningRate,
 }
 
 impl StateManager {
     pub fn new() -> Self {
         Self {
             inner: Arc::new(RwLock::new(HashMap::new())),
+            learning_rate: DEFAULT_STATE_LEARNING_RATE,
         }
     }
 
+    /// Schedule the timing averages of domains created from now on use.
+    pub fn with_learning_rate(mut self, learning_rate: LearningRate) -> Self {
+        self.learning_rate = learning_rate;
+        self
+    }
+
+    fn new_domain(&self) -> DomainState {
+        let mut state = DomainState::default();
+        state.timing.learning_rate = self.learning_rate;
+        state
+    }
+
     pub fn get(&self, domain: &str) -> Option<DomainState> {
         self.inner
             .read()
@@ -420,7 +709,10 @@ impl StateManager {
 
     pub fn get_or_create(&self, domain: &str) -> DomainState {
         let mut guard = self.inner.write().expect("state lock poisoned");
-        guard.entry(domain.to_string()).or_default().clone()
+        guard
+            .entry(domain.to_string())
+            .or_insert_with(|| self.new_domain())
+            .clone()
     }
 
     pub fn update<F>(&self, domain: &str, mut f: F)
@@ -428,11 +720,52 @@ impl StateManager {
         F: FnMut(&mut DomainState),
     {
         if let Ok(mut guard) = self.inner.write() {
-            let state = guard.entry(domain.to_string()).or_default();
+            let state = guard
+                .entry(domain.to_string())
+                .or_insert_with(|| self.new_domain());
             f(state);
         }
     }
 
+    /// Save every domain's durable state under [`STATE_STORAGE_KEY`].
+    pub fn save_to(&self, storage: &dyn StorageBackend) -> Result<(), StorageError> {
+        let snapshot: HashMap<String, PersistedDomain> = self
+            .inner
+            .read()
+            .expect("state lock poisoned")
+            .iter()
+            .map(|(domain, state)| (domain.clone(), PersistedDomain::capture(state)))
+            .collect();
+        store_json(storage, STATE_STORAGE_KEY, &snapshot)
+    }
+
+    /// Merge a snapshot written by [`save_to`](Self::save_to) into the
+    /// current state. Returns whether one was found.
+    pub fn load_from(&self, storage: &dyn StorageBackend) -> Result<bool, StorageError> {
+        let Some(snapshot) =
+            load_json::<HashMap<String, PersistedDomain>>(storage, STATE_STORAGE_KEY)?
+        else {
+            return Ok(false);
+        };
+        let mut guard = self.inner.write().expect("state lock poisoned");
+        for (domain, persisted) in snapshot {
+            persisted.apply(guard.entry(domain).or_insert_with(|| self.new_domain()));
+        }
+        Ok(true)
+    }
+
+    /// Merge `seed` into the state of `domain`, overriding existing keys.
+    pub fn seed(&self, domain: &str, seed: &DomainSeed) {
+        self.update(domain, |state| {
+            for (name, value) in &seed.cookies {
+                state.set_cookie(name.clone(), value.clone());
+            }
+            for (name, value) in &seed.headers {
+                state.set_header(name.clone(), value.clone());
+            }
+        });
+    }
+
     pub fn record_success(&self, domain: &str) {
         self.update(domain, |state| state.record_success());
     }
@@ -455,6 +788,45 @@ impl StateManager {
         });
     }
 
+    pub fn record_outcome_detailed(
+        &self,
+        domain: &str,
+        response_time: Option<Duration>,
+        applied_delay: Option<Duration>,
+        failure: Option<FailureContext>,
+    ) {
+        self.update(domain, |state| {
+            state.record_outcome_detailed(response_time, applied_delay, failure.clone());
+        });
+    }
+
+    /// Delay learned from successful requests to `domain`, if any.
+    pub fn optimal_delay(&self, domain: &str) -> Option<Duration> {
+        self.get(domain)
+            .and_then(|state| state.timing.optimal_delay)
+    }
+
+    /// Last known challenge status for `domain`.
+    pub fn challenge_status(&self, domain: &str) -> ChallengeStatus {
+        self.get(domain)
+            .map(|state| state.challenge_status_at(Utc::now()))
+            .unwrap_or_default()
+    }
+
+    /// See [`DomainState::clearance_expiry`].
+    pub fn clearance_expiry(&self, domain: &str) -> Option<DateTime<Utc>> {
+        self.get(domain).and_then(|state| state.clearance_expiry())
+    }
+
+    /// See [`DomainState::note_escalation`].
+    pub fn note_escalation(&self, domain: &str) -> bool {
+        let mut escalated = false;
+        self.update(domain, |state| {
+            escalated = state.note_escalation(Utc::now())
+        });
+        escalated
+    }
+
     pub fn mark_request(&self, domain: &str) {
         self.update(domain, |state| state.mark_request());
     }
@@ -487,11 +859,18 @@ impl FailureRecorder for StateManager {
     fn record_failure(&self, domain: &str, reason: &str) {
         StateManager::record_failure(self, domain, reason.to_string());
     }
+
+    fn record_failure_detailed(&self, domain: &str, context: FailureContext) {
+        self.update(domain, |state| {
+            state.record_failure_detailed(context.clone())
+        });
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::modules::storage::MemoryStorage;
 
     #[test]
     fn tracks_success_and_failure() {
@@ -504,4 +883,145 @@ mod tests {
         assert!(state.last_success.is_some());
         assert!(state.recent_errors.is_empty());
     }
+
+    #[test]
+    fn detailed_failures_keep_their_context() {
+        let manager = StateManager::new();
+        let recorder: &dyn FailureRecorder = &manager;
+        recorder.record_failure_detailed(
+            "example.com",
+            FailureContext::new("captcha_unsolved")
+                .with_status(403)
+                .with_challenge_type(ChallengeType::Turnstile)
+                .with_proxy("http://p1:8080")
+                .captcha_failure(),
+        );
+        manager.record_failure("example.com", "timeout");
+
+        let state = manager.get("example.com").unwrap();
+        assert_eq!(state.failure_streak, 2);
+        let detailed = &state.recent_errors[0];
+        assert_eq!(detailed.message, "captcha_unsolved");
+        assert_eq!(detailed.code, Some(403));
+        assert_eq!(detailed.challenge_type, Some(ChallengeType::Turnstile));
+        assert_eq!(detailed.proxy.as_deref(), Some("http://p1:8080"));
+        assert!(detailed.captcha_failure);
+        let plain = &state.recent_errors[1];
+        assert_eq!(plain.message, "timeout");
+        assert_eq!((plain.code, plain.challenge_type), (None, None));
+        assert_eq!(state.last_error.as_deref(), Some("timeout"));
+    }
+
+    #[test]
+    fn expired_clearance_reads_as_unknown() {
+        let manager = StateManager::new();
+        assert_eq!(
+            manager.challenge_status("example.com"),
+            ChallengeStatus::Unknown
+        );
+
+        let until = Utc::now() + chrono::Duration::minutes(30);
+        manager.update("example.com", |state| state.mark_clear(Some(until)));
+        manager.update("example.com", |state| state.mark_clear(None));
+        assert_eq!(
+            manager.challenge_status("example.com"),
+            ChallengeStatus::Clear { until: Some(until) }
+        );
+
+        let state = manager.get("example.com").unwrap();
+        let later = until + chrono::Duration::seconds(1);
+        assert_eq!(state.challenge_status_at(later), ChallengeStatus::Unknown);
+    }
+
+    #[test]
+    fn challenge_inside_clearance_window_flags_under_attack() {
+        let manager = StateManager::new();
+        assert!(!manager.note_escalation("example.com"));
+
+        manager.update("example.com", |state| state.mark_clear(None));
+        assert!(!manager.note_escalation("example.com"));
+
+        let until = Utc::now() + chrono::Duration::minutes(30);
+        manager.update("example.com", |state| state.mark_clear(Some(until)));
+        assert!(manager.note_escalation("example.com"));
+        assert!(manager.note_escalation("example.com"));
+
+        let state = manager.get("example.com").unwrap();
+        assert!(state.under_attack());
+        assert_eq!(state.metadata[UNDER_ATTACK_METADATA_KEY]["count"], 2);
+    }
+
+    #[test]
+    fn clean_streak_lifts_under_attack() {
+        let manager = StateManager::new();
+        manager.update("example.com", |state| {
+            state.mark_clear(Some(Utc::now() + chrono::Duration::hours(1)))
+        });
+        assert!(manager.note_escalation("example.com"));
+
+        for _ in 1..UNDER_ATTACK_RECOVERY_STREAK {
+            manager.record_success("example.com");
+        }
+        assert!(manager.get("example.com").unwrap().under_attack());
+        manager.record_success("example.com");
+        let state = manager.get("example.com").unwrap();
+        assert!(!state.under_attack());
+        assert_eq!(state.metadata[UNDER_ATTACK_METADATA_KEY]["count"], 1);
+    }
+
+    #[test]
+    fn aged_bot_cookie_needs_refresh() {
+        let now = Utc::now();
+        let mut state = DomainState::default();
+        state.mark_clear(Some(now + chrono::Duration::hours(1)));
+        state.note_bot_cookie(
+            now - chrono::Duration::minutes(10),
+            now + chrono::Duration::minutes(20),
+        );
+        assert!(matches!(
+            state.challenge_status_at(now),
+            ChallengeStatus::Clear { .. }
+        ));
+
+        let aged = now + chrono::Duration::minutes(19);
+        assert_eq!(
+            state.challenge_status_at(aged),
+            ChallengeStatus::NeedsRefresh {
+                bot_cookie_expires: now + chrono::Duration::minutes(20)
+            }
+        );
+
+        state.mark_challenged(ChallengeType::Turnstile);
+        assert_eq!(
+            state.challenge_status_at(aged),
+            ChallengeStatus::Challenged(ChallengeType::Turnstile)
+        );
+    }
+
+    #[test]
+    fn state_round_trips_through_memory_storage() {
+        let storage = MemoryStorage::new();
+        let until = Utc::now() + chrono::Duration::minutes(30);
+        let manager = StateManager::new();
+        manager.update("example.com", |state| {
+            state.set_cookie("cf_clearance", "granted");
+            state.set_header("x-token", "abc");
+            state.mark_clear(Some(until));
+            state.record_failure("blocked");
+        });
+        manager.save_to(&storage).unwrap();
+
+        let restored = StateManager::new();
+        assert!(!restored.load_from(&MemoryStorage::new()).unwrap());
+        assert!(restored.load_from(&storage).unwrap());
+        let state = restored.get("example.com").unwrap();
+        assert_eq!(state.cookies["cf_clearance"], "granted");
+        assert_eq!(state.sticky_headers["x-token"], "abc");
+        assert_eq!(state.failure_streak, 1);
+        assert_eq!(restored.clearance_expiry("example.com"), Some(until));
+        assert_eq!(
+            restored.challenge_status("example.com"),
+            ChallengeStatus::Clear { until: Some(until) }
+        );
+    }
 }
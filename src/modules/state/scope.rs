@@ -0,0 +1,69 @@
+//! How hosts are grouped into the domains per-domain state is keyed by.
+
+use std::borrow::Cow;
+use std::net::IpAddr;
+
+use once_cell::sync::Lazy;
+use publicsuffix::{List, Psl};
+
+/// Snapshot of <https://publicsuffix.org/list/>, ICANN and private sections.
+///
+/// `public_suffix_list.dat` is the unmodified upstream file, distributed
+/// under the Mozilla Public License 2.0 as stated in its header. Refresh it
+/// with `curl -o src/modules/state/public_suffix_list.dat
+/// https://publicsuffix.org/list/public_suffix_list.dat`.
+static PUBLIC_SUFFIXES: Lazy<List> = Lazy::new(|| {
+    include_str!("public_suffix_list.dat")
+        .parse()
+        .expect("bundled public suffix list is valid")
+});
+
+/// Granularity of state, fingerprints, proxies, pacing and clearance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StateScope {
+    /// Every host is tracked on its own.
+    #[default]
+    Host,
+    /// Hosts under the same registrable domain (eTLD+1) share one entry, so
+    /// `a.example.com` reuses what `b.example.com` learned.
+    RegistrableDomain,
+}
+
+impl StateScope {
+    /// Key `host` is tracked under. IP addresses and hosts without a
+    /// registrable domain, such as `localhost`, are kept as they are.
+    pub fn key<'a>(&self, host: &'a str) -> Cow<'a, str> {
+        match self {
+            Self::Host => Cow::Borrowed(host),
+            Self::RegistrableDomain => registrable_domain(host)
+                .map(Cow::Borrowed)
+                .unwrap_or(Cow::Borrowed(host)),
+        }
+    }
+}
+
+fn registrable_domain(host: &str) -> Option<&str> {
+    if host.starts_with('[') || host.parse::<IpAddr>().is_ok() {
+        return None;
+    }
+    let host = host.trim_end_matches('.');
+    let domain = PUBLIC_SUFFIXES.domain(host.as_bytes())?;
+    let len = domain.as_bytes().len();
+    host.get(host.len() - len..)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registrable_domain_groups_sibling_hosts() {
+        let scope = StateScope::RegistrableDomain;
+        assert_eq!(scope.key("a.example.com"), "example.com");
+        assert_eq!(scope.key("b.shop.example.co.uk"), "example.co.uk");
+        assert_eq!(scope.key("alice.github.io"), "alice.github.io");
+        assert_eq!(scope.key("127.0.0.1"), "127.0.0.1");
+        assert_eq!(scope.key("localhost"), "localhost");
+        assert_eq!(StateScope::Host.key("a.example.com"), "a.example.com");
+    }
+}
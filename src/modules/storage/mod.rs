@@ -0,0 +1,170 @@
+//! Pluggable persistence for learned scraper state.
+//!
+//! [`StateManager`](crate::modules::state::StateManager),
+//! [`ChallengeDetector`](crate::challenges::detectors::ChallengeDetector),
+//! [`MLOptimizer`](crate::modules::ml::MLOptimizer) and
+//! [`ProxyManager`](crate::modules::proxy::ProxyManager) snapshot themselves
+//! as JSON under a fixed key of a [`StorageBackend`]. Implement the trait to
+//! keep those snapshots in Redis, S3 or anywhere else.
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use thiserror::Error;
+
+/// Errors raised while saving or restoring a snapshot.
+#[derive(Debug, Error)]
+pub enum StorageError {
+    #[error("storage io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("malformed snapshot: {0}")]
+    Format(#[from] serde_json::Error),
+    #[error("invalid snapshot: {0}")]
+    Invalid(String),
+    #[error("storage backend error: {0}")]
+    Backend(String),
+}
+
+/// Byte store keyed by snapshot name.
+pub trait StorageBackend: Debug + Send + Sync {
+    /// Bytes last stored under `key`, or `None` if nothing was.
+    fn load(&self, key: &str) -> Result<Option<Vec<u8>>, StorageError>;
+
+    /// Replace whatever is stored under `key`.
+    fn store(&self, key: &str, bytes: &[u8]) -> Result<(), StorageError>;
+}
+
+/// Serialize `value` as JSON under `key`.
+pub fn store_json<T: Serialize>(
+    storage: &dyn StorageBackend,
+    key: &str,
+    value: &T,
+) -> Result<(), StorageError> {
+    storage.store(key, &serde_json::to_vec(value)?)
+}
+
+/// Deserialize the JSON stored under `key`, if any.
+pub fn load_json<T: DeserializeOwned>(
+    storage: &dyn StorageBackend,
+    key: &str,
+) -> Result<Option<T>, StorageError> {
+    match storage.load(key)? {
+        Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+        None => Ok(None),
+    }
+}
+
+/// Snapshots kept in process memory, e.g. for tests or to hand state to a
+/// new scraper.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    entries: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self
+            .entries
+            .lock()
+            .expect("storage lock poisoned")
+            .keys()
+            .cloned()
+            .collect();
+        keys.sort();
+        keys
+    }
+}
+
+impl StorageBackend for MemoryStorage {
+    fn load(&self, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        Ok(self
+            .entries
+            .lock()
+            .expect("storage lock poisoned")
+            .get(key)
+            .cloned())
+    }
+
+    fn store(&self, key: &str, bytes: &[u8]) -> Result<(), StorageError> {
+        self.entries
+            .lock()
+            .expect("storage lock poisoned")
+            .insert(key.to_string(), bytes.to_vec());
+        Ok(())
+    }
+}
+
+/// One `<key>.json` file per snapshot inside a directory.
+#[derive(Debug, Clone)]
+pub struct FileStorage {
+    dir: PathBuf,
+}
+
+impl FileStorage {
+    /// The directory is created on the first store.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Characters outside `[A-Za-z0-9._-]` are replaced so keys cannot
+    /// escape the directory.
+    fn path(&self, key: &str) -> PathBuf {
+        let name: String = key
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-') {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        self.dir
+            .join(format!("{}.json", name.trim_start_matches('.')))
+    }
+}
+
+impl StorageBackend for FileStorage {
+    fn load(&self, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        match std::fs::read(self.path(key)) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Written to a temporary file first, so readers never see half a snapshot.
+    fn store(&self, key: &str, bytes: &[u8]) -> Result<(), StorageError> {
+        std::fs::create_dir_all(&self.dir)?;
+        let path = self.path(key);
+        let partial = path.with_extension("json.partial");
+        std::fs::write(&partial, bytes)?;
+        std::fs::rename(&partial, &path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_storage_keeps_keys_inside_its_directory() {
+        let dir = std::env::temp_dir().join(format!("cloudscraper-storage-{}", std::process::id()));
+        let storage = FileStorage::new(&dir);
+        assert!(storage.load("state").unwrap().is_none());
+
+        storage.store("../state", b"{}").unwrap();
+        assert_eq!(storage.path("../state"), dir.join("_state.json"));
+        assert_eq!(storage.load("../state").unwrap().unwrap(), b"{}");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
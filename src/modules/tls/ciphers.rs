@@ -0,0 +1,193 @@
+//! Cipher-suite name mapping.
+//!
+//! Profiles list suites either by IANA name (`TLS_AES_128_GCM_SHA256`) or by
+//! OpenSSL name (`ECDHE-RSA-AES128-GCM-SHA256`). This resolves both to IANA
+//! code points and, with the `rustls` feature, to rustls suites.
+
+/// (IANA name, OpenSSL name, IANA code point)
+const CIPHER_SUITES: &[(&str, &str, u16)] = &[
+    ("TLS_AES_128_GCM_SHA256", "TLS_AES_128_GCM_SHA256", 0x1301),
+    ("TLS_AES_256_GCM_SHA384", "TLS_AES_256_GCM_SHA384", 0x1302),
+    (
+        "TLS_CHACHA20_POLY1305_SHA256",
+        "TLS_CHACHA20_POLY1305_SHA256",
+        0x1303,
+    ),
+    (
+        "TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256",
+        "ECDHE-ECDSA-AES128-GCM-SHA256",
+        0xc02b,
+    ),
+    (
+        "TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256",
+        "ECDHE-RSA-AES128-GCM-SHA256",
+        0xc02f,
+    ),
+    (
+        "TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384",
+        "ECDHE-ECDSA-AES256-GCM-SHA384",
+        0xc02c,
+    ),
+    (
+        "TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384",
+        "ECDHE-RSA-AES256-GCM-SHA384",
+        0xc030,
+    ),
+    (
+        "TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256",
+        "ECDHE-ECDSA-CHACHA20-POLY1305",
+        0xcca9,
+    ),
+    (
+        "TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256",
+        "ECDHE-RSA-CHACHA20-POLY1305",
+        0xcca8,
+    ),
+    (
+        "TLS_ECDHE_ECDSA_WITH_AES_128_CBC_SHA",
+        "ECDHE-ECDSA-AES128-SHA",
+        0xc009,
+    ),
+    (
+        "TLS_ECDHE_ECDSA_WITH_AES_256_CBC_SHA",
+        "ECDHE-ECDSA-AES256-SHA",
+        0xc00a,
+    ),
+    (
+        "TLS_ECDHE_RSA_WITH_AES_128_CBC_SHA",
+        "ECDHE-RSA-AES128-SHA",
+        0xc013,
+    ),
+    (
+        "TLS_ECDHE_RSA_WITH_AES_256_CBC_SHA",
+        "ECDHE-RSA-AES256-SHA",
+        0xc014,
+    ),
+    (
+        "TLS_RSA_WITH_AES_128_GCM_SHA256",
+        "AES128-GCM-SHA256",
+        0x009c,
+    ),
+    (
+        "TLS_RSA_WITH_AES_256_GCM_SHA384",
+        "AES256-GCM-SHA384",
+        0x009d,
+    ),
+    ("TLS_RSA_WITH_AES_128_CBC_SHA", "AES128-SHA", 0x002f),
+    ("TLS_RSA_WITH_AES_256_CBC_SHA", "AES256-SHA", 0x0035),
+];
+
+/// IANA code point for a cipher suite given its IANA or OpenSSL name.
+pub fn cipher_suite_code(name: &str) -> Option<u16> {
+    let name = name.trim();
+    CIPHER_SUITES
+        .iter()
+        .find(|(iana, openssl, _)| iana.eq_ignore_ascii_case(name) || *openssl == name)
+        .map(|(_, _, code)| *code)
+}
+
+#[cfg(feature = "rustls")]
+mod rustls_support {
+    use std::sync::Arc;
+
+    use rustls::crypto::ring;
+    use rustls::{ClientConfig, RootCertStore, SupportedCipherSuite};
+
+    use super::cipher_suite_code;
+    use crate::modules::tls::TlsVersion;
+
+    /// Resolve profile cipher names to rustls suites, preserving order.
+    ///
+    /// Names rustls cannot express (CBC suites, GREASE placeholders, unknown
+    /// names) are logged and skipped.
+    pub fn rustls_cipher_suites(names: &[String]) -> Vec<SupportedCipherSuite> {
+        let available = ring::default_provider().cipher_suites;
+        let mut suites: Vec<SupportedCipherSuite> = Vec::new();
+        for name in names {
+            let Some(code) = cipher_suite_code(name) else {
+                if !name.starts_with("GREASE_") {
+                    log::debug!("skipping unknown cipher suite {name}");
+                }
+                continue;
+            };
+            match available
+                .iter()
+                .find(|suite| u16::from(suite.suite()) == code)
+            {
+                Some(suite) if !suites.contains(suite) => suites.push(*suite),
+                Some(_) => {}
+                None => log::debug!("skipping cipher suite {name}: not supported by rustls"),
+            }
+        }
+        suites
+    }
+
+    /// rustls client configuration offering `cipher_suites` in order.
+    ///
+    /// Falls back to the rustls defaults when none of the names map.
+    pub fn rustls_client_config(
+        cipher_suites: &[String],
+        min_version: TlsVersion,
+    ) -> Result<ClientConfig, rustls::Error> {
+        let mut provider = ring::default_provider();
+        let suites = rustls_cipher_suites(cipher_suites);
+        if !suites.is_empty() {
+            provider.cipher_suites = suites;
+        }
+        let versions: &[&rustls::SupportedProtocolVersion] = match min_version {
+            TlsVersion::Tls12 => &[&rustls::version::TLS13, &rustls::version::TLS12],
+            TlsVersion::Tls13 => &[&rustls::version::TLS13],
+        };
+        let roots = RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        };
+        Ok(ClientConfig::builder_with_provider(Arc::new(provider))
+            .with_protocol_versions(versions)?
+            .with_root_certificates(roots)
+            .with_no_client_auth())
+    }
+}
+
+#[cfg(feature = "rustls")]
+pub use rustls_support::{rustls_cipher_suites, rustls_client_config};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_iana_and_openssl_names() {
+        assert_eq!(cipher_suite_code("TLS_AES_128_GCM_SHA256"), Some(0x1301));
+        assert_eq!(
+            cipher_suite_code("ECDHE-RSA-AES128-GCM-SHA256"),
+            cipher_suite_code("TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256")
+        );
+        assert_eq!(
+            cipher_suite_code("ECDHE-ECDSA-CHACHA20-POLY1305"),
+            Some(0xcca9)
+        );
+        assert_eq!(cipher_suite_code("RC4-MD5"), None);
+    }
+
+    #[cfg(feature = "rustls")]
+    #[test]
+    fn resolves_rustls_suites_and_skips_unsupported() {
+        let names: Vec<String> = [
+            "GREASE_0x0A0A",
+            "TLS_AES_256_GCM_SHA384",
+            "ECDHE-RSA-AES128-GCM-SHA256",
+            "ECDHE-RSA-AES128-SHA",
+            "RC4-MD5",
+        ]
+        .iter()
+        .map(|name| name.to_string())
+        .collect();
+
+        let codes: Vec<u16> = rustls_cipher_suites(&names)
+            .iter()
+            .map(|suite| u16::from(suite.suite()))
+            .collect();
+        assert_eq!(codes, vec![0x1302, 0xc02f]);
+        assert!(rustls_client_config(&names, crate::modules::tls::TlsVersion::Tls12).is_ok());
+    }
+}
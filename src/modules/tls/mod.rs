@@ -3,14 +3,64 @@
 //! Supplies browser TLS profiles plus per-domain rotation to vary JA3
 //! fingerprints and cipher suites.
 
-use rand::Rng;
+use rand::rngs::StdRng;
 use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
 use std::collections::HashMap;
+use thiserror::Error;
 
-use super::spoofing::BrowserType;
+use super::spoofing::{BrowserType, FieldDiff};
+
+mod ciphers;
+
+pub use ciphers::cipher_suite_code;
+#[cfg(feature = "rustls")]
+pub use ciphers::{rustls_cipher_suites, rustls_client_config};
 
 use crate::challenges::solvers::TlsProfileManager;
 
+/// Lowest protocol version a profile is allowed to negotiate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TlsVersion {
+    Tls12,
+    Tls13,
+}
+
+/// TLS stack used to build the HTTP clients.
+///
+/// * `NativeTls` — the platform library. Always available, but it picks its
+///   own cipher order and ClientHello shape, so profiles only set the
+///   protocol floor.
+/// * `Rustls` (`rustls` feature) — offers the profile's cipher suites in
+///   order; the ClientHello extension layout is still rustls' own.
+/// * `Boring` (`tls-boring` feature) — BoringSSL, able to mirror Chrome's
+///   cipher and extension ordering. Plumbing only for now; building a client
+///   with it returns an error.
+///
+/// Every variant exists regardless of features; building a client with one
+/// whose feature is off fails with [`CloudScraperError::Tls`](crate::CloudScraperError::Tls).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TlsBackend {
+    NativeTls,
+    Rustls,
+    Boring,
+}
+
+impl Default for TlsBackend {
+    /// The most fingerprint-capable backend compiled in, short of the
+    /// unfinished BoringSSL one.
+    fn default() -> Self {
+        #[cfg(feature = "rustls")]
+        {
+            TlsBackend::Rustls
+        }
+        #[cfg(not(feature = "rustls"))]
+        {
+            TlsBackend::NativeTls
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct BrowserProfile {
     pub browser: BrowserType,
@@ -18,6 +68,170 @@ pub struct BrowserProfile {
     pub cipher_suites: Vec<String>,
     pub alpn_protocols: Vec<String>,
     pub tls_extensions: Vec<u16>,
+    pub min_tls_version: TlsVersion,
+    /// Named groups (curves) in IANA order, e.g. `29` for x25519.
+    pub supported_groups: Vec<u16>,
+}
+
+/// Reserved GREASE values (RFC 8701).
+const GREASE_VALUES: [u16; 16] = [
+    0x0a0a, 0x1a1a, 0x2a2a, 0x3a3a, 0x4a4a, 0x5a5a, 0x6a6a, 0x7a7a, 0x8a8a, 0x9a9a, 0xaaaa, 0xbaba,
+    0xcaca, 0xdada, 0xeaea, 0xfafa,
+];
+
+/// Whether a cipher/extension/group identifier is a GREASE placeholder.
+pub fn is_grease(value: u16) -> bool {
+    value & 0x0f0f == 0x0a0a && value >> 8 == value & 0xff
+}
+
+fn strip_grease(values: &[u16]) -> Vec<u16> {
+    values.iter().copied().filter(|v| !is_grease(*v)).collect()
+}
+
+/// Inconsistencies detected while validating a TLS profile.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum TlsProfileError {
+    #[error("malformed ja3 string: {0}")]
+    MalformedJa3(String),
+    #[error("ja3 curves {ja3:?} do not match supported groups {declared:?}")]
+    CurveMismatch { ja3: Vec<u16>, declared: Vec<u16> },
+}
+
+impl BrowserProfile {
+    /// Named groups declared in the JA3 curve field.
+    pub fn ja3_curves(&self) -> Result<Vec<u16>, TlsProfileError> {
+        let field = self
+            .ja3
+            .split(',')
+            .nth(3)
+            .ok_or_else(|| TlsProfileError::MalformedJa3(self.ja3.clone()))?;
+        if field.is_empty() {
+            return Ok(Vec::new());
+        }
+        field
+            .split('-')
+            .map(|value| {
+                value
+                    .parse::<u16>()
+                    .map_err(|_| TlsProfileError::MalformedJa3(self.ja3.clone()))
+            })
+            .collect()
+    }
+
+    /// Ensure the declared supported groups agree with the JA3 curve list.
+    ///
+    /// GREASE values are ignored on both sides.
+    pub fn validate(&self) -> Result<(), TlsProfileError> {
+        let ja3 = strip_grease(&self.ja3_curves()?);
+        let declared = strip_grease(&self.supported_groups);
+        if ja3 != declared {
+            return Err(TlsProfileError::CurveMismatch { ja3, declared });
+        }
+        Ok(())
+    }
+
+    /// Fields that differ from `other`; lists are compared in order, since
+    /// order is part of the fingerprint.
+    pub fn diff(&self, other: &BrowserProfile) -> Vec<FieldDiff> {
+        let join = |values: &[u16]| {
+            values
+                .iter()
+                .map(u16::to_string)
+                .collect::<Vec<_>>()
+                .join("-")
+        };
+        let mut diffs = Vec::new();
+        FieldDiff::compare(
+            &mut diffs,
+            "browser",
+            format!("{:?}", self.browser),
+            format!("{:?}", other.browser),
+        );
+        FieldDiff::compare(&mut diffs, "ja3_hash", self.ja3_hash(), other.ja3_hash());
+        FieldDiff::compare(
+            &mut diffs,
+            "cipher_suites",
+            self.cipher_suites.join(","),
+            other.cipher_suites.join(","),
+        );
+        FieldDiff::compare(
+            &mut diffs,
+            "alpn_protocols",
+            self.alpn_protocols.join(","),
+            other.alpn_protocols.join(","),
+        );
+        FieldDiff::compare(
+            &mut diffs,
+            "tls_extensions",
+            join(&self.tls_extensions),
+            join(&other.tls_extensions),
+        );
+        FieldDiff::compare(
+            &mut diffs,
+            "min_tls_version",
+            format!("{:?}", self.min_tls_version),
+            format!("{:?}", other.min_tls_version),
+        );
+        FieldDiff::compare(
+            &mut diffs,
+            "supported_groups",
+            join(&self.supported_groups),
+            join(&other.supported_groups),
+        );
+        diffs
+    }
+
+    /// JA3 fingerprint hash (MD5), computed with GREASE values removed as the
+    /// JA3 specification requires.
+    pub fn ja3_hash(&self) -> String {
+        let normalized = self
+            .ja3
+            .split(',')
+            .map(|field| {
+                field
+                    .split('-')
+                    .filter(|value| !value.parse::<u16>().is_ok_and(is_grease))
+                    .collect::<Vec<_>>()
+                    .join("-")
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        format!("{:x}", md5::compute(normalized.as_bytes()))
+    }
+
+    /// Copy of the profile with random GREASE placeholders inserted into the
+    /// cipher, extension and group lists (and the raw JA3 string).
+    fn with_grease(&self, rng: &mut impl Rng) -> Self {
+        let mut pick = || GREASE_VALUES[rng.gen_range(0..GREASE_VALUES.len())];
+        let (cipher, ext_first, ext_last, group) = (pick(), pick(), pick(), pick());
+
+        let mut profile = self.clone();
+        profile
+            .cipher_suites
+            .insert(0, format!("GREASE_0x{cipher:04X}"));
+        profile.tls_extensions.insert(0, ext_first);
+        profile.tls_extensions.push(ext_last);
+        profile.supported_groups.insert(0, group);
+
+        let mut fields: Vec<String> = self.ja3.split(',').map(str::to_string).collect();
+        let mut prepend = |index: usize, value: u16| {
+            if let Some(field) = fields.get_mut(index) {
+                *field = if field.is_empty() {
+                    value.to_string()
+                } else {
+                    format!("{value}-{field}")
+                };
+            }
+        };
+        prepend(1, cipher);
+        prepend(2, ext_first);
+        prepend(3, group);
+        if let Some(field) = fields.get_mut(2) {
+            field.push_str(&format!("-{ext_last}"));
+        }
+        profile.ja3 = fields.join(",");
+        profile
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -26,6 +240,11 @@ pub struct TLSConfig {
     pub rotate_ciphers: bool,
     pub preferred_browser: BrowserType,
     pub rotation_interval: usize,
+    /// Insert randomized GREASE values into emitted profiles, as Chrome does.
+    ///
+    /// This only changes the advertised fingerprint when the transport
+    /// actually applies the profile to its ClientHello.
+    pub grease: bool,
 }
 
 impl Default for TLSConfig {
@@ -35,6 +254,7 @@ impl Default for TLSConfig {
             rotate_ciphers: true,
             preferred_browser: BrowserType::Chrome,
             rotation_interval: 5,
+            grease: false,
         }
     }
 }
@@ -60,14 +280,14 @@ pub struct DefaultTLSManager {
     config: TLSConfig,
     profiles: Vec<BrowserProfile>,
     per_domain: HashMap<String, DomainTLSState>,
-    rng: rand::rngs::ThreadRng,
+    rng: StdRng,
 }
 
 impl DefaultTLSManager {
     pub fn new(config: TLSConfig) -> Self {
         let mut manager = Self {
             profiles: build_default_profiles(),
-            rng: rand::thread_rng(),
+            rng: StdRng::from_entropy(),
             per_domain: HashMap::new(),
             config,
         };
@@ -76,6 +296,12 @@ impl DefaultTLSManager {
         manager
     }
 
+    /// Drive profile assignment, rotation and GREASE values from `rng`.
+    pub fn with_rng(mut self, rng: StdRng) -> Self {
+        self.rng = rng;
+        self
+    }
+
     fn promote_preferred_profile(&mut self) {
         if let Some(pos) = self
             .profiles
@@ -93,6 +319,22 @@ impl DefaultTLSManager {
             .or_insert_with(|| DomainTLSState::new(idx))
     }
 
+    /// Profile assigned to `domain`, without counting a request towards
+    /// rotation; `None` before the domain's first request.
+    pub fn assigned_profile(&self, domain: &str) -> Option<&BrowserProfile> {
+        self.per_domain
+            .get(domain)
+            .and_then(|state| self.profiles.get(state.profile_index))
+    }
+
+    /// Profile assigned to `domain`, assigning one on first use; unlike
+    /// [`current_profile`](Self::current_profile) it never counts a request
+    /// towards rotation.
+    pub fn assign_profile(&mut self, domain: &str) -> &BrowserProfile {
+        let index = self.domain_state_mut(domain).profile_index;
+        &self.profiles[index]
+    }
+
     pub fn current_profile(&mut self, domain: &str) -> BrowserProfile {
         let should_rotate = {
             let state = self.domain_state_mut(domain);
@@ -105,7 +347,11 @@ impl DefaultTLSManager {
         }
 
         let index = self.domain_state_mut(domain).profile_index;
-        self.profiles[index].clone()
+        if self.config.grease {
+            self.profiles[index].with_grease(&mut self.rng)
+        } else {
+            self.profiles[index].clone()
+        }
     }
 
     pub fn rotate_profile(&mut self, domain: &str) {
@@ -128,8 +374,45 @@ impl DefaultTLSManager {
         }
     }
 
-    pub fn add_custom_profile(&mut self, profile: BrowserProfile) {
+    /// Switch the preferred browser and forget per-domain profile assignments.
+    pub fn reset_for_browser(&mut self, browser: BrowserType) {
+        self.config.preferred_browser = browser;
+        self.per_domain.clear();
+        self.promote_preferred_profile();
+    }
+
+    /// Profile for the preferred browser, used when building transport clients.
+    pub fn preferred_profile(&self) -> &BrowserProfile {
+        &self.profiles[0]
+    }
+
+    /// Register an additional profile after checking it is internally consistent.
+    pub fn add_custom_profile(&mut self, profile: BrowserProfile) -> Result<(), TlsProfileError> {
+        profile.validate()?;
         self.profiles.push(profile);
+        Ok(())
+    }
+
+    /// Check that `browser`'s profile still hashes to `expected_ja3_hash`, to
+    /// catch accidental fingerprint changes.
+    pub fn assert_profile(
+        &self,
+        browser: BrowserType,
+        expected_ja3_hash: &str,
+    ) -> Result<(), String> {
+        let profile = self
+            .profiles
+            .iter()
+            .find(|profile| profile.browser == browser)
+            .ok_or_else(|| format!("no TLS profile for {browser:?}"))?;
+        let actual = profile.ja3_hash();
+        if actual.eq_ignore_ascii_case(expected_ja3_hash) {
+            Ok(())
+        } else {
+            Err(format!(
+                "{browser:?} ja3 hash is {actual}, expected {expected_ja3_hash}"
+            ))
+        }
     }
 }
 
@@ -145,6 +428,13 @@ impl TlsProfileManager for DefaultTLSManager {
     }
 }
 
+/// Built-in profile for `browser`, whether or not a manager is in use.
+pub fn default_profile(browser: BrowserType) -> Option<BrowserProfile> {
+    build_default_profiles()
+        .into_iter()
+        .find(|profile| profile.browser == browser)
+}
+
 fn build_default_profiles() -> Vec<BrowserProfile> {
     vec![
         BrowserProfile {
@@ -157,6 +447,8 @@ fn build_default_profiles() -> Vec<BrowserProfile> {
             ],
             alpn_protocols: vec!["h2".into(), "http/1.1".into()],
             tls_extensions: vec![0, 11, 10, 35, 13, 45, 16, 43],
+            min_tls_version: TlsVersion::Tls12,
+            supported_groups: vec![29, 23, 24],
         },
         BrowserProfile {
             browser: BrowserType::Firefox,
@@ -168,6 +460,8 @@ fn build_default_profiles() -> Vec<BrowserProfile> {
             ],
             alpn_protocols: vec!["h2".into(), "http/1.1".into()],
             tls_extensions: vec![0, 11, 10, 35, 13, 27],
+            min_tls_version: TlsVersion::Tls12,
+            supported_groups: vec![23, 24],
         },
         BrowserProfile {
             browser: BrowserType::Safari,
@@ -179,6 +473,8 @@ fn build_default_profiles() -> Vec<BrowserProfile> {
             ],
             alpn_protocols: vec!["h2".into(), "http/1.1".into()],
             tls_extensions: vec![0, 11, 10, 35, 13, 16],
+            min_tls_version: TlsVersion::Tls12,
+            supported_groups: vec![29, 23, 24],
         },
         BrowserProfile {
             browser: BrowserType::MobileChrome,
@@ -189,6 +485,8 @@ fn build_default_profiles() -> Vec<BrowserProfile> {
             ],
             alpn_protocols: vec!["h2".into(), "http/1.1".into()],
             tls_extensions: vec![0, 11, 10, 35, 13, 45],
+            min_tls_version: TlsVersion::Tls12,
+            supported_groups: vec![29, 23, 24],
         },
         BrowserProfile {
             browser: BrowserType::MobileSafari,
@@ -199,6 +497,8 @@ fn build_default_profiles() -> Vec<BrowserProfile> {
             ],
             alpn_protocols: vec!["h2".into(), "http/1.1".into()],
             tls_extensions: vec![0, 11, 10, 35, 16],
+            min_tls_version: TlsVersion::Tls12,
+            supported_groups: vec![29, 23, 24],
         },
     ]
 }
@@ -215,4 +515,71 @@ mod tests {
         let profile2 = manager.current_profile("example.com");
         assert!(profile1.ja3 != profile2.ja3 || profile1.browser != profile2.browser);
     }
+
+    #[test]
+    fn default_profiles_match_their_ja3_curves() {
+        for profile in build_default_profiles() {
+            assert_eq!(profile.validate(), Ok(()), "{:?}", profile.browser);
+        }
+    }
+
+    #[test]
+    fn grease_is_advertised_but_excluded_from_ja3_hash() {
+        let base = build_default_profiles().remove(0);
+        let greased = base.with_grease(&mut rand::thread_rng());
+
+        assert!(is_grease(greased.tls_extensions[0]));
+        assert!(is_grease(*greased.tls_extensions.last().unwrap()));
+        assert!(is_grease(greased.supported_groups[0]));
+        assert!(greased.cipher_suites[0].starts_with("GREASE_"));
+        assert_ne!(greased.ja3, base.ja3);
+
+        assert_eq!(greased.ja3_hash(), base.ja3_hash());
+        assert_eq!(greased.validate(), Ok(()));
+    }
+
+    #[test]
+    fn rejects_profile_with_mismatched_curves() {
+        let mut profile = build_default_profiles().remove(0);
+        profile.supported_groups = vec![23, 24];
+
+        let mut manager = DefaultTLSManager::default();
+        assert_eq!(
+            manager.add_custom_profile(profile),
+            Err(TlsProfileError::CurveMismatch {
+                ja3: vec![29, 23, 24],
+                declared: vec![23, 24],
+            })
+        );
+    }
+
+    /// Pinned fingerprints of the built-in profiles. Update these only when a
+    /// profile is changed on purpose.
+    const PINNED_JA3_HASHES: [(BrowserType, &str); 5] = [
+        (BrowserType::Chrome, "4e04c99ad14bae5115f0fca371814d02"),
+        (BrowserType::Firefox, "88a4e2bb4aabd7f6bb650814157e3b6e"),
+        (BrowserType::Safari, "1d8f2f0044fe9ed96bf9b31f8839f40b"),
+        (
+            BrowserType::MobileChrome,
+            "bfc9ec7b8b8af29c80c2e93d9ba186cb",
+        ),
+        (
+            BrowserType::MobileSafari,
+            "0f85cb8fb96088b828a95ff25fe3ee98",
+        ),
+    ];
+
+    #[test]
+    fn default_profiles_keep_their_pinned_ja3_hashes() {
+        let manager = DefaultTLSManager::default();
+        assert_eq!(build_default_profiles().len(), PINNED_JA3_HASHES.len());
+        for (browser, hash) in PINNED_JA3_HASHES {
+            assert_eq!(manager.assert_profile(browser, hash), Ok(()));
+        }
+        assert!(
+            manager
+                .assert_profile(BrowserType::Chrome, PINNED_JA3_HASHES[1].1)
+                .is_err()
+        );
+    }
 }
@@ -0,0 +1,157 @@
+//! Buffered `multipart/form-data` bodies.
+//!
+//! A challenge can interrupt an upload, after which the request is sent
+//! again with clearance. Streaming forms cannot be rewound, so
+//! [`MultipartForm`] keeps every part in memory and encodes the same bytes,
+//! under the same boundary, for each attempt.
+
+use bytes::Bytes;
+use uuid::Uuid;
+
+/// One field of a [`MultipartForm`].
+#[derive(Debug, Clone)]
+pub struct MultipartPart {
+    name: String,
+    file_name: Option<String>,
+    content_type: Option<String>,
+    data: Bytes,
+}
+
+impl MultipartPart {
+    /// A plain text field.
+    pub fn text(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            file_name: None,
+            content_type: None,
+            data: Bytes::from(value.into()),
+        }
+    }
+
+    /// A file field, sent as `application/octet-stream` unless
+    /// [`with_content_type`](Self::with_content_type) says otherwise.
+    pub fn file(
+        name: impl Into<String>,
+        file_name: impl Into<String>,
+        data: impl Into<Bytes>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            file_name: Some(file_name.into()),
+            content_type: None,
+            data: data.into(),
+        }
+    }
+
+    pub fn with_content_type(mut self, content_type: impl Into<String>) -> Self {
+        self.content_type = Some(content_type.into());
+        self
+    }
+
+    fn encode_into(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(b"Content-Disposition: form-data; name=\"");
+        out.extend_from_slice(escape(&self.name).as_bytes());
+        out.push(b'"');
+        if let Some(file_name) = &self.file_name {
+            out.extend_from_slice(b"; filename=\"");
+            out.extend_from_slice(escape(file_name).as_bytes());
+            out.push(b'"');
+        }
+        out.extend_from_slice(b"\r\n");
+        let content_type = match (&self.content_type, &self.file_name) {
+            (Some(content_type), _) => Some(content_type.as_str()),
+            (None, Some(_)) => Some("application/octet-stream"),
+            (None, None) => None,
+        };
+        if let Some(content_type) = content_type {
+            out.extend_from_slice(format!("Content-Type: {content_type}\r\n").as_bytes());
+        }
+        out.extend_from_slice(b"\r\n");
+        out.extend_from_slice(&self.data);
+        out.extend_from_slice(b"\r\n");
+    }
+}
+
+/// A `multipart/form-data` body for
+/// [`CloudScraper::post_multipart`](crate::CloudScraper::post_multipart).
+#[derive(Debug, Clone)]
+pub struct MultipartForm {
+    boundary: String,
+    parts: Vec<MultipartPart>,
+}
+
+impl Default for MultipartForm {
+    fn default() -> Self {
+        let id = Uuid::new_v4().simple().to_string();
+        Self {
+            boundary: format!("----WebKitFormBoundary{}", &id[..16]),
+            parts: Vec::new(),
+        }
+    }
+}
+
+impl MultipartForm {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn text(self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.part(MultipartPart::text(name, value))
+    }
+
+    pub fn part(mut self, part: MultipartPart) -> Self {
+        self.parts.push(part);
+        self
+    }
+
+    pub fn boundary(&self) -> &str {
+        &self.boundary
+    }
+
+    /// `Content-Type` header value announcing the boundary.
+    pub fn content_type(&self) -> String {
+        format!("multipart/form-data; boundary={}", self.boundary)
+    }
+
+    /// The encoded body; identical on every call.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for part in &self.parts {
+            out.extend_from_slice(format!("--{}\r\n", self.boundary).as_bytes());
+            part.encode_into(&mut out);
+        }
+        out.extend_from_slice(format!("--{}--\r\n", self.boundary).as_bytes());
+        out
+    }
+}
+
+/// Percent-encode the characters that would break a quoted header parameter,
+/// as browsers do.
+fn escape(value: &str) -> String {
+    value
+        .replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_text_and_file_parts() {
+        let form = MultipartForm::new().text("note", "hi").part(
+            MultipartPart::file("upload", "a\"b.txt", &b"data"[..]).with_content_type("text/plain"),
+        );
+        let boundary = form.boundary().to_string();
+        assert_eq!(
+            String::from_utf8(form.to_bytes()).unwrap(),
+            format!(
+                "--{boundary}\r\nContent-Disposition: form-data; name=\"note\"\r\n\r\nhi\r\n\
+                 --{boundary}\r\nContent-Disposition: form-data; name=\"upload\"; filename=\"a%22b.txt\"\r\n\
+                 Content-Type: text/plain\r\n\r\ndata\r\n--{boundary}--\r\n"
+            )
+        );
+        assert_eq!(form.to_bytes(), form.to_bytes());
+    }
+}
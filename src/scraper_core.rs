@@ -0,0 +1,346 @@
+//! Transport-agnostic scraper logic.
+//!
+//! [`ScraperCore`] holds the same detection, solving, pacing, anti-detection,
+//! fingerprint and state machinery as [`CloudScraper`](crate::CloudScraper),
+//! but never sends anything. Callers prepare a request, send it with any
+//! transport (curl-impersonate, a browser automation backend, ...) and feed
+//! the response back to learn what to do next.
+
+use std::time::Duration;
+
+use bytes::Bytes;
+use http::header::USER_AGENT;
+use http::{HeaderMap, Method};
+use url::Url;
+
+use crate::challenges::core::{
+    ChallengeResponse, ChallengeSubmission, clearance_expiry, decode_body,
+};
+use crate::challenges::detectors::{ChallengeDetection, ChallengeType};
+use crate::challenges::pipeline::{PipelineContext, PipelineError, PipelinePrepareResult};
+use crate::challenges::solvers::{FingerprintManager, MitigationPlan, TlsProfileManager};
+use crate::cloudscraper::{
+    CloudScraperInner, CloudScraperResult, PrepareScope, confirms_clearance, status_failure,
+};
+use crate::modules::state::{StateManager, StateScope};
+
+/// A request [`ScraperCore::prepare`] shaped; send it as-is.
+#[derive(Debug, Clone)]
+pub struct PreparedRequest {
+    pub method: Method,
+    pub url: Url,
+    pub headers: HeaderMap,
+    /// Send through this proxy when the scraper rotates proxies.
+    pub proxy: Option<String>,
+    /// Wait this long before sending.
+    pub delay: Duration,
+}
+
+/// A response received for a [`PreparedRequest`] or a challenge submission.
+#[derive(Debug, Clone)]
+pub struct CoreResponse {
+    /// Final URL after redirects.
+    pub url: Url,
+    pub status: u16,
+    pub headers: HeaderMap,
+    /// Raw body; compressed bodies are decoded per `Content-Encoding`.
+    pub body: Bytes,
+    /// Time on the wire, excluding the prepared delay.
+    pub latency: Duration,
+}
+
+/// What to do with a response, from [`ScraperCore::process`].
+#[derive(Debug)]
+pub enum CoreDecision {
+    /// Not a challenge; the response is the requested page.
+    Accept,
+    /// Send `submission` and process its response in turn.
+    Submit {
+        detection: ChallengeDetection,
+        submission: ChallengeSubmission,
+    },
+    /// Wait for `plan.wait`, adjust as the plan says, and prepare the request
+    /// again; give up instead when `plan.should_retry` is false.
+    Retry {
+        detection: ChallengeDetection,
+        plan: MitigationPlan,
+    },
+    /// The challenge cannot be solved with this configuration.
+    Fail {
+        detection: ChallengeDetection,
+        error: PipelineError,
+    },
+}
+
+/// Parts of a built scraper the core drives.
+pub(crate) struct CoreParts {
+    pub inner: CloudScraperInner,
+    pub state: StateManager,
+    pub state_scope: StateScope,
+    pub accept_languages: Vec<String>,
+}
+
+/// Detection, solving and pacing without an HTTP client.
+///
+/// Built with [`CloudScraperBuilder::build_core`](crate::CloudScraperBuilder::build_core),
+/// so it honours the same configuration as a [`CloudScraper`](crate::CloudScraper).
+pub struct ScraperCore {
+    parts: CoreParts,
+}
+
+impl ScraperCore {
+    pub(crate) fn new(parts: CoreParts) -> Self {
+        Self { parts }
+    }
+
+    /// Per-domain state learned from processed responses.
+    pub fn state(&self) -> &StateManager {
+        &self.parts.state
+    }
+
+    /// Headers, proxy and pre-request delay for `method url`, prepared
+    /// exactly as [`CloudScraper`](crate::CloudScraper) prepares its own.
+    pub fn prepare(&mut self, url: &Url, method: Method) -> CloudScraperResult<PreparedRequest> {
+        let CoreParts {
+            inner,
+            state,
+            state_scope,
+            accept_languages,
+        } = &mut self.parts;
+        let scope = PrepareScope {
+            state,
+            state_scope: *state_scope,
+            accept_languages,
+        };
+        let (headers, _, context) = inner.prepare(scope, &method, url, 0, None, None)?;
+        Ok(PreparedRequest {
+            method,
+            url: url.clone(),
+            headers,
+            proxy: context.proxy,
+            delay: context.delay,
+        })
+    }
+
+    /// Classify `response` to `request`, solving any challenge it carries,
+    /// and learn from the outcome.
+    pub async fn process(
+        &mut self,
+        request: &PreparedRequest,
+        response: &CoreResponse,
+    ) -> CoreDecision {
+        let parts = &mut self.parts;
+        let body = decode_body(&response.body, &response.headers);
+        let challenge_response = ChallengeResponse {
+            url: &response.url,
+            status: response.status,
+            headers: &response.headers,
+            body: &body,
+            request_method: &request.method,
+        };
+        let user_agent = request
+            .headers
+            .get(USER_AGENT)
+            .and_then(|value| value.to_str().ok());
+
+        let inner = &mut parts.inner;
+        let result = inner
+            .pipeline
+            .prepare(
+                &challenge_response,
+                PipelineContext {
+                    failure_recorder: Some(&parts.state),
+                    fingerprint_manager: inner
+                        .fingerprint
+                        .as_mut()
+                        .map(|fp| fp as &mut dyn FingerprintManager),
+                    tls_manager: inner
+                        .tls_manager
+                        .as_mut()
+                        .map(|tls| tls as &mut dyn TlsProfileManager),
+                    user_agent,
+                    ..Default::default()
+                },
+            )
+            .await;
+
+        let decision = match result {
+            PipelinePrepareResult::NoChallenge => CoreDecision::Accept,
+            PipelinePrepareResult::Prepared {
+                detection,
+                submission,
+            } => CoreDecision::Submit {
+                detection,
+                submission,
+            },
+            PipelinePrepareResult::Mitigation { detection, plan } => {
+                CoreDecision::Retry { detection, plan }
+            }
+            PipelinePrepareResult::Failed { detection, error } => {
+                CoreDecision::Fail { detection, error }
+            }
+        };
+        self.learn(request, response, &body, &decision);
+        decision
+    }
+
+    /// Only a response that [confirms clearance](confirms_clearance) marks
+    /// the domain clear; other accepted responses leave its status alone.
+    fn learn(
+        &mut self,
+        request: &PreparedRequest,
+        response: &CoreResponse,
+        body: &str,
+        decision: &CoreDecision,
+    ) {
+        let parts = &mut self.parts;
+        let domain = parts
+            .state_scope
+            .key(response.url.host_str().unwrap_or_default());
+        let challenge_type = match decision {
+            CoreDecision::Accept => None,
+            CoreDecision::Submit { detection, .. }
+            | CoreDecision::Retry { detection, .. }
+            | CoreDecision::Fail { detection, .. } => Some(detection.challenge_type),
+        };
+
+        let failure = challenge_type.map(|challenge_type| {
+            status_failure(response.status, request.proxy.as_deref())
+                .with_challenge_type(challenge_type)
+        });
+        let success = failure.is_none();
+        parts.state.record_outcome_detailed(
+            &domain,
+            Some(response.latency),
+            Some(request.delay),
+            failure,
+        );
+        match challenge_type {
+            None if confirms_clearance(response.status, &response.headers, body) => {
+                let until = clearance_expiry(&response.headers, chrono::Utc::now());
+                parts.state.update(&domain, |state| state.mark_clear(until));
+            }
+            None | Some(ChallengeType::OriginError) => {}
+            Some(challenge_type) => {
+                parts
+                    .state
+                    .update(&domain, |state| state.mark_challenged(challenge_type));
+            }
+        }
+
+        let alerts = parts.inner.learn(
+            &domain,
+            response.status,
+            response.latency,
+            request.delay,
+            success,
+        );
+        if !alerts.is_empty() {
+            log::warn!("{domain} performance alerts: {alerts:#?}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use crate::CloudScraper;
+    use crate::challenges::detectors::ChallengeType;
+    use crate::external_deps::interpreters::{InterpreterError, JavascriptInterpreter};
+    use crate::modules::state::ChallengeStatus;
+
+    struct FixedAnswer;
+
+    impl JavascriptInterpreter for FixedAnswer {
+        fn solve_challenge(
+            &self,
+            _page_html: &str,
+            _host: &str,
+        ) -> Result<String, InterpreterError> {
+            Ok("42".into())
+        }
+    }
+
+    fn cloudflare(status: u16, url: &Url, body: &'static str) -> CoreResponse {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            http::header::SERVER,
+            http::HeaderValue::from_static("cloudflare"),
+        );
+        CoreResponse {
+            url: url.clone(),
+            status,
+            headers,
+            body: Bytes::from_static(body.as_bytes()),
+            latency: Duration::from_millis(30),
+        }
+    }
+
+    #[tokio::test]
+    async fn prepare_and_process_cycle_solves_a_challenge() {
+        let mut core = CloudScraper::builder()
+            .disable_adaptive_timing()
+            .with_interpreter(Arc::new(FixedAnswer))
+            .build_core()
+            .unwrap();
+        let url = Url::parse("https://example.com/page").unwrap();
+
+        let request = core.prepare(&url, Method::GET).unwrap();
+        assert!(request.headers.contains_key(USER_AGENT));
+        assert_eq!(request.delay, Duration::ZERO);
+
+        let challenge = cloudflare(503, &url, include_str!("../tests/fixtures/iuam_v1.html"));
+        match core.process(&request, &challenge).await {
+            CoreDecision::Submit {
+                detection,
+                submission,
+            } => {
+                assert_eq!(detection.challenge_type, ChallengeType::JavaScriptV1);
+                assert_eq!(submission.form_fields["jschl_answer"], "42");
+            }
+            other => panic!("expected a submission, got {other:?}"),
+        }
+        assert_eq!(
+            core.state().challenge_status("example.com"),
+            ChallengeStatus::Challenged(ChallengeType::JavaScriptV1)
+        );
+
+        let page = cloudflare(200, &url, "<p>welcome</p>");
+        assert!(matches!(
+            core.process(&request, &page).await,
+            CoreDecision::Accept
+        ));
+        assert!(matches!(
+            core.state().challenge_status("example.com"),
+            ChallengeStatus::Clear { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn accepted_error_pages_do_not_mark_the_domain_clear() {
+        let mut core = CloudScraper::builder()
+            .disable_adaptive_timing()
+            .build_core()
+            .unwrap();
+        let url = Url::parse("https://example.com/missing").unwrap();
+        let request = core.prepare(&url, Method::GET).unwrap();
+
+        let not_found = CoreResponse {
+            url: url.clone(),
+            status: 404,
+            headers: HeaderMap::new(),
+            body: Bytes::from_static(b"<p>not found</p>"),
+            latency: Duration::from_millis(30),
+        };
+        assert!(matches!(
+            core.process(&request, &not_found).await,
+            CoreDecision::Accept
+        ));
+        assert_eq!(
+            core.state().challenge_status("example.com"),
+            ChallengeStatus::Unknown
+        );
+    }
+}
@@ -0,0 +1,90 @@
+//! Serde adapters that encode durations as integer milliseconds.
+//!
+//! Use with `#[serde(with = "crate::serde_millis::...")]`. Instants have no
+//! portable representation, so they are written as their age in milliseconds
+//! and rebuilt relative to the deserializing process's clock.
+
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+fn to_millis(duration: &Duration) -> u64 {
+    duration.as_millis().min(u64::MAX as u128) as u64
+}
+
+pub mod duration {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(value: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(to_millis(value))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_millis)
+    }
+}
+
+pub mod option_duration {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        value: &Option<Duration>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        value.as_ref().map(to_millis).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Duration>, D::Error> {
+        Ok(Option::<u64>::deserialize(deserializer)?.map(Duration::from_millis))
+    }
+}
+
+/// `Vec<(String, Duration)>`, e.g. per-domain latencies.
+pub mod named_durations {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        value: &[(String, Duration)],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(
+            value
+                .iter()
+                .map(|(name, duration)| (name, to_millis(duration))),
+        )
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<(String, Duration)>, D::Error> {
+        Ok(Vec::<(String, u64)>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(name, millis)| (name, Duration::from_millis(millis)))
+            .collect())
+    }
+}
+
+/// `Option<Instant>` written as milliseconds elapsed since that instant.
+pub mod option_instant_age {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        value: &Option<Instant>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        value
+            .map(|instant| to_millis(&instant.elapsed()))
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Instant>, D::Error> {
+        Ok(Option::<u64>::deserialize(deserializer)?.map(|age| {
+            let now = Instant::now();
+            now.checked_sub(Duration::from_millis(age)).unwrap_or(now)
+        }))
+    }
+}
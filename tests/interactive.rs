@@ -98,6 +98,7 @@ fn interactive_full_stack() -> Result<(), Box<dyn Error>> {
         ban_time: Duration::from_secs(120),
         failure_threshold: 2,
         cooldown: Duration::from_secs(30),
+        ..ProxyConfig::default()
     };
 
     let tls_config = TLSConfig {
@@ -109,6 +110,7 @@ fn interactive_full_stack() -> Result<(), Box<dyn Error>> {
             BrowserType::Chrome
         },
         rotation_interval: 3,
+        ..TLSConfig::default()
     };
 
     let mut builder = CloudScraper::builder()
@@ -172,6 +174,7 @@ fn exercise_supporting_modules(domain: &str) -> Result<(), Box<dyn Error>> {
         ban_time: Duration::from_secs(90),
         failure_threshold: 1,
         cooldown: Duration::from_secs(20),
+        ..ProxyConfig::default()
     });
     proxy_manager.load(["http://127.0.0.1:8080", "http://127.0.0.1:9090"]);
     if let Some(proxy) = proxy_manager.next_proxy() {
@@ -188,6 +191,7 @@ fn exercise_supporting_modules(domain: &str) -> Result<(), Box<dyn Error>> {
         rotate_ciphers: true,
         preferred_browser: BrowserType::Firefox,
         rotation_interval: 2,
+        ..TLSConfig::default()
     });
     let tls_profile = tls_manager.current_profile(domain);
     println!("TLS profile for {} -> {:?}", domain, tls_profile.browser);